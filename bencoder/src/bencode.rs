@@ -70,6 +70,12 @@ impl ToBencode for BTreeMap<Vec<u8>, Bencode> {
     }
 }
 
+impl ToBencode for Bencode {
+    fn to_bencode(&self) -> Bencode {
+        self.clone()
+    }
+}
+
 impl<T: ToBencode> ToBencode for Vec<T> {
     fn to_bencode(&self) -> Bencode {
         Bencode::BList(self.iter().map(|s| s.to_bencode()).collect())
@@ -79,6 +85,8 @@ impl<T: ToBencode> ToBencode for Vec<T> {
 impl Bencode {
     /// Parses a bencoded vec of bytes into a Bencode enum.
     ///
+    /// It returns a `BencodeError` if the data is not valid bencode, including when it's truncated.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -101,6 +109,27 @@ impl Bencode {
         Ok(bencode)
     }
 
+    /// Parses a bencoded value at the start of the given bytes, returning it along with the number of bytes it used.
+    ///
+    /// Useful when the bencoded value is followed by raw data.
+    ///
+    /// It returns a `BencodeError` if the bytes don't start with a valid bencoded value, including when it's truncated.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::Bencode;
+    ///
+    /// let data = b"i123eraw";
+    /// let (bencode, length) = Bencode::decode_with_length(data).unwrap();
+    ///
+    /// assert_eq!(bencode, Bencode::BNumber(123));
+    /// assert_eq!(&data[length..], b"raw");
+    /// ```
+    pub fn decode_with_length(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
+        Bencode::do_decode(data)
+    }
+
     fn do_decode(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         if data.is_empty() {
             return Err(BencodeError::InvalidBencode);
@@ -116,7 +145,7 @@ impl Bencode {
 
     fn decode_string(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         let mut i = 0;
-        while data[i] != b':' {
+        while *data.get(i).ok_or(BencodeError::InvalidBencodeString)? != b':' {
             i += 1;
         }
         let length = &data[0..i];
@@ -124,22 +153,23 @@ impl Bencode {
             Ok(s) => s,
             Err(_) => return Err(BencodeError::InvalidBencodeString),
         };
-        let length = match length.parse::<i64>() {
+        let length = match length.parse::<usize>() {
             Ok(n) => n,
             Err(_) => return Err(BencodeError::InvalidBencodeString),
         };
-        let mut i = i + 1;
-        let mut string = Vec::new();
-        for _ in 0..length {
-            string.push(data[i]);
-            i += 1;
-        }
-        Ok((Bencode::BString(string), i))
+        let start = i + 1;
+        let end = start
+            .checked_add(length)
+            .ok_or(BencodeError::InvalidBencodeString)?;
+        let string = data
+            .get(start..end)
+            .ok_or(BencodeError::InvalidBencodeString)?;
+        Ok((Bencode::BString(string.to_vec()), end))
     }
 
     fn decode_number(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         let mut i = 1;
-        while data[i] != b'e' {
+        while *data.get(i).ok_or(BencodeError::InvalidBencodeNumber)? != b'e' {
             i += 1;
         }
         let number = &data[1..i];
@@ -157,7 +187,7 @@ impl Bencode {
     fn decode_list(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         let mut i = 1;
         let mut list = Vec::new();
-        while data[i] != b'e' {
+        while *data.get(i).ok_or(BencodeError::InvalidBencodeList)? != b'e' {
             let (value, size) = Bencode::do_decode(&data[i..])?;
             list.push(value);
             i += size;
@@ -168,7 +198,7 @@ impl Bencode {
     fn decode_dict(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         let mut i = 1;
         let mut dict = BTreeMap::new();
-        while data[i] != b'e' {
+        while *data.get(i).ok_or(BencodeError::InvalidBencodeDict)? != b'e' {
             let (key, size) = Bencode::do_decode(&data[i..])?;
             i += size;
             let (value, size) = Bencode::do_decode(&data[i..])?;
@@ -259,6 +289,41 @@ mod tests {
         assert_eq!(Bencode::decode(data), Err(BencodeError::InvalidBencode));
     }
 
+    #[test]
+    fn test_decode_truncated_data() {
+        assert_eq!(Bencode::decode(b"l"), Err(BencodeError::InvalidBencodeList));
+        assert_eq!(
+            Bencode::decode(b"i12"),
+            Err(BencodeError::InvalidBencodeNumber)
+        );
+        assert_eq!(
+            Bencode::decode(b"5:ab"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+        assert_eq!(
+            Bencode::decode(b"5"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+        assert_eq!(
+            Bencode::decode(b"d1:md"),
+            Err(BencodeError::InvalidBencodeDict)
+        );
+        assert_eq!(Bencode::decode(b"d1:m"), Err(BencodeError::InvalidBencode));
+    }
+
+    #[test]
+    fn test_decode_string_with_an_invalid_length() {
+        assert_eq!(Bencode::decode(b"-1:a"), Err(BencodeError::InvalidBencode));
+        assert_eq!(
+            Bencode::decode(b"1-:a"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+        assert_eq!(
+            Bencode::decode(format!("{}:a", usize::MAX).as_bytes()),
+            Err(BencodeError::InvalidBencodeString)
+        );
+    }
+
     #[test]
     fn test_decode_string() {
         let data = b"4:spam";
@@ -446,4 +511,39 @@ mod tests {
         let data: Vec<String> = vec![];
         assert_eq!(Bencode::encode(&data), b"le");
     }
+
+    #[test]
+    fn test_encode_bencode() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"spam".to_vec(), Bencode::BList(vec![Bencode::BNumber(1)]));
+        let data = Bencode::BDict(dict);
+        assert_eq!(Bencode::encode(&data), b"d4:spamli1eee");
+    }
+
+    #[test]
+    fn test_decode_with_length_and_trailing_data() {
+        let data = b"d3:keyi1eeabc";
+        let (bencode, length) = Bencode::decode_with_length(data).unwrap();
+
+        let mut dict = BTreeMap::new();
+        dict.insert(b"key".to_vec(), Bencode::BNumber(1));
+        assert_eq!(bencode, Bencode::BDict(dict));
+        assert_eq!(length, 10);
+    }
+
+    #[test]
+    fn test_decode_with_length_of_truncated_data() {
+        assert_eq!(
+            Bencode::decode_with_length(b"d8:msg_typei1e5:piecei0e"),
+            Err(BencodeError::InvalidBencodeDict)
+        );
+        assert_eq!(
+            Bencode::decode_with_length(b"d8:msg_typei1"),
+            Err(BencodeError::InvalidBencodeNumber)
+        );
+        assert_eq!(
+            Bencode::decode_with_length(b"d8:msg_ty"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+    }
 }
@@ -0,0 +1,71 @@
+use dtorrent::bt_client::btclient::BtClient;
+use dtorrent::statistics::torrent_stats::TorrentStats;
+use dtorrent::util::format::format_speed;
+use std::{
+    env,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_CONFIG_PATH: &str = "config.cfg";
+const PRINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs the client without the GTK interface, printing the progress of every torrent to stdout.
+///
+/// Usage: bt_headless <torrents directory> [config file]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        return eprintln!("Usage: bt_headless <torrents directory> [config file]");
+    }
+    let torrents_directory = args[1].clone();
+    let config_path = args
+        .get(2)
+        .map_or(DEFAULT_CONFIG_PATH, |path| path.as_str());
+
+    let client = match BtClient::init_with_config(torrents_directory, config_path) {
+        Ok(client) => client,
+        Err(error) => return eprintln!("{}", error),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let printer = thread::Builder::new()
+        .name("Progress printer".to_string())
+        .spawn(move || print_progress(receiver));
+    if let Err(error) = printer {
+        return eprintln!("{}", error);
+    }
+
+    if let Err(error) = client.run(sender) {
+        return eprintln!("{}", error);
+    }
+    println!("All torrents finished downloading.");
+}
+
+/// Prints the statistics received, at most once every `PRINT_INTERVAL`.
+fn print_progress(receiver: Receiver<Vec<TorrentStats>>) {
+    let mut last_print: Option<Instant> = None;
+
+    for statistics in receiver {
+        if last_print.is_some_and(|last| last.elapsed() < PRINT_INTERVAL) {
+            continue;
+        }
+        last_print = Some(Instant::now());
+
+        for torrent in statistics {
+            println!(
+                "{}: {} - {}/{} pieces ({:.2}%) - down: {} - up: {} - peers: {} - eta: {}",
+                torrent.torrent_name,
+                torrent.state,
+                torrent.downloaded_pieces_amount,
+                torrent.pieces_amount,
+                torrent.download_percentage() * 100.0,
+                format_speed(torrent.download_speed),
+                format_speed(torrent.upload_speed),
+                torrent.peers_amount,
+                torrent.eta
+            );
+        }
+    }
+}
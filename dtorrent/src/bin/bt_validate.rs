@@ -0,0 +1,57 @@
+use dtorrent::torrent_parser::parser::TorrentParser;
+use std::{env, fs, path::Path};
+
+/// Parses and validates .torrent files without downloading them, printing a summary of each one.
+///
+/// Usage: bt_validate <.torrent file or directory>
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        return eprintln!("Usage: bt_validate <.torrent file or directory>");
+    }
+
+    let path = Path::new(&args[1]);
+    let filepaths = if path.is_dir() {
+        match torrent_files_in_directory(path) {
+            Ok(filepaths) => filepaths,
+            Err(error) => return eprintln!("Couldn't read directory {}: {}", args[1], error),
+        }
+    } else {
+        vec![args[1].clone()]
+    };
+
+    let mut invalid_torrents = 0;
+    for filepath in filepaths {
+        match TorrentParser::validate(&filepath) {
+            Ok(summary) => println!(
+                "OK {} - {} - info hash: {} - trackers: {}",
+                filepath,
+                summary,
+                summary.info_hash,
+                summary.trackers.join(", ")
+            ),
+            Err(error) => {
+                invalid_torrents += 1;
+                println!("INVALID {} - {:?}", filepath, error);
+            }
+        }
+    }
+
+    if invalid_torrents > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn torrent_files_in_directory(directory: &Path) -> std::io::Result<Vec<String>> {
+    let mut filepaths: Vec<String> = fs::read_dir(directory)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "torrent")
+        })
+        .map(|path| path.display().to_string())
+        .collect();
+    filepaths.sort();
+    Ok(filepaths)
+}
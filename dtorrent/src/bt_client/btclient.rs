@@ -3,24 +3,33 @@ use crate::{
     bt_client::error_message::ErrorMessage,
     bt_server::server::BtServer,
     config::cfg::Cfg,
+    metrics::prometheus::{self, TorrentSample},
+    port_mapping::{gateway, nat_pmp::NAT_PMP_PORT, PortMapper},
+    statistics::statistics_sender::StatisticsSender,
     statistics::statistics_updater::StatisticsUpdater,
-    statistics::torrent_stats::TorrentStats,
-    torrent_handler::{handler::TorrentHandler, status::AtomicTorrentStatus},
+    torrent_handler::{event::TorrentEvent, handler::TorrentHandler, status::AtomicTorrentStatus},
     torrent_parser::parser::TorrentParser,
     torrent_parser::torrent::Torrent,
 };
-use gtk::glib;
 use logger::logger_receiver::Logger;
 use logger::logger_sender::LoggerSender;
-use rand::Rng;
+use rand::{distributions::Alphanumeric, Rng};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fs, io,
-    sync::Arc,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 const CONFIG_FILE_PATH: &str = "config.cfg";
+const PEER_ID_LENGTH: usize = 20;
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /**
 Represents the BitTorrent client application.
@@ -33,6 +42,13 @@ pub struct BtClient {
     logger: Logger,
     torrents: Vec<Torrent>,
     client_peer_id: String,
+    subscribers: Vec<Sender<TorrentEvent>>,
+    // Shutdown state, shared between `run()` and `shutdown()`.
+    shutting_down: AtomicBool,
+    running: AtomicBool,
+    torrent_statuses: Mutex<Vec<Arc<AtomicTorrentStatus>>>,
+    stop_signals: Mutex<Vec<Arc<AtomicBool>>>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl BtClient {
@@ -44,9 +60,20 @@ impl BtClient {
     It reads the configuration file (./config.cfg), starts a Logger writing to the folder indicated by that configuration file, and then attempts to parse the torrent files placed inside the provided torrents directory.
 
     The corrently parsed torrents are stored inside the BtClient struct, and will begin downloading when the '.run()' method is called.
+
+    It returns a `TorrentDirectoryNotFound` or `TorrentDirectoryIsNotADirectory` error if the torrents directory path doesn't exist or isn't a directory.
     */
     pub fn init(torrents_directory: String) -> Result<Self, BtClientError> {
-        let config = Self::read_configuration_file(CONFIG_FILE_PATH)?;
+        Self::init_with_config(torrents_directory, CONFIG_FILE_PATH)
+    }
+
+    /// Same as `init`, but reading the configuration from the given file path.
+    pub fn init_with_config(
+        torrents_directory: String,
+        config_path: &str,
+    ) -> Result<Self, BtClientError> {
+        Self::validate_torrents_directory(&torrents_directory)?;
+        let config = Self::read_configuration_file(config_path)?;
         let logger = Logger::new(&config.log_directory, config.max_log_file_kb_size * 1000)?;
 
         let logger_sender = logger.new_sender();
@@ -55,98 +82,447 @@ impl BtClient {
 
         let torrents = Self::parse_torrents_in_directory(logger_sender, torrents_directory)?;
 
-        let client_peer_id = Self::generate_peer_id();
+        let client_peer_id = Self::generate_peer_id(&config.peer_id_prefix);
+
+        Ok(Self::new(config, logger, torrents, client_peer_id))
+    }
 
-        Ok(Self {
+    fn new(config: Cfg, logger: Logger, torrents: Vec<Torrent>, client_peer_id: String) -> Self {
+        Self {
             config,
             logger,
             torrents,
             client_peer_id,
-        })
+            subscribers: Vec::new(),
+            shutting_down: AtomicBool::new(false),
+            running: AtomicBool::new(false),
+            torrent_statuses: Mutex::new(Vec::new()),
+            stop_signals: Mutex::new(Vec::new()),
+            threads: Mutex::new(Vec::new()),
+        }
     }
 
-    /// Generates a random peer ID.
-    fn generate_peer_id() -> String {
-        let mut peer_id = String::from("DTorrent:");
+    /// Subscribes to the `TorrentEvent`s of every torrent, such as completed pieces and torrents or connected peers, which will be sent through the given `sender`.
+    ///
+    /// It must be called before `run()`.
+    pub fn subscribe(&mut self, sender: Sender<TorrentEvent>) {
+        self.subscribers.push(sender);
+    }
 
-        let mut rng = rand::thread_rng();
-        for _ in 0..11 {
-            let n: u32 = rng.gen_range(0..10);
-            peer_id.push_str(&n.to_string())
-        }
+    /// Generates a random 20 bytes peer ID in Azureus-style: the given prefix (such as `-DT0001-`) followed by random characters.
+    ///
+    /// The random characters are alphanumeric, so the peer ID can be sent to the tracker without encoding.
+    fn generate_peer_id(prefix: &str) -> String {
+        let mut peer_id = String::from(prefix);
+        peer_id.truncate(PEER_ID_LENGTH);
+
+        let random_length = PEER_ID_LENGTH - peer_id.len();
+        peer_id.extend(
+            rand::thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(random_length)
+                .map(char::from),
+        );
         peer_id
     }
 
     /// Method for starting the torrent downloading process.
-    pub fn run(&self, sender: glib::Sender<Vec<TorrentStats>>) {
+    ///
+    /// The statistics of the torrents are periodically sent through the given `sender`.
+    ///
+    /// It returns once every torrent finished downloading, or once the client is shut down with `shutdown()`.
+    ///
+    /// It returns a `SpawningThreadError` if no thread could be spawned to run the torrent handlers.
+    pub fn run(&self, sender: impl StatisticsSender + 'static) -> Result<(), BtClientError> {
+        self.running.store(true, Ordering::Relaxed);
         let logger = self.logger.new_sender();
         logger.info("Starting client...");
 
+        // The listener is opened before the torrents start, so the port announced to the trackers is the one chosen.
+        let (listener, mut config) = self.bind_server();
+        if listener.is_some() && config.enable_port_mapping {
+            self.map_port(&mut config);
+        }
+
         let mut torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>> = HashMap::new();
         let mut handler_status_list = Vec::new();
-        let mut torrent_handlers_joins = Vec::new();
-        self.torrents.iter().for_each(|torrent| {
-            let handler = TorrentHandler::new(torrent.clone(), self.config.clone(), logger.clone(), self.client_peer_id.clone());
+        let mut handlers = Vec::new();
+        for torrent in &self.torrents {
+            let handler = TorrentHandler::new(
+                torrent.clone(),
+                config.clone(),
+                logger.clone(),
+                self.client_peer_id.clone(),
+                config.download_directory_for(&torrent.name()),
+            );
+            for subscriber in &self.subscribers {
+                handler.status().subscribe(subscriber.clone());
+            }
+            self.register_torrent_status(handler.status());
             handler_status_list.push(handler.status());
             torrents_with_status.insert(torrent.clone(), handler.status());
-            let thread_handle = self.spawn_torrent_handler(torrent, handler);
-            match thread_handle {
-                Ok(handle) => {
-                    torrent_handlers_joins.push(handle);
-                }
-                Err(error) => {
-                    let error_message = format!("An error occurred while trying to spawn a new thread for a torrent_handler: {:?}", error);
-                    logger.error(&error_message);
-                    handler_status_list.pop();
-                    torrents_with_status.remove(torrent);
+            handlers.push((torrent.name(), handler));
+        }
+
+        let max_active_torrents = self
+            .config
+            .max_active_torrents
+            .unwrap_or(self.torrents.len());
+        let torrent_handlers_joins =
+            match self.spawn_torrent_handlers(handlers, max_active_torrents) {
+                Ok(joins) => joins,
+                Err(err) => {
+                    self.running.store(false, Ordering::Relaxed);
+                    return Err(err);
                 }
-            }
-        });
+            };
 
-        let runner = StatisticsUpdater::new(handler_status_list, sender);
-        let _jh = self.spawn_statistics_runner(runner);
+        let runner = StatisticsUpdater::new(
+            handler_status_list,
+            Box::new(sender),
+            self.config.stats_json_file.clone(),
+            self.config.statistics_update_interval(),
+        );
+        self.register_stop_signal(runner.stop_signal());
+        match self.spawn_statistics_runner(runner) {
+            Ok(join) => self.register_thread(join),
+            Err(err) => logger.error(&format!("{:?}", err)),
+        }
 
-        self.start_server(torrents_with_status);
+        if let Some(listener) = listener {
+            self.start_server(torrents_with_status, listener, config);
+        }
 
         self.join_handles(torrent_handlers_joins);
+        self.running.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Shuts down the client: stops every torrent handler and peer session, the server and the statistics runner.
+    ///
+    /// The torrents that were stopped are announced to their trackers with the `stopped` event.
+    ///
+    /// It waits up to the given `timeout` for every thread of the client to finish, and returns a `ShutdownTimeout` error if some of them are still running.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), BtClientError> {
+        let logger = self.logger.new_sender();
+        logger.info("Shutting down client...");
+
+        self.shutting_down.store(true, Ordering::Relaxed);
+        if let Ok(torrent_statuses) = self.torrent_statuses.lock() {
+            torrent_statuses.iter().for_each(|status| status.stop());
+        }
+        if let Ok(stop_signals) = self.stop_signals.lock() {
+            stop_signals
+                .iter()
+                .for_each(|signal| signal.store(true, Ordering::Relaxed));
+        }
+
+        let deadline = Instant::now() + timeout;
+        while !self.threads_finished() {
+            if Instant::now() >= deadline {
+                let error = BtClientError::ShutdownTimeout(ErrorMessage::new(format!(
+                    "threads still running after {:?}",
+                    timeout
+                )));
+                logger.error(&format!("{}", error));
+                return Err(error);
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+
+        if let Ok(mut threads) = self.threads.lock() {
+            self.join_handles(threads.drain(..).collect());
+        }
+        logger.info("Client shut down.");
+        Ok(())
     }
 
-    fn start_server(&self, torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>) {
+    /// Stops the torrent with the given info hash, which is announced to its trackers with the `stopped` event.
+    ///
+    /// The torrent is no longer included in the statistics.
+    ///
+    /// It returns a `TorrentNotFound` error if there is no running torrent with that info hash.
+    pub fn remove_torrent(&self, info_hash: &str) -> Result<(), BtClientError> {
+        let status = match self.torrent_statuses.lock() {
+            Ok(mut torrent_statuses) => torrent_statuses
+                .iter()
+                .position(|status| status.torrent.info_hash_hex() == info_hash)
+                .map(|index| torrent_statuses.remove(index)),
+            Err(_) => None,
+        };
+
+        match status {
+            Some(status) => {
+                status.stop();
+                self.logger
+                    .new_sender()
+                    .info(&format!("Torrent removed: {}", status.torrent.name()));
+                Ok(())
+            }
+            None => Err(BtClientError::TorrentNotFound(ErrorMessage::new(
+                info_hash.to_string(),
+            ))),
+        }
+    }
+
+    /// Removes the torrents whose info hashes are received through the given `receiver`, until every sender is dropped.
+    ///
+    /// The info hashes that don't match a running torrent are logged as errors.
+    pub fn remove_torrents_from(&self, receiver: Receiver<String>) {
+        for info_hash in receiver {
+            if let Err(err) = self.remove_torrent(&info_hash) {
+                self.logger.new_sender().error(&format!("{}", err));
+            }
+        }
+    }
+
+    /// Renders the metrics of the running torrents in the Prometheus text exposition format.
+    pub fn metrics(&self) -> String {
+        let samples: Vec<TorrentSample> = match self.torrent_statuses.lock() {
+            Ok(torrent_statuses) => torrent_statuses
+                .iter()
+                .map(|status| TorrentSample {
+                    info_hash: status.torrent.info_hash_hex(),
+                    name: status.torrent.name(),
+                    metrics: status.metrics().snapshot(),
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        prometheus::render(&samples)
+    }
+
+    /// Returns true if `run()` returned and every thread it left running finished.
+    fn threads_finished(&self) -> bool {
+        let threads_finished = match self.threads.lock() {
+            Ok(threads) => threads.iter().all(|thread| thread.is_finished()),
+            Err(_) => false,
+        };
+        threads_finished && !self.running.load(Ordering::Relaxed)
+    }
+
+    // Status and signals registered after `shutdown()` was called are stopped right away.
+    // As the flag is set before taking the lock in `shutdown()`, none of them is missed.
+
+    fn register_torrent_status(&self, status: Arc<AtomicTorrentStatus>) {
+        if let Ok(mut torrent_statuses) = self.torrent_statuses.lock() {
+            torrent_statuses.push(status.clone());
+        }
+        if self.shutting_down.load(Ordering::Relaxed) {
+            status.stop();
+        }
+    }
+
+    fn register_stop_signal(&self, signal: Arc<AtomicBool>) {
+        if let Ok(mut stop_signals) = self.stop_signals.lock() {
+            stop_signals.push(signal.clone());
+        }
+        if self.shutting_down.load(Ordering::Relaxed) {
+            signal.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn register_thread(&self, join: JoinHandle<()>) {
+        if let Ok(mut threads) = self.threads.lock() {
+            threads.push(join);
+        }
+    }
+
+    /// Opens the listener of the server on the first of the configured ports that is not in use.
+    ///
+    /// It returns the listener, if any port could be used, and the config with its `tcp_port` set to the port chosen.
+    fn bind_server(&self) -> (Option<TcpListener>, Cfg) {
+        let logger = self.logger.new_sender();
+        let mut config = self.config.clone();
+        let listener = match BtServer::bind(&config.tcp_ports()) {
+            Ok(listener) => listener,
+            Err(err) => {
+                logger.error(&format!("The server couldn't be started: {:?}", err));
+                return (None, config);
+            }
+        };
+        match listener.local_addr() {
+            Ok(addr) => {
+                config.tcp_port = addr.port();
+                logger.info(&format!("Listening for peers on port {}.", config.tcp_port));
+            }
+            Err(err) => logger.warn(&format!("Couldn't get the port listened on: {:?}", err)),
+        }
+        (Some(listener), config)
+    }
+
+    /// Maps the listening port on the gateway with NAT-PMP, and keeps it mapped in a new thread until the client is shut down.
+    ///
+    /// On success the `tcp_port` of the config is set to the external port, and the external address is announced unless an `announce_ip` was configured.
+    /// Errors are only logged, as the client still works without incoming connections from outside the local network.
+    fn map_port(&self, config: &mut Cfg) {
+        let logger = self.logger.new_sender();
+        let gateway = match config.port_mapping_gateway {
+            Some(gateway) => gateway,
+            None => match gateway::default_gateway() {
+                Ok(gateway) => gateway,
+                Err(err) => {
+                    logger.warn(&format!(
+                        "Couldn't find the gateway to map the port: {:?}",
+                        err
+                    ));
+                    return;
+                }
+            },
+        };
+        let mapper =
+            match PortMapper::map(SocketAddr::from((gateway, NAT_PMP_PORT)), config.tcp_port) {
+                Ok(mapper) => mapper,
+                Err(err) => {
+                    logger.warn(&format!("Couldn't map the port on the gateway: {:?}", err));
+                    return;
+                }
+            };
+
+        let mapping = mapper.mapping();
+        config.tcp_port = mapping.external_port;
+        config
+            .announce_ip
+            .get_or_insert(mapping.external_ip.to_string());
+        logger.info(&format!(
+            "Port mapped on the gateway to {}:{}.",
+            mapping.external_ip, mapping.external_port
+        ));
+
+        self.register_stop_signal(mapper.stop_signal());
+        let builder = thread::Builder::new().name("Port mapping".to_string());
+        let mapper_logger = logger.clone();
+        let join = builder.spawn(move || {
+            if let Err(err) = mapper.keep_mapped(mapper_logger.clone()) {
+                mapper_logger.warn(&format!("Couldn't keep the port mapped: {:?}", err));
+            }
+        });
+        match join {
+            Ok(join) => self.register_thread(join),
+            Err(err) => logger.error(&format!("{:?}", err)),
+        }
+    }
+
+    fn start_server(
+        &self,
+        torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+        listener: TcpListener,
+        config: Cfg,
+    ) {
         let mut server = BtServer::new(
             torrents_with_status,
-            self.config.clone(),
+            config,
             self.logger.new_sender(),
             self.client_peer_id.clone(),
         );
 
+        self.register_stop_signal(server.stop_signal());
+
         let builder = thread::Builder::new().name("Server".to_string());
         let server_logger_sender = self.logger.new_sender();
 
-        let join = builder.spawn(move || match server.init() {
+        let join = builder.spawn(move || match server.serve(listener) {
             Ok(_) => (),
             Err(err) => {
                 server_logger_sender.error(&format!("The server couldn't be started: {:?}", err));
             }
         });
         match join {
-            Ok(_) => (),
+            Ok(join) => self.register_thread(join),
             Err(err) => self.logger.new_sender().error(&format!("{:?}", err)),
         }
     }
 
-    fn spawn_torrent_handler(
+    /// Spawns the threads that run the torrent handlers, with at most `max_active_torrents` of them downloading at the same time.
+    ///
+    /// The rest of the torrents are queued, and each one starts once an active torrent finishes.
+    ///
+    /// It returns a `SpawningThreadError` if no thread could be spawned.
+    fn spawn_torrent_handlers(
         &self,
-        torrent: &Torrent,
-        mut torrent_handler: TorrentHandler,
-    ) -> Result<JoinHandle<()>, io::Error> {
-        let logger = self.logger.new_sender();
+        handlers: Vec<(String, TorrentHandler)>,
+        max_active_torrents: usize,
+    ) -> Result<Vec<JoinHandle<()>>, BtClientError> {
+        let run_logger = self.logger.new_sender();
+        let joins =
+            Self::spawn_queued(handlers, max_active_torrents, move |(name, mut handler)| {
+                run_logger.info(&format!("Starting torrent: {}", name));
+                if let Err(torrent_error) = handler.handle() {
+                    run_logger.error(&format!("{:?}", torrent_error));
+                }
+            });
 
-        let builder = thread::Builder::new().name(format!("Torrent handler: {}", torrent.name()));
-        builder.spawn(move || {
-            if let Err(torrent_error) = torrent_handler.handle() {
-                logger.error(&format!("{:?}", torrent_error));
+        Self::spawned_joins(joins, &self.logger.new_sender())
+    }
+
+    /// Keeps the threads that could be spawned by `spawn_queued()`.
+    ///
+    /// As every thread takes its jobs from the same queue, the jobs still run while at least one thread was spawned, so the other errors are only logged.
+    /// Otherwise the jobs would never run and a `SpawningThreadError` is returned.
+    fn spawned_joins(
+        joins: Vec<Result<JoinHandle<()>, io::Error>>,
+        logger: &LoggerSender,
+    ) -> Result<Vec<JoinHandle<()>>, BtClientError> {
+        let mut spawned = Vec::new();
+        let mut last_error = None;
+        for join in joins {
+            match join {
+                Ok(join) => spawned.push(join),
+                Err(error) => {
+                    logger.warn(&format!(
+                        "An error occurred while trying to spawn a new thread for a torrent_handler: {:?}",
+                        error
+                    ));
+                    last_error = Some(error);
+                }
             }
-        })
+        }
+
+        match last_error {
+            Some(error) if spawned.is_empty() => {
+                let spawn_error = BtClientError::SpawningThreadError(ErrorMessage::new(format!(
+                    "no torrent handler could be run: {}",
+                    error
+                )));
+                logger.error(&format!("{}", spawn_error));
+                Err(spawn_error)
+            }
+            _ => Ok(spawned),
+        }
+    }
+
+    /// Spawns up to `max_active` threads that run the given jobs in order, each thread taking the next queued job once it finishes its current one.
+    fn spawn_queued<T, F>(
+        jobs: Vec<T>,
+        max_active: usize,
+        run: F,
+    ) -> Vec<Result<JoinHandle<()>, io::Error>>
+    where
+        T: Send + 'static,
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let threads = max_active.min(jobs.len());
+        let queue = Arc::new(Mutex::new(VecDeque::from(jobs)));
+        let run = Arc::new(run);
+
+        (0..threads)
+            .map(|i| {
+                let queue = queue.clone();
+                let run = run.clone();
+                let builder = thread::Builder::new().name(format!("Torrent handler {}", i));
+                builder.spawn(move || loop {
+                    // The queue is locked only while taking a job, not while running it.
+                    let job = match queue.lock() {
+                        Ok(mut queue) => queue.pop_front(),
+                        Err(_) => None,
+                    };
+                    match job {
+                        Some(job) => run(job),
+                        None => break,
+                    }
+                })
+            })
+            .collect()
     }
 
     fn spawn_statistics_runner(
@@ -177,14 +553,28 @@ impl BtClient {
         match Cfg::new(filename) {
             Ok(config) => Ok(config),
             Err(io_error) => {
-                let message = format!("Couldn't read configuration file: {}", io_error);
                 let config_error =
-                    BtClientError::ConfigurationFileError(ErrorMessage::new(message));
+                    BtClientError::ConfigurationFileError(ErrorMessage::new(io_error.to_string()));
                 Err(config_error)
             }
         }
     }
 
+    /// Checks that the torrents directory exists and is a directory, before anything is initialized.
+    fn validate_torrents_directory(torrents_directory: &str) -> Result<(), BtClientError> {
+        let metadata = fs::metadata(torrents_directory).map_err(|_| {
+            BtClientError::TorrentDirectoryNotFound(ErrorMessage::new(
+                torrents_directory.to_string(),
+            ))
+        })?;
+        if !metadata.is_dir() {
+            return Err(BtClientError::TorrentDirectoryIsNotADirectory(
+                ErrorMessage::new(torrents_directory.to_string()),
+            ));
+        }
+        Ok(())
+    }
+
     fn parse_torrents_in_directory(
         log_sender: LoggerSender,
         torrents_directory: String,
@@ -223,12 +613,15 @@ impl BtClient {
         log_sender: &LoggerSender,
         directory: String,
     ) -> Result<Vec<String>, BtClientError> {
-        let filenames = Self::open_directory(log_sender, directory)?
+        let filenames: Vec<String> = Self::open_directory(log_sender, directory.clone())?
             .flatten()
             .flat_map(|dir_entry| dir_entry.file_name().into_string())
             .filter(|filename| filename.ends_with(".torrent"))
             .collect();
 
+        if filenames.is_empty() {
+            log_sender.warn(&format!("No .torrent files found in {}", directory));
+        }
         Ok(filenames)
     }
 
@@ -239,12 +632,292 @@ impl BtClient {
         match fs::read_dir(directory) {
             Ok(dir) => Ok(dir),
             Err(error) => {
-                let directory_error = BtClientError::TorrentDirectoryError(ErrorMessage::new(
-                    format!("Failed to read the given torrents directory: {}", error),
-                ));
-                log_sender.error(&format!("{:?}", directory_error));
+                let directory_error =
+                    BtClientError::TorrentDirectoryError(ErrorMessage::new(error.to_string()));
+                log_sender.error(&format!("{}", directory_error));
                 Err(directory_error)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        time::Duration,
+    };
+
+    use crate::{
+        config::constants::DEFAULT_PEER_ID_PREFIX, statistics::torrent_stats::TorrentStats,
+        torrent_parser::info::Info,
+    };
+
+    use super::*;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_generated_peer_id_has_the_prefix() {
+        let peer_id = BtClient::generate_peer_id(DEFAULT_PEER_ID_PREFIX);
+
+        assert_eq!(peer_id.len(), 20);
+        assert!(peer_id.starts_with("-DT0001-"));
+        assert!(peer_id[8..].chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_only_max_active_jobs_run_at_the_same_time() {
+        let (started_sender, started_receiver) = mpsc::channel();
+        let (finish_sender, finish_receiver) = mpsc::channel::<()>();
+        let finish_receiver = Arc::new(Mutex::new(finish_receiver));
+
+        let joins = BtClient::spawn_queued(vec![1, 2, 3], 2, move |job| {
+            started_sender.send(job).unwrap();
+            let _ = finish_receiver.lock().unwrap().recv();
+        });
+
+        let mut started: Vec<i32> = started_receiver.iter().take(2).collect();
+        started.sort();
+        assert_eq!(joins.len(), 2);
+        assert_eq!(started, vec![1, 2]);
+        assert!(started_receiver
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+
+        // The queued job starts once an active one finishes.
+        finish_sender.send(()).unwrap();
+        assert_eq!(started_receiver.recv_timeout(Duration::from_secs(1)), Ok(3));
+        drop(finish_sender);
+        joins
+            .into_iter()
+            .for_each(|join| join.unwrap().join().unwrap());
+    }
+
+    #[test]
+    fn test_failed_spawns_are_dropped_while_a_thread_runs_the_queue() {
+        let logger = create_test_logger();
+        let joins = vec![
+            Err(io::Error::other("test spawn error")),
+            Ok(thread::spawn(|| ())),
+        ];
+
+        let spawned = BtClient::spawned_joins(joins, &logger.new_sender()).unwrap();
+
+        assert_eq!(spawned.len(), 1);
+    }
+
+    #[test]
+    fn test_no_spawned_thread_is_an_error() {
+        let logger = create_test_logger();
+        let joins = vec![
+            Err(io::Error::other("test spawn error")),
+            Err(io::Error::other("test spawn error")),
+        ];
+
+        assert!(matches!(
+            BtClient::spawned_joins(joins, &logger.new_sender()),
+            Err(BtClientError::SpawningThreadError(_))
+        ));
+    }
+
+    #[test]
+    fn test_shutdown_stops_every_thread() {
+        let tracker_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let announce_url = format!("http://{}/announce", tracker_listener.local_addr().unwrap());
+        let (stopped_sender, stopped_receiver) = mpsc::channel();
+        thread::spawn(move || answer_test_announces(tracker_listener, stopped_sender));
+
+        let client = Arc::new(create_test_client(&announce_url));
+        let run_client = client.clone();
+        let (stats_sender, stats_receiver) = mpsc::channel::<Vec<TorrentStats>>();
+        let run_join = thread::spawn(move || run_client.run(stats_sender));
+
+        // Wait for the client to be running.
+        stats_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        client.shutdown(Duration::from_secs(5)).unwrap();
+
+        run_join.join().unwrap().unwrap();
+        assert!(client.threads.lock().unwrap().is_empty());
+        stopped_receiver
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_generated_peer_ids_are_random() {
+        let first = BtClient::generate_peer_id("-XX1234-");
+        let second = BtClient::generate_peer_id("-XX1234-");
+
+        assert!(first.starts_with("-XX1234-"));
+        assert_eq!(second.len(), 20);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_init_with_a_missing_torrents_directory() {
+        let directory = "./test_init_with_a_missing_torrents_directory";
+
+        match BtClient::init_with_config(directory.to_string(), CONFIG_PATH) {
+            Err(BtClientError::TorrentDirectoryNotFound(path)) => {
+                assert_eq!(path.to_string(), directory)
+            }
+            _ => panic!("the missing directory should be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_init_with_a_file_as_torrents_directory() {
+        let file = "./test_init_with_a_file_as_torrents_directory.torrent";
+        fs::write(file, b"").unwrap();
+
+        let result = BtClient::init_with_config(file.to_string(), CONFIG_PATH);
+        fs::remove_file(file).unwrap();
+
+        match result {
+            Err(BtClientError::TorrentDirectoryIsNotADirectory(path)) => {
+                assert_eq!(path.to_string(), file)
+            }
+            _ => panic!("the file should be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_empty_torrents_directory_is_warned() {
+        let directory = "./test_empty_torrents_directory_is_warned";
+        fs::create_dir_all(directory).unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        let torrents =
+            BtClient::parse_torrents_in_directory(LoggerSender::new(sender), directory.to_string())
+                .unwrap();
+        fs::remove_dir(directory).unwrap();
+
+        assert!(torrents.is_empty());
+        let logs: Vec<String> = receiver.try_iter().collect();
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("WARN") && log.contains("No .torrent files found")));
+    }
+
+    #[test]
+    fn test_remove_torrent_stops_it() {
+        let client = create_test_client("http://127.0.0.1:1/announce");
+        let status = create_test_status(&client);
+
+        client
+            .remove_torrent(&status.torrent.info_hash_hex())
+            .unwrap();
+
+        assert!(status.is_stopped());
+        assert!(client.torrent_statuses.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_remove_unknown_torrent() {
+        let client = create_test_client("http://127.0.0.1:1/announce");
+        let status = create_test_status(&client);
+
+        let result = client.remove_torrent("unknown_info_hash");
+
+        assert!(matches!(result, Err(BtClientError::TorrentNotFound(_))));
+        assert!(!status.is_stopped());
+    }
+
+    #[test]
+    fn test_remove_torrents_from_receiver() {
+        let client = create_test_client("http://127.0.0.1:1/announce");
+        let status = create_test_status(&client);
+        let (sender, receiver) = mpsc::channel();
+
+        sender.send(status.torrent.info_hash_hex()).unwrap();
+        drop(sender);
+        client.remove_torrents_from(receiver);
+
+        assert!(status.is_stopped());
+    }
+
+    #[test]
+    fn test_metrics_of_running_torrents() {
+        let client = create_test_client("http://127.0.0.1:1/announce");
+        let status = create_test_status(&client);
+
+        status.metrics().bytes_uploaded(16384);
+        status.metrics().piece_failed();
+
+        let metrics = client.metrics();
+        let labels = format!(
+            "{{info_hash=\"{}\",name=\"test_shutdown_stops_every_thread\"}}",
+            status.torrent.info_hash_hex()
+        );
+        assert!(metrics.contains(&format!("bytes_uploaded_total{} 16384\n", labels)));
+        assert!(metrics.contains(&format!("pieces_failed_total{} 1\n", labels)));
+    }
+
+    // Auxiliary functions
+
+    /// Registers a status for the torrent of the test client, as `run()` does.
+    fn create_test_status(client: &BtClient) -> Arc<AtomicTorrentStatus> {
+        let (status, _) = AtomicTorrentStatus::new(&client.torrents[0], client.config.clone());
+        let status = Arc::new(status);
+        client.register_torrent_status(status.clone());
+        status
+    }
+
+    /// Answers every announce with an empty peer list, and notifies once the `stopped` event is announced.
+    fn answer_test_announces(tracker_listener: TcpListener, stopped_sender: Sender<()>) {
+        for stream in tracker_listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let bytes_read = stream.read(&mut buf).unwrap();
+                if bytes_read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..bytes_read]);
+            }
+
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\nd8:intervali1e5:peerslee");
+            if String::from_utf8_lossy(&request).contains("event=stopped") {
+                stopped_sender.send(()).unwrap();
+                return;
+            }
+        }
+    }
+
+    fn create_test_client(announce_url: &str) -> BtClient {
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.tcp_port = 0;
+        let logger =
+            Logger::new(&config.log_directory, config.max_log_file_kb_size * 1000).unwrap();
+
+        let info = Info {
+            length: 10,
+            name: "test_shutdown_stops_every_thread".to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            files: None,
+        };
+        let torrent = Torrent {
+            announce_url: announce_url.to_string(),
+            info,
+            info_hash: [0; 20],
+            info_bytes: Vec::new(),
+        };
+
+        BtClient::new(
+            config,
+            logger,
+            vec![torrent],
+            "-DT0001-000000000000".to_string(),
+        )
+    }
+
+    fn create_test_logger() -> Logger {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        Logger::new(&config.log_directory, config.max_log_file_kb_size * 1000).unwrap()
+    }
+}
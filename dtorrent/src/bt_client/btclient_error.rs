@@ -1,14 +1,21 @@
+use std::fmt;
+
 use crate::bt_client::error_message::ErrorMessage;
 use logger::logger_error::LoggerError;
 
-/// Represents an error that happened while initializing a BtClient struct
+/// Represents an error that happened while initializing or shutting down a BtClient struct
 #[derive(Debug)]
 pub enum BtClientError {
     ConfigurationFileError(ErrorMessage),
     TorrentDirectoryError(ErrorMessage),
+    TorrentDirectoryNotFound(ErrorMessage),
+    TorrentDirectoryIsNotADirectory(ErrorMessage),
     LogError(LoggerError),
     ArgumentError(ErrorMessage),
     UIBuildingError(ErrorMessage),
+    ShutdownTimeout(ErrorMessage),
+    SpawningThreadError(ErrorMessage),
+    TorrentNotFound(ErrorMessage),
 }
 
 impl From<LoggerError> for BtClientError {
@@ -16,3 +23,126 @@ impl From<LoggerError> for BtClientError {
         BtClientError::LogError(err)
     }
 }
+
+impl fmt::Display for BtClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConfigurationFileError(message) => {
+                write!(f, "couldn't read configuration file: {}", message)
+            }
+            Self::TorrentDirectoryError(message) => {
+                write!(f, "couldn't read torrents directory: {}", message)
+            }
+            Self::TorrentDirectoryNotFound(path) => {
+                write!(f, "torrents directory not found: {}", path)
+            }
+            Self::TorrentDirectoryIsNotADirectory(path) => {
+                write!(f, "torrents directory is not a directory: {}", path)
+            }
+            Self::LogError(err) => write!(f, "couldn't start the logger: {}", err),
+            Self::ArgumentError(message) => {
+                write!(f, "incorrect number of arguments: {}", message)
+            }
+            Self::UIBuildingError(message) => write!(f, "couldn't build the UI: {}", message),
+            Self::ShutdownTimeout(message) => write!(f, "couldn't shut down in time: {}", message),
+            Self::SpawningThreadError(message) => {
+                write!(f, "couldn't spawn a thread: {}", message)
+            }
+            Self::TorrentNotFound(info_hash) => write!(f, "torrent not found: {}", info_hash),
+        }
+    }
+}
+
+impl std::error::Error for BtClientError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configuration_file_error_display() {
+        let err = BtClientError::ConfigurationFileError(message("No such file or directory"));
+        assert_eq!(
+            err.to_string(),
+            "couldn't read configuration file: No such file or directory"
+        );
+    }
+
+    #[test]
+    fn test_torrent_directory_error_display() {
+        let err = BtClientError::TorrentDirectoryError(message("./torrents"));
+        assert_eq!(
+            err.to_string(),
+            "couldn't read torrents directory: ./torrents"
+        );
+    }
+
+    #[test]
+    fn test_torrent_directory_not_found_display() {
+        let err = BtClientError::TorrentDirectoryNotFound(message("./torrents"));
+        assert_eq!(err.to_string(), "torrents directory not found: ./torrents");
+    }
+
+    #[test]
+    fn test_torrent_directory_is_not_a_directory_display() {
+        let err = BtClientError::TorrentDirectoryIsNotADirectory(message("./file.torrent"));
+        assert_eq!(
+            err.to_string(),
+            "torrents directory is not a directory: ./file.torrent"
+        );
+    }
+
+    #[test]
+    fn test_log_error_display() {
+        let err = BtClientError::LogError(LoggerError::SpawnThreadError);
+        assert_eq!(
+            err.to_string(),
+            "couldn't start the logger: couldn't spawn the logger thread"
+        );
+    }
+
+    #[test]
+    fn test_argument_error_display() {
+        let err = BtClientError::ArgumentError(message("expected a torrents directory"));
+        assert_eq!(
+            err.to_string(),
+            "incorrect number of arguments: expected a torrents directory"
+        );
+    }
+
+    #[test]
+    fn test_ui_building_error_display() {
+        let err = BtClientError::UIBuildingError(message("missing window"));
+        assert_eq!(err.to_string(), "couldn't build the UI: missing window");
+    }
+
+    #[test]
+    fn test_shutdown_timeout_display() {
+        let err = BtClientError::ShutdownTimeout(message("2 threads still running"));
+        assert_eq!(
+            err.to_string(),
+            "couldn't shut down in time: 2 threads still running"
+        );
+    }
+
+    #[test]
+    fn test_spawning_thread_error_display() {
+        let err = BtClientError::SpawningThreadError(message("resource unavailable"));
+        assert_eq!(
+            err.to_string(),
+            "couldn't spawn a thread: resource unavailable"
+        );
+    }
+
+    #[test]
+    fn test_torrent_not_found_display() {
+        let err = BtClientError::TorrentNotFound(message("info_hash"));
+        assert_eq!(err.to_string(), "torrent not found: info_hash");
+    }
+
+    // Auxiliary functions
+
+    fn message(message: &str) -> ErrorMessage {
+        ErrorMessage::new(message.to_string())
+    }
+}
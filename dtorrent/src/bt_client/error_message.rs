@@ -1,7 +1,7 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
 /**
-Represents the inside message of an error, and can be nicely printed using Debug.
+Represents the inside message of an error, and can be nicely printed using Debug or Display.
 
 ```rust
 use dtorrent::bt_client::error_message::ErrorMessage;
@@ -28,8 +28,25 @@ impl Debug for ErrorMessage {
     }
 }
 
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.message)
+    }
+}
+
 impl ErrorMessage {
     pub fn new(message: String) -> Self {
         Self { message }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_is_the_message() {
+        let message = ErrorMessage::new("Something bad happened...".to_string());
+        assert_eq!(message.to_string(), "Something bad happened...");
+    }
+}
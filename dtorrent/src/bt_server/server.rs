@@ -1,17 +1,28 @@
 use crate::config::cfg::Cfg;
 use crate::peer::bt_peer::{BtPeer, BtPeerError};
+use crate::peer::mse::{EncryptionMode, MseError, MseHandshake};
 use crate::peer::peer_session::{PeerSession, PeerSessionError};
+use crate::peer::peer_stream::PeerStream;
 use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
 use crate::torrent_parser::torrent::Torrent;
 use logger::logger_sender::LoggerSender;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
 use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// A plaintext handshake starts with the length of the protocol string followed by the string.
+const PLAINTEXT_HANDSHAKE_PREFIX: &[u8; 20] = b"\x13BitTorrent protocol";
+
 /// Struct for handling the server side.
 ///
+/// A single listener serves every loaded torrent: the torrent of each incoming connection is chosen by the info_hash of its handshake.
+///
 /// To create a new `BtServer`, use BtServer::new(torrent, config, logger_sender).
 #[derive(Debug)]
 pub struct BtServer {
@@ -19,6 +30,7 @@ pub struct BtServer {
     torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>,
     logger_sender: LoggerSender,
     client_peer_id: String,
+    stop: Arc<AtomicBool>,
 }
 
 /// Posible BtServer errors.
@@ -32,6 +44,40 @@ pub enum BtServerError {
     TorrentNotFound(String),
     ErrorSettingStreamTimeout,
     MaxPeersConnectedReached(String),
+    SeedRatioReached(String),
+    EncryptionError(MseError),
+}
+
+impl fmt::Display for BtServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TorrentStatusError(_) => write!(f, "couldn't use the torrent status"),
+            Self::OpeningListenerError(_) => write!(f, "couldn't open the listener"),
+            Self::HandleConnectionError(_) => write!(f, "couldn't handle an incoming connection"),
+            Self::PeerSessionError(_) => write!(f, "couldn't start a peer session"),
+            Self::BtPeerError(_) => write!(f, "couldn't handshake with the peer"),
+            Self::TorrentNotFound(info_hash) => write!(f, "torrent not found: {}", info_hash),
+            Self::ErrorSettingStreamTimeout => write!(f, "couldn't set the stream timeouts"),
+            Self::MaxPeersConnectedReached(info_hash) => {
+                write!(f, "max peers connected reached for torrent: {}", info_hash)
+            }
+            Self::SeedRatioReached(name) => write!(f, "seed ratio reached for torrent: {}", name),
+            Self::EncryptionError(_) => write!(f, "couldn't encrypt the connection to the peer"),
+        }
+    }
+}
+
+impl std::error::Error for BtServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::TorrentStatusError(err) => Some(err),
+            Self::OpeningListenerError(err) | Self::HandleConnectionError(err) => Some(err),
+            Self::PeerSessionError(err) => Some(err),
+            Self::BtPeerError(err) => Some(err),
+            Self::EncryptionError(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl BtServer {
@@ -47,45 +93,98 @@ impl BtServer {
             torrents_with_status,
             logger_sender,
             client_peer_id,
+            stop: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    /// Starts the server and starts listening for connections.
+    /// Returns the signal that stops the server once it is set to true.
+    ///
+    /// Connections that were already accepted are ended by stopping their torrent status.
+    pub fn stop_signal(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Starts the server on the first of the configured `tcp_ports()` that is not in use, and starts listening for connections.
     ///
     /// # Errors
-    /// - `OpeningListenerError` if the TcpLister couldn't be opened.
+    /// - `OpeningListenerError` if the TcpLister couldn't be opened on any of the ports.
     pub fn init(&mut self) -> Result<(), BtServerError> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.config.tcp_port))
-            .map_err(BtServerError::OpeningListenerError)?;
+        let listener = Self::bind(&self.config.tcp_ports())?;
 
+        self.serve(listener)
+    }
+
+    /// Opens a listener on the first of the given ports that is not in use. The port chosen is the one of its local address.
+    ///
+    /// # Errors
+    /// - `OpeningListenerError` with the error of the last port tried if the listener couldn't be opened on any of them.
+    pub fn bind(ports: &[u16]) -> Result<TcpListener, BtServerError> {
+        let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "no ports to listen on");
+        for port in ports {
+            match TcpListener::bind(format!("0.0.0.0:{}", port)) {
+                Ok(listener) => return Ok(listener),
+                Err(err) => last_error = err,
+            }
+        }
+        Err(BtServerError::OpeningListenerError(last_error))
+    }
+
+    /// Listens for connections on an already opened listener, until the stop signal is set.
+    ///
+    /// # Errors
+    /// - `OpeningListenerError` if the listener couldn't be set to non-blocking.
+    pub fn serve(&mut self, listener: TcpListener) -> Result<(), BtServerError> {
+        // The listener is polled so the stop signal is checked between connections.
+        listener
+            .set_nonblocking(true)
+            .map_err(BtServerError::OpeningListenerError)?;
         self.logger_sender
             .info("Server started, listening for connections.");
 
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => match self.handle_connection(stream) {
+        while !self.stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => match self.handle_connection(stream) {
                     Ok(_) => (),
                     Err(e) => self
                         .logger_sender
                         .warn(&format!("Could't handle incoming connection: {:?}", e)),
                 },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL)
+                }
                 Err(e) => self
                     .logger_sender
                     .warn(&format!("Could't handle incoming connection: {:?}", e)),
             }
         }
 
+        self.logger_sender.info("Server stopped.");
         Ok(())
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) -> Result<(), BtServerError> {
+    fn handle_connection(&self, stream: TcpStream) -> Result<(), BtServerError> {
         let addr = stream
             .peer_addr()
             .map_err(BtServerError::HandleConnectionError)?;
 
+        // Accepted streams inherit the non-blocking mode of the listener.
+        stream
+            .set_nonblocking(false)
+            .map_err(BtServerError::HandleConnectionError)?;
+        let mut stream = PeerStream::new(stream);
         // set timeouts
         self.set_stream_timeouts(&mut stream)?;
 
+        self.accept_encryption(&mut stream).map_err(|err| {
+            self.logger_sender.warn(&format!(
+                "{:?} for peer: {}:{}",
+                err,
+                addr.ip(),
+                addr.port() as i64
+            ));
+            err
+        })?;
+
         let mut peer = BtPeer::new(addr.ip().to_string(), addr.port() as i64);
 
         let info_hash = peer.receive_handshake(&mut stream).map_err(|err| {
@@ -98,11 +197,22 @@ impl BtServer {
             BtServerError::BtPeerError(err)
         })?;
 
-        // See if the torrent is in the list of torrents.
-        let (torrent, torrent_status) = match self.find_torrent_and_status(info_hash) {
-            Ok(value) => value,
-            Err(value) => return value,
-        };
+        // See if the torrent is in the list of torrents. If not, the connection is closed before any peer accounting.
+        let (torrent, torrent_status) =
+            self.find_torrent_and_status(&info_hash).map_err(|err| {
+                self.logger_sender.warn(&format!(
+                    "{:?} for peer: {}:{}",
+                    err,
+                    addr.ip(),
+                    addr.port() as i64
+                ));
+                err
+            })?;
+
+        // Once the seed ratio limit is reached, the torrent no longer accepts leechers.
+        if torrent_status.seed_ratio_reached() {
+            return Err(BtServerError::SeedRatioReached(torrent.name()));
+        }
 
         let current_peers = torrent_status.all_current_peers();
         // if we reached the max number of peers, we can't accept any more connections.
@@ -123,25 +233,51 @@ impl BtServer {
         Ok(())
     }
 
+    /// Answers the encryption handshake of the peer, unless encryption is disabled.
+    ///
+    /// Plaintext handshakes are accepted as well unless encryption is required: anything else is taken as the start of an encryption handshake.
+    fn accept_encryption(&self, stream: &mut PeerStream) -> Result<(), BtServerError> {
+        let mode = self.config.encryption_mode;
+        if mode == EncryptionMode::Disabled {
+            return Ok(());
+        }
+
+        // Both a plaintext handshake and the public key of an encryption handshake are longer than the prefix.
+        let mut prefix = [0; PLAINTEXT_HANDSHAKE_PREFIX.len()];
+        stream
+            .read_exact(&mut prefix)
+            .map_err(BtServerError::HandleConnectionError)?;
+        stream.unread(&prefix);
+        if mode == EncryptionMode::Enabled && &prefix == PLAINTEXT_HANDSHAKE_PREFIX {
+            return Ok(());
+        }
+
+        let info_hashes: Vec<Vec<u8>> = self
+            .torrents_with_status
+            .keys()
+            .map(|torrent| torrent.get_info_hash_as_bytes().to_vec())
+            .collect();
+        MseHandshake::new()
+            .respond(stream, &info_hashes, mode.crypto_provide())
+            .map_err(BtServerError::EncryptionError)?;
+        Ok(())
+    }
+
+    /// Returns the torrent with the given info hash and its status.
+    ///
+    /// It returns a `TorrentNotFound` error with the info hash in hex if there's no such torrent.
     fn find_torrent_and_status(
         &self,
-        info_hash: Vec<u8>,
-    ) -> Result<(&Torrent, &Arc<AtomicTorrentStatus>), Result<(), BtServerError>> {
-        let (torrent, torrent_status) =
-            match self.torrents_with_status.iter().find(|(torrent, _)| {
-                match torrent.get_info_hash_as_bytes() {
-                    Ok(info_hash_bytes) => info_hash_bytes == info_hash,
-                    Err(_) => false,
-                }
-            }) {
-                Some((torrent, torrent_status)) => (torrent, torrent_status),
-                None => {
-                    return Err(Err(BtServerError::TorrentNotFound(
-                        String::from_utf8_lossy(&info_hash).to_string(),
-                    )))
-                }
-            };
-        Ok((torrent, torrent_status))
+        info_hash: &[u8],
+    ) -> Result<(&Torrent, &Arc<AtomicTorrentStatus>), BtServerError> {
+        self.torrents_with_status
+            .iter()
+            .find(|(torrent, _)| torrent.get_info_hash_as_bytes() == info_hash)
+            .ok_or_else(|| {
+                BtServerError::TorrentNotFound(
+                    info_hash.iter().map(|b| format!("{:02x}", b)).collect(),
+                )
+            })
     }
 
     fn create_peer_session(
@@ -163,17 +299,13 @@ impl BtServer {
     }
 
     /// Sets read and write timeouts for the stream.
-    fn set_stream_timeouts(&self, stream: &mut TcpStream) -> Result<(), BtServerError> {
+    fn set_stream_timeouts(&self, stream: &mut PeerStream) -> Result<(), BtServerError> {
         stream
-            .set_read_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_read_timeout(Some(self.config.read_timeout()))
             .map_err(|_| BtServerError::ErrorSettingStreamTimeout)?;
 
         stream
-            .set_write_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_write_timeout(Some(self.config.write_timeout()))
             .map_err(|_| BtServerError::ErrorSettingStreamTimeout)?;
         Ok(())
     }
@@ -182,7 +314,7 @@ impl BtServer {
         &self,
         mut peer_session: PeerSession,
         peer: BtPeer,
-        mut stream: TcpStream,
+        mut stream: PeerStream,
         torrent: Torrent,
         torrent_status: &Arc<AtomicTorrentStatus>,
     ) -> Result<(), BtServerError> {
@@ -213,3 +345,288 @@ impl BtServer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        fs,
+        io::{Read, Write},
+        sync::mpsc,
+    };
+
+    use crate::{
+        peer::{
+            handshake::Handshake,
+            mse::{CRYPTO_PLAINTEXT, CRYPTO_RC4},
+            peer_message::Bitfield,
+        },
+        torrent_parser::info::Info,
+    };
+
+    use super::*;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_torrents_are_served_through_one_listener() {
+        let first = create_test_torrent([1; 20]);
+        let second = create_test_torrent([2; 20]);
+        let addr = spawn_test_server(&[first.clone(), second.clone()]);
+
+        for torrent in [first, second] {
+            let info_hash = torrent.get_info_hash_as_bytes().to_vec();
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(&Handshake::new(info_hash.clone(), vec![1; 20]).as_bytes())
+                .unwrap();
+
+            let mut response = [0; 68];
+            stream.read_exact(&mut response).unwrap();
+            let handshake = Handshake::from_bytes(&response).unwrap();
+
+            assert_eq!(handshake.info_hash, info_hash);
+            assert_eq!(handshake.peer_id, b"-DT0001-000000000000");
+        }
+    }
+
+    #[test]
+    fn test_unknown_torrent_is_not_accounted() {
+        let torrent = create_test_torrent([1; 20]);
+        let (addr, torrents_with_status, logs) = spawn_test_server_with_status(&[torrent]);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&Handshake::new(vec![0xab; 20], vec![1; 20]).as_bytes())
+            .unwrap();
+
+        // The connection is closed without answering the handshake.
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        assert!(response.is_empty());
+
+        let torrent_status = torrents_with_status.values().next().unwrap();
+        assert_eq!(torrent_status.all_current_peers(), 0);
+        assert!(torrent_status.get_connected_peers().unwrap().is_empty());
+        let not_found = format!("TorrentNotFound(\"{}\")", "ab".repeat(20));
+        assert!(logs.try_iter().any(|log| log.contains(&not_found)));
+    }
+
+    #[test]
+    fn test_leechers_are_refused_once_the_seed_ratio_is_reached() {
+        let torrent = create_test_torrent([3; 20]);
+        let info_hash = torrent.get_info_hash_as_bytes().to_vec();
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.seed_ratio_limit = Some(2.0);
+        let (addr, torrents_with_status, logs) =
+            spawn_test_server_with_config(std::slice::from_ref(&torrent), config.clone());
+        let torrent_status = torrents_with_status.values().next().unwrap();
+        let index = torrent_status
+            .select_piece(&Bitfield::new(vec![0b10000000]))
+            .unwrap()
+            .unwrap();
+        torrent_status.piece_downloaded(index, &[0; 10]).unwrap();
+
+        // Below the limit leechers are still accepted.
+        torrent_status.metrics().bytes_uploaded(19);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&Handshake::new(info_hash.clone(), vec![1; 20]).as_bytes())
+            .unwrap();
+        let mut response = [0; 68];
+        stream.read_exact(&mut response).unwrap();
+
+        torrent_status.metrics().bytes_uploaded(2);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&Handshake::new(info_hash, vec![2; 20]).as_bytes())
+            .unwrap();
+
+        // The connection is closed without answering the handshake.
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        assert!(response.is_empty());
+        let refused = format!("SeedRatioReached(\"{}\")", torrent.name());
+        assert!(logs.iter().any(|log| log.contains(&refused)));
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_connections_are_accepted() {
+        let torrent = create_test_torrent([4; 20]);
+        let info_hash = torrent.get_info_hash_as_bytes().to_vec();
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.encryption_mode = EncryptionMode::Required;
+        let (addr, _, _) = spawn_test_server_with_config(&[torrent], config);
+
+        let mut stream = PeerStream::new(TcpStream::connect(addr).unwrap());
+        let select = MseHandshake::new()
+            .initiate(&mut stream, &info_hash, CRYPTO_PLAINTEXT | CRYPTO_RC4)
+            .unwrap();
+        stream
+            .write_all(&Handshake::new(info_hash.clone(), vec![1; 20]).as_bytes())
+            .unwrap();
+
+        let mut response = [0; 68];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(select, CRYPTO_RC4);
+        assert_eq!(
+            Handshake::from_bytes(&response).unwrap().info_hash,
+            info_hash
+        );
+    }
+
+    #[test]
+    fn test_plaintext_connections_are_accepted_if_encryption_is_not_required() {
+        let torrent = create_test_torrent([5; 20]);
+        let info_hash = torrent.get_info_hash_as_bytes().to_vec();
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.encryption_mode = EncryptionMode::Enabled;
+        let (addr, _, _) = spawn_test_server_with_config(&[torrent], config);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&Handshake::new(info_hash.clone(), vec![1; 20]).as_bytes())
+            .unwrap();
+
+        let mut response = [0; 68];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(
+            Handshake::from_bytes(&response).unwrap().info_hash,
+            info_hash
+        );
+    }
+
+    #[test]
+    fn test_public_key_starting_like_a_plaintext_handshake_is_taken_as_encryption() {
+        let torrent = create_test_torrent([6; 20]);
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.encryption_mode = EncryptionMode::Enabled;
+        let (addr, _, _) = spawn_test_server_with_config(&[torrent], config);
+
+        // A public key whose first byte is the length of the protocol string.
+        let mut public_key = [0xab; 96];
+        public_key[0] = 19;
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(&public_key).unwrap();
+
+        // The server answers with its own public key.
+        let mut response = [0; 96];
+        stream.read_exact(&mut response).unwrap();
+        assert_ne!(response, [0; 96]);
+    }
+
+    #[test]
+    fn test_bind_falls_back_to_the_next_port_when_one_is_taken() {
+        let taken = TcpListener::bind("0.0.0.0:0").unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+        let free_port = TcpListener::bind("0.0.0.0:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let listener = BtServer::bind(&[taken_port, free_port]).unwrap();
+
+        assert_eq!(listener.local_addr().unwrap().port(), free_port);
+    }
+
+    #[test]
+    fn test_bind_fails_when_every_port_is_taken() {
+        let taken = TcpListener::bind("0.0.0.0:0").unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        assert!(matches!(
+            BtServer::bind(&[taken_port]),
+            Err(BtServerError::OpeningListenerError(_))
+        ));
+    }
+
+    #[test]
+    fn test_error_display_and_source() {
+        let io_error = io::Error::new(io::ErrorKind::AddrInUse, "address in use");
+        let err = BtServerError::OpeningListenerError(io_error);
+
+        assert_eq!(err.to_string(), "couldn't open the listener");
+        assert_eq!(err.source().unwrap().to_string(), "address in use");
+    }
+
+    #[test]
+    fn test_error_without_source() {
+        let err = BtServerError::TorrentNotFound("info_hash".to_string());
+
+        assert_eq!(err.to_string(), "torrent not found: info_hash");
+        assert!(err.source().is_none());
+    }
+
+    // Auxiliary functions
+
+    /// Spawns a server for the given torrents on a random port and returns its address.
+    fn spawn_test_server(torrents: &[Torrent]) -> std::net::SocketAddr {
+        spawn_test_server_with_status(torrents).0
+    }
+
+    /// Spawns a server for the given torrents on a random port and returns its address, the status of each torrent and the receiver of its logs.
+    fn spawn_test_server_with_status(
+        torrents: &[Torrent],
+    ) -> (
+        std::net::SocketAddr,
+        HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+        mpsc::Receiver<String>,
+    ) {
+        spawn_test_server_with_config(torrents, Cfg::new(CONFIG_PATH).unwrap())
+    }
+
+    /// Same as `spawn_test_server_with_status`, but the server and the torrents use the given config.
+    fn spawn_test_server_with_config(
+        torrents: &[Torrent],
+        config: Cfg,
+    ) -> (
+        std::net::SocketAddr,
+        HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+        mpsc::Receiver<String>,
+    ) {
+        let torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>> = torrents
+            .iter()
+            .map(|torrent| {
+                let (status, _) = AtomicTorrentStatus::new(torrent, config.clone());
+                (torrent.clone(), Arc::new(status))
+            })
+            .collect();
+        let (sender, receiver) = mpsc::channel();
+        let mut server = BtServer::new(
+            torrents_with_status.clone(),
+            config,
+            LoggerSender::new(sender),
+            "-DT0001-000000000000".to_string(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+        (addr, torrents_with_status, receiver)
+    }
+
+    fn create_test_torrent(info_hash: [u8; 20]) -> Torrent {
+        let info = Info {
+            length: 10,
+            name: String::new(),
+            piece_length: 10,
+            pieces: vec![],
+            files: None,
+        };
+
+        let mut torrent = Torrent {
+            announce_url: "announce".to_string(),
+            info,
+            info_hash,
+            info_bytes: Vec::new(),
+        };
+        torrent.info.name = format!("test_bt_server_{}", torrent.info_hash_hex());
+        torrent
+    }
+}
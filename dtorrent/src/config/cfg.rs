@@ -1,10 +1,15 @@
+use std::cmp::Ordering;
 use std::fs::File;
 use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::net::Ipv4Addr;
 use std::str::FromStr;
+use std::time::Duration;
 
 use super::constants;
+use crate::peer::mse::EncryptionMode;
+use crate::torrent_handler::selection_strategy::SelectionStrategy;
 
 /// `Cfg` struct containing the config file information, previusly created with `Cfg::new`.
 ///
@@ -15,6 +20,22 @@ use super::constants;
 /// - `read_write_seconds_timeout`: timeout in seconds for the read and write operations to a peer,
 /// - `max_peers_per_torrent`: maximum number of simultaneous peers that a torrent can have,
 /// - `max_log_file_kb_size`: max file size in kilobytes the log can have,
+/// - `stats_json_file`: optional path where the torrents statistics are periodically written as JSON,
+/// - `max_active_torrents`: optional maximum number of torrents downloading at the same time, the rest wait in a queue. Unlimited by default,
+/// - `peer_id_prefix`: optional prefix of the client peer id, in Azureus-style (`-XX1234-`). Defaults to `DEFAULT_PEER_ID_PREFIX`,
+/// - `piece_request_seconds_timeout`: optional timeout in seconds to receive the blocks requested to a peer, after which the piece is aborted. Defaults to `DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT`,
+/// - `connect_seconds_timeout`: optional timeout in seconds to connect to a peer. Defaults to `read_write_seconds_timeout`,
+/// - `read_seconds_timeout`: optional timeout in seconds for the read operations to a peer. Defaults to `read_write_seconds_timeout`,
+/// - `write_seconds_timeout`: optional timeout in seconds for the write operations to a peer. Defaults to `read_write_seconds_timeout`,
+/// - `piece_selection_strategy`: optional strategy to choose the next piece to download: `rarest`, `random` or `sequential`. Defaults to `sequential`,
+/// - `statistics_update_millis`: optional milliseconds between the updates of the torrents statistics. Defaults to `DEFAULT_STATISTICS_UPDATE_MILLIS`,
+/// - `seed_ratio_limit`: optional ratio between the bytes uploaded and the size of a torrent after which it stops seeding. Seeds indefinitely by default,
+/// - `tcp_port_fallbacks`: optional comma separated ports to listen on, in order, if `tcp_port` is already in use,
+/// - `enable_port_mapping`: optional `true` to map the listening port on the gateway with NAT-PMP. Disabled by default,
+/// - `port_mapping_gateway`: optional IPv4 address of the gateway to map the port on. Defaults to the gateway of the default route,
+/// - `announce_ip`: optional address announced to the trackers. Defaults to the external address of the port mapping, if any,
+/// - `encryption_mode`: optional encryption of the connections to peers: `disabled`, `enabled` or `required`. Defaults to `disabled`,
+/// - `torrent_download_directories`: optional comma separated `name:directory` pairs, to store the torrent with that name in its own directory instead of `download_directory`,
 #[derive(Debug, Clone)]
 pub struct Cfg {
     pub tcp_port: u16,
@@ -24,6 +45,22 @@ pub struct Cfg {
     pub read_write_seconds_timeout: u64,
     pub max_peers_per_torrent: u32,
     pub max_log_file_kb_size: u32,
+    pub stats_json_file: Option<String>,
+    pub max_active_torrents: Option<usize>,
+    pub peer_id_prefix: String,
+    pub piece_request_seconds_timeout: u64,
+    pub connect_seconds_timeout: Option<u64>,
+    pub read_seconds_timeout: Option<u64>,
+    pub write_seconds_timeout: Option<u64>,
+    pub piece_selection_strategy: SelectionStrategy,
+    pub statistics_update_millis: u64,
+    pub seed_ratio_limit: Option<f64>,
+    pub tcp_port_fallbacks: Vec<u16>,
+    pub enable_port_mapping: bool,
+    pub port_mapping_gateway: Option<Ipv4Addr>,
+    pub announce_ip: Option<String>,
+    pub encryption_mode: EncryptionMode,
+    pub torrent_download_directories: Vec<(String, String)>,
 }
 
 impl Cfg {
@@ -40,7 +77,19 @@ impl Cfg {
     /// - read_write_timeout setting is not a valid number in the config file.
     /// - max_peers_per_torrent  setting is not a valid number in the config file.
     /// - max_log_file_size setting is not a valid number in the config file.
-    /// - Minimum number of correct settings were not reached.
+    /// - max_active_torrents setting is not a positive number in the config file.
+    /// - peer_id_prefix setting is not shorter than a peer id or has characters that are not alphanumeric or `-`.
+    /// - piece_request_seconds_timeout setting is not a positive number in the config file.
+    /// - connect_seconds_timeout, read_seconds_timeout or write_seconds_timeout settings are not positive numbers in the config file.
+    /// - piece_selection_strategy setting is not `rarest`, `random` or `sequential`.
+    /// - statistics_update_millis setting is not a positive number in the config file.
+    /// - seed_ratio_limit setting is not a positive number in the config file.
+    /// - tcp_port_fallbacks setting is not a comma separated list of valid numbers in the config file.
+    /// - enable_port_mapping setting is not `true` or `false` in the config file.
+    /// - port_mapping_gateway setting is not a valid IPv4 address in the config file.
+    /// - encryption_mode setting is not `disabled`, `enabled` or `required`.
+    /// - torrent_download_directories setting is not a comma separated list of `name:directory` pairs in the config file.
+    /// - One of the required settings is missing from the config file.
     pub fn new(path: &str) -> io::Result<Self> {
         let mut cfg = Self {
             tcp_port: 0,
@@ -50,12 +99,28 @@ impl Cfg {
             read_write_seconds_timeout: 0,
             max_peers_per_torrent: 0,
             max_log_file_kb_size: 0,
+            stats_json_file: None,
+            max_active_torrents: None,
+            peer_id_prefix: String::from(constants::DEFAULT_PEER_ID_PREFIX),
+            piece_request_seconds_timeout: constants::DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT,
+            connect_seconds_timeout: None,
+            read_seconds_timeout: None,
+            write_seconds_timeout: None,
+            piece_selection_strategy: SelectionStrategy::default(),
+            statistics_update_millis: constants::DEFAULT_STATISTICS_UPDATE_MILLIS,
+            seed_ratio_limit: None,
+            tcp_port_fallbacks: Vec::new(),
+            enable_port_mapping: false,
+            port_mapping_gateway: None,
+            announce_ip: None,
+            encryption_mode: EncryptionMode::default(),
+            torrent_download_directories: Vec::new(),
         };
 
         let file = File::open(path)?;
         let reader = BufReader::new(file);
 
-        let mut settings_loaded = 0;
+        let mut settings_loaded = Vec::new();
 
         for line in reader.lines() {
             let current_line = line?;
@@ -68,15 +133,15 @@ impl Cfg {
                 ));
             }
             cfg = Self::load_setting(cfg, setting[0], setting[1])?;
-            settings_loaded += 1;
+            settings_loaded.push(setting[0].to_string());
         }
-        if settings_loaded < constants::MIN_SETTINGS {
+        if let Some(missing) = constants::REQUIRED_SETTINGS
+            .iter()
+            .find(|required| !settings_loaded.iter().any(|name| name == *required))
+        {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                format!(
-                    "Minimum number of correct settings were not reached: {}",
-                    settings_loaded
-                ),
+                format!("Missing required setting: {}", missing),
             ));
         }
         Ok(cfg)
@@ -110,6 +175,96 @@ impl Cfg {
                     self.parse_value(value, constants::MAX_LOG_FILE_KB_SIZE)?;
             }
 
+            constants::STATS_JSON_FILE => self.stats_json_file = Some(String::from(value)),
+
+            constants::MAX_ACTIVE_TORRENTS => {
+                self.max_active_torrents =
+                    Some(self.parse_positive_value(value, constants::MAX_ACTIVE_TORRENTS)?);
+            }
+
+            constants::PEER_ID_PREFIX => {
+                // Leaves room for the random part of the peer id, which is sent without encoding.
+                if value.len() >= 20
+                    || !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Invalid setting: {}, is not a valid prefix: {}",
+                            name, value
+                        ),
+                    ));
+                }
+                self.peer_id_prefix = String::from(value);
+            }
+
+            constants::PIECE_REQUEST_SECONDS_TIMEOUT => {
+                self.piece_request_seconds_timeout =
+                    self.parse_positive_value(value, constants::PIECE_REQUEST_SECONDS_TIMEOUT)?;
+            }
+
+            constants::CONNECT_SECONDS_TIMEOUT => {
+                self.connect_seconds_timeout =
+                    Some(self.parse_positive_value(value, constants::CONNECT_SECONDS_TIMEOUT)?);
+            }
+
+            constants::READ_SECONDS_TIMEOUT => {
+                self.read_seconds_timeout =
+                    Some(self.parse_positive_value(value, constants::READ_SECONDS_TIMEOUT)?);
+            }
+
+            constants::WRITE_SECONDS_TIMEOUT => {
+                self.write_seconds_timeout =
+                    Some(self.parse_positive_value(value, constants::WRITE_SECONDS_TIMEOUT)?);
+            }
+
+            constants::PIECE_SELECTION_STRATEGY => {
+                self.piece_selection_strategy =
+                    self.parse_value(value, constants::PIECE_SELECTION_STRATEGY)?;
+            }
+
+            constants::STATISTICS_UPDATE_MILLIS => {
+                self.statistics_update_millis =
+                    self.parse_positive_value(value, constants::STATISTICS_UPDATE_MILLIS)?;
+            }
+
+            constants::SEED_RATIO_LIMIT => {
+                self.seed_ratio_limit =
+                    Some(self.parse_positive_value(value, constants::SEED_RATIO_LIMIT)?);
+            }
+
+            constants::TCP_PORT_FALLBACKS => {
+                self.tcp_port_fallbacks = value
+                    .split(',')
+                    .map(|port| self.parse_value(port.trim(), constants::TCP_PORT_FALLBACKS))
+                    .collect::<io::Result<Vec<u16>>>()?;
+            }
+
+            constants::ENABLE_PORT_MAPPING => {
+                self.enable_port_mapping =
+                    self.parse_value(value, constants::ENABLE_PORT_MAPPING)?;
+            }
+
+            constants::PORT_MAPPING_GATEWAY => {
+                self.port_mapping_gateway =
+                    Some(self.parse_value(value, constants::PORT_MAPPING_GATEWAY)?);
+            }
+
+            constants::ANNOUNCE_IP => {
+                self.announce_ip = Some(String::from(value));
+            }
+
+            constants::ENCRYPTION_MODE => {
+                self.encryption_mode = self.parse_value(value, constants::ENCRYPTION_MODE)?;
+            }
+
+            constants::TORRENT_DOWNLOAD_DIRECTORIES => {
+                self.torrent_download_directories = value
+                    .split(',')
+                    .map(|pair| Self::parse_torrent_download_directory(pair.trim()))
+                    .collect::<io::Result<Vec<(String, String)>>>()?;
+            }
+
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
@@ -136,6 +291,83 @@ impl Cfg {
             Ok(parse) => Ok(parse),
         }
     }
+
+    /// Parses a value that must be greater than zero.
+    fn parse_positive_value<F>(&self, value: &str, setting: &str) -> io::Result<F>
+    where
+        F: FromStr + Default + PartialOrd,
+    {
+        let parse: F = self.parse_value(value, setting)?;
+        // Written as a comparison with greater so that a NaN float is not positive either.
+        if parse.partial_cmp(&F::default()) != Some(Ordering::Greater) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid setting: {}, must be positive: {}", setting, value),
+            ));
+        }
+        Ok(parse)
+    }
+
+    /// Parses a `name:directory` pair of the torrent download directories setting.
+    fn parse_torrent_download_directory(pair: &str) -> io::Result<(String, String)> {
+        match pair.split_once(':') {
+            Some((name, directory)) if !name.is_empty() && !directory.is_empty() => {
+                Ok((String::from(name), String::from(directory)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid setting: {}, is not a name:directory pair: {}",
+                    constants::TORRENT_DOWNLOAD_DIRECTORIES,
+                    pair
+                ),
+            )),
+        }
+    }
+
+    /// Returns the directory configured for the torrent with the given name, if it has one instead of `download_directory`.
+    pub fn download_directory_for(&self, torrent_name: &str) -> Option<String> {
+        self.torrent_download_directories
+            .iter()
+            .find(|(name, _)| name == torrent_name)
+            .map(|(_, directory)| directory.clone())
+    }
+
+    /// Returns the ports to listen on, in the order they are tried: `tcp_port` followed by its fallbacks.
+    pub fn tcp_ports(&self) -> Vec<u16> {
+        let mut ports = vec![self.tcp_port];
+        ports.extend(&self.tcp_port_fallbacks);
+        ports
+    }
+
+    /// Returns the timeout to connect to a peer.
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.connect_seconds_timeout
+                .unwrap_or(self.read_write_seconds_timeout),
+        )
+    }
+
+    /// Returns the timeout for the read operations to a peer.
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.read_seconds_timeout
+                .unwrap_or(self.read_write_seconds_timeout),
+        )
+    }
+
+    /// Returns the timeout for the write operations to a peer.
+    pub fn write_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.write_seconds_timeout
+                .unwrap_or(self.read_write_seconds_timeout),
+        )
+    }
+
+    /// Returns the time between the updates of the torrents statistics.
+    pub fn statistics_update_interval(&self) -> Duration {
+        Duration::from_millis(self.statistics_update_millis)
+    }
 }
 
 #[cfg(test)]
@@ -167,10 +399,203 @@ mod tests {
             read_write_seconds_timeout: 120,
             max_peers_per_torrent: 5,
             max_log_file_kb_size: 100,
+            stats_json_file: None,
+            max_active_torrents: None,
+            peer_id_prefix: String::from(constants::DEFAULT_PEER_ID_PREFIX),
+            piece_request_seconds_timeout: constants::DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT,
+            connect_seconds_timeout: None,
+            read_seconds_timeout: None,
+            write_seconds_timeout: None,
+            piece_selection_strategy: SelectionStrategy::default(),
+            statistics_update_millis: constants::DEFAULT_STATISTICS_UPDATE_MILLIS,
+            seed_ratio_limit: None,
+            tcp_port_fallbacks: Vec::new(),
+            enable_port_mapping: false,
+            port_mapping_gateway: None,
+            announce_ip: None,
+            encryption_mode: EncryptionMode::default(),
+            torrent_download_directories: Vec::new(),
         };
         create_and_assert_config_is_ok(path, good_config);
     }
 
+    #[test]
+    fn test_good_config_with_stats_json_file() {
+        let path = "./test_good_config_with_stats_json_file.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSTATS_JSON_FILE=./stats.json";
+        create_and_write_file(path, contents);
+
+        let good_config = Cfg {
+            tcp_port: 1000,
+            log_directory: String::from("./log"),
+            download_directory: String::from("./download"),
+            pipelining_size: 5,
+            read_write_seconds_timeout: 120,
+            max_peers_per_torrent: 5,
+            max_log_file_kb_size: 100,
+            stats_json_file: Some(String::from("./stats.json")),
+            max_active_torrents: None,
+            peer_id_prefix: String::from(constants::DEFAULT_PEER_ID_PREFIX),
+            piece_request_seconds_timeout: constants::DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT,
+            connect_seconds_timeout: None,
+            read_seconds_timeout: None,
+            write_seconds_timeout: None,
+            piece_selection_strategy: SelectionStrategy::default(),
+            statistics_update_millis: constants::DEFAULT_STATISTICS_UPDATE_MILLIS,
+            seed_ratio_limit: None,
+            tcp_port_fallbacks: Vec::new(),
+            enable_port_mapping: false,
+            port_mapping_gateway: None,
+            announce_ip: None,
+            encryption_mode: EncryptionMode::default(),
+            torrent_download_directories: Vec::new(),
+        };
+        create_and_assert_config_is_ok(path, good_config);
+    }
+
+    #[test]
+    fn test_good_config_with_peer_id_prefix() {
+        let path = "./test_good_config_with_peer_id_prefix.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPEER_ID_PREFIX=-XX1234-";
+        create_and_write_file(path, contents);
+
+        let good_config = Cfg {
+            tcp_port: 1000,
+            log_directory: String::from("./log"),
+            download_directory: String::from("./download"),
+            pipelining_size: 5,
+            read_write_seconds_timeout: 120,
+            max_peers_per_torrent: 5,
+            max_log_file_kb_size: 100,
+            stats_json_file: None,
+            max_active_torrents: None,
+            peer_id_prefix: String::from("-XX1234-"),
+            piece_request_seconds_timeout: constants::DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT,
+            connect_seconds_timeout: None,
+            read_seconds_timeout: None,
+            write_seconds_timeout: None,
+            piece_selection_strategy: SelectionStrategy::default(),
+            statistics_update_millis: constants::DEFAULT_STATISTICS_UPDATE_MILLIS,
+            seed_ratio_limit: None,
+            tcp_port_fallbacks: Vec::new(),
+            enable_port_mapping: false,
+            port_mapping_gateway: None,
+            announce_ip: None,
+            encryption_mode: EncryptionMode::default(),
+            torrent_download_directories: Vec::new(),
+        };
+        create_and_assert_config_is_ok(path, good_config);
+    }
+
+    #[test]
+    fn test_good_config_with_max_active_torrents() {
+        let path = "./test_good_config_with_max_active_torrents.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_ACTIVE_TORRENTS=2";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.max_active_torrents, Some(2));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_zero_max_active_torrents() {
+        let path = "./test_zero_max_active_torrents.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_ACTIVE_TORRENTS=0";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_piece_request_timeout() {
+        let path = "./test_good_config_with_piece_request_timeout.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPIECE_REQUEST_SECONDS_TIMEOUT=30";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.piece_request_seconds_timeout, 30);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_zero_piece_request_timeout() {
+        let path = "./test_zero_piece_request_timeout.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPIECE_REQUEST_SECONDS_TIMEOUT=0";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_connect_read_and_write_timeouts() {
+        let path = "./test_good_config_with_connect_read_and_write_timeouts.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nCONNECT_SECONDS_TIMEOUT=5\nREAD_SECONDS_TIMEOUT=30\nWRITE_SECONDS_TIMEOUT=10";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.connect_timeout(), Duration::from_secs(5));
+        assert_eq!(config.read_timeout(), Duration::from_secs(30));
+        assert_eq!(config.write_timeout(), Duration::from_secs(10));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_connect_read_and_write_timeouts_default_to_read_write_timeout() {
+        let path = "./test_connect_read_and_write_timeouts_default_to_read_write_timeout.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nREAD_SECONDS_TIMEOUT=30";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.connect_timeout(), Duration::from_secs(120));
+        assert_eq!(config.read_timeout(), Duration::from_secs(30));
+        assert_eq!(config.write_timeout(), Duration::from_secs(120));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_zero_connect_timeout() {
+        let path = "./test_zero_connect_timeout.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nCONNECT_SECONDS_TIMEOUT=0";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_piece_selection_strategy() {
+        let path = "./test_good_config_with_piece_selection_strategy.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPIECE_SELECTION_STRATEGY=rarest";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.piece_selection_strategy, SelectionStrategy::Rarest);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_piece_selection_strategy() {
+        let path = "./test_invalid_piece_selection_strategy.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPIECE_SELECTION_STRATEGY=fastest";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_invalid_peer_id_prefix() {
+        let path = "./test_invalid_peer_id_prefix.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPEER_ID_PREFIX=-XX 1234&-";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
     #[test]
     fn test_bad_path() {
         let path = "bad path";
@@ -250,6 +675,154 @@ mod tests {
         create_and_assert_config_is_not_ok(path);
     }
 
+    #[test]
+    fn test_good_config_with_statistics_update_millis() {
+        let path = "./test_good_config_with_statistics_update_millis.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSTATISTICS_UPDATE_MILLIS=1000";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.statistics_update_interval(), Duration::from_secs(1));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_zero_statistics_update_millis() {
+        let path = "./test_zero_statistics_update_millis.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSTATISTICS_UPDATE_MILLIS=0";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_seed_ratio_limit() {
+        let path = "./test_good_config_with_seed_ratio_limit.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSEED_RATIO_LIMIT=1.5";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.seed_ratio_limit, Some(1.5));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_negative_seed_ratio_limit() {
+        let path = "./test_negative_seed_ratio_limit.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSEED_RATIO_LIMIT=-1";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_tcp_port_fallbacks() {
+        let path = "./test_good_config_with_tcp_port_fallbacks.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nTCP_PORT_FALLBACKS=1001, 1002";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.tcp_ports(), vec![1000, 1001, 1002]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_port_fallbacks_not_numbers() {
+        let path = "./test_tcp_port_fallbacks_not_numbers.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nTCP_PORT_FALLBACKS=1001,abc";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_torrent_download_directories() {
+        let path = "./test_good_config_with_torrent_download_directories.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nTORRENT_DOWNLOAD_DIRECTORIES=movie.mkv:./movies, debian.iso:/srv/isos";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(
+            config.download_directory_for("movie.mkv"),
+            Some(String::from("./movies"))
+        );
+        assert_eq!(
+            config.download_directory_for("debian.iso"),
+            Some(String::from("/srv/isos"))
+        );
+        assert_eq!(config.download_directory_for("other.txt"), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_torrent_download_directories_without_directory() {
+        let path = "./test_torrent_download_directories_without_directory.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nTORRENT_DOWNLOAD_DIRECTORIES=movie.mkv";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_port_mapping() {
+        let path = "./test_good_config_with_port_mapping.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENABLE_PORT_MAPPING=true\nPORT_MAPPING_GATEWAY=192.168.1.1\nANNOUNCE_IP=203.0.113.7";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert!(config.enable_port_mapping);
+        assert_eq!(
+            config.port_mapping_gateway,
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+        assert_eq!(config.announce_ip, Some(String::from("203.0.113.7")));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_enable_port_mapping_not_bool() {
+        let path = "./test_enable_port_mapping_not_bool.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENABLE_PORT_MAPPING=yes";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_port_mapping_gateway_not_ipv4() {
+        let path = "./test_port_mapping_gateway_not_ipv4.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nPORT_MAPPING_GATEWAY=192.168.1";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_good_config_with_encryption_mode() {
+        let path = "./test_good_config_with_encryption_mode.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENCRYPTION_MODE=required";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+
+        assert_eq!(config.encryption_mode, EncryptionMode::Required);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_encryption_mode() {
+        let path = "./test_invalid_encryption_mode.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENCRYPTION_MODE=rc4";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
     #[test]
     fn test_order_doesnt_matter() {
         let path = "./test_order_doesnt_matter.cfg";
@@ -264,10 +837,38 @@ mod tests {
             read_write_seconds_timeout: 10,
             max_peers_per_torrent: 1,
             max_log_file_kb_size: 100,
+            stats_json_file: None,
+            max_active_torrents: None,
+            peer_id_prefix: String::from(constants::DEFAULT_PEER_ID_PREFIX),
+            piece_request_seconds_timeout: constants::DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT,
+            connect_seconds_timeout: None,
+            read_seconds_timeout: None,
+            write_seconds_timeout: None,
+            piece_selection_strategy: SelectionStrategy::default(),
+            statistics_update_millis: constants::DEFAULT_STATISTICS_UPDATE_MILLIS,
+            seed_ratio_limit: None,
+            tcp_port_fallbacks: Vec::new(),
+            enable_port_mapping: false,
+            port_mapping_gateway: None,
+            announce_ip: None,
+            encryption_mode: EncryptionMode::default(),
+            torrent_download_directories: Vec::new(),
         };
         create_and_assert_config_is_ok(path, good_config);
     }
 
+    #[test]
+    fn test_optional_settings_dont_replace_required_ones() {
+        let path = "./test_optional_settings_dont_replace_required_ones.cfg";
+        let contents = b"LOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENCRYPTION_MODE=enabled\nSEED_RATIO_LIMIT=2";
+        create_and_write_file(path, contents);
+
+        let err = Cfg::new(path).unwrap_err();
+
+        assert_eq!(err.to_string(), "Missing required setting: TCP_PORT");
+        fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn test_bad_format() {
         let path = "./test_bad_format.cfg";
@@ -309,6 +910,46 @@ mod tests {
             config.max_log_file_kb_size,
             good_config.max_log_file_kb_size
         );
+        assert_eq!(config.stats_json_file, good_config.stats_json_file);
+        assert_eq!(config.max_active_torrents, good_config.max_active_torrents);
+        assert_eq!(config.peer_id_prefix, good_config.peer_id_prefix);
+        assert_eq!(
+            config.piece_request_seconds_timeout,
+            good_config.piece_request_seconds_timeout
+        );
+        assert_eq!(
+            config.connect_seconds_timeout,
+            good_config.connect_seconds_timeout
+        );
+        assert_eq!(
+            config.read_seconds_timeout,
+            good_config.read_seconds_timeout
+        );
+        assert_eq!(
+            config.write_seconds_timeout,
+            good_config.write_seconds_timeout
+        );
+        assert_eq!(
+            config.piece_selection_strategy,
+            good_config.piece_selection_strategy
+        );
+        assert_eq!(
+            config.statistics_update_millis,
+            good_config.statistics_update_millis
+        );
+        assert_eq!(config.seed_ratio_limit, good_config.seed_ratio_limit);
+        assert_eq!(config.tcp_port_fallbacks, good_config.tcp_port_fallbacks);
+        assert_eq!(config.enable_port_mapping, good_config.enable_port_mapping);
+        assert_eq!(
+            config.port_mapping_gateway,
+            good_config.port_mapping_gateway
+        );
+        assert_eq!(config.announce_ip, good_config.announce_ip);
+        assert_eq!(config.encryption_mode, good_config.encryption_mode);
+        assert_eq!(
+            config.torrent_download_directories,
+            good_config.torrent_download_directories
+        );
 
         fs::remove_file(path).unwrap_or_else(|_| panic!("Error removing file in path: {}", &path));
     }
@@ -5,5 +5,39 @@ pub const PIPELINING_SIZE: &str = "PIPELINING_SIZE";
 pub const READ_WRITE_SECONDS_TIMEOUT: &str = "READ_WRITE_SECONDS_TIMEOUT";
 pub const MAX_PEERS_PER_TORRENT: &str = "MAX_PEERS_PER_TORRENT";
 pub const MAX_LOG_FILE_KB_SIZE: &str = "MAX_LOG_FILE_KB_SIZE";
+pub const STATS_JSON_FILE: &str = "STATS_JSON_FILE";
+pub const PEER_ID_PREFIX: &str = "PEER_ID_PREFIX";
+pub const MAX_ACTIVE_TORRENTS: &str = "MAX_ACTIVE_TORRENTS";
+pub const PIECE_REQUEST_SECONDS_TIMEOUT: &str = "PIECE_REQUEST_SECONDS_TIMEOUT";
+pub const CONNECT_SECONDS_TIMEOUT: &str = "CONNECT_SECONDS_TIMEOUT";
+pub const READ_SECONDS_TIMEOUT: &str = "READ_SECONDS_TIMEOUT";
+pub const WRITE_SECONDS_TIMEOUT: &str = "WRITE_SECONDS_TIMEOUT";
+pub const PIECE_SELECTION_STRATEGY: &str = "PIECE_SELECTION_STRATEGY";
+pub const STATISTICS_UPDATE_MILLIS: &str = "STATISTICS_UPDATE_MILLIS";
+pub const SEED_RATIO_LIMIT: &str = "SEED_RATIO_LIMIT";
+pub const TCP_PORT_FALLBACKS: &str = "TCP_PORT_FALLBACKS";
+pub const ENABLE_PORT_MAPPING: &str = "ENABLE_PORT_MAPPING";
+pub const PORT_MAPPING_GATEWAY: &str = "PORT_MAPPING_GATEWAY";
+pub const ANNOUNCE_IP: &str = "ANNOUNCE_IP";
+pub const ENCRYPTION_MODE: &str = "ENCRYPTION_MODE";
+pub const TORRENT_DOWNLOAD_DIRECTORIES: &str = "TORRENT_DOWNLOAD_DIRECTORIES";
 
-pub const MIN_SETTINGS: i8 = 7;
+/// Settings every config file must have, the rest are optional.
+pub const REQUIRED_SETTINGS: [&str; 7] = [
+    TCP_PORT,
+    LOG_DIRECTORY,
+    DOWNLOAD_DIRECTORY,
+    PIPELINING_SIZE,
+    READ_WRITE_SECONDS_TIMEOUT,
+    MAX_PEERS_PER_TORRENT,
+    MAX_LOG_FILE_KB_SIZE,
+];
+
+/// Azureus-style prefix of the peer id: the client id and its version.
+pub const DEFAULT_PEER_ID_PREFIX: &str = "-DT0001-";
+
+/// Seconds to wait for the blocks requested to a peer before aborting the piece.
+pub const DEFAULT_PIECE_REQUEST_SECONDS_TIMEOUT: u64 = 60;
+
+/// Milliseconds between the updates of the torrents statistics.
+pub const DEFAULT_STATISTICS_UPDATE_MILLIS: u64 = 300;
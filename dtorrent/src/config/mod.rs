@@ -1,2 +1,3 @@
 pub mod cfg;
 pub mod constants;
+pub mod sort_settings;
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+
+/// The column a list is sorted by, and in which direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnSort {
+    pub column: u32,
+    pub descending: bool,
+}
+
+/// `SortSettings` struct containing how the lists of the user interface are sorted, so it can be restored on startup.
+///
+/// - `torrents`: sorting of the torrents list. By name, ascending, by default,
+/// - `peers`: sorting of the peers list. By download speed, descending, by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortSettings {
+    pub torrents: ColumnSort,
+    pub peers: ColumnSort,
+}
+
+impl Default for SortSettings {
+    fn default() -> Self {
+        Self {
+            torrents: ColumnSort {
+                column: 0,
+                descending: false,
+            },
+            peers: ColumnSort {
+                column: 7,
+                descending: true,
+            },
+        }
+    }
+}
+
+impl SortSettings {
+    /// Loads the settings from the JSON file in the given path.
+    ///
+    /// It returns an io::Error if:
+    /// - The file does not exist or could not be read.
+    /// - The file contents are not valid settings.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::from_json(&contents)
+    }
+
+    /// Saves the settings as JSON to the file in the given path, replacing it if it exists.
+    ///
+    /// It returns an io::Error if the file could not be written.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+
+    /// Serializes the settings as a JSON string.
+    pub fn to_json(&self) -> io::Result<String> {
+        serde_json::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Deserializes the settings from a JSON string.
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        serde_json::from_str(json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let settings = SortSettings {
+            torrents: ColumnSort {
+                column: 11,
+                descending: true,
+            },
+            peers: ColumnSort {
+                column: 1,
+                descending: false,
+            },
+        };
+
+        let json = settings.to_json().unwrap();
+
+        assert_eq!(SortSettings::from_json(&json).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = "./test_sort_settings_save_and_load.json";
+        let settings = SortSettings {
+            torrents: ColumnSort {
+                column: 4,
+                descending: true,
+            },
+            ..SortSettings::default()
+        };
+
+        settings.save(path).unwrap();
+        let loaded = SortSettings::load(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.unwrap(), settings);
+    }
+
+    #[test]
+    fn test_default_sorts_peers_by_download_speed_descending() {
+        let settings = SortSettings::default();
+
+        assert_eq!(settings.peers.column, 7);
+        assert!(settings.peers.descending);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let result = SortSettings::from_json("{\"torrents\": 0}");
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let result = SortSettings::load("./test_sort_settings_missing_file.json");
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -1,10 +1,13 @@
 pub mod bt_client;
 pub mod bt_server;
 pub mod config;
+pub mod metrics;
 pub mod peer;
+pub mod port_mapping;
 pub mod statistics;
 pub mod storage_manager;
 pub mod torrent_handler;
 pub mod torrent_parser;
 pub mod tracker;
 pub mod ui;
+pub mod util;
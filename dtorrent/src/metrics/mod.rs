@@ -0,0 +1,2 @@
+pub mod prometheus;
+pub mod torrent_metrics;
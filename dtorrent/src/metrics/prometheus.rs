@@ -0,0 +1,156 @@
+use super::torrent_metrics::MetricsSnapshot;
+use std::fmt::Write;
+
+/// A metric family: its name, type, help and how to get its value from a snapshot.
+type MetricFamily = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&MetricsSnapshot) -> u64,
+);
+
+/// The metric families exported.
+const METRICS: [MetricFamily; 5] = [
+    (
+        "pieces_downloaded_total",
+        "counter",
+        "Pieces downloaded and saved.",
+        |snapshot| snapshot.pieces_downloaded,
+    ),
+    (
+        "pieces_failed_total",
+        "counter",
+        "Pieces downloaded that didn't match their hash.",
+        |snapshot| snapshot.pieces_failed,
+    ),
+    (
+        "bytes_downloaded_total",
+        "counter",
+        "Bytes of blocks received from peers.",
+        |snapshot| snapshot.bytes_downloaded,
+    ),
+    (
+        "bytes_uploaded_total",
+        "counter",
+        "Bytes of blocks sent to peers.",
+        |snapshot| snapshot.bytes_uploaded,
+    ),
+    (
+        "active_peers",
+        "gauge",
+        "Peers currently connected.",
+        |snapshot| snapshot.active_peers,
+    ),
+];
+
+/// The metrics of a torrent to render, labeled by its info hash and name.
+#[derive(Debug, Clone)]
+pub struct TorrentSample {
+    pub info_hash: String,
+    pub name: String,
+    pub metrics: MetricsSnapshot,
+}
+
+/// Renders the metrics of the given torrents in the Prometheus text exposition format.
+///
+/// Each metric family is written once, with a sample for each torrent.
+pub fn render(torrents: &[TorrentSample]) -> String {
+    let mut text = String::new();
+    for (name, metric_type, help, value) in METRICS {
+        // Writing to a String can't fail.
+        let _ = writeln!(text, "# HELP {} {}", name, help);
+        let _ = writeln!(text, "# TYPE {} {}", name, metric_type);
+        for torrent in torrents {
+            let _ = writeln!(
+                text,
+                "{}{{info_hash=\"{}\",name=\"{}\"}} {}",
+                name,
+                escape_label_value(&torrent.info_hash),
+                escape_label_value(&torrent.name),
+                value(&torrent.metrics)
+            );
+        }
+    }
+    text
+}
+
+/// Escapes the backslashes, double quotes and line feeds of a label value.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::torrent_metrics::TorrentMetrics;
+
+    #[test]
+    fn test_render_counters_after_activity() {
+        let metrics = TorrentMetrics::default();
+        metrics.piece_downloaded();
+        metrics.piece_downloaded();
+        metrics.piece_downloaded();
+        metrics.piece_failed();
+        metrics.bytes_downloaded(49152);
+        metrics.bytes_uploaded(16384);
+        metrics.set_active_peers(4);
+
+        let text = render(&[create_test_sample("abc", "test.bin", &metrics)]);
+
+        let labels = "{info_hash=\"abc\",name=\"test.bin\"}";
+        assert!(text.contains(&format!("pieces_downloaded_total{} 3\n", labels)));
+        assert!(text.contains(&format!("pieces_failed_total{} 1\n", labels)));
+        assert!(text.contains(&format!("bytes_downloaded_total{} 49152\n", labels)));
+        assert!(text.contains(&format!("bytes_uploaded_total{} 16384\n", labels)));
+        assert!(text.contains(&format!("active_peers{} 4\n", labels)));
+    }
+
+    #[test]
+    fn test_render_types_and_help() {
+        let text = render(&[]);
+
+        assert!(text.contains("# TYPE pieces_downloaded_total counter\n"));
+        assert!(text.contains("# TYPE active_peers gauge\n"));
+        assert!(text.contains("# HELP bytes_uploaded_total Bytes of blocks sent to peers.\n"));
+        assert!(!text.contains('{'));
+    }
+
+    #[test]
+    fn test_render_writes_each_family_once() {
+        let first = TorrentMetrics::default();
+        first.piece_downloaded();
+        let second = TorrentMetrics::default();
+
+        let text = render(&[
+            create_test_sample("first", "first.bin", &first),
+            create_test_sample("second", "second.bin", &second),
+        ]);
+
+        assert_eq!(text.matches("# TYPE pieces_downloaded_total").count(), 1);
+        assert!(text.contains(
+            "# TYPE pieces_downloaded_total counter\npieces_downloaded_total{info_hash=\"first\",name=\"first.bin\"} 1\npieces_downloaded_total{info_hash=\"second\",name=\"second.bin\"} 0\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_escapes_label_values() {
+        let metrics = TorrentMetrics::default();
+
+        let text = render(&[create_test_sample("abc", "a \"b\"\\c\nd", &metrics)]);
+
+        assert!(text.contains("active_peers{info_hash=\"abc\",name=\"a \\\"b\\\"\\\\c\\nd\"} 0\n"));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_sample(info_hash: &str, name: &str, metrics: &TorrentMetrics) -> TorrentSample {
+        TorrentSample {
+            info_hash: info_hash.to_string(),
+            name: name.to_string(),
+            metrics: metrics.snapshot(),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters and gauges of a torrent that are exported as metrics.
+///
+/// Counters only go up for as long as the torrent runs, while gauges hold the current value.
+/// It can be updated from multiple threads at the same time.
+#[derive(Debug, Default)]
+pub struct TorrentMetrics {
+    pieces_downloaded: AtomicU64,
+    pieces_failed: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    active_peers: AtomicU64,
+}
+
+/// A point in time copy of the values of `TorrentMetrics`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub pieces_downloaded: u64,
+    pub pieces_failed: u64,
+    pub bytes_downloaded: u64,
+    pub bytes_uploaded: u64,
+    pub active_peers: u64,
+}
+
+impl TorrentMetrics {
+    /// Counts a piece downloaded and saved.
+    pub fn piece_downloaded(&self) {
+        self.pieces_downloaded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a piece downloaded that didn't match its hash.
+    pub fn piece_failed(&self) {
+        self.pieces_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the bytes of a block received from a peer.
+    pub fn bytes_downloaded(&self, bytes: usize) {
+        self.bytes_downloaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Counts the bytes of a block sent to a peer.
+    pub fn bytes_uploaded(&self, bytes: usize) {
+        self.bytes_uploaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Sets the number of peers currently connected.
+    pub fn set_active_peers(&self, peers: usize) {
+        self.active_peers.store(peers as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the current values of the metrics.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            pieces_downloaded: self.pieces_downloaded.load(Ordering::Relaxed),
+            pieces_failed: self.pieces_failed.load(Ordering::Relaxed),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.load(Ordering::Relaxed),
+            active_peers: self.active_peers.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_metrics_are_zero() {
+        let metrics = TorrentMetrics::default();
+
+        assert_eq!(metrics.snapshot(), MetricsSnapshot::default());
+    }
+
+    #[test]
+    fn test_counters_add_up() {
+        let metrics = TorrentMetrics::default();
+
+        metrics.piece_downloaded();
+        metrics.piece_downloaded();
+        metrics.piece_failed();
+        metrics.bytes_downloaded(16384);
+        metrics.bytes_downloaded(100);
+        metrics.bytes_uploaded(512);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.pieces_downloaded, 2);
+        assert_eq!(snapshot.pieces_failed, 1);
+        assert_eq!(snapshot.bytes_downloaded, 16484);
+        assert_eq!(snapshot.bytes_uploaded, 512);
+    }
+
+    #[test]
+    fn test_active_peers_is_replaced() {
+        let metrics = TorrentMetrics::default();
+
+        metrics.set_active_peers(3);
+        metrics.set_active_peers(1);
+
+        assert_eq!(metrics.snapshot().active_peers, 1);
+    }
+}
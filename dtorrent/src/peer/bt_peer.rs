@@ -1,10 +1,17 @@
-use bencoder::bencode::Bencode;
+use bencoder::bencode::{Bencode, ToBencode};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::io::Read;
 use std::io::Write;
-use std::net::TcpStream;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpStream, ToSocketAddrs};
 
 use super::handshake::Handshake;
 
+/// Length of a peer in the compact format: a 4 bytes IPv4 address and a 2 bytes port.
+pub const COMPACT_PEER_LENGTH: usize = 6;
+/// Length of a peer in the compact IPv6 format: a 16 bytes IPv6 address and a 2 bytes port.
+pub const COMPACT_PEER6_LENGTH: usize = 18;
+
 /// `BtPeer` struct containing individual BtPeer information.
 ///
 /// To create a new `BtPeer` use the method builder `from()`.
@@ -14,6 +21,8 @@ pub struct BtPeer {
     pub ip: String,
     pub port: i64,
     pub info_hash: Option<Vec<u8>>,
+    pub supports_extensions: bool,
+    pub supports_fast_extension: bool,
 }
 
 impl PartialEq for BtPeer {
@@ -39,6 +48,37 @@ pub enum BtPeerError {
     InvalidPort,
     NotADict,
     HandshakeError,
+    InvalidCompactLength(usize),
+}
+
+impl fmt::Display for BtPeerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPeerId => write!(f, "invalid peer id"),
+            Self::InvalidIp => write!(f, "invalid peer ip"),
+            Self::InvalidPort => write!(f, "invalid peer port"),
+            Self::NotADict => write!(f, "peer is not a bencoded dict"),
+            Self::HandshakeError => write!(f, "couldn't receive the handshake of the peer"),
+            Self::InvalidCompactLength(length) => {
+                write!(f, "invalid compact peer length: {}", length)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BtPeerError {}
+
+impl ToBencode for BtPeer {
+    /// Encodes the peer in the dictionary model of the tracker responses. The `peer id` is left out if it's unknown.
+    fn to_bencode(&self) -> Bencode {
+        let mut peer = BTreeMap::new();
+        if let Some(peer_id) = &self.peer_id {
+            peer.insert(b"peer id".to_vec(), peer_id.to_bencode());
+        }
+        peer.insert(b"ip".to_vec(), self.ip.to_bencode());
+        peer.insert(b"port".to_vec(), self.port.to_bencode());
+        peer.to_bencode()
+    }
 }
 
 impl BtPeer {
@@ -49,6 +89,8 @@ impl BtPeer {
             ip,
             port,
             info_hash: None,
+            supports_extensions: false,
+            supports_fast_extension: false,
         }
     }
 
@@ -85,9 +127,38 @@ impl BtPeer {
             ip,
             port,
             info_hash: None,
+            supports_extensions: false,
+            supports_fast_extension: false,
         })
     }
 
+    /// Builds a new `BtPeer` from a peer in the compact format of the tracker responses: a 4 bytes IPv4 address and a 2 bytes big endian port.
+    ///
+    /// It returns an `InvalidCompactLength` error if the peer is not `COMPACT_PEER_LENGTH` bytes long.
+    pub fn from_compact(bytes: &[u8]) -> Result<BtPeer, BtPeerError> {
+        let bytes: &[u8; COMPACT_PEER_LENGTH] = bytes
+            .try_into()
+            .map_err(|_| BtPeerError::InvalidCompactLength(bytes.len()))?;
+        let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+        let port = u16::from_be_bytes([bytes[4], bytes[5]]);
+
+        Ok(BtPeer::new(ip.to_string(), port.into()))
+    }
+
+    /// Builds a new `BtPeer` from a peer in the compact IPv6 format of the tracker responses: a 16 bytes IPv6 address and a 2 bytes big endian port.
+    ///
+    /// It returns an `InvalidCompactLength` error if the peer is not `COMPACT_PEER6_LENGTH` bytes long.
+    pub fn from_compact6(bytes: &[u8]) -> Result<BtPeer, BtPeerError> {
+        let bytes: &[u8; COMPACT_PEER6_LENGTH] = bytes
+            .try_into()
+            .map_err(|_| BtPeerError::InvalidCompactLength(bytes.len()))?;
+        let mut ip = [0; 16];
+        ip.copy_from_slice(&bytes[..16]);
+        let port = u16::from_be_bytes([bytes[16], bytes[17]]);
+
+        Ok(BtPeer::new(Ipv6Addr::from(ip).to_string(), port.into()))
+    }
+
     fn create_peer_id(bencode: &Bencode) -> Result<Vec<u8>, BtPeerError> {
         let peer_id = match bencode {
             Bencode::BString(s) => s.clone(),
@@ -108,7 +179,27 @@ impl BtPeer {
             Err(_) => return Err(BtPeerError::InvalidIp),
         };
 
-        Ok(ip)
+        Ok(Self::normalize_ip(&ip))
+    }
+
+    /// Normalizes a peer address to the textual form of its `IpAddr`, so peers compare equal to the addresses of incoming connections.
+    ///
+    /// Hostnames are resolved, preferring IPv4 addresses. If the address can't be resolved it's returned unchanged.
+    pub fn normalize_ip(ip: &str) -> String {
+        if let Ok(ip) = ip.parse::<IpAddr>() {
+            return ip.to_string();
+        }
+
+        let resolved: Vec<IpAddr> = match (ip, 0).to_socket_addrs() {
+            Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+            Err(_) => return ip.to_string(),
+        };
+        resolved
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or_else(|| resolved.first())
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|| ip.to_string())
     }
 
     fn create_port(bencode: &Bencode) -> Result<i64, BtPeerError> {
@@ -120,18 +211,28 @@ impl BtPeer {
         Ok(port)
     }
 
-    /// Reads a handshake from the peer and returns the info hash.
+    /// Reads a handshake from the peer, stores its peer_id and extension support, and returns the 20 bytes info hash.
     ///
-    /// It returns an error if the handshake could not be read or the handshake was not successful.
-    pub fn receive_handshake(&mut self, stream: &mut TcpStream) -> Result<Vec<u8>, BtPeerError> {
-        let mut buffer = [0; 68];
+    /// The handshake may arrive in several parts (e.g. the peer_id after the info_hash), so it keeps reading until the whole handshake is received, as long as it's within the stream read timeout.
+    ///
+    /// It returns an error if the handshake could not be read, the connection was closed before receiving it all or the handshake was not successful.
+    pub fn receive_handshake(&mut self, stream: &mut impl Read) -> Result<Vec<u8>, BtPeerError> {
+        let mut pstrlen = [0; 1];
         stream
-            .read_exact(&mut buffer)
+            .read_exact(&mut pstrlen)
+            .map_err(|_| BtPeerError::HandshakeError)?;
+
+        let mut buffer = vec![0; Handshake::length(pstrlen[0])];
+        buffer[0] = pstrlen[0];
+        stream
+            .read_exact(&mut buffer[1..])
             .map_err(|_| BtPeerError::HandshakeError)?;
 
         let handshake = Handshake::from_bytes(&buffer).map_err(|_| BtPeerError::HandshakeError)?;
 
         self.info_hash = Some(handshake.info_hash.clone());
+        self.supports_extensions = handshake.supports_extension_protocol();
+        self.supports_fast_extension = handshake.supports_fast_extension();
         self.peer_id = Some(handshake.peer_id);
 
         Ok(handshake.info_hash)
@@ -157,7 +258,7 @@ impl BtPeer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::BTreeMap;
+    use std::{net::TcpListener, thread, time::Duration};
 
     #[test]
     fn test_from_bt_peer() {
@@ -175,6 +276,81 @@ mod tests {
         assert_eq!(bt_peer.port, 6868);
     }
 
+    #[test]
+    fn test_from_bt_peer_with_hostname() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"peer id".to_vec(), Bencode::BString(b"peer id".to_vec()));
+        dict.insert(b"ip".to_vec(), Bencode::BString(b"localhost".to_vec()));
+        dict.insert(b"port".to_vec(), Bencode::BNumber(6868));
+
+        let bt_peer = BtPeer::from(Bencode::BDict(dict)).unwrap();
+
+        assert_eq!(bt_peer.ip, "127.0.0.1");
+        // The same endpoint as an incoming connection from the resolved address.
+        assert_eq!(bt_peer, BtPeer::new("127.0.0.1".to_string(), 6868));
+    }
+
+    #[test]
+    fn test_to_bencode_is_read_back() {
+        let mut bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+        bt_peer.peer_id = Some(b"peer id".to_vec());
+
+        let decoded = BtPeer::from(bt_peer.to_bencode()).unwrap();
+
+        assert_eq!(decoded, bt_peer);
+        assert_eq!(decoded.peer_id, bt_peer.peer_id);
+    }
+
+    #[test]
+    fn test_normalize_ipv6() {
+        assert_eq!(BtPeer::normalize_ip("0:0:0:0:0:0:0:1"), "::1");
+    }
+
+    #[test]
+    fn test_normalize_unresolvable_hostname() {
+        assert_eq!(
+            BtPeer::normalize_ip("invalid host name"),
+            "invalid host name"
+        );
+    }
+
+    #[test]
+    fn test_from_compact() {
+        let bt_peer = BtPeer::from_compact(&[192, 168, 1, 10, 0x1a, 0xe1]).unwrap();
+
+        assert_eq!(bt_peer.peer_id, None);
+        assert_eq!(bt_peer.ip, "192.168.1.10");
+        assert_eq!(bt_peer.port, 6881);
+    }
+
+    #[test]
+    fn test_from_compact6() {
+        let mut bytes = vec![0x20, 0x01, 0x0d, 0xb8];
+        bytes.extend([0; 11]);
+        bytes.extend([1, 0xc8, 0xd5]);
+
+        let bt_peer = BtPeer::from_compact6(&bytes).unwrap();
+
+        assert_eq!(bt_peer.ip, "2001:db8::1");
+        assert_eq!(bt_peer.port, 51413);
+    }
+
+    #[test]
+    fn test_from_compact_with_wrong_length() {
+        assert!(matches!(
+            BtPeer::from_compact(&[127, 0, 0, 1, 0x1a]),
+            Err(BtPeerError::InvalidCompactLength(5))
+        ));
+        assert!(matches!(
+            BtPeer::from_compact(&[0; COMPACT_PEER6_LENGTH]),
+            Err(BtPeerError::InvalidCompactLength(COMPACT_PEER6_LENGTH))
+        ));
+        assert!(matches!(
+            BtPeer::from_compact6(&[0; COMPACT_PEER_LENGTH]),
+            Err(BtPeerError::InvalidCompactLength(COMPACT_PEER_LENGTH))
+        ));
+    }
+
     #[test]
     fn test_new_peer() {
         let bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
@@ -183,4 +359,81 @@ mod tests {
         assert_eq!(bt_peer.ip, "127.0.0.1");
         assert_eq!(bt_peer.port, 6868);
     }
+
+    #[test]
+    fn test_receive_handshake() {
+        let mut bytes = Handshake::new(vec![1; 20], vec![2; 20]).as_bytes();
+        // A message sent right after the handshake is left in the stream.
+        bytes.extend([0, 0, 0, 0]);
+        let mut stream = bytes.as_slice();
+
+        let mut bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+        let info_hash = bt_peer.receive_handshake(&mut stream).unwrap();
+
+        assert_eq!(info_hash, vec![1; 20]);
+        assert_eq!(bt_peer.info_hash, Some(vec![1; 20]));
+        assert_eq!(bt_peer.peer_id, Some(vec![2; 20]));
+        assert!(bt_peer.supports_extensions);
+        assert!(bt_peer.supports_fast_extension);
+        assert_eq!(stream, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_receive_handshake_with_another_protocol() {
+        let mut bytes = Handshake::new(vec![1; 20], vec![2; 20]).as_bytes();
+        bytes[1..20].copy_from_slice(b"Other file protocol");
+
+        let mut bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+
+        assert!(matches!(
+            bt_peer.receive_handshake(&mut bytes.as_slice()),
+            Err(BtPeerError::HandshakeError)
+        ));
+        assert_eq!(bt_peer.peer_id, None);
+        assert_eq!(bt_peer.info_hash, None);
+    }
+
+    #[test]
+    fn test_receive_handshake_in_two_writes() {
+        let (mut client, mut stream) = create_test_connection();
+        let bytes = Handshake::new(vec![1; 20], vec![2; 20]).as_bytes();
+
+        let sender = thread::spawn(move || {
+            client.write_all(&bytes[..48]).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            client.write_all(&bytes[48..]).unwrap();
+        });
+        let mut bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+        let info_hash = bt_peer.receive_handshake(&mut stream).unwrap();
+        sender.join().unwrap();
+
+        assert_eq!(info_hash, vec![1; 20]);
+        assert_eq!(bt_peer.peer_id, Some(vec![2; 20]));
+    }
+
+    #[test]
+    fn test_receive_handshake_closed_before_peer_id() {
+        let (mut client, mut stream) = create_test_connection();
+        let bytes = Handshake::new(vec![1; 20], vec![2; 20]).as_bytes();
+        client.write_all(&bytes[..48]).unwrap();
+        drop(client);
+
+        let mut bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+
+        assert!(matches!(
+            bt_peer.receive_handshake(&mut stream),
+            Err(BtPeerError::HandshakeError)
+        ));
+        assert_eq!(bt_peer.peer_id, None);
+    }
+
+    // Auxiliary functions
+
+    /// Returns both ends of a local connection: the client and the accepted stream.
+    fn create_test_connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (client, stream)
+    }
 }
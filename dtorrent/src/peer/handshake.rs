@@ -1,6 +1,8 @@
-#[derive(Debug)]
-pub enum FromHandshakeError {
-    InvalidHandshake,
+/// Posible `Handshake` parsing errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    InvalidLength,
+    UnsupportedProtocol,
 }
 
 /// Represents a handshake message.
@@ -15,19 +17,49 @@ pub struct Handshake {
 }
 
 const PSTR: &str = "BitTorrent protocol";
+/// Length of a handshake without its pstr: pstrlen (1), reserved (8), info_hash (20) and peer_id (20).
+const HANDSHAKE_BASE_LENGTH: usize = 49;
+
+// Reserved byte and bit used to signal support for the extension protocol (BEP 10).
+const EXTENSION_PROTOCOL_BYTE: usize = 5;
+const EXTENSION_PROTOCOL_BIT: u8 = 0x10;
+// Reserved byte and bit used to signal support for the fast extension (BEP 6).
+const FAST_EXTENSION_BYTE: usize = 7;
+const FAST_EXTENSION_BIT: u8 = 0x04;
 
 impl Handshake {
     /// Creates a new `Handshake` message.
+    ///
+    /// The reserved bytes advertise support for the extension protocol and the fast extension.
     pub fn new(info_hash: Vec<u8>, peer_id: Vec<u8>) -> Self {
+        let mut reserved = [0; 8];
+        reserved[EXTENSION_PROTOCOL_BYTE] |= EXTENSION_PROTOCOL_BIT;
+        reserved[FAST_EXTENSION_BYTE] |= FAST_EXTENSION_BIT;
+
         Self {
             pstrlen: 19,
             pstr: PSTR.to_string(),
-            reserved: [0; 8],
+            reserved,
             info_hash,
             peer_id,
         }
     }
 
+    /// Returns the length in bytes of a handshake with the given `pstrlen`.
+    pub fn length(pstrlen: u8) -> usize {
+        HANDSHAKE_BASE_LENGTH + pstrlen as usize
+    }
+
+    /// Returns true if the reserved bytes indicate support for the extension protocol (BEP 10).
+    pub fn supports_extension_protocol(&self) -> bool {
+        self.reserved[EXTENSION_PROTOCOL_BYTE] & EXTENSION_PROTOCOL_BIT != 0
+    }
+
+    /// Returns true if the reserved bytes indicate support for the fast extension (BEP 6).
+    pub fn supports_fast_extension(&self) -> bool {
+        self.reserved[FAST_EXTENSION_BYTE] & FAST_EXTENSION_BIT != 0
+    }
+
     /// Converts a `Handshake` message to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![self.pstrlen];
@@ -39,35 +71,34 @@ impl Handshake {
     }
 
     /// Parses a byte array into a `Handshake` message.
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromHandshakeError> {
-        if bytes.len() != 68 {
-            return Err(FromHandshakeError::InvalidHandshake);
+    ///
+    /// The handshake is read from the start of the array, so any bytes after it are ignored.
+    ///
+    /// It returns a `HandshakeError` if:
+    /// - The array is shorter than the handshake length given by its `pstrlen`.
+    /// - The protocol is not `BitTorrent protocol`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HandshakeError> {
+        let pstrlen = *bytes.first().ok_or(HandshakeError::InvalidLength)?;
+        let pstr_end = 1 + pstrlen as usize;
+        if bytes.len() < Self::length(pstrlen) {
+            return Err(HandshakeError::InvalidLength);
         }
 
-        let pstrlen = bytes[0];
-        if pstrlen != 19 {
-            return Err(FromHandshakeError::InvalidHandshake);
+        let pstr = String::from_utf8(bytes[1..pstr_end].to_vec())
+            .map_err(|_| HandshakeError::UnsupportedProtocol)?;
+        if pstr != PSTR {
+            return Err(HandshakeError::UnsupportedProtocol);
         }
 
-        let pstr = String::from_utf8(bytes[1..pstrlen as usize + 1].to_vec())
-            .map_err(|_| FromHandshakeError::InvalidHandshake)?;
-        let reserved = &bytes[pstrlen as usize + 1..pstrlen as usize + 9];
-        let info_hash = &bytes[pstrlen as usize + 9..pstrlen as usize + 29];
-        let peer_id = &bytes[pstrlen as usize + 29..];
+        let mut reserved = [0; 8];
+        reserved.copy_from_slice(&bytes[pstr_end..pstr_end + 8]);
+        let info_hash = &bytes[pstr_end + 8..pstr_end + 28];
+        let peer_id = &bytes[pstr_end + 28..pstr_end + 48];
 
         Ok(Self {
             pstrlen,
             pstr,
-            reserved: [
-                reserved[0],
-                reserved[1],
-                reserved[2],
-                reserved[3],
-                reserved[4],
-                reserved[5],
-                reserved[6],
-                reserved[7],
-            ],
+            reserved,
             info_hash: info_hash.to_vec(),
             peer_id: peer_id.to_vec(),
         })
@@ -83,7 +114,7 @@ mod tests {
         let expected_handshake_len = 68;
         let expected_pstrlen = 19;
         let expected_pstr = b"BitTorrent protocol".to_vec();
-        let expected_reserved = [0; 8];
+        let expected_reserved = [0, 0, 0, 0, 0, 0x10, 0, 0x04];
 
         let info_hash: Vec<u8> = (1..=20).collect();
         let peer_id: Vec<u8> = (21..=40).collect();
@@ -103,7 +134,7 @@ mod tests {
     fn test_from_bytes() {
         let expected_pstrlen = 19;
         let expected_pstr = "BitTorrent protocol";
-        let expected_reserved = [0; 8];
+        let expected_reserved = [0, 0, 0, 0, 0, 0x10, 0, 0x04];
 
         let info_hash: Vec<u8> = (1..=20).collect();
         let peer_id: Vec<u8> = (21..=40).collect();
@@ -118,4 +149,87 @@ mod tests {
         assert_eq!(handshake.info_hash, info_hash);
         assert_eq!(handshake.peer_id, peer_id);
     }
+
+    #[test]
+    fn test_from_bytes_ignores_trailing_bytes() {
+        let info_hash: Vec<u8> = (1..=20).collect();
+        let peer_id: Vec<u8> = (21..=40).collect();
+        let mut bytes = Handshake::new(info_hash.clone(), peer_id.clone()).as_bytes();
+        // A message sent right after the handshake.
+        bytes.extend([0, 0, 0, 1, 2]);
+
+        let handshake = Handshake::from_bytes(&bytes).unwrap();
+
+        assert_eq!(handshake.info_hash, info_hash);
+        assert_eq!(handshake.peer_id, peer_id);
+    }
+
+    #[test]
+    fn test_from_bytes_truncated() {
+        let bytes = Handshake::new((1..=20).collect(), (21..=40).collect()).as_bytes();
+
+        for length in [0, 1, 20, 48, 67] {
+            assert_eq!(
+                Handshake::from_bytes(&bytes[..length]).unwrap_err(),
+                HandshakeError::InvalidLength
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_pstrlen_longer_than_buffer() {
+        let mut bytes = Handshake::new((1..=20).collect(), (21..=40).collect()).as_bytes();
+        bytes[0] = 20;
+
+        assert_eq!(
+            Handshake::from_bytes(&bytes).unwrap_err(),
+            HandshakeError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_unsupported_protocol() {
+        let mut bytes = Handshake::new((1..=20).collect(), (21..=40).collect()).as_bytes();
+        bytes[1..20].copy_from_slice(b"BitTorrent protoc0l");
+
+        assert_eq!(
+            Handshake::from_bytes(&bytes).unwrap_err(),
+            HandshakeError::UnsupportedProtocol
+        );
+    }
+
+    #[test]
+    fn test_supports_extension_protocol() {
+        let handshake = Handshake::new((1..=20).collect(), (21..=40).collect());
+
+        assert!(handshake.supports_extension_protocol());
+    }
+
+    #[test]
+    fn test_does_not_support_extension_protocol() {
+        let mut bytes = Handshake::new((1..=20).collect(), (21..=40).collect()).as_bytes();
+        bytes[25] = 0;
+
+        let handshake = Handshake::from_bytes(&bytes).unwrap();
+
+        assert!(!handshake.supports_extension_protocol());
+    }
+
+    #[test]
+    fn test_supports_fast_extension() {
+        let handshake = Handshake::new((1..=20).collect(), (21..=40).collect());
+
+        assert!(handshake.supports_fast_extension());
+    }
+
+    #[test]
+    fn test_does_not_support_fast_extension() {
+        let mut bytes = Handshake::new((1..=20).collect(), (21..=40).collect()).as_bytes();
+        bytes[27] = 0;
+
+        let handshake = Handshake::from_bytes(&bytes).unwrap();
+
+        assert!(!handshake.supports_fast_extension());
+        assert!(handshake.supports_extension_protocol());
+    }
 }
@@ -1,5 +1,6 @@
 use logger::logger_sender::LoggerSender;
-use std::{io::Write, net::TcpStream, sync::Arc};
+use std::fmt;
+use std::{io::Write, sync::Arc};
 
 use crate::{
     torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError},
@@ -8,15 +9,61 @@ use crate::{
 
 use super::{
     handshake::Handshake,
-    peer_message::{Bitfield, Message, MessageId, Request},
+    peer_message::{
+        Bitfield, BitfieldError, ExtendedMessage, ExtendedMessageError, FastMessage,
+        FastMessageError, Message, MessageId, MetadataMessage, MetadataMessageError, Request,
+        RequestError, METADATA_PIECE_SIZE, SUPPORTED_EXTENSIONS,
+    },
+    peer_stream::PeerStream,
 };
 
+/// Length of the payload of a Piece message before the block: index (4) and begin (4).
+const PIECE_HEADER_LENGTH: usize = 8;
+/// Length of the payload of a Have message: the piece index.
+const HAVE_LENGTH: usize = 4;
+
 #[derive(Debug)]
 pub enum MessageHandlerError {
     ErrorGettingBitfield(AtomicTorrentStatusError),
     ErrorGettingPiece(AtomicTorrentStatusError),
     HandshakeError,
     MessageError(MessageId),
+    ExtendedMessageError(ExtendedMessageError),
+    MetadataMessageError(MetadataMessageError),
+    FastMessageError(FastMessageError),
+    InvalidPayloadLength(MessageId, usize),
+    InvalidRequest(RequestError),
+}
+
+impl fmt::Display for MessageHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ErrorGettingBitfield(_) => write!(f, "couldn't get our bitfield"),
+            Self::ErrorGettingPiece(_) => write!(f, "couldn't get the requested piece"),
+            Self::HandshakeError => write!(f, "couldn't send the handshake"),
+            Self::MessageError(id) => write!(f, "couldn't send {:?} message", id),
+            Self::ExtendedMessageError(_) => write!(f, "invalid extended message"),
+            Self::MetadataMessageError(_) => write!(f, "invalid metadata message"),
+            Self::FastMessageError(_) => write!(f, "invalid fast extension message"),
+            Self::InvalidPayloadLength(id, length) => {
+                write!(f, "invalid {:?} message payload length: {}", id, length)
+            }
+            Self::InvalidRequest(_) => write!(f, "invalid request message"),
+        }
+    }
+}
+
+impl std::error::Error for MessageHandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ErrorGettingBitfield(err) | Self::ErrorGettingPiece(err) => Some(err),
+            Self::ExtendedMessageError(err) => Some(err),
+            Self::MetadataMessageError(err) => Some(err),
+            Self::FastMessageError(err) => Some(err),
+            Self::InvalidRequest(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 /// Message handler for a peer session.
@@ -48,21 +95,71 @@ impl MessageHandler {
     /// Receiving messages
 
     /// Handles a bitfield message received from the peer.
-    pub fn handle_bitfield(&mut self, message: Message) -> Bitfield {
-        Bitfield::new(message.payload)
-    }
-
-    /// Handles a piece message received from the peer.
-    pub fn handle_piece(&mut self, message: Message) -> Vec<u8> {
-        let block = &message.payload[8..];
-        block.to_vec()
+    ///
+    /// It returns an error if the bitfield doesn't match the torrent's pieces.
+    pub fn handle_bitfield(&mut self, message: Message) -> Result<Bitfield, BitfieldError> {
+        Bitfield::from_received(message.payload, self.torrent.total_pieces())
     }
 
-    // Returns the received piece index
-    pub fn handle_have(&mut self, message: Message) -> u32 {
+    /// Handles a piece message received from the peer, returning the piece index, the offset of the block and the block.
+    ///
+    /// It returns an `InvalidPayloadLength` error if the payload is too short to hold the index and offset.
+    pub fn handle_piece(
+        &mut self,
+        message: Message,
+    ) -> Result<(u32, u32, Vec<u8>), MessageHandlerError> {
+        if message.payload.len() < PIECE_HEADER_LENGTH {
+            return Err(MessageHandlerError::InvalidPayloadLength(
+                MessageId::Piece,
+                message.payload.len(),
+            ));
+        }
         let mut index: [u8; 4] = [0; 4];
         index.copy_from_slice(&message.payload[0..4]);
-        u32::from_be_bytes(index)
+        let mut begin: [u8; 4] = [0; 4];
+        begin.copy_from_slice(&message.payload[4..8]);
+        let block = message.payload[PIECE_HEADER_LENGTH..].to_vec();
+        Ok((u32::from_be_bytes(index), u32::from_be_bytes(begin), block))
+    }
+
+    /// Handles a request message received from the peer.
+    ///
+    /// It returns an `InvalidRequest` error if the payload is not a valid request.
+    pub fn handle_request(&mut self, message: Message) -> Result<Request, MessageHandlerError> {
+        Request::from_bytes(&message.payload).map_err(MessageHandlerError::InvalidRequest)
+    }
+
+    /// Returns the received piece index.
+    ///
+    /// It returns an `InvalidPayloadLength` error if the payload is not 4 bytes long.
+    pub fn handle_have(&mut self, message: Message) -> Result<u32, MessageHandlerError> {
+        let index: [u8; HAVE_LENGTH] = message.payload.as_slice().try_into().map_err(|_| {
+            MessageHandlerError::InvalidPayloadLength(MessageId::Have, message.payload.len())
+        })?;
+        Ok(u32::from_be_bytes(index))
+    }
+
+    /// Handles an extended message received from the peer.
+    pub fn handle_extended(
+        &mut self,
+        message: Message,
+    ) -> Result<ExtendedMessage, MessageHandlerError> {
+        ExtendedMessage::from_bytes(&message.payload)
+            .map_err(MessageHandlerError::ExtendedMessageError)
+    }
+
+    /// Handles a fast extension message received from the peer.
+    pub fn handle_fast(&mut self, message: Message) -> Result<FastMessage, MessageHandlerError> {
+        FastMessage::from_message(&message).map_err(MessageHandlerError::FastMessageError)
+    }
+
+    /// Handles a `ut_metadata` message received from the peer, given the payload of the extended message.
+    pub fn handle_metadata(
+        &mut self,
+        message: Message,
+    ) -> Result<MetadataMessage, MessageHandlerError> {
+        MetadataMessage::from_bytes(&message.payload[1..])
+            .map_err(MessageHandlerError::MetadataMessageError)
     }
 
     /// ------------------------------------------------------------------------------------------------
@@ -74,7 +171,7 @@ impl MessageHandler {
         index: u32,
         begin: u32,
         block: &[u8],
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let mut payload = vec![];
         payload.extend(index.to_be_bytes());
@@ -91,21 +188,53 @@ impl MessageHandler {
     }
 
     /// Sends a unchoked message to the peer.
-    pub fn send_unchoked(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    pub fn send_unchoked(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
         let unchoked_msg = Message::new(MessageId::Unchoke, vec![]);
         self.send(stream, unchoked_msg)?;
         Ok(())
     }
 
     /// Sends a bitfield message to the peer.
-    pub fn send_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    ///
+    /// If the fast extension was negotiated, a `HaveAll` or `HaveNone` message is sent instead when we have every piece or none of them.
+    ///
+    /// It returns the bitfield that was sent.
+    pub fn send_bitfield(
+        &mut self,
+        fast_extension: bool,
+        stream: &mut PeerStream,
+    ) -> Result<Bitfield, MessageHandlerError> {
         let bitfield = self
             .torrent_status
             .get_bitfield()
             .map_err(MessageHandlerError::ErrorGettingBitfield)?;
 
-        let bitfield_msg = Message::new(MessageId::Bitfield, bitfield.get_vec());
+        let total_pieces = self.torrent.total_pieces();
+        let bitfield_msg = match bitfield.count_pieces(total_pieces) {
+            0 if fast_extension => Message::new(MessageId::HaveNone, vec![]),
+            pieces if fast_extension && pieces == total_pieces => {
+                Message::new(MessageId::HaveAll, vec![])
+            }
+            _ => Message::new(MessageId::Bitfield, bitfield.get_vec()),
+        };
         self.send(stream, bitfield_msg)?;
+        Ok(bitfield)
+    }
+
+    /// Sends a reject request message to the peer, telling it the block of its request won't be sent.
+    pub fn send_reject_request(
+        &mut self,
+        request: &Request,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let reject_msg = Message::new(MessageId::RejectRequest, request.as_bytes());
+        self.send(stream, reject_msg)?;
+
+        self.logger_sender.info(&format!(
+            "Rejected request of piece: {} / Offset: {}",
+            request.index(),
+            request.begin()
+        ));
         Ok(())
     }
 
@@ -115,7 +244,7 @@ impl MessageHandler {
         index: u32,
         begin: u32,
         length: u32,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let payload = Request::new(index, begin, length).as_bytes();
 
@@ -125,7 +254,7 @@ impl MessageHandler {
     }
 
     /// Sends an interested message to the peer.
-    pub fn send_interested(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    pub fn send_interested(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
         let interested_msg = Message::new(MessageId::Interested, vec![]);
         self.send(stream, interested_msg)?;
         Ok(())
@@ -137,7 +266,7 @@ impl MessageHandler {
         index: u32,
         begin: u32,
         length: u32,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let mut payload = vec![];
         payload.extend(index.to_be_bytes());
@@ -155,7 +284,7 @@ impl MessageHandler {
     pub fn send_have(
         &mut self,
         index: u32,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let mut payload = vec![];
         payload.extend(index.to_be_bytes());
@@ -166,8 +295,66 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Sends an extended handshake to the peer advertising the supported extensions.
+    ///
+    /// The size of the metadata is only advertised if we have it.
+    pub fn send_extended_handshake(
+        &mut self,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let metadata_size = Some(self.torrent.info_bytes.len()).filter(|size| *size > 0);
+        let handshake = ExtendedMessage::handshake(SUPPORTED_EXTENSIONS, metadata_size);
+
+        let extended_msg = Message::new(MessageId::Extended, handshake.as_bytes());
+        self.send(stream, extended_msg)?;
+        Ok(())
+    }
+
+    /// Answers a `ut_metadata` request with the requested piece of the torrent metadata.
+    ///
+    /// If the piece doesn't exist, a reject message is sent instead.
+    pub fn send_metadata_piece(
+        &mut self,
+        peer_ext_id: u8,
+        piece: u32,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let metadata = &self.torrent.info_bytes;
+        let start = piece as usize * METADATA_PIECE_SIZE;
+
+        let metadata_msg = if start < metadata.len() {
+            let end = (start + METADATA_PIECE_SIZE).min(metadata.len());
+            MetadataMessage::data(piece, metadata.len() as u32, metadata[start..end].to_vec())
+        } else {
+            MetadataMessage::reject(piece)
+        };
+        self.send_metadata(peer_ext_id, metadata_msg, stream)
+    }
+
+    /// Sends a `ut_metadata` request for the given piece of the torrent metadata.
+    pub fn send_metadata_request(
+        &mut self,
+        peer_ext_id: u8,
+        piece: u32,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        self.send_metadata(peer_ext_id, MetadataMessage::request(piece), stream)
+    }
+
+    /// Sends a `ut_metadata` message inside an extended message with the ID the peer wants to receive it with.
+    fn send_metadata(
+        &self,
+        peer_ext_id: u8,
+        metadata_msg: MetadataMessage,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let mut payload = vec![peer_ext_id];
+        payload.extend(metadata_msg.as_bytes());
+        self.send(stream, Message::new(MessageId::Extended, payload))
+    }
+
     /// Generic sending function.
-    fn send(&self, stream: &mut TcpStream, message: Message) -> Result<(), MessageHandlerError> {
+    fn send(&self, stream: &mut PeerStream, message: Message) -> Result<(), MessageHandlerError> {
         stream
             .write_all(&message.as_bytes())
             .map_err(|_| MessageHandlerError::MessageError(message.id))?;
@@ -180,13 +367,11 @@ impl MessageHandler {
     /// Sends a handshake to the peer.
     ///
     /// It returns an error if the handshake could not be sent or the handshake was not successful.
-    pub fn send_handshake(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
-        let info_hash = self
-            .torrent
-            .get_info_hash_as_bytes()
-            .map_err(|_| MessageHandlerError::HandshakeError)?;
-
-        let handshake = Handshake::new(info_hash, self.client_peer_id.as_bytes().to_vec());
+    pub fn send_handshake(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
+        let handshake = Handshake::new(
+            self.torrent.get_info_hash_as_bytes().to_vec(),
+            self.client_peer_id.as_bytes().to_vec(),
+        );
         stream
             .write_all(&handshake.as_bytes())
             .map_err(|_| MessageHandlerError::HandshakeError)?;
@@ -1,6 +1,9 @@
 pub mod bt_peer;
-mod handshake;
+pub mod handshake;
 mod message_handler;
+pub mod mse;
 pub mod peer_message;
 pub mod peer_session;
+pub mod peer_stream;
 pub mod session_status;
+mod speed;
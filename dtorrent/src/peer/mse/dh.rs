@@ -0,0 +1,173 @@
+//! Diffie-Hellman key exchange of Message Stream Encryption, over the 768 bits prime of the spec with generator 2.
+
+/// Length in bytes of the public keys and of the shared secret.
+pub const KEY_LENGTH: usize = 96;
+
+const PRIME: [u8; KEY_LENGTH] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC9, 0x0F, 0xDA, 0xA2, 0x21, 0x68, 0xC2, 0x34,
+    0xC4, 0xC6, 0x62, 0x8B, 0x80, 0xDC, 0x1C, 0xD1, 0x29, 0x02, 0x4E, 0x08, 0x8A, 0x67, 0xCC, 0x74,
+    0x02, 0x0B, 0xBE, 0xA6, 0x3B, 0x13, 0x9B, 0x22, 0x51, 0x4A, 0x08, 0x79, 0x8E, 0x34, 0x04, 0xDD,
+    0xEF, 0x95, 0x19, 0xB3, 0xCD, 0x3A, 0x43, 0x1B, 0x30, 0x2B, 0x0A, 0x6D, 0xF2, 0x5F, 0x14, 0x37,
+    0x4F, 0xE1, 0x35, 0x6D, 0x6D, 0x51, 0xC2, 0x45, 0xE4, 0x85, 0xB5, 0x76, 0x62, 0x5E, 0x7E, 0xC6,
+    0xF4, 0x4C, 0x42, 0xE9, 0xA6, 0x3A, 0x36, 0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x05, 0x63,
+];
+const GENERATOR: u8 = 2;
+
+// Numbers are little endian 64 bits limbs, with a spare limb so doubling a number below the prime doesn't overflow.
+const LIMBS: usize = KEY_LENGTH / 8 + 1;
+type Number = [u64; LIMBS];
+
+/// Returns the public key `G^private_key mod P` of the given private key, in big endian.
+pub fn public_key(private_key: &[u8]) -> [u8; KEY_LENGTH] {
+    to_be_bytes(&pow_mod(&from_be_bytes(&[GENERATOR]), private_key))
+}
+
+/// Returns the secret `peer_public_key^private_key mod P` shared with the peer, in big endian.
+pub fn shared_secret(peer_public_key: &[u8; KEY_LENGTH], private_key: &[u8]) -> [u8; KEY_LENGTH] {
+    let prime = from_be_bytes(&PRIME);
+    let mut base = from_be_bytes(peer_public_key);
+    // A key of 96 bytes is less than twice the prime.
+    if !is_less(&base, &prime) {
+        sub_assign(&mut base, &prime);
+    }
+    to_be_bytes(&pow_mod(&base, private_key))
+}
+
+/// Returns `base^exponent mod P`, with the exponent in big endian. The base must be less than the prime.
+fn pow_mod(base: &Number, exponent: &[u8]) -> Number {
+    let prime = from_be_bytes(&PRIME);
+    let mut result = from_be_bytes(&[1]);
+    for byte in exponent {
+        for bit in (0..8).rev() {
+            result = mul_mod(&result, &result, &prime);
+            if byte >> bit & 1 == 1 {
+                result = mul_mod(&result, base, &prime);
+            }
+        }
+    }
+    result
+}
+
+/// Returns `a * b mod prime` by doubling and adding, both less than the prime.
+fn mul_mod(a: &Number, b: &Number, prime: &Number) -> Number {
+    let mut result = [0; LIMBS];
+    for bit in (0..KEY_LENGTH * 8).rev() {
+        shl1_assign(&mut result);
+        if !is_less(&result, prime) {
+            sub_assign(&mut result, prime);
+        }
+        if b[bit / 64] >> (bit % 64) & 1 == 1 {
+            add_assign(&mut result, a);
+            if !is_less(&result, prime) {
+                sub_assign(&mut result, prime);
+            }
+        }
+    }
+    result
+}
+
+fn is_less(a: &Number, b: &Number) -> bool {
+    a.iter().rev().lt(b.iter().rev())
+}
+
+fn add_assign(a: &mut Number, b: &Number) {
+    let mut carry = false;
+    for (a, b) in a.iter_mut().zip(b) {
+        let (sum, overflow) = a.overflowing_add(*b);
+        let (sum, carry_overflow) = sum.overflowing_add(carry as u64);
+        *a = sum;
+        carry = overflow || carry_overflow;
+    }
+}
+
+/// Subtracts `b` from `a`, which must not be less than `b`.
+fn sub_assign(a: &mut Number, b: &Number) {
+    let mut borrow = false;
+    for (a, b) in a.iter_mut().zip(b) {
+        let (difference, overflow) = a.overflowing_sub(*b);
+        let (difference, borrow_overflow) = difference.overflowing_sub(borrow as u64);
+        *a = difference;
+        borrow = overflow || borrow_overflow;
+    }
+}
+
+fn shl1_assign(a: &mut Number) {
+    let mut carry = 0;
+    for limb in a.iter_mut() {
+        let next_carry = *limb >> 63;
+        *limb = *limb << 1 | carry;
+        carry = next_carry;
+    }
+}
+
+/// Reads a big endian number of at most `KEY_LENGTH` bytes.
+fn from_be_bytes(bytes: &[u8]) -> Number {
+    let mut number = [0; LIMBS];
+    for (i, byte) in bytes.iter().rev().enumerate() {
+        number[i / 8] |= (*byte as u64) << (i % 8 * 8);
+    }
+    number
+}
+
+fn to_be_bytes(number: &Number) -> [u8; KEY_LENGTH] {
+    let mut bytes = [0; KEY_LENGTH];
+    for (i, byte) in bytes.iter_mut().rev().enumerate() {
+        *byte = (number[i / 8] >> (i % 8 * 8)) as u8;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys computed independently, with arbitrary precision integers.
+    const PRIVATE_KEY_A: [u8; 20] = [
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+    ];
+    const PRIVATE_KEY_B: [u8; 20] = [
+        21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40,
+    ];
+    const PUBLIC_KEY_A: &str = "96e112dab29e8c5272accb9b17b26887ce54a144a4e3b697c7d159b7a817e556b0918db2b4c658e02a87f7e5fb14b18a553e084cbf3dad2d30f16596ccb982d406258c61b30c5c1dae2ddc60bdbd48d79896312aad63238c39e1a633821eb693";
+    const PUBLIC_KEY_B: &str = "a3e981e821087e74b889c5bfc373ec5be9ed09c85282b9cdb6b07dbaabe0ee5138a91e6245657219d1019ce47ed3613f037c6932a69069a3928b146b354e79cbfeaad601d12598943c43b5b5529915b2c604e43fd6f9ced4efbe68f9edfaeee7";
+    const SHARED_SECRET: &str = "994aac6c359990cf4f678a1742b587eb1a5248ec7fcc0d0bcfcb12d2461bc1fe25417b70869697d9ca884832f1c5f2a2fd3318c22a5a6ba170d36aac91405457c1e8137b1534a776865ed353f12422ff6afc58435f8bd443f61dd051a37bcdeb";
+
+    #[test]
+    fn test_public_keys() {
+        assert_eq!(hex(&public_key(&PRIVATE_KEY_A)), PUBLIC_KEY_A);
+        assert_eq!(hex(&public_key(&PRIVATE_KEY_B)), PUBLIC_KEY_B);
+    }
+
+    #[test]
+    fn test_both_sides_share_the_secret() {
+        let secret_a = shared_secret(&public_key(&PRIVATE_KEY_B), &PRIVATE_KEY_A);
+        let secret_b = shared_secret(&public_key(&PRIVATE_KEY_A), &PRIVATE_KEY_B);
+
+        assert_eq!(hex(&secret_a), SHARED_SECRET);
+        assert_eq!(secret_a, secret_b);
+    }
+
+    #[test]
+    fn test_public_key_is_reduced_by_the_prime() {
+        // The prime plus one is the same as one, and one to any power is one.
+        let mut prime_plus_one = from_be_bytes(&PRIME);
+        add_assign(&mut prime_plus_one, &from_be_bytes(&[1]));
+
+        let secret = shared_secret(&to_be_bytes(&prime_plus_one), &PRIVATE_KEY_A);
+
+        assert_eq!(secret, to_be_bytes(&from_be_bytes(&[1])));
+    }
+
+    #[test]
+    fn test_bytes_conversion() {
+        let bytes = public_key(&PRIVATE_KEY_A);
+
+        assert_eq!(to_be_bytes(&from_be_bytes(&bytes)), bytes);
+    }
+
+    // Auxiliary functions
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
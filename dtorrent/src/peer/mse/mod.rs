@@ -0,0 +1,641 @@
+//! Message Stream Encryption (MSE/PE): an obfuscated handshake negotiated before the BitTorrent handshake, after which the connection is encrypted with RC4 or left in plaintext.
+//!
+//! Both the initiator and the responder side are supported. Our side sends no PadC/PadD and no initial payload (IA), but accepts them from the peer.
+
+pub mod dh;
+pub mod rc4;
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use rand::Rng;
+use sha1::{Digest, Sha1};
+
+use self::{dh::KEY_LENGTH, rc4::Rc4};
+
+use super::peer_stream::PeerStream;
+
+/// The connection continues in plaintext after the handshake.
+pub const CRYPTO_PLAINTEXT: u32 = 0x01;
+/// The connection continues encrypted with RC4 after the handshake.
+pub const CRYPTO_RC4: u32 = 0x02;
+
+const VERIFICATION_CONSTANT: [u8; 8] = [0; 8];
+const MAX_PAD_LENGTH: usize = 512;
+const PRIVATE_KEY_LENGTH: usize = 20;
+const HASH_LENGTH: usize = 20;
+// The first bytes of the RC4 keystream are discarded, as they leak information about the key.
+const RC4_DISCARD: usize = 1024;
+
+/// Whether connections to peers are encrypted with Message Stream Encryption.
+///
+/// - `Disabled`: connections are always in plaintext.
+/// - `Enabled`: encryption is offered, falling back to plaintext if the peer doesn't support it.
+/// - `Required`: only encrypted connections are accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncryptionMode {
+    #[default]
+    Disabled,
+    Enabled,
+    Required,
+}
+
+/// The setting is not one of the encryption modes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidEncryptionMode;
+
+/// Posible Message Stream Encryption errors.
+#[derive(Debug)]
+pub enum MseError {
+    IoError(io::Error),
+    SyncNotFound,
+    InvalidPadLength(usize),
+    UnknownInfoHash,
+    InvalidVerificationConstant,
+    NoCommonCrypto(u32),
+    InvalidCryptoSelect(u32),
+}
+
+impl fmt::Display for MseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(_) => write!(f, "couldn't communicate with the peer"),
+            Self::SyncNotFound => write!(f, "the peer's handshake wasn't found after its padding"),
+            Self::InvalidPadLength(length) => write!(f, "invalid padding length: {}", length),
+            Self::UnknownInfoHash => write!(f, "the peer asked for an unknown torrent"),
+            Self::InvalidVerificationConstant => {
+                write!(f, "the peer's handshake couldn't be decrypted")
+            }
+            Self::NoCommonCrypto(provide) => {
+                write!(
+                    f,
+                    "none of the crypto methods offered are allowed: {}",
+                    provide
+                )
+            }
+            Self::InvalidCryptoSelect(select) => {
+                write!(f, "the peer chose an invalid crypto method: {}", select)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// One side of a Message Stream Encryption handshake, with its Diffie-Hellman private key and the random padding sent after its public key.
+///
+/// To create a new `MseHandshake` use the method builder `new()`, and then `initiate()` or `respond()` depending on which side opened the connection.
+#[derive(Debug)]
+pub struct MseHandshake {
+    private_key: [u8; PRIVATE_KEY_LENGTH],
+    pad: Vec<u8>,
+}
+
+impl EncryptionMode {
+    /// Returns the crypto methods offered to peers.
+    pub fn crypto_provide(&self) -> u32 {
+        match self {
+            Self::Disabled => CRYPTO_PLAINTEXT,
+            Self::Enabled => CRYPTO_PLAINTEXT | CRYPTO_RC4,
+            Self::Required => CRYPTO_RC4,
+        }
+    }
+}
+
+impl FromStr for EncryptionMode {
+    type Err = InvalidEncryptionMode;
+
+    /// Parses a mode from its lowercase name: `disabled`, `enabled` or `required`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(Self::Disabled),
+            "enabled" => Ok(Self::Enabled),
+            "required" => Ok(Self::Required),
+            _ => Err(InvalidEncryptionMode),
+        }
+    }
+}
+
+impl Default for MseHandshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MseHandshake {
+    /// Creates a new `MseHandshake` with a random private key and a random padding.
+    pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let pad_length = rng.gen_range(0..=MAX_PAD_LENGTH);
+        Self {
+            private_key: rng.gen(),
+            pad: (0..pad_length).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    /// Negotiates the encryption of a connection we opened to download the torrent with the given info hash, offering the `crypto_provide` methods.
+    ///
+    /// Once it returns the crypto method chosen by the peer, the stream is encrypted if it was RC4 and the BitTorrent handshake can be sent.
+    ///
+    /// # Errors
+    /// - `IoError` if the stream couldn't be read or written.
+    /// - `SyncNotFound` if the peer's answer wasn't found after its padding.
+    /// - `InvalidPadLength` if the peer's padding is longer than allowed.
+    /// - `InvalidCryptoSelect` if the peer didn't choose exactly one of the offered methods.
+    pub fn initiate(
+        self,
+        stream: &mut PeerStream,
+        info_hash: &[u8],
+        crypto_provide: u32,
+    ) -> Result<u32, MseError> {
+        self.send_public_key(stream)?;
+        let secret = self.receive_shared_secret(stream)?;
+
+        let mut encryptor = create_cipher(b"keyA", &secret, info_hash);
+        let mut decryptor = create_cipher(b"keyB", &secret, info_hash);
+
+        let mut message = hash(&[b"req1", &secret]).to_vec();
+        let req2 = hash(&[b"req2", info_hash]);
+        let req3 = hash(&[b"req3", &secret]);
+        message.extend(req2.iter().zip(req3).map(|(a, b)| a ^ b));
+        let mut encrypted = VERIFICATION_CONSTANT.to_vec();
+        encrypted.extend(crypto_provide.to_be_bytes());
+        // No PadC nor initial payload: the BitTorrent handshake is sent once the method is chosen.
+        encrypted.extend(0u16.to_be_bytes());
+        encrypted.extend(0u16.to_be_bytes());
+        encryptor.apply(&mut encrypted);
+        message.extend(encrypted);
+        write(stream, &message)?;
+
+        let mut expected = VERIFICATION_CONSTANT;
+        decryptor.clone().apply(&mut expected);
+        synchronize(stream, &expected, MAX_PAD_LENGTH)?;
+        decryptor.discard(VERIFICATION_CONSTANT.len());
+
+        let select = u32::from_be_bytes(read_decrypted_array(stream, &mut decryptor)?);
+        let pad_length = read_pad_length(stream, &mut decryptor)?;
+        read_decrypted(stream, &mut decryptor, pad_length)?;
+
+        if select.count_ones() != 1 || select & crypto_provide == 0 {
+            return Err(MseError::InvalidCryptoSelect(select));
+        }
+        if select == CRYPTO_RC4 {
+            stream.set_cipher(encryptor, decryptor);
+        }
+        Ok(select)
+    }
+
+    /// Negotiates the encryption of a connection opened by a peer, for one of the torrents with the given info hashes, choosing RC4 over plaintext among the `crypto_allowed` methods.
+    ///
+    /// Once it returns the info hash the peer asked for, the stream is encrypted if RC4 was chosen and the BitTorrent handshake can be received.
+    ///
+    /// # Errors
+    /// - `IoError` if the stream couldn't be read or written.
+    /// - `SyncNotFound` if the peer's request wasn't found after its padding.
+    /// - `UnknownInfoHash` if the peer asked for none of the info hashes.
+    /// - `InvalidVerificationConstant` if the peer's request couldn't be decrypted.
+    /// - `InvalidPadLength` if the peer's padding is longer than allowed.
+    /// - `NoCommonCrypto` if the peer offered none of the allowed methods.
+    pub fn respond(
+        self,
+        stream: &mut PeerStream,
+        info_hashes: &[Vec<u8>],
+        crypto_allowed: u32,
+    ) -> Result<Vec<u8>, MseError> {
+        let secret = self.receive_shared_secret(stream)?;
+        self.send_public_key(stream)?;
+
+        synchronize(stream, &hash(&[b"req1", &secret]), MAX_PAD_LENGTH)?;
+        let req2_xor_req3 = read(stream, HASH_LENGTH)?;
+        let req3 = hash(&[b"req3", &secret]);
+        let req2: Vec<u8> = req2_xor_req3.iter().zip(req3).map(|(a, b)| a ^ b).collect();
+        let info_hash = info_hashes
+            .iter()
+            .find(|info_hash| hash(&[b"req2", info_hash]) == req2.as_slice())
+            .ok_or(MseError::UnknownInfoHash)?;
+
+        let mut encryptor = create_cipher(b"keyB", &secret, info_hash);
+        let mut decryptor = create_cipher(b"keyA", &secret, info_hash);
+
+        if read_decrypted(stream, &mut decryptor, VERIFICATION_CONSTANT.len())?
+            != VERIFICATION_CONSTANT
+        {
+            return Err(MseError::InvalidVerificationConstant);
+        }
+        let provide = u32::from_be_bytes(read_decrypted_array(stream, &mut decryptor)?);
+        let pad_length = read_pad_length(stream, &mut decryptor)?;
+        read_decrypted(stream, &mut decryptor, pad_length)?;
+        let initial_payload_length =
+            u16::from_be_bytes(read_decrypted_array(stream, &mut decryptor)?);
+        let initial_payload =
+            read_decrypted(stream, &mut decryptor, initial_payload_length as usize)?;
+
+        let select = [CRYPTO_RC4, CRYPTO_PLAINTEXT]
+            .into_iter()
+            .find(|method| provide & crypto_allowed & method != 0)
+            .ok_or(MseError::NoCommonCrypto(provide))?;
+
+        let mut message = VERIFICATION_CONSTANT.to_vec();
+        message.extend(select.to_be_bytes());
+        message.extend(0u16.to_be_bytes());
+        encryptor.apply(&mut message);
+        write(stream, &message)?;
+
+        if select == CRYPTO_RC4 {
+            stream.set_cipher(encryptor, decryptor);
+        }
+        stream.unread(&initial_payload);
+        Ok(info_hash.clone())
+    }
+
+    /// Sends our public key followed by the padding.
+    fn send_public_key(&self, stream: &mut PeerStream) -> Result<(), MseError> {
+        let mut message = dh::public_key(&self.private_key).to_vec();
+        message.extend(&self.pad);
+        write(stream, &message)
+    }
+
+    /// Receives the peer's public key and returns the secret shared with it.
+    fn receive_shared_secret(&self, stream: &mut PeerStream) -> Result<[u8; KEY_LENGTH], MseError> {
+        let mut peer_public_key = [0; KEY_LENGTH];
+        stream
+            .read_exact(&mut peer_public_key)
+            .map_err(MseError::IoError)?;
+        Ok(dh::shared_secret(&peer_public_key, &self.private_key))
+    }
+}
+
+/// Returns the SHA1 hash of the concatenated parts.
+fn hash(parts: &[&[u8]]) -> [u8; HASH_LENGTH] {
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Creates the RC4 cipher of one direction of the connection: `keyA` for the data sent by the initiator and `keyB` for the one sent by the responder.
+fn create_cipher(key_name: &[u8], secret: &[u8], info_hash: &[u8]) -> Rc4 {
+    let mut cipher = Rc4::new(&hash(&[key_name, secret, info_hash]));
+    cipher.discard(RC4_DISCARD);
+    cipher
+}
+
+/// Reads byte by byte until the last ones read are the `pattern`, skipping at most `max_skipped` bytes before it.
+///
+/// Bytes are read one at a time so nothing after the pattern is consumed.
+fn synchronize(
+    stream: &mut PeerStream,
+    pattern: &[u8],
+    max_skipped: usize,
+) -> Result<(), MseError> {
+    let mut read_bytes = read(stream, pattern.len())?;
+    while !read_bytes.ends_with(pattern) {
+        if read_bytes.len() >= max_skipped + pattern.len() {
+            return Err(MseError::SyncNotFound);
+        }
+        read_bytes.extend(read(stream, 1)?);
+    }
+    Ok(())
+}
+
+fn read_pad_length(stream: &mut PeerStream, decryptor: &mut Rc4) -> Result<usize, MseError> {
+    let length = u16::from_be_bytes(read_decrypted_array(stream, decryptor)?) as usize;
+    if length > MAX_PAD_LENGTH {
+        return Err(MseError::InvalidPadLength(length));
+    }
+    Ok(length)
+}
+
+fn read_decrypted(
+    stream: &mut PeerStream,
+    decryptor: &mut Rc4,
+    length: usize,
+) -> Result<Vec<u8>, MseError> {
+    let mut bytes = read(stream, length)?;
+    decryptor.apply(&mut bytes);
+    Ok(bytes)
+}
+
+fn read_decrypted_array<const N: usize>(
+    stream: &mut PeerStream,
+    decryptor: &mut Rc4,
+) -> Result<[u8; N], MseError> {
+    let mut bytes = [0; N];
+    stream.read_exact(&mut bytes).map_err(MseError::IoError)?;
+    decryptor.apply(&mut bytes);
+    Ok(bytes)
+}
+
+fn read(stream: &mut PeerStream, length: usize) -> Result<Vec<u8>, MseError> {
+    let mut bytes = vec![0; length];
+    stream.read_exact(&mut bytes).map_err(MseError::IoError)?;
+    Ok(bytes)
+}
+
+fn write(stream: &mut PeerStream, bytes: &[u8]) -> Result<(), MseError> {
+    stream.write_all(bytes).map_err(MseError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use super::*;
+
+    // Vectors computed independently for the private keys below, with empty paddings and `INFO_HASH` as SKEY.
+    const PRIVATE_KEY_A: [u8; 20] = [
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+    ];
+    const PRIVATE_KEY_B: [u8; 20] = [
+        21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40,
+    ];
+    const INFO_HASH: [u8; 20] = [0xab; 20];
+    // Sent by the initiator offering RC4 and plaintext.
+    const REQUEST: &str = "83c8d8a3d91a5bf40d723666c4258731c8427ccb5d2b60d497282fdf9f32c22363701bd9cc7c5f57a9daa7e33accfb976e2448a2b838cb63";
+    // Sent by the responder selecting RC4.
+    const ANSWER: &str = "549a0482af4436f46b4560017735";
+
+    #[test]
+    fn test_initiator_request_and_answer_match_known_vectors() {
+        let (mut initiator, mut responder) = create_test_connection();
+        let responder = thread::spawn(move || {
+            let mut public_key = [0; KEY_LENGTH];
+            responder.read_exact(&mut public_key).unwrap();
+            assert_eq!(public_key, dh::public_key(&PRIVATE_KEY_A));
+            responder
+                .write_all(&dh::public_key(&PRIVATE_KEY_B))
+                .unwrap();
+
+            let mut request = vec![0; REQUEST.len() / 2];
+            responder.read_exact(&mut request).unwrap();
+            responder.write_all(&from_hex(ANSWER)).unwrap();
+            request
+        });
+
+        let select = create_test_handshake(PRIVATE_KEY_A)
+            .initiate(&mut initiator, &INFO_HASH, CRYPTO_PLAINTEXT | CRYPTO_RC4)
+            .unwrap();
+
+        assert_eq!(responder.join().unwrap(), from_hex(REQUEST));
+        assert_eq!(select, CRYPTO_RC4);
+        assert!(initiator.is_encrypted());
+    }
+
+    #[test]
+    fn test_responder_answer_matches_known_vector() {
+        let (mut initiator, mut responder) = create_test_connection();
+        let initiator = thread::spawn(move || {
+            initiator
+                .write_all(&dh::public_key(&PRIVATE_KEY_A))
+                .unwrap();
+            let mut public_key = [0; KEY_LENGTH];
+            initiator.read_exact(&mut public_key).unwrap();
+            assert_eq!(public_key, dh::public_key(&PRIVATE_KEY_B));
+
+            initiator.write_all(&from_hex(REQUEST)).unwrap();
+            let mut answer = vec![0; ANSWER.len() / 2];
+            initiator.read_exact(&mut answer).unwrap();
+            answer
+        });
+
+        let info_hash = create_test_handshake(PRIVATE_KEY_B)
+            .respond(
+                &mut responder,
+                &[vec![0; 20], INFO_HASH.to_vec()],
+                CRYPTO_PLAINTEXT | CRYPTO_RC4,
+            )
+            .unwrap();
+
+        assert_eq!(initiator.join().unwrap(), from_hex(ANSWER));
+        assert_eq!(info_hash, INFO_HASH);
+        assert!(responder.is_encrypted());
+    }
+
+    #[test]
+    fn test_responder_skips_the_pads_and_keeps_the_initial_payload() {
+        let (mut initiator, mut responder) = create_test_connection();
+        thread::spawn(move || {
+            let mut message = dh::public_key(&PRIVATE_KEY_A).to_vec();
+            message.extend([7; 100]);
+            initiator.write_all(&message).unwrap();
+            let mut public_key = [0; KEY_LENGTH];
+            initiator.read_exact(&mut public_key).unwrap();
+            let secret = dh::shared_secret(&public_key, &PRIVATE_KEY_A);
+
+            let mut message = hash(&[b"req1", &secret]).to_vec();
+            let req3 = hash(&[b"req3", &secret]);
+            message.extend(
+                hash(&[b"req2", &INFO_HASH])
+                    .iter()
+                    .zip(req3)
+                    .map(|(a, b)| a ^ b),
+            );
+            let mut encrypted = VERIFICATION_CONSTANT.to_vec();
+            encrypted.extend(CRYPTO_RC4.to_be_bytes());
+            encrypted.extend([0, 3, 1, 2, 3]);
+            encrypted.extend([0, 5]);
+            encrypted.extend(b"hello");
+            create_cipher(b"keyA", &secret, &INFO_HASH).apply(&mut encrypted);
+            message.extend(encrypted);
+            initiator.write_all(&message).unwrap();
+            initiator.read_exact(&mut [0; 14]).unwrap();
+        });
+
+        create_test_handshake(PRIVATE_KEY_B)
+            .respond(&mut responder, &[INFO_HASH.to_vec()], CRYPTO_RC4)
+            .unwrap();
+
+        let mut initial_payload = [0; 5];
+        responder.read_exact(&mut initial_payload).unwrap();
+        assert_eq!(&initial_payload, b"hello");
+    }
+
+    #[test]
+    fn test_encrypted_connection() {
+        let (mut initiator, mut responder) = create_test_connection();
+        let responder = thread::spawn(move || {
+            MseHandshake::new()
+                .respond(
+                    &mut responder,
+                    &[INFO_HASH.to_vec()],
+                    CRYPTO_PLAINTEXT | CRYPTO_RC4,
+                )
+                .unwrap();
+            responder.write_all(b"world").unwrap();
+            let mut received = [0; 5];
+            responder.read_exact(&mut received).unwrap();
+            received
+        });
+
+        let select = MseHandshake::new()
+            .initiate(&mut initiator, &INFO_HASH, CRYPTO_PLAINTEXT | CRYPTO_RC4)
+            .unwrap();
+        initiator.write_all(b"hello").unwrap();
+        let mut received = [0; 5];
+        initiator.read_exact(&mut received).unwrap();
+
+        assert_eq!(select, CRYPTO_RC4);
+        assert_eq!(&received, b"world");
+        assert_eq!(&responder.join().unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_plaintext_is_selected_if_it_is_the_only_one_offered() {
+        let (mut initiator, mut responder) = create_test_connection();
+        let responder = thread::spawn(move || {
+            MseHandshake::new()
+                .respond(
+                    &mut responder,
+                    &[INFO_HASH.to_vec()],
+                    CRYPTO_PLAINTEXT | CRYPTO_RC4,
+                )
+                .unwrap();
+            responder
+        });
+
+        let select = MseHandshake::new()
+            .initiate(&mut initiator, &INFO_HASH, CRYPTO_PLAINTEXT)
+            .unwrap();
+
+        assert_eq!(select, CRYPTO_PLAINTEXT);
+        assert!(!initiator.is_encrypted());
+        assert!(!responder.join().unwrap().is_encrypted());
+    }
+
+    #[test]
+    fn test_no_common_crypto() {
+        let (mut initiator, mut responder) = create_test_connection();
+        let initiator = thread::spawn(move || {
+            MseHandshake::new().initiate(&mut initiator, &INFO_HASH, CRYPTO_PLAINTEXT)
+        });
+
+        let result = MseHandshake::new().respond(&mut responder, &[INFO_HASH.to_vec()], CRYPTO_RC4);
+        drop(responder);
+
+        assert!(matches!(
+            result,
+            Err(MseError::NoCommonCrypto(CRYPTO_PLAINTEXT))
+        ));
+        assert!(initiator.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_initiator_rejects_a_crypto_select_not_provided() {
+        let (mut initiator, mut responder) = create_test_connection();
+        thread::spawn(move || {
+            let mut public_key = [0; KEY_LENGTH];
+            responder.read_exact(&mut public_key).unwrap();
+            responder
+                .write_all(&dh::public_key(&PRIVATE_KEY_B))
+                .unwrap();
+            let secret = dh::shared_secret(&public_key, &PRIVATE_KEY_B);
+            responder.read_exact(&mut [0; 56]).unwrap();
+
+            let mut answer = VERIFICATION_CONSTANT.to_vec();
+            answer.extend(CRYPTO_RC4.to_be_bytes());
+            answer.extend([0, 0]);
+            create_cipher(b"keyB", &secret, &INFO_HASH).apply(&mut answer);
+            responder.write_all(&answer).unwrap();
+            // Waits for the initiator to close the connection, so it isn't reset.
+            responder.read_to_end(&mut Vec::new()).unwrap();
+        });
+
+        let result = create_test_handshake(PRIVATE_KEY_A).initiate(
+            &mut initiator,
+            &INFO_HASH,
+            CRYPTO_PLAINTEXT,
+        );
+
+        assert!(matches!(
+            result,
+            Err(MseError::InvalidCryptoSelect(CRYPTO_RC4))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_info_hash() {
+        let (mut initiator, mut responder) = create_test_connection();
+        thread::spawn(move || {
+            let _ = MseHandshake::new().initiate(&mut initiator, &INFO_HASH, CRYPTO_RC4);
+        });
+
+        let result = MseHandshake::new().respond(&mut responder, &[vec![0; 20]], CRYPTO_RC4);
+
+        assert!(matches!(result, Err(MseError::UnknownInfoHash)));
+    }
+
+    #[test]
+    fn test_sync_not_found_after_the_longest_pad() {
+        let (mut initiator, mut responder) = create_test_connection();
+        thread::spawn(move || {
+            let mut message = dh::public_key(&PRIVATE_KEY_A).to_vec();
+            message.extend([7; MAX_PAD_LENGTH + HASH_LENGTH + 1]);
+            initiator.write_all(&message).unwrap();
+            initiator.read_exact(&mut [0; KEY_LENGTH]).unwrap();
+        });
+
+        let result = create_test_handshake(PRIVATE_KEY_B).respond(
+            &mut responder,
+            &[INFO_HASH.to_vec()],
+            CRYPTO_RC4,
+        );
+
+        assert!(matches!(result, Err(MseError::SyncNotFound)));
+    }
+
+    #[test]
+    fn test_parse_encryption_modes() {
+        assert_eq!("disabled".parse(), Ok(EncryptionMode::Disabled));
+        assert_eq!("enabled".parse(), Ok(EncryptionMode::Enabled));
+        assert_eq!("required".parse(), Ok(EncryptionMode::Required));
+        assert_eq!("rc4".parse::<EncryptionMode>(), Err(InvalidEncryptionMode));
+    }
+
+    #[test]
+    fn test_crypto_provide_of_each_mode() {
+        assert_eq!(EncryptionMode::Disabled.crypto_provide(), CRYPTO_PLAINTEXT);
+        assert_eq!(
+            EncryptionMode::Enabled.crypto_provide(),
+            CRYPTO_PLAINTEXT | CRYPTO_RC4
+        );
+        assert_eq!(EncryptionMode::Required.crypto_provide(), CRYPTO_RC4);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_handshake(private_key: [u8; PRIVATE_KEY_LENGTH]) -> MseHandshake {
+        MseHandshake {
+            private_key,
+            pad: Vec::new(),
+        }
+    }
+
+    /// Returns both ends of a local connection: the initiator and the responder.
+    fn create_test_connection() -> (PeerStream, PeerStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let initiator = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (responder, _) = listener.accept().unwrap();
+        (PeerStream::new(initiator), PeerStream::new(responder))
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+}
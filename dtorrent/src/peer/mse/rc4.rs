@@ -0,0 +1,82 @@
+/// RC4 stream cipher. Encrypting and decrypting are the same operation: XORing with the keystream.
+#[derive(Debug, Clone)]
+pub struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    /// Creates a new `Rc4` cipher from a key of 1 to 256 bytes.
+    pub fn new(key: &[u8]) -> Self {
+        let mut state = [0; 256];
+        for (i, byte) in state.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+        Self { state, i: 0, j: 0 }
+    }
+
+    /// Encrypts or decrypts the data in place.
+    pub fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte ^= self.next_byte();
+        }
+    }
+
+    /// Skips the next `length` bytes of the keystream.
+    pub fn discard(&mut self, length: usize) {
+        for _ in 0..length {
+            self.next_byte();
+        }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        self.i = self.i.wrapping_add(1);
+        self.j = self.j.wrapping_add(self.state[self.i as usize]);
+        self.state.swap(self.i as usize, self.j as usize);
+        let index = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+        self.state[index as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_keystream() {
+        let mut data = b"Plaintext".to_vec();
+
+        Rc4::new(b"Key").apply(&mut data);
+
+        assert_eq!(data, [0xbb, 0xf3, 0x16, 0xe8, 0xd9, 0x40, 0xaf, 0x0a, 0xd3]);
+    }
+
+    #[test]
+    fn test_decrypts_what_it_encrypts() {
+        let mut data = b"Attack at dawn".to_vec();
+
+        Rc4::new(b"Secret").apply(&mut data);
+        Rc4::new(b"Secret").apply(&mut data);
+
+        assert_eq!(data, b"Attack at dawn");
+    }
+
+    #[test]
+    fn test_discard_skips_the_keystream() {
+        let mut discarded = Rc4::new(b"Key");
+        discarded.discard(4);
+        let mut data = [0; 5];
+        discarded.apply(&mut data);
+
+        let mut keystream = [0; 9];
+        Rc4::new(b"Key").apply(&mut keystream);
+
+        assert_eq!(data, keystream[4..]);
+    }
+}
@@ -1,6 +1,33 @@
-use std::collections::HashMap;
-
 use crate::torrent_handler::status::PieceStatus;
+use std::fmt;
+
+/// Bitfield possible errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BitfieldError {
+    TooShort { expected: usize, received: usize },
+    TooLong { expected: usize, received: usize },
+    SpareBitsSet,
+}
+
+impl fmt::Display for BitfieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort { expected, received } => write!(
+                f,
+                "bitfield too short: expected {} bytes, received {}",
+                expected, received
+            ),
+            Self::TooLong { expected, received } => write!(
+                f,
+                "bitfield too long: expected {} bytes, received {}",
+                expected, received
+            ),
+            Self::SpareBitsSet => write!(f, "bitfield has spare bits set"),
+        }
+    }
+}
+
+impl std::error::Error for BitfieldError {}
 
 /// Represents a Bitfield.
 ///
@@ -15,10 +42,69 @@ impl Bitfield {
         Bitfield { bitfield }
     }
 
+    /// Creates a bitfield without any piece for a torrent with `total_pieces` pieces, with the spare bits after the last piece cleared.
+    pub fn empty(total_pieces: u32) -> Bitfield {
+        Self::new(vec![0; Self::bytes_for(total_pieces)])
+    }
+
+    /// Creates a bitfield with every piece of a torrent with `total_pieces` pieces, with the spare bits after the last piece cleared.
+    pub fn full(total_pieces: u32) -> Bitfield {
+        let mut bitfield = Self::empty(total_pieces);
+        for index in 0..total_pieces {
+            bitfield.set_bit(index, true);
+        }
+        bitfield
+    }
+
+    /// Returns the number of pieces the bitfield can hold, which includes the spare bits of its last byte.
+    pub fn len_pieces(&self) -> u32 {
+        self.bitfield.len() as u32 * 8
+    }
+
+    /// Creates a bitfield from the bytes received from a peer, for a torrent with `total_pieces` pieces.
+    ///
+    /// It returns a `BitfieldError` if:
+    /// - There are fewer or more than `ceil(total_pieces / 8)` bytes.
+    /// - Any of the spare bits after the last piece is set.
+    pub fn from_received(bytes: Vec<u8>, total_pieces: u32) -> Result<Bitfield, BitfieldError> {
+        let expected = Self::bytes_for(total_pieces);
+        if bytes.len() < expected {
+            return Err(BitfieldError::TooShort {
+                expected,
+                received: bytes.len(),
+            });
+        }
+        if bytes.len() > expected {
+            return Err(BitfieldError::TooLong {
+                expected,
+                received: bytes.len(),
+            });
+        }
+
+        let spare_bits = expected * 8 - total_pieces as usize;
+        if let Some(last_byte) = bytes.last() {
+            let spare_mask = ((1u16 << spare_bits) - 1) as u8;
+            if last_byte & spare_mask != 0 {
+                return Err(BitfieldError::SpareBitsSet);
+            }
+        }
+        Ok(Self::new(bytes))
+    }
+
+    /// Returns the number of bytes needed to represent `total_pieces` pieces.
+    pub fn bytes_for(total_pieces: u32) -> usize {
+        (total_pieces as usize).div_ceil(8)
+    }
+
     /// Returns whether the bitfield has the piece with the given index.
+    ///
+    /// Indices beyond the end of the bitfield are not present.
     pub fn has_piece(&self, index: u32) -> bool {
         let byte_index = (index / 8) as usize;
-        let byte = self.bitfield[byte_index];
+        let byte = match self.bitfield.get(byte_index) {
+            Some(byte) => *byte,
+            None => return false,
+        };
 
         let bit_index = 7 - (index % 8); // Gets the bit index in the byte (from the right)
 
@@ -33,20 +119,48 @@ impl Bitfield {
         self.bitfield.iter().all(|byte| *byte == 0b1111_1111)
     }
 
-    /// Creates a bitfield from pieces status
-    pub fn from(pieces_status: &HashMap<u32, PieceStatus>) -> Bitfield {
-        let bytes_count = (pieces_status.len() + 7) / 8;
-        let mut bitfield = vec![0; bytes_count];
+    /// Returns the number of pieces set in the bitfield, of a torrent with `total_pieces` pieces.
+    ///
+    /// The spare bits beyond `total_pieces` are ignored.
+    pub fn count_pieces(&self, total_pieces: u32) -> u32 {
+        let full_bytes = (total_pieces / 8) as usize;
+        let mut pieces = self
+            .bitfield
+            .iter()
+            .take(full_bytes)
+            .map(|byte| byte.count_ones())
+            .sum();
+
+        let pieces_in_last_byte = total_pieces % 8;
+        if let Some(last_byte) = self.bitfield.get(full_bytes) {
+            if pieces_in_last_byte != 0 {
+                pieces += (last_byte & !(0b1111_1111 >> pieces_in_last_byte)).count_ones();
+            }
+        }
+        pieces
+    }
 
-        for (piece_index, status) in pieces_status {
-            if status == &PieceStatus::Finished {
-                let byte_index = (piece_index / 8) as usize;
-                let byte = bitfield[byte_index];
+    /// Returns the ratio between the pieces set in the bitfield and `total_pieces`, from 0 to 1.
+    ///
+    /// The spare bits beyond `total_pieces` are ignored.
+    pub fn completeness(&self, total_pieces: u32) -> f32 {
+        if total_pieces == 0 {
+            return 0.0;
+        }
+        let pieces = (0..total_pieces)
+            .filter(|index| self.has_piece(*index))
+            .count();
+        pieces as f32 / total_pieces as f32
+    }
 
-                let bit_index = 7 - (piece_index % 8); // Gets the bit index in the byte (from the right)
-                let bit = 1 << bit_index; // Shifts 1 to the left bit_index times
+    /// Creates a bitfield from pieces status, indexed by piece number.
+    pub fn from(pieces_status: &[PieceStatus]) -> Bitfield {
+        let mut bitfield = vec![0; pieces_status.len().div_ceil(8)];
 
-                bitfield[byte_index] = byte | bit;
+        for (piece_index, status) in pieces_status.iter().enumerate() {
+            if status == &PieceStatus::Finished {
+                let bit_index = 7 - (piece_index % 8); // Gets the bit index in the byte (from the right)
+                bitfield[piece_index / 8] |= 1 << bit_index;
             }
         }
 
@@ -74,9 +188,14 @@ impl Bitfield {
     }
 
     /// Sets the indexth bit to the given value.
+    ///
+    /// Indices beyond the end of the bitfield are ignored.
     pub fn set_bit(&mut self, index: u32, value: bool) {
         let byte_index = (index / 8) as usize;
-        let byte = self.bitfield[byte_index];
+        let byte = match self.bitfield.get(byte_index) {
+            Some(byte) => *byte,
+            None => return,
+        };
 
         let bit_index = 7 - (index % 8); // Gets the bit index in the byte (from the right)
         let bit = 1 << bit_index; // Shifts 1 to the left bit_index times
@@ -118,14 +237,155 @@ mod tests {
         assert!(!bitfield.has_piece(22));
     }
 
+    #[test]
+    fn test_bitfield_has_piece_past_the_end() {
+        let bitfield = Bitfield::new(vec![0b11111111]);
+
+        assert!(!bitfield.has_piece(8));
+        assert!(!bitfield.has_piece(u32::MAX));
+    }
+
+    #[test]
+    fn test_set_bit_past_the_end_is_ignored() {
+        let mut bitfield = Bitfield::new(vec![0b00000000]);
+
+        bitfield.set_bit(20, true);
+
+        assert_eq!(bitfield.get_vec(), vec![0b00000000]);
+    }
+
+    #[test]
+    fn test_empty_with_non_byte_aligned_pieces() {
+        let bitfield = Bitfield::empty(10);
+
+        assert_eq!(bitfield.get_vec(), vec![0, 0]);
+        assert_eq!(bitfield.len_pieces(), 16);
+        assert_eq!(bitfield.count_pieces(16), 0);
+    }
+
+    #[test]
+    fn test_full_with_non_byte_aligned_pieces() {
+        let bitfield = Bitfield::full(10);
+
+        assert_eq!(bitfield.get_vec(), vec![0b11111111, 0b11000000]);
+        assert_eq!(bitfield.count_pieces(10), 10);
+        assert!(Bitfield::from_received(bitfield.get_vec(), 10).is_ok());
+    }
+
+    #[test]
+    fn test_empty_with_byte_aligned_pieces() {
+        let bitfield = Bitfield::empty(16);
+
+        assert_eq!(bitfield.get_vec(), vec![0, 0]);
+        assert_eq!(bitfield.len_pieces(), 16);
+    }
+
+    #[test]
+    fn test_empty_without_pieces() {
+        let bitfield = Bitfield::empty(0);
+
+        assert!(bitfield.get_vec().is_empty());
+        assert_eq!(bitfield.len_pieces(), 0);
+    }
+
+    #[test]
+    fn test_empty_is_accepted_as_received() {
+        let bitfield = Bitfield::empty(10);
+
+        assert!(Bitfield::from_received(bitfield.get_vec(), 10).is_ok());
+    }
+
+    #[test]
+    fn test_from_received_too_short() {
+        assert_eq!(
+            Bitfield::from_received(vec![0b11111111], 9).unwrap_err(),
+            BitfieldError::TooShort {
+                expected: 2,
+                received: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_received_too_long() {
+        assert_eq!(
+            Bitfield::from_received(vec![0b11111111, 0b00000000], 8).unwrap_err(),
+            BitfieldError::TooLong {
+                expected: 1,
+                received: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_received_with_spare_bits_set() {
+        assert_eq!(
+            Bitfield::from_received(vec![0b11111111, 0b10100000], 10).unwrap_err(),
+            BitfieldError::SpareBitsSet
+        );
+    }
+
+    #[test]
+    fn test_from_received_with_byte_aligned_pieces() {
+        let bitfield = Bitfield::from_received(vec![0b11111111, 0b11111111], 16).unwrap();
+
+        assert!(bitfield.has_piece(15));
+    }
+
+    #[test]
+    fn test_from_received_with_enough_bytes() {
+        let bitfield = Bitfield::from_received(vec![0b11111111, 0b10000000], 9).unwrap();
+
+        assert!(bitfield.has_piece(8));
+    }
+
+    #[test]
+    fn test_count_pieces_partially_filled() {
+        let bitfield = Bitfield::new(vec![0b10100000, 0b00000001]);
+
+        assert_eq!(bitfield.count_pieces(16), 3);
+        assert_eq!(bitfield.completeness(16), 3.0 / 16.0);
+    }
+
+    #[test]
+    fn test_count_pieces_all_set_with_non_byte_aligned_pieces() {
+        let bitfield = Bitfield::from_received(vec![0b11111111, 0b11000000], 10).unwrap();
+
+        assert_eq!(bitfield.count_pieces(10), 10);
+        assert_eq!(bitfield.completeness(10), 1.0);
+    }
+
+    #[test]
+    fn test_count_pieces_ignores_spare_bits() {
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+
+        assert_eq!(bitfield.count_pieces(10), 10);
+    }
+
+    #[test]
+    fn test_count_pieces_of_a_short_bitfield() {
+        let bitfield = Bitfield::new(vec![0b11111111]);
+
+        assert_eq!(bitfield.count_pieces(16), 8);
+    }
+
+    #[test]
+    fn test_completeness_ignores_spare_bits() {
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+
+        assert_eq!(bitfield.completeness(10), 1.0);
+    }
+
+    #[test]
+    fn test_completeness_without_pieces() {
+        assert_eq!(Bitfield::new(vec![]).completeness(0), 0.0);
+    }
+
     #[test]
     fn test_bitfield_from_one_piece_finished() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..8 {
-            pieces_status.insert(i, PieceStatus::Free);
-        }
+        let mut pieces_status = vec![PieceStatus::Free; 8];
 
-        pieces_status.insert(0, PieceStatus::Finished);
+        pieces_status[0] = PieceStatus::Finished;
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -134,12 +394,9 @@ mod tests {
 
     #[test]
     fn test_bitfield_from_one_piece_finished_in_the_middle() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..8 {
-            pieces_status.insert(i, PieceStatus::Free);
-        }
+        let mut pieces_status = vec![PieceStatus::Free; 8];
 
-        pieces_status.insert(3, PieceStatus::Finished);
+        pieces_status[3] = PieceStatus::Finished;
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -148,10 +405,7 @@ mod tests {
 
     #[test]
     fn test_bitfield_from_all_pieces_finished() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..8 {
-            pieces_status.insert(i, PieceStatus::Finished);
-        }
+        let pieces_status = vec![PieceStatus::Finished; 8];
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -160,10 +414,7 @@ mod tests {
 
     #[test]
     fn test_from_two_bytes() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..9 {
-            pieces_status.insert(i, PieceStatus::Finished);
-        }
+        let pieces_status = vec![PieceStatus::Finished; 9];
 
         let bitfield = Bitfield::from(&pieces_status);
 
@@ -172,16 +423,28 @@ mod tests {
 
     #[test]
     fn test_from_two_bytes_complete() {
-        let mut pieces_status = HashMap::new();
-        for i in 0..16 {
-            pieces_status.insert(i, PieceStatus::Finished);
-        }
+        let pieces_status = vec![PieceStatus::Finished; 16];
 
         let bitfield = Bitfield::from(&pieces_status);
 
         assert_eq!(bitfield.get_vec(), vec![0b1111_1111, 0b1111_1111]);
     }
 
+    #[test]
+    fn test_bitfield_from_downloading_pieces() {
+        let pieces_status = vec![
+            PieceStatus::Finished,
+            PieceStatus::Downloading,
+            PieceStatus::Free,
+            PieceStatus::Finished,
+            PieceStatus::Downloading,
+        ];
+
+        let bitfield = Bitfield::from(&pieces_status);
+
+        assert_eq!(bitfield.get_vec(), vec![0b1001_0000]);
+    }
+
     #[test]
     fn test_diff() {
         let bitfield1 = Bitfield::new(vec![0b11111100, 0b11111111]);
@@ -195,7 +458,7 @@ mod tests {
         let bitfield1 = Bitfield::new(vec![0b11111100, 0b11111111]);
         let bitfield2 = Bitfield::new(vec![0b11111100, 0b11111111]);
 
-        assert_eq!(bitfield2.diff(&bitfield1), vec![]);
+        assert_eq!(bitfield2.diff(&bitfield1), Vec::<usize>::new());
     }
 
     #[test]
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use bencoder::bencode::Bencode;
+
+use super::metadata::{UT_METADATA, UT_METADATA_ID};
+
+/// Extended message ID reserved for the extension protocol handshake.
+pub const EXTENDED_HANDSHAKE_ID: u8 = 0;
+
+/// Extensions supported by the client, by name and the extended message ID we want to receive them with.
+pub const SUPPORTED_EXTENSIONS: &[(&str, u8)] = &[(UT_METADATA, UT_METADATA_ID)];
+
+/// Name the client advertises in the `v` key of the extended handshake.
+const CLIENT_VERSION: &str = "dTorrent";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExtendedMessageError {
+    EmptyPayload,
+    InvalidBencode,
+    NotADict,
+}
+
+impl fmt::Display for ExtendedMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyPayload => write!(f, "extended message without payload"),
+            Self::InvalidBencode => write!(f, "extended message payload is not valid bencode"),
+            Self::NotADict => write!(f, "extended message payload is not a dict"),
+        }
+    }
+}
+
+impl std::error::Error for ExtendedMessageError {}
+
+/// An extended message as defined in the extension protocol (BEP 10).
+///
+/// It's sent with message ID 20, and its payload starts with the extended message ID followed by a bencoded dictionary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedMessage {
+    pub ext_id: u8,
+    pub payload: Bencode,
+}
+
+impl ExtendedMessage {
+    /// Creates a new `ExtendedMessage` from an extended message ID and a bencoded payload.
+    pub fn new(ext_id: u8, payload: Bencode) -> Self {
+        Self { ext_id, payload }
+    }
+
+    /// Creates the extended handshake advertising the given extensions.
+    ///
+    /// Each extension is a pair of its name and the extended message ID we want to receive it with.
+    /// If given, the `metadata_size` is advertised for the metadata exchange extension.
+    pub fn handshake(extensions: &[(&str, u8)], metadata_size: Option<usize>) -> Self {
+        let mut supported = BTreeMap::new();
+        for (name, id) in extensions {
+            supported.insert(name.as_bytes().to_vec(), Bencode::BNumber(*id as i64));
+        }
+
+        let mut dict = BTreeMap::new();
+        dict.insert(b"m".to_vec(), Bencode::BDict(supported));
+        if let Some(metadata_size) = metadata_size {
+            dict.insert(
+                b"metadata_size".to_vec(),
+                Bencode::BNumber(metadata_size as i64),
+            );
+        }
+        dict.insert(
+            b"v".to_vec(),
+            Bencode::BString(CLIENT_VERSION.as_bytes().to_vec()),
+        );
+
+        Self::new(EXTENDED_HANDSHAKE_ID, Bencode::BDict(dict))
+    }
+
+    /// Returns true if the message is an extended handshake.
+    pub fn is_handshake(&self) -> bool {
+        self.ext_id == EXTENDED_HANDSHAKE_ID
+    }
+
+    /// Returns the extensions advertised in the `m` dictionary of an extended handshake, by name and extended message ID.
+    ///
+    /// Entries with an invalid ID are ignored.
+    pub fn supported_extensions(&self) -> HashMap<String, u8> {
+        let mut extensions = HashMap::new();
+
+        let dict = match &self.payload {
+            Bencode::BDict(dict) => dict,
+            _ => return extensions,
+        };
+        let supported = match dict.get(&b"m".to_vec()) {
+            Some(Bencode::BDict(supported)) => supported,
+            _ => return extensions,
+        };
+
+        for (name, id) in supported {
+            if let (Ok(name), Bencode::BNumber(id)) = (String::from_utf8(name.clone()), id) {
+                if let Ok(id) = u8::try_from(*id) {
+                    extensions.insert(name, id);
+                }
+            }
+        }
+        extensions
+    }
+
+    /// Returns the `metadata_size` advertised in an extended handshake, if any.
+    pub fn metadata_size(&self) -> Option<usize> {
+        match &self.payload {
+            Bencode::BDict(dict) => match dict.get(&b"metadata_size".to_vec()) {
+                Some(Bencode::BNumber(size)) => usize::try_from(*size).ok(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses the payload of a message with ID 20 into an `ExtendedMessage`.
+    ///
+    /// It returns an `ExtendedMessageError` if:
+    /// - The payload is empty.
+    /// - The payload is not a valid bencoded dictionary.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExtendedMessageError> {
+        if bytes.is_empty() {
+            return Err(ExtendedMessageError::EmptyPayload);
+        }
+
+        let ext_id = bytes[0];
+        let payload =
+            Bencode::decode(&bytes[1..]).map_err(|_| ExtendedMessageError::InvalidBencode)?;
+
+        match payload {
+            Bencode::BDict(_) => Ok(Self { ext_id, payload }),
+            _ => Err(ExtendedMessageError::NotADict),
+        }
+    }
+
+    /// Converts an `ExtendedMessage` to the payload of a message with ID 20.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.ext_id];
+        bytes.extend(Bencode::encode(&self.payload));
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_as_bytes() {
+        let handshake = ExtendedMessage::handshake(&[("ut_metadata", 3)], None);
+
+        let bytes = handshake.as_bytes();
+
+        assert_eq!(bytes[0], EXTENDED_HANDSHAKE_ID);
+        assert_eq!(&bytes[1..], b"d1:md11:ut_metadatai3ee1:v8:dTorrente");
+    }
+
+    #[test]
+    fn test_handshake_from_bytes() {
+        let mut bytes = vec![0];
+        bytes.extend(b"d1:md6:ut_pexi1e11:ut_metadatai2ee1:v4:teste");
+
+        let message = ExtendedMessage::from_bytes(&bytes).unwrap();
+
+        assert!(message.is_handshake());
+        let extensions = message.supported_extensions();
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions.get("ut_metadata"), Some(&2));
+        assert_eq!(extensions.get("ut_pex"), Some(&1));
+    }
+
+    #[test]
+    fn test_handshake_roundtrip() {
+        let handshake =
+            ExtendedMessage::handshake(&[("ut_metadata", 1), ("ut_pex", 2)], Some(1234));
+
+        let message = ExtendedMessage::from_bytes(&handshake.as_bytes()).unwrap();
+
+        assert_eq!(message, handshake);
+        assert_eq!(message.metadata_size(), Some(1234));
+    }
+
+    #[test]
+    fn test_supported_extensions_ignores_invalid_ids() {
+        let mut bytes = vec![0];
+        bytes.extend(b"d1:md6:ut_pexi300e11:ut_metadatai2eee");
+
+        let message = ExtendedMessage::from_bytes(&bytes).unwrap();
+
+        let extensions = message.supported_extensions();
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions.get("ut_metadata"), Some(&2));
+    }
+
+    #[test]
+    fn test_from_bytes_empty_payload() {
+        assert_eq!(
+            ExtendedMessage::from_bytes(&[]),
+            Err(ExtendedMessageError::EmptyPayload)
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_not_a_dict() {
+        let mut bytes = vec![1];
+        bytes.extend(b"i12e");
+
+        assert_eq!(
+            ExtendedMessage::from_bytes(&bytes),
+            Err(ExtendedMessageError::NotADict)
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_truncated_payload() {
+        for truncated in [&b"l"[..], b"i12", b"5:ab", b"d1:md", b"d1:m"] {
+            let mut bytes = vec![0];
+            bytes.extend(truncated);
+
+            assert_eq!(
+                ExtendedMessage::from_bytes(&bytes),
+                Err(ExtendedMessageError::InvalidBencode)
+            );
+        }
+    }
+}
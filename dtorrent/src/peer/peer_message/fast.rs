@@ -0,0 +1,206 @@
+use std::fmt;
+
+use super::{
+    message::{Message, MessageId},
+    request::{Request, RequestError},
+};
+
+/// Length of the payload of the fast extension messages with a piece index.
+const PIECE_INDEX_LENGTH: usize = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FastMessageError {
+    NotAFastMessage(MessageId),
+    InvalidLength { expected: usize, received: usize },
+    InvalidRequest(RequestError),
+}
+
+impl fmt::Display for FastMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAFastMessage(id) => write!(f, "{:?} is not a fast extension message", id),
+            Self::InvalidLength { expected, received } => write!(
+                f,
+                "fast extension message payload must be {} bytes, received {}",
+                expected, received
+            ),
+            Self::InvalidRequest(_) => write!(f, "invalid rejected request"),
+        }
+    }
+}
+
+impl std::error::Error for FastMessageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidRequest(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A message of the fast extension (BEP 6).
+///
+/// They can only be sent by peers that set the fast extension bit in their handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FastMessage {
+    /// The peer suggests downloading the piece with the given index.
+    SuggestPiece(u32),
+    /// The peer has every piece, sent instead of a bitfield.
+    HaveAll,
+    /// The peer has no pieces, sent instead of a bitfield.
+    HaveNone,
+    /// The peer won't send the block of a request.
+    RejectRequest(Request),
+    /// The piece with the given index can be requested even while the peer chokes us.
+    AllowedFast(u32),
+}
+
+impl FastMessage {
+    /// Parses a fast extension message.
+    ///
+    /// It returns a `FastMessageError` if:
+    /// - The message is not one of the fast extension.
+    /// - The payload doesn't have the length of the message.
+    pub fn from_message(message: &Message) -> Result<Self, FastMessageError> {
+        match message.id {
+            MessageId::SuggestPiece => Ok(Self::SuggestPiece(Self::piece_index(message)?)),
+            MessageId::HaveAll => Self::without_payload(message, Self::HaveAll),
+            MessageId::HaveNone => Self::without_payload(message, Self::HaveNone),
+            MessageId::RejectRequest => Ok(Self::RejectRequest(
+                Request::from_bytes(&message.payload).map_err(FastMessageError::InvalidRequest)?,
+            )),
+            MessageId::AllowedFast => Ok(Self::AllowedFast(Self::piece_index(message)?)),
+            _ => Err(FastMessageError::NotAFastMessage(message.id.clone())),
+        }
+    }
+
+    fn without_payload(message: &Message, fast_message: Self) -> Result<Self, FastMessageError> {
+        if !message.payload.is_empty() {
+            return Err(FastMessageError::InvalidLength {
+                expected: 0,
+                received: message.payload.len(),
+            });
+        }
+        Ok(fast_message)
+    }
+
+    fn piece_index(message: &Message) -> Result<u32, FastMessageError> {
+        let index: [u8; PIECE_INDEX_LENGTH] =
+            message
+                .payload
+                .as_slice()
+                .try_into()
+                .map_err(|_| FastMessageError::InvalidLength {
+                    expected: PIECE_INDEX_LENGTH,
+                    received: message.payload.len(),
+                })?;
+        Ok(u32::from_be_bytes(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest_piece() {
+        let message = Message::new(MessageId::SuggestPiece, 7u32.to_be_bytes().to_vec());
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap(),
+            FastMessage::SuggestPiece(7)
+        );
+    }
+
+    #[test]
+    fn test_have_all() {
+        let message = Message::new(MessageId::HaveAll, vec![]);
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap(),
+            FastMessage::HaveAll
+        );
+    }
+
+    #[test]
+    fn test_have_none() {
+        let message = Message::new(MessageId::HaveNone, vec![]);
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap(),
+            FastMessage::HaveNone
+        );
+    }
+
+    #[test]
+    fn test_reject_request() {
+        let request = Request::new(2, 16384, 16384);
+        let message = Message::new(MessageId::RejectRequest, request.as_bytes());
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap(),
+            FastMessage::RejectRequest(request)
+        );
+    }
+
+    #[test]
+    fn test_allowed_fast() {
+        let message = Message::new(MessageId::AllowedFast, 1u32.to_be_bytes().to_vec());
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap(),
+            FastMessage::AllowedFast(1)
+        );
+    }
+
+    #[test]
+    fn test_parsed_from_bytes() {
+        let bytes = Message::new(MessageId::AllowedFast, 5u32.to_be_bytes().to_vec()).as_bytes();
+        let message = Message::from_bytes(&bytes[4..]).unwrap();
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap(),
+            FastMessage::AllowedFast(5)
+        );
+    }
+
+    #[test]
+    fn test_piece_index_with_invalid_length() {
+        let message = Message::new(MessageId::SuggestPiece, vec![0, 0, 1]);
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap_err(),
+            FastMessageError::InvalidLength {
+                expected: 4,
+                received: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_have_all_with_payload() {
+        let message = Message::new(MessageId::HaveAll, vec![1]);
+
+        assert!(FastMessage::from_message(&message).is_err());
+    }
+
+    #[test]
+    fn test_reject_request_with_invalid_length() {
+        let message = Message::new(MessageId::RejectRequest, vec![0; 8]);
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap_err(),
+            FastMessageError::InvalidRequest(RequestError::InvalidLength(8))
+        );
+    }
+
+    #[test]
+    fn test_not_a_fast_message() {
+        let message = Message::new(MessageId::Have, 1u32.to_be_bytes().to_vec());
+
+        assert_eq!(
+            FastMessage::from_message(&message).unwrap_err(),
+            FastMessageError::NotAFastMessage(MessageId::Have)
+        );
+    }
+}
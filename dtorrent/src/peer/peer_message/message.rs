@@ -1,5 +1,7 @@
+use std::fmt;
+
 // IDs of the messages defined in the protocol.
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum MessageId {
     KeepAlive = -1,
     Choke = 0,
@@ -12,6 +14,13 @@ pub enum MessageId {
     Piece = 7,
     Cancel = 8,
     Port = 9,
+    // Messages of the fast extension (BEP 6).
+    SuggestPiece = 13,
+    HaveAll = 14,
+    HaveNone = 15,
+    RejectRequest = 16,
+    AllowedFast = 17,
+    Extended = 20,
 }
 
 /// The message that is sent to the peer.
@@ -28,6 +37,16 @@ pub enum MessageError {
     InvalidMessage,
 }
 
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid message"),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
 impl Message {
     /// Creates a new `Message` from a message ID and a payload.
     pub fn new(id: MessageId, payload: Vec<u8>) -> Self {
@@ -47,6 +66,12 @@ impl Message {
             7 => MessageId::Piece,
             8 => MessageId::Cancel,
             9 => MessageId::Port,
+            13 => MessageId::SuggestPiece,
+            14 => MessageId::HaveAll,
+            15 => MessageId::HaveNone,
+            16 => MessageId::RejectRequest,
+            17 => MessageId::AllowedFast,
+            20 => MessageId::Extended,
             _ => return Err(MessageError::InvalidMessage),
         };
 
@@ -84,7 +109,7 @@ mod tests {
         let msg = Message::from_bytes(&payload).unwrap();
 
         assert_eq!(msg.id, MessageId::Unchoke);
-        assert_eq!(msg.payload, vec![]);
+        assert_eq!(msg.payload, Vec::<u8>::new());
     }
 
     #[test]
@@ -93,7 +118,40 @@ mod tests {
         let msg = Message::from_bytes(&payload).unwrap();
 
         assert_eq!(msg.id, MessageId::Interested);
-        assert_eq!(msg.payload, vec![]);
+        assert_eq!(msg.payload, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_message_extended_from_bytes() {
+        let mut bytes = vec![20, 0];
+        bytes.extend(b"de");
+        let msg = Message::from_bytes(&bytes).unwrap();
+
+        assert_eq!(msg.id, MessageId::Extended);
+        assert_eq!(msg.payload, vec![0, b'd', b'e']);
+    }
+
+    #[test]
+    fn test_fast_extension_messages_from_bytes() {
+        let messages = [
+            (13, MessageId::SuggestPiece),
+            (14, MessageId::HaveAll),
+            (15, MessageId::HaveNone),
+            (16, MessageId::RejectRequest),
+            (17, MessageId::AllowedFast),
+        ];
+
+        for (id, expected) in messages {
+            let msg = Message::from_bytes(&[id]).unwrap();
+
+            assert_eq!(msg.id, expected);
+            assert_eq!(msg.id.clone() as u8, id);
+        }
+    }
+
+    #[test]
+    fn test_unknown_message_id() {
+        assert!(Message::from_bytes(&[12]).is_err());
     }
 
     #[test]
@@ -108,7 +166,7 @@ mod tests {
 
         let len = 13u32.to_be_bytes();
         let msg_type = 6u8.to_be_bytes();
-        let mut expected = vec![];
+        let mut expected: Vec<u8> = vec![];
         expected.extend(&len);
         expected.extend(&msg_type);
         expected.extend(&payload);
@@ -124,7 +182,7 @@ mod tests {
 
         let len = 1u32.to_be_bytes();
         let msg_type = 2u8.to_be_bytes();
-        let mut expected = vec![];
+        let mut expected: Vec<u8> = vec![];
         expected.extend(&len);
         expected.extend(&msg_type);
 
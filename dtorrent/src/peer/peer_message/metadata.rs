@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bencoder::bencode::Bencode;
+
+/// Name of the metadata exchange extension (BEP 9).
+pub const UT_METADATA: &str = "ut_metadata";
+
+/// Extended message ID we want to receive `ut_metadata` messages with.
+pub const UT_METADATA_ID: u8 = 1;
+
+/// Size of every metadata piece except the last one.
+pub const METADATA_PIECE_SIZE: usize = 16384;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MetadataMessageError {
+    InvalidBencode,
+    NotADict,
+    MissingMessageType,
+    InvalidMessageType(i64),
+    MissingPiece,
+    MissingTotalSize,
+}
+
+impl fmt::Display for MetadataMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidBencode => write!(f, "metadata message is not valid bencode"),
+            Self::NotADict => write!(f, "metadata message is not a dict"),
+            Self::MissingMessageType => write!(f, "metadata message without msg_type"),
+            Self::InvalidMessageType(msg_type) => {
+                write!(f, "invalid metadata message type: {}", msg_type)
+            }
+            Self::MissingPiece => write!(f, "metadata message without piece"),
+            Self::MissingTotalSize => write!(f, "metadata data message without total_size"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataMessageError {}
+
+/// Types of the `ut_metadata` messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataMessageType {
+    Request = 0,
+    Data = 1,
+    Reject = 2,
+}
+
+/// A `ut_metadata` message, sent inside an extended message.
+///
+/// Data messages carry the `total_size` of the metadata, and the piece `data` appended after the bencoded dictionary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataMessage {
+    pub msg_type: MetadataMessageType,
+    pub piece: u32,
+    pub total_size: Option<u32>,
+    pub data: Vec<u8>,
+}
+
+impl MetadataMessage {
+    /// Creates a request message for the given metadata piece.
+    pub fn request(piece: u32) -> Self {
+        Self {
+            msg_type: MetadataMessageType::Request,
+            piece,
+            total_size: None,
+            data: vec![],
+        }
+    }
+
+    /// Creates a data message for the given metadata piece.
+    pub fn data(piece: u32, total_size: u32, data: Vec<u8>) -> Self {
+        Self {
+            msg_type: MetadataMessageType::Data,
+            piece,
+            total_size: Some(total_size),
+            data,
+        }
+    }
+
+    /// Creates a reject message for the given metadata piece.
+    pub fn reject(piece: u32) -> Self {
+        Self {
+            msg_type: MetadataMessageType::Reject,
+            piece,
+            total_size: None,
+            data: vec![],
+        }
+    }
+
+    /// Parses the payload of an extended message (without the extended message ID) into a `MetadataMessage`.
+    ///
+    /// It returns a `MetadataMessageError` if:
+    /// - The payload doesn't start with a bencoded dictionary.
+    /// - The message type or piece are missing or invalid.
+    /// - A data message doesn't have the total size.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MetadataMessageError> {
+        if bytes.is_empty() {
+            return Err(MetadataMessageError::InvalidBencode);
+        }
+        let (bencode, length) =
+            Bencode::decode_with_length(bytes).map_err(|_| MetadataMessageError::InvalidBencode)?;
+
+        let dict = match bencode {
+            Bencode::BDict(dict) => dict,
+            _ => return Err(MetadataMessageError::NotADict),
+        };
+
+        let msg_type = match Self::get_number(&dict, b"msg_type") {
+            Some(0) => MetadataMessageType::Request,
+            Some(1) => MetadataMessageType::Data,
+            Some(2) => MetadataMessageType::Reject,
+            Some(other) => return Err(MetadataMessageError::InvalidMessageType(other)),
+            None => return Err(MetadataMessageError::MissingMessageType),
+        };
+
+        let piece = Self::get_number(&dict, b"piece")
+            .and_then(|piece| u32::try_from(piece).ok())
+            .ok_or(MetadataMessageError::MissingPiece)?;
+
+        let total_size =
+            Self::get_number(&dict, b"total_size").and_then(|size| u32::try_from(size).ok());
+
+        if msg_type == MetadataMessageType::Data && total_size.is_none() {
+            return Err(MetadataMessageError::MissingTotalSize);
+        }
+
+        Ok(Self {
+            msg_type,
+            piece,
+            total_size,
+            data: bytes[length..].to_vec(),
+        })
+    }
+
+    /// Converts a `MetadataMessage` to the payload of an extended message (without the extended message ID).
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"msg_type".to_vec(),
+            Bencode::BNumber(self.msg_type.clone() as i64),
+        );
+        dict.insert(b"piece".to_vec(), Bencode::BNumber(self.piece.into()));
+        if let Some(total_size) = self.total_size {
+            dict.insert(b"total_size".to_vec(), Bencode::BNumber(total_size.into()));
+        }
+
+        let mut bytes = Bencode::encode(&Bencode::BDict(dict));
+        bytes.extend(&self.data);
+        bytes
+    }
+
+    fn get_number(dict: &BTreeMap<Vec<u8>, Bencode>, key: &[u8]) -> Option<i64> {
+        match dict.get(key) {
+            Some(Bencode::BNumber(n)) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_as_bytes() {
+        let message = MetadataMessage::request(2);
+
+        assert_eq!(message.as_bytes(), b"d8:msg_typei0e5:piecei2ee");
+    }
+
+    #[test]
+    fn test_reject_from_bytes() {
+        let message = MetadataMessage::from_bytes(b"d8:msg_typei2e5:piecei0ee").unwrap();
+
+        assert_eq!(message, MetadataMessage::reject(0));
+    }
+
+    #[test]
+    fn test_data_roundtrip() {
+        let message = MetadataMessage::data(1, 16390, vec![1, 2, 3, 4, 5, 6]);
+
+        let bytes = message.as_bytes();
+
+        assert_eq!(
+            bytes,
+            b"d8:msg_typei1e5:piecei1e10:total_sizei16390ee\x01\x02\x03\x04\x05\x06"
+        );
+        assert_eq!(MetadataMessage::from_bytes(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn test_data_without_total_size() {
+        assert_eq!(
+            MetadataMessage::from_bytes(b"d8:msg_typei1e5:piecei0eeabc"),
+            Err(MetadataMessageError::MissingTotalSize)
+        );
+    }
+
+    #[test]
+    fn test_invalid_message_type() {
+        assert_eq!(
+            MetadataMessage::from_bytes(b"d8:msg_typei7e5:piecei0ee"),
+            Err(MetadataMessageError::InvalidMessageType(7))
+        );
+    }
+
+    #[test]
+    fn test_truncated_message() {
+        for truncated in [
+            &b"d8:msg_typei1e5:piecei0e"[..],
+            b"d8:msg_typei1",
+            b"d8:msg_ty",
+            b"d",
+        ] {
+            assert_eq!(
+                MetadataMessage::from_bytes(truncated),
+                Err(MetadataMessageError::InvalidBencode)
+            );
+        }
+    }
+}
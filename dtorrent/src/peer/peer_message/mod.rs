@@ -1,7 +1,13 @@
 mod bitfield;
+mod extended;
+mod fast;
 mod message;
+mod metadata;
 mod request;
 
 pub use self::bitfield::*;
+pub use self::extended::*;
+pub use self::fast::*;
 pub use self::message::*;
+pub use self::metadata::*;
 pub use self::request::*;
@@ -1,5 +1,31 @@
+use std::fmt;
+
+/// Length of the payload of a Request message: index (4), begin (4) and length (4).
+const REQUEST_LENGTH: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestError {
+    InvalidLength(usize),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength(length) => write!(
+                f,
+                "request payload must be {} bytes, received {}",
+                REQUEST_LENGTH, length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
 /// Represents the payload of a Request message.
-#[derive(Debug)]
+///
+/// Cancel and RejectRequest messages have the same payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Request {
     index: u32,
     begin: u32,
@@ -16,9 +42,39 @@ impl Request {
         }
     }
 
+    /// Parses the payload of a `Request` message.
+    ///
+    /// It returns an `InvalidLength` error if the payload is not 12 bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RequestError> {
+        if bytes.len() != REQUEST_LENGTH {
+            return Err(RequestError::InvalidLength(bytes.len()));
+        }
+        let field = |start: usize| {
+            let mut value = [0; 4];
+            value.copy_from_slice(&bytes[start..start + 4]);
+            u32::from_be_bytes(value)
+        };
+        Ok(Self::new(field(0), field(4), field(8)))
+    }
+
+    /// Returns the index of the piece requested.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the offset of the block requested within its piece.
+    pub fn begin(&self) -> u32 {
+        self.begin
+    }
+
+    /// Returns the length of the block requested.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
     /// Converts a `Request` message to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![0; 12];
+        let mut bytes = vec![0; REQUEST_LENGTH];
         bytes[0..4].copy_from_slice(&self.index.to_be_bytes());
         bytes[4..8].copy_from_slice(&self.begin.to_be_bytes());
         bytes[8..12].copy_from_slice(&self.length.to_be_bytes());
@@ -39,11 +95,35 @@ mod tests {
 
         let bytes = request.as_bytes();
 
-        let mut expected = vec![];
+        let mut expected: Vec<u8> = vec![];
         expected.extend(&index.to_be_bytes());
         expected.extend(&begin.to_be_bytes());
         expected.extend(&length.to_be_bytes());
 
         assert_eq!(bytes, expected);
     }
+
+    #[test]
+    fn test_request_from_bytes() {
+        let request = Request::new(3, 16384, 1000);
+
+        let parsed = Request::from_bytes(&request.as_bytes()).unwrap();
+
+        assert_eq!(parsed, request);
+        assert_eq!(parsed.index(), 3);
+        assert_eq!(parsed.begin(), 16384);
+        assert_eq!(parsed.length(), 1000);
+    }
+
+    #[test]
+    fn test_request_from_bytes_with_invalid_length() {
+        assert_eq!(
+            Request::from_bytes(&[0; 11]).unwrap_err(),
+            RequestError::InvalidLength(11)
+        );
+        assert_eq!(
+            Request::from_bytes(&[0; 13]).unwrap_err(),
+            RequestError::InvalidLength(13)
+        );
+    }
 }
@@ -1,9 +1,10 @@
 use std::{
-    fmt::Write,
-    io::{self, Read, Write as IOWrite},
-    net::TcpStream,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Write},
+    io::{self, Read},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use chrono::{DateTime, Local};
@@ -12,15 +13,27 @@ use sha1::{Digest, Sha1};
 
 use crate::{
     config::cfg::Cfg,
-    torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError},
-    torrent_parser::torrent::Torrent,
+    torrent_handler::{
+        dial_limiter::DialLimiter,
+        status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+    },
+    torrent_parser::{
+        metadata::{Metadata, MetadataError},
+        torrent::Torrent,
+    },
 };
 
 use super::{
     bt_peer::{BtPeer, BtPeerError},
     message_handler::{MessageHandler, MessageHandlerError},
-    peer_message::{Bitfield, Message, MessageError, MessageId},
+    mse::{EncryptionMode, MseError, MseHandshake, CRYPTO_RC4},
+    peer_message::{
+        Bitfield, BitfieldError, FastMessage, Message, MessageError, MessageId,
+        MetadataMessageType, Request, UT_METADATA, UT_METADATA_ID,
+    },
+    peer_stream::PeerStream,
     session_status::SessionStatus,
+    speed::{kilobits_per_second, SpeedAverage},
 };
 
 const BLOCK_SIZE: u32 = 16384;
@@ -47,6 +60,104 @@ pub enum PeerSessionError {
     ErrorSettingStreamTimeout,
     BtPeerError(BtPeerError),
     PeerIsOurself,
+    InvalidBitfield(BitfieldError),
+    TorrentStopped,
+    ErrorSavingBlock(AtomicTorrentStatusError),
+    PieceRequestTimeout,
+    BlockRejected(u32, u32),
+    ErrorUpdatingPieceAvailability(AtomicTorrentStatusError),
+    FastExtensionNotNegotiated(MessageId),
+    EncryptionError(MseError),
+    MetadataNotSupported,
+    MetadataRejected(u32),
+    MetadataRequestTimeout,
+    InvalidMetadata(MetadataError),
+}
+
+impl fmt::Display for PeerSessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ErrorReadingMessage(_) => write!(f, "couldn't read a message from the peer"),
+            Self::MessageDoesNotExist(_) => {
+                write!(f, "the peer sent a message that does not exist")
+            }
+            Self::CouldNotConnectToPeer => write!(f, "couldn't connect to the peer"),
+            Self::ErrorDisconnectingFromPeer(_) => write!(f, "couldn't disconnect from the peer"),
+            Self::ErrorAbortingPiece(_) => write!(f, "couldn't abort the piece"),
+            Self::ErrorSelectingPiece(_) => write!(f, "couldn't select a piece to download"),
+            Self::ErrorNotifyingPieceDownloaded(_) => {
+                write!(f, "couldn't notify that the piece was downloaded")
+            }
+            Self::ErrorConnectingToPeer(_) => {
+                write!(f, "couldn't register the connection to the peer")
+            }
+            Self::PieceHashDoesNotMatch => write!(f, "the piece hash does not match"),
+            Self::NoPiecesLeftToDownloadInThisPeer => {
+                write!(f, "no pieces left to download from the peer")
+            }
+            Self::ErrorGettingBitfield(_) => write!(f, "couldn't get our bitfield"),
+            Self::ErrorGettingPiece(_) => write!(f, "couldn't get the requested piece"),
+            Self::ErrorGettingSessionsStatus(_) => write!(f, "couldn't update the session status"),
+            Self::PeerNotInterested => write!(f, "the peer is not interested"),
+            Self::MessageHandlerError(_) => write!(f, "couldn't handle a message"),
+            Self::MessageError(id) => write!(f, "couldn't send {:?} message", id),
+            Self::MessageLengthTooLong => write!(f, "the peer sent a message too long"),
+            Self::ErrorSettingStreamTimeout => write!(f, "couldn't set the stream timeouts"),
+            Self::BtPeerError(_) => write!(f, "couldn't handshake with the peer"),
+            Self::PeerIsOurself => write!(f, "the peer is ourself"),
+            Self::InvalidBitfield(_) => write!(f, "the peer sent an invalid bitfield"),
+            Self::TorrentStopped => write!(f, "the torrent was stopped"),
+            Self::ErrorSavingBlock(_) => write!(f, "couldn't save the downloaded block"),
+            Self::PieceRequestTimeout => write!(f, "the requested blocks didn't arrive in time"),
+            Self::BlockRejected(index, begin) => write!(
+                f,
+                "the peer rejected the block of piece {} at offset {}",
+                index, begin
+            ),
+            Self::ErrorUpdatingPieceAvailability(_) => {
+                write!(f, "couldn't update the availability of the pieces")
+            }
+            Self::FastExtensionNotNegotiated(id) => write!(
+                f,
+                "the peer sent {:?} message without negotiating the fast extension",
+                id
+            ),
+            Self::EncryptionError(_) => write!(f, "couldn't encrypt the connection to the peer"),
+            Self::MetadataNotSupported => {
+                write!(f, "the peer doesn't support the metadata exchange")
+            }
+            Self::MetadataRejected(piece) => {
+                write!(f, "the peer rejected the metadata piece: {}", piece)
+            }
+            Self::MetadataRequestTimeout => write!(f, "the metadata didn't arrive in time"),
+            Self::InvalidMetadata(_) => write!(f, "the peer sent invalid metadata"),
+        }
+    }
+}
+
+impl std::error::Error for PeerSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ErrorReadingMessage(err) => Some(err),
+            Self::MessageDoesNotExist(err) => Some(err),
+            Self::ErrorDisconnectingFromPeer(err)
+            | Self::ErrorAbortingPiece(err)
+            | Self::ErrorSelectingPiece(err)
+            | Self::ErrorNotifyingPieceDownloaded(err)
+            | Self::ErrorConnectingToPeer(err)
+            | Self::ErrorGettingBitfield(err)
+            | Self::ErrorGettingPiece(err)
+            | Self::ErrorGettingSessionsStatus(err)
+            | Self::ErrorSavingBlock(err)
+            | Self::ErrorUpdatingPieceAvailability(err) => Some(err),
+            Self::MessageHandlerError(err) => Some(err),
+            Self::BtPeerError(err) => Some(err),
+            Self::InvalidBitfield(err) => Some(err),
+            Self::EncryptionError(err) => Some(err),
+            Self::InvalidMetadata(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 /// A PeerSession represents a connection to a peer.
@@ -58,12 +169,27 @@ pub struct PeerSession {
     bitfield: Bitfield,
     status: SessionStatus,
     piece: Vec<u8>,
+    // Blocks received of the piece being downloaded, by offset.
+    piece_blocks: BTreeMap<u32, Vec<u8>>,
     torrent_status: Arc<AtomicTorrentStatus>,
     current_piece: u32,
     config: Cfg,
     logger_sender: LoggerSender,
     message_handler: MessageHandler,
     client_peer_id: String,
+    peer_extensions: HashMap<String, u8>,
+    // Size of the metadata advertised by the peer in its extended handshake.
+    peer_metadata_size: Option<usize>,
+    // Metadata being fetched from the peer, if any.
+    metadata: Option<Metadata>,
+    // Requests rejected by the peer with the fast extension, not handled yet.
+    rejected_requests: Vec<Request>,
+    // Pieces the peer rejected a block of, which are left for other peers.
+    rejected_pieces: HashSet<u32>,
+    // Blocks of the piece being downloaded requested to the peer and not received yet.
+    pending_requests: Vec<Request>,
+    download_speed: SpeedAverage,
+    upload_speed: SpeedAverage,
 }
 
 impl PeerSession {
@@ -94,15 +220,24 @@ impl PeerSession {
         Ok(PeerSession {
             torrent,
             peer,
-            bitfield: Bitfield::new(vec![0; (pieces_count / 8) as usize]),
+            bitfield: Bitfield::empty(pieces_count),
             status: SessionStatus::new(our_bitfield),
             piece: vec![],
+            piece_blocks: BTreeMap::new(),
             torrent_status,
             current_piece: 0,
             config,
             logger_sender,
             message_handler,
             client_peer_id,
+            peer_extensions: HashMap::new(),
+            peer_metadata_size: None,
+            metadata: None,
+            rejected_requests: Vec::new(),
+            rejected_pieces: HashSet::new(),
+            pending_requests: Vec::new(),
+            download_speed: SpeedAverage::new(),
+            upload_speed: SpeedAverage::new(),
         })
     }
 
@@ -112,7 +247,7 @@ impl PeerSession {
     /// Handshakes with an incoming leecher.
     pub fn handshake_incoming_leecher(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
         self.message_handler
             .send_handshake(stream)
@@ -123,8 +258,10 @@ impl PeerSession {
             self.peer.ip, self.peer.port
         ));
 
-        self.message_handler
-            .send_bitfield(stream)
+        // With the fast extension, the pieces we have must be the first message after the handshake.
+        self.status.bitfield = self
+            .message_handler
+            .send_bitfield(self.peer.supports_fast_extension, stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
 
         self.logger_sender.info(&format!(
@@ -132,12 +269,13 @@ impl PeerSession {
             self.peer.ip, self.peer.port
         ));
 
+        self.send_extended_handshake(stream)?;
         Ok(())
     }
 
     pub fn unchoke_incoming_leecher(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
         self.torrent_status
             .peer_connected(&self.peer)
@@ -145,6 +283,9 @@ impl PeerSession {
         match self.unchoke_incoming_leecher_wrap(stream) {
             Ok(_) => Ok(()),
             Err(e) => {
+                self.torrent_status
+                    .peer_lost_pieces(&self.bitfield)
+                    .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
                 self.torrent_status
                     .peer_disconnected(&self.peer)
                     .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
@@ -156,13 +297,12 @@ impl PeerSession {
     /// Sends an unchoke message to the peer to start sending pieces.
     pub fn unchoke_incoming_leecher_wrap(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
         let mut id = self.read_message_from_stream(stream)?;
         while id != MessageId::Interested {
             // if we receive a `not interested` message, we close the connection.
             if id == MessageId::NotInterested {
-                self.status.peer_interested = false;
                 // peer disconnected
                 return Err(PeerSessionError::PeerNotInterested);
             }
@@ -170,14 +310,11 @@ impl PeerSession {
             id = self.read_message_from_stream(stream)?;
         }
 
-        // Peer is interested
-        self.status.peer_interested = true;
-
         self.message_handler
             .send_unchoked(stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
 
-        self.status.peer_choked = false;
+        self.status.message_sent(&MessageId::Unchoke);
 
         loop {
             self.update_bitfield(stream)?;
@@ -192,11 +329,20 @@ impl PeerSession {
 
     /// Starts a connection to an outgoing seeder to start downloading pieces.
     ///
+    /// The connection is set up once the `dial_limiter` allows it, holding its permit until the handshakes finish.
+    ///
     /// It returns an error if:
     /// - The connection could not be established
     /// - The handshake was not successful
-    pub fn start_outgoing_seeder(&mut self) -> Result<(), PeerSessionError> {
-        let mut stream = match self.set_up_peer_session() {
+    pub fn start_outgoing_seeder(
+        &mut self,
+        dial_limiter: &DialLimiter,
+    ) -> Result<(), PeerSessionError> {
+        let dial_permit = dial_limiter.acquire();
+        let set_up = self.set_up_peer_session();
+        drop(dial_permit);
+
+        let mut stream = match set_up {
             Ok(stream) => stream,
             Err(e) => {
                 self.torrent_status.peer_connecting_failed();
@@ -211,6 +357,9 @@ impl PeerSession {
         match self.start_outgoing_seeder_wrap(&mut stream) {
             Ok(_) => Ok(()),
             Err(e) => {
+                self.torrent_status
+                    .peer_lost_pieces(&self.bitfield)
+                    .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
                 self.torrent_status
                     .peer_disconnected(&self.peer)
                     .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
@@ -219,13 +368,30 @@ impl PeerSession {
         }
     }
 
-    fn set_up_peer_session(&mut self) -> Result<TcpStream, PeerSessionError> {
-        let peer_socket = format!("{}:{}", self.peer.ip, self.peer.port);
-
-        let mut stream =
-            TcpStream::connect(peer_socket).map_err(|_| PeerSessionError::CouldNotConnectToPeer)?;
-
-        self.set_stream_timeouts(&mut stream)?;
+    /// Connects to the peer and handshakes with it.
+    ///
+    /// Unless encryption is disabled, the connection is encrypted first. If that fails and encryption is not required, it connects again in plaintext.
+    fn set_up_peer_session(&mut self) -> Result<PeerStream, PeerSessionError> {
+        let peer_socket = format!("{}:{}", self.peer.ip, self.peer.port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or(PeerSessionError::CouldNotConnectToPeer)?;
+
+        let mut stream = match self.config.encryption_mode {
+            EncryptionMode::Disabled => self.connect(&peer_socket)?,
+            EncryptionMode::Enabled => match self.connect_encrypted(&peer_socket) {
+                Err(PeerSessionError::EncryptionError(err)) => {
+                    self.logger_sender.info(&format!(
+                        "IP: {}:{} Encryption failed, connecting in plaintext: {}",
+                        self.peer.ip, self.peer.port, err
+                    ));
+                    self.connect(&peer_socket)?
+                }
+                result => result?,
+            },
+            EncryptionMode::Required => self.connect_encrypted(&peer_socket)?,
+        };
 
         self.message_handler
             .send_handshake(&mut stream)
@@ -246,12 +412,62 @@ impl PeerSession {
             }
             None => (),
         }
+
+        // Without the fast extension the bitfield is optional, and a downloading session has no pieces to tell about.
+        if self.peer.supports_fast_extension {
+            self.message_handler
+                .send_bitfield(true, &mut stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
+
+        self.send_extended_handshake(&mut stream)?;
+        Ok(stream)
+    }
+
+    /// Opens a plaintext connection to the peer.
+    fn connect(&self, peer_socket: &SocketAddr) -> Result<PeerStream, PeerSessionError> {
+        let stream = TcpStream::connect_timeout(peer_socket, self.config.connect_timeout())
+            .map_err(|_| PeerSessionError::CouldNotConnectToPeer)?;
+        let mut stream = PeerStream::new(stream);
+
+        self.set_stream_timeouts(&mut stream)?;
+        Ok(stream)
+    }
+
+    /// Opens a connection to the peer and negotiates its encryption with Message Stream Encryption.
+    ///
+    /// Peers that don't support it usually wait for our BitTorrent handshake, so their answers are only awaited for the connect timeout.
+    fn connect_encrypted(&self, peer_socket: &SocketAddr) -> Result<PeerStream, PeerSessionError> {
+        let mut stream = self.connect(peer_socket)?;
+        stream
+            .set_read_timeout(Some(self.config.connect_timeout()))
+            .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
+
+        let select = MseHandshake::new()
+            .initiate(
+                &mut stream,
+                self.torrent.get_info_hash_as_bytes(),
+                self.config.encryption_mode.crypto_provide(),
+            )
+            .map_err(PeerSessionError::EncryptionError)?;
+        self.set_stream_timeouts(&mut stream)?;
+
+        self.logger_sender.info(&format!(
+            "IP: {}:{} Encryption negotiated: {}",
+            self.peer.ip,
+            self.peer.port,
+            if select == CRYPTO_RC4 {
+                "RC4"
+            } else {
+                "plaintext"
+            }
+        ));
         Ok(stream)
     }
 
     fn start_outgoing_seeder_wrap(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
         loop {
             self.read_message_from_stream(stream)?;
@@ -261,7 +477,7 @@ impl PeerSession {
                     .send_interested(stream)
                     .map_err(PeerSessionError::MessageHandlerError)?;
 
-                self.status.interested = true;
+                self.status.message_sent(&MessageId::Interested);
             }
 
             if !self.status.choked && self.status.interested {
@@ -270,11 +486,14 @@ impl PeerSession {
         }
     }
 
-    fn request_pieces(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+    /// Downloads the pieces the peer has, one at a time, until we are choked or there are no pieces left to download from it.
+    ///
+    /// A piece the peer rejects a block of is released for other peers, and isn't selected again from this peer.
+    fn request_pieces(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
         loop {
             let piece_index = self
                 .torrent_status
-                .select_piece(&self.bitfield)
+                .select_piece(&self.selectable_pieces())
                 .map_err(PeerSessionError::ErrorSelectingPiece)?;
 
             match piece_index {
@@ -291,7 +510,18 @@ impl PeerSession {
                                 .piece_aborted(piece_index)
                                 .map_err(PeerSessionError::ErrorAbortingPiece)?;
 
-                            return Err(e);
+                            match e {
+                                // A choked peer rejects every pending request, so the piece can be requested again once unchoked.
+                                PeerSessionError::BlockRejected(_, _) if !self.status.choked => {
+                                    self.logger_sender.info(&format!(
+                                        "IP: {}:{} {}, leaving piece {} for other peers",
+                                        self.peer.ip, self.peer.port, e, piece_index
+                                    ));
+                                    self.rejected_pieces.insert(piece_index);
+                                }
+                                PeerSessionError::BlockRejected(_, _) => (),
+                                _ => return Err(e),
+                            }
                         }
                     }
                     if self.status.choked {
@@ -307,18 +537,32 @@ impl PeerSession {
     }
 
     /// Downloads a piece from the peer given the piece index.
+    ///
+    /// Only the blocks that weren't downloaded yet, by this or another session, are requested.
     fn download_piece(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
         piece_index: u32,
     ) -> Result<(), PeerSessionError> {
-        self.piece = vec![]; // reset piece
+        self.pending_requests.clear();
+        // Resume from the blocks already downloaded, wherever they are in the piece.
+        self.piece_blocks = self
+            .torrent_status
+            .downloaded_blocks_by_offset(piece_index)
+            .map_err(PeerSessionError::ErrorGettingPiece)?;
 
-        let entire_blocks_in_piece = self.download_with_pipeline(piece_index, stream)?;
+        self.download_with_pipeline(piece_index, stream)?;
 
-        self.check_last_piece_block(piece_index, entire_blocks_in_piece, stream)?;
+        let piece_blocks = std::mem::take(&mut self.piece_blocks);
+        self.piece = self.assemble_piece(piece_index, piece_blocks);
 
-        self.validate_piece(&self.piece, piece_index)?;
+        if let Err(err) = self.validate_piece(&self.piece, piece_index) {
+            self.torrent_status.metrics().piece_failed();
+            self.torrent_status
+                .discard_blocks(piece_index)
+                .map_err(PeerSessionError::ErrorAbortingPiece)?;
+            return Err(err);
+        }
         self.logger_sender
             .info(&format!("Piece {} downloaded!", piece_index));
 
@@ -333,122 +577,232 @@ impl PeerSession {
         Ok(())
     }
 
-    /// Downloads a piece in 'chunks' of blocks.
+    /// Downloads the missing blocks of a piece in 'chunks'.
     ///
     /// If the pipelinening size in the config is 5, then it will request 5 blocks and wait for those 5 blocks to be received.
     ///
-    /// If there are less than 5 blocks left in the piece, it will request the remaining blocks and wait for those blocks to be received.
+    /// If there are less than 5 blocks missing in the piece, it will request the remaining blocks and wait for those blocks to be received.
     fn download_with_pipeline(
         &mut self,
         piece_index: u32,
-        stream: &mut TcpStream,
-    ) -> Result<u32, PeerSessionError> {
-        let entire_blocks_in_piece = self.complete_blocks_in_torrent_piece(piece_index);
-        let mut blocks_downloaded = 0;
-        while blocks_downloaded < entire_blocks_in_piece {
-            let remaining_blocks = entire_blocks_in_piece - blocks_downloaded;
-            let blocks_to_download = if remaining_blocks % self.config.pipelining_size == 0 {
-                self.config.pipelining_size
-            } else {
-                remaining_blocks
-            };
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        let missing_blocks: Vec<(u32, u32)> = self
+            .blocks_in_torrent_piece(piece_index)
+            .into_iter()
+            .filter(|(begin, _)| !self.piece_blocks.contains_key(begin))
+            .collect();
 
+        for blocks in missing_blocks.chunks(self.config.pipelining_size.max(1) as usize) {
             let download_start_time = Local::now();
 
             // request blocks
-            for block in 0..blocks_to_download {
+            for (begin, length) in blocks {
                 self.message_handler
-                    .send_request(
-                        piece_index,
-                        (block + blocks_downloaded) * BLOCK_SIZE,
-                        BLOCK_SIZE,
-                        stream,
-                    )
+                    .send_request(piece_index, *begin, *length, stream)
                     .map_err(PeerSessionError::MessageHandlerError)?;
+                self.pending_requests
+                    .push(Request::new(piece_index, *begin, *length));
             }
 
             // If we are in the endgame phase, and we already downloaded all the blocks, we send a cancel message.
             if self.torrent_status.is_finished() {
-                for block in 0..blocks_to_download {
+                for (begin, length) in blocks {
                     self.message_handler
-                        .send_cancel(
-                            piece_index,
-                            (block + blocks_downloaded) * BLOCK_SIZE,
-                            BLOCK_SIZE,
-                            stream,
-                        )
+                        .send_cancel(piece_index, *begin, *length, stream)
                         .map_err(PeerSessionError::MessageHandlerError)?;
                 }
             }
 
-            // Check that we receive a piece message.
-            // If we receive another message we handle it accordingly.
-            let mut current_blocks_downloaded = 0;
-            while current_blocks_downloaded < blocks_to_download {
-                if self.read_message_from_stream(stream)? == MessageId::Piece {
-                    current_blocks_downloaded += 1;
-                    blocks_downloaded += 1;
-                }
-            }
+            let offsets: Vec<u32> = blocks.iter().map(|(begin, _)| *begin).collect();
+            self.wait_for_blocks(&offsets, stream)?;
+
             // Calculate download speed
-            let download_speed = self.calculate_kilobits_per_second(
-                download_start_time,
-                (blocks_to_download * BLOCK_SIZE).into(),
-            );
-            self.status.download_speed = download_speed;
+            let downloaded: u32 = blocks.iter().map(|(_, length)| length).sum();
+            let download_speed =
+                self.calculate_kilobits_per_second(download_start_time, downloaded.into());
+            self.status.download_speed = self.download_speed.update(download_speed);
             self.update_peer_status()?;
         }
-        Ok(entire_blocks_in_piece)
+        Ok(())
     }
 
-    fn check_last_piece_block(
+    /// Waits for the blocks at the given offsets of the current piece, handling any other message received in between.
+    ///
+    /// It returns an error if the blocks don't arrive before the piece request timeout in the config,
+    /// so a peer that keeps the connection alive without sending them doesn't hold the piece forever,
+    /// or if the peer rejects one of them, as it won't send it if requested again.
+    /// The blocks that did arrive are kept by the torrent status, so the piece can be resumed from them.
+    fn wait_for_blocks(
         &mut self,
-        piece_index: u32,
-        entire_blocks_in_piece: u32,
-        stream: &mut TcpStream,
+        offsets: &[u32],
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
-        let last_block_size = self.torrent.last_piece_size() % BLOCK_SIZE;
+        let deadline =
+            Instant::now() + Duration::from_secs(self.config.piece_request_seconds_timeout);
+        while !offsets
+            .iter()
+            .all(|begin| self.piece_blocks.contains_key(begin))
+        {
+            if Instant::now() >= deadline {
+                return Err(PeerSessionError::PieceRequestTimeout);
+            }
+            self.read_message_from_stream(stream)?;
+            self.check_rejected_blocks()?;
+        }
+        Ok(())
+    }
 
-        let last_piece_index = self.torrent.total_pieces() - 1;
+    /// Returns a `BlockRejected` error if the peer rejected a block of the current piece.
+    ///
+    /// The rejected blocks are no longer waited for, so they are dropped from the pending requests.
+    fn check_rejected_blocks(&mut self) -> Result<(), PeerSessionError> {
+        let mut result = Ok(());
+        for request in std::mem::take(&mut self.rejected_requests) {
+            self.pending_requests.retain(|pending| *pending != request);
+            if request.index() == self.current_piece && result.is_ok() {
+                result = Err(PeerSessionError::BlockRejected(
+                    request.index(),
+                    request.begin(),
+                ));
+            }
+        }
+        result
+    }
 
-        if last_block_size != 0 && piece_index == last_piece_index {
-            self.message_handler
-                .send_request(
-                    piece_index,
-                    entire_blocks_in_piece * BLOCK_SIZE,
-                    last_block_size,
-                    stream,
-                )
-                .map_err(PeerSessionError::MessageHandlerError)?;
+    /// Returns the pieces the peer has, except for the ones it rejected a block of.
+    fn selectable_pieces(&self) -> Bitfield {
+        let mut bitfield = self.bitfield.clone();
+        for index in &self.rejected_pieces {
+            bitfield.set_bit(*index, false);
+        }
+        bitfield
+    }
 
-            while self.read_message_from_stream(stream)? != MessageId::Piece {
-                continue;
+    /// Writes each block at its offset of a buffer the size of the piece, so the order in which they were received doesn't matter.
+    ///
+    /// Blocks that don't fit in the piece are left out, as are missing blocks, which makes the piece fail its hash.
+    fn assemble_piece(
+        &self,
+        piece_index: u32,
+        blocks: impl IntoIterator<Item = (u32, Vec<u8>)>,
+    ) -> Vec<u8> {
+        let mut piece = vec![0; self.torrent.piece_size(piece_index) as usize];
+        for (begin, block) in blocks {
+            let begin = begin as usize;
+            if let Some(range) = piece.get_mut(begin..begin + block.len()) {
+                range.copy_from_slice(&block);
             }
         }
-        Ok(())
+        piece
     }
 
-    fn complete_blocks_in_torrent_piece(&self, piece_index: u32) -> u32 {
-        let last_piece_index = self.torrent.total_pieces() - 1;
+    /// Returns the `(begin, length)` of each block of the piece. Every block is `BLOCK_SIZE` long, except for a shorter last block.
+    fn blocks_in_torrent_piece(&self, piece_index: u32) -> Vec<(u32, u32)> {
+        let piece_size = self.torrent.piece_size(piece_index);
+        (0..piece_size)
+            .step_by(BLOCK_SIZE as usize)
+            .map(|begin| (begin, BLOCK_SIZE.min(piece_size - begin)))
+            .collect()
+    }
 
-        if piece_index != last_piece_index {
-            self.torrent.piece_length() / BLOCK_SIZE
-        } else {
-            let last_piece_size = self.torrent.last_piece_size();
+    /// ------------------------------------------------------------------------------------------------
+    /// Fetching metadata
 
-            // If the last piece is multiple of the piece length, then is the same as the other pieces.
-            if last_piece_size == 0 {
-                self.torrent.piece_length() / BLOCK_SIZE
-            } else {
-                (last_piece_size as f64 / BLOCK_SIZE as f64).floor() as u32
+    /// Fetches the metadata of the torrent from the peer with `ut_metadata` (BEP 9), for torrents known only by their info hash, like magnet links.
+    ///
+    /// Only the info hash and announce URL of the session torrent are used. The connection is set up once the `dial_limiter` allows it.
+    ///
+    /// It returns an error if:
+    /// - The connection could not be established
+    /// - The handshake was not successful
+    /// - The peer doesn't support `ut_metadata`, or rejects a request
+    /// - The metadata is not valid or doesn't match the info hash
+    pub fn fetch_metadata(
+        &mut self,
+        dial_limiter: &DialLimiter,
+    ) -> Result<Torrent, PeerSessionError> {
+        let dial_permit = dial_limiter.acquire();
+        let set_up = self.set_up_peer_session();
+        drop(dial_permit);
+
+        let mut stream = set_up?;
+        let result = self.request_metadata(&mut stream);
+        self.metadata = None;
+        result
+    }
+
+    /// Requests every metadata piece once the peer advertises the metadata size in its extended handshake, and builds the `Torrent` from the received pieces.
+    ///
+    /// It returns a `MetadataRequestTimeout` error if the metadata doesn't arrive before the piece request timeout in the config.
+    fn request_metadata(&mut self, stream: &mut PeerStream) -> Result<Torrent, PeerSessionError> {
+        if !self.peer.supports_extensions {
+            return Err(PeerSessionError::MetadataNotSupported);
+        }
+        let deadline =
+            Instant::now() + Duration::from_secs(self.config.piece_request_seconds_timeout);
+
+        let metadata_size = loop {
+            if let Some(size) = self.peer_metadata_size {
+                break size;
             }
+            self.read_metadata_message(deadline, stream)?;
+        };
+        let peer_ext_id = *self
+            .peer_extensions
+            .get(UT_METADATA)
+            .ok_or(PeerSessionError::MetadataNotSupported)?;
+
+        let metadata = Metadata::new(self.torrent.info_hash.to_vec(), metadata_size)
+            .map_err(PeerSessionError::InvalidMetadata)?;
+        for piece in metadata.missing_pieces() {
+            self.message_handler
+                .send_metadata_request(peer_ext_id, piece, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
+        self.metadata = Some(metadata);
+
+        while !self.metadata.as_ref().is_some_and(Metadata::is_complete) {
+            self.read_metadata_message(deadline, stream)?;
+        }
+
+        let metadata = self
+            .metadata
+            .take()
+            .ok_or(PeerSessionError::InvalidMetadata(MetadataError::Incomplete))?;
+        let torrent = metadata
+            .into_torrent(self.torrent.announce_url.clone())
+            .map_err(PeerSessionError::InvalidMetadata)?;
+
+        self.logger_sender.info(&format!(
+            "IP: {}:{} Metadata fetched for torrent: {}",
+            self.peer.ip,
+            self.peer.port,
+            torrent.name()
+        ));
+        Ok(torrent)
+    }
+
+    /// Reads & handles a message from the stream while fetching the metadata, unless the deadline has passed.
+    fn read_metadata_message(
+        &mut self,
+        deadline: Instant,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        if Instant::now() >= deadline {
+            return Err(PeerSessionError::MetadataRequestTimeout);
         }
+        self.read_message_from_stream(stream)?;
+        Ok(())
     }
 
     /// ------------------------------------------------------------------------------------------------
     /// Commons for download and upload
 
-    fn update_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+    /// Sends a `Have` message for each piece we got since the peer was last told about our pieces.
+    ///
+    /// The whole bitfield is only sent once, right after the handshake.
+    fn update_bitfield(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
         let updated_bitfield = self
             .torrent_status
             .get_bitfield()
@@ -463,21 +817,21 @@ impl PeerSession {
         }
 
         self.status.bitfield = updated_bitfield;
+        Ok(())
+    }
 
-        let bitfield_msg = Message::new(MessageId::Bitfield, self.status.bitfield.get_vec());
-        stream
-            .write_all(&bitfield_msg.as_bytes())
-            .map_err(|_| PeerSessionError::MessageError(MessageId::Bitfield))?;
+    /// Sends the extended handshake if the peer supports the extension protocol.
+    fn send_extended_handshake(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        if self.peer.supports_extensions {
+            self.message_handler
+                .send_extended_handshake(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
         Ok(())
     }
 
     fn calculate_kilobits_per_second(&self, start_time: DateTime<Local>, size: u64) -> f64 {
-        let elapsed_time = Local::now().signed_duration_since(start_time);
-        let elapsed_time_in_seconds = match elapsed_time.num_microseconds() {
-            Some(x) => x as f64 / 1_000_000.0,
-            None => return 0.0,
-        };
-        (size as f64 / elapsed_time_in_seconds) * 8.0 / 1024.0
+        kilobits_per_second(size, Local::now().signed_duration_since(start_time))
     }
 
     fn update_peer_status(&mut self) -> Result<(), PeerSessionError> {
@@ -493,8 +847,11 @@ impl PeerSession {
     /// - The message could not be read
     fn read_message_from_stream(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<MessageId, PeerSessionError> {
+        if self.torrent_status.is_stopped() {
+            return Err(PeerSessionError::TorrentStopped);
+        }
         let mut length = [0; 4];
 
         stream
@@ -531,81 +888,270 @@ impl PeerSession {
     fn handle_message(
         &mut self,
         message: Message,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
+        self.status.message_received(&message.id);
+
         match message.id {
-            MessageId::Unchoke => {
-                self.status.choked = false;
-            }
-            MessageId::Choke => {
-                self.status.choked = true;
-            }
             MessageId::Bitfield => {
-                self.bitfield = self.message_handler.handle_bitfield(message);
-            }
-            MessageId::Piece => {
-                let mut block = self.message_handler.handle_piece(message);
-                self.piece.append(&mut block);
+                let bitfield = self
+                    .message_handler
+                    .handle_bitfield(message)
+                    .map_err(PeerSessionError::InvalidBitfield)?;
+                self.set_peer_bitfield(bitfield)?;
             }
+            MessageId::Piece => self.handle_piece(message, stream)?,
             MessageId::Request => self.handle_request(message, stream)?,
             MessageId::Have => {
-                let index = self.message_handler.handle_have(message);
-                self.bitfield.set_bit(index, true);
+                // Peers may announce their pieces only through `Have` messages, without a bitfield.
+                let index = self
+                    .message_handler
+                    .handle_have(message)
+                    .map_err(PeerSessionError::MessageHandlerError)?;
+                if index < self.torrent.total_pieces() && !self.bitfield.has_piece(index) {
+                    self.bitfield.set_bit(index, true);
+                    self.torrent_status
+                        .peer_has_piece(index)
+                        .map_err(PeerSessionError::ErrorUpdatingPieceAvailability)?;
+                }
             }
+            MessageId::Extended => self.handle_extended(message, stream)?,
+            MessageId::SuggestPiece
+            | MessageId::HaveAll
+            | MessageId::HaveNone
+            | MessageId::RejectRequest
+            | MessageId::AllowedFast => self.handle_fast(message)?,
             _ => {} // TODO: handle other messages,
         }
         Ok(())
     }
 
+    /// Replaces the pieces the peer has, updating their availability.
+    fn set_peer_bitfield(&mut self, bitfield: Bitfield) -> Result<(), PeerSessionError> {
+        self.torrent_status
+            .peer_lost_pieces(&self.bitfield)
+            .and_then(|_| self.torrent_status.peer_has_pieces(&bitfield))
+            .map_err(PeerSessionError::ErrorUpdatingPieceAvailability)?;
+        self.bitfield = bitfield;
+        Ok(())
+    }
+
+    /// Handles a piece message received from the peer, keeping the block only if it's one of the pending requests.
+    ///
+    /// A block of a requested piece and offset but with another length is requested again, and blocks that weren't requested are dropped, so neither of them corrupts the piece.
+    fn handle_piece(
+        &mut self,
+        message: Message,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        let (index, begin, block) = self
+            .message_handler
+            .handle_piece(message)
+            .map_err(PeerSessionError::MessageHandlerError)?;
+
+        let position = match self
+            .pending_requests
+            .iter()
+            .position(|request| request.index() == index && request.begin() == begin)
+        {
+            Some(position) => position,
+            None => {
+                self.logger_sender.warn(&format!(
+                    "IP: {}:{} Dropped unrequested block: piece {} / offset {}",
+                    self.peer.ip, self.peer.port, index, begin
+                ));
+                return Ok(());
+            }
+        };
+        let request = self.pending_requests[position].clone();
+        if request.length() as usize != block.len() {
+            self.logger_sender.warn(&format!(
+                "IP: {}:{} Block of piece {} / offset {} has {} bytes instead of {}, requesting it again",
+                self.peer.ip,
+                self.peer.port,
+                index,
+                begin,
+                block.len(),
+                request.length()
+            ));
+            return self
+                .message_handler
+                .send_request(index, begin, request.length(), stream)
+                .map_err(PeerSessionError::MessageHandlerError);
+        }
+
+        self.pending_requests.remove(position);
+        self.torrent_status
+            .block_downloaded(index, begin, &block)
+            .map_err(PeerSessionError::ErrorSavingBlock)?;
+        self.piece_blocks.insert(begin, block);
+        Ok(())
+    }
+
+    /// Handles a fast extension message received from the peer.
+    ///
+    /// `HaveAll` and `HaveNone` replace the bitfield of the peer, and rejected requests abort the download of their piece.
+    /// Suggested and allowed fast pieces are only logged.
+    ///
+    /// It returns a `FastExtensionNotNegotiated` error if the peer didn't advertise the fast extension in its handshake.
+    fn handle_fast(&mut self, message: Message) -> Result<(), PeerSessionError> {
+        if !self.peer.supports_fast_extension {
+            return Err(PeerSessionError::FastExtensionNotNegotiated(message.id));
+        }
+        let fast_message = self
+            .message_handler
+            .handle_fast(message)
+            .map_err(PeerSessionError::MessageHandlerError)?;
+
+        match fast_message {
+            FastMessage::HaveAll => {
+                self.set_peer_bitfield(Bitfield::full(self.torrent.total_pieces()))?
+            }
+            FastMessage::HaveNone => {
+                self.set_peer_bitfield(Bitfield::empty(self.torrent.total_pieces()))?
+            }
+            FastMessage::RejectRequest(request) => self.rejected_requests.push(request),
+            FastMessage::SuggestPiece(index) => self.logger_sender.info(&format!(
+                "IP: {}:{} Suggested piece: {}",
+                self.peer.ip, self.peer.port, index
+            )),
+            FastMessage::AllowedFast(index) => self.logger_sender.info(&format!(
+                "IP: {}:{} Allowed fast piece: {}",
+                self.peer.ip, self.peer.port, index
+            )),
+        }
+        Ok(())
+    }
+
     /// Sets read and write timeouts for the stream.
-    fn set_stream_timeouts(&self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+    fn set_stream_timeouts(&self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
         stream
-            .set_read_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_read_timeout(Some(self.config.read_timeout()))
             .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
 
         stream
-            .set_write_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
+            .set_write_timeout(Some(self.config.write_timeout()))
             .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
         Ok(())
     }
 
+    /// Handles an extended message received from the peer.
+    ///
+    /// The extended handshake stores the extensions the peer supports and the size of its metadata, and `ut_metadata` messages are handled by `handle_metadata`.
+    fn handle_extended(
+        &mut self,
+        message: Message,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        if message.payload.first() == Some(&UT_METADATA_ID) {
+            return self.handle_metadata(message, stream);
+        }
+
+        let extended = self
+            .message_handler
+            .handle_extended(message)
+            .map_err(PeerSessionError::MessageHandlerError)?;
+
+        if extended.is_handshake() {
+            self.peer_extensions = extended.supported_extensions();
+            self.peer_metadata_size = extended.metadata_size();
+            self.logger_sender.info(&format!(
+                "IP: {}:{} Extended handshake received, supported extensions: {:?}",
+                self.peer.ip,
+                self.peer.port,
+                self.peer_extensions.keys()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Handles a `ut_metadata` message received from the peer.
+    ///
+    /// Requests are answered with our metadata. Data and rejects are only handled while fetching the metadata, storing the received piece or failing the fetch.
+    fn handle_metadata(
+        &mut self,
+        message: Message,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        let metadata_msg = self
+            .message_handler
+            .handle_metadata(message)
+            .map_err(PeerSessionError::MessageHandlerError)?;
+
+        match metadata_msg.msg_type {
+            MetadataMessageType::Request => {
+                if let Some(peer_ext_id) = self.peer_extensions.get(UT_METADATA) {
+                    self.message_handler
+                        .send_metadata_piece(*peer_ext_id, metadata_msg.piece, stream)
+                        .map_err(PeerSessionError::MessageHandlerError)?;
+                }
+            }
+            MetadataMessageType::Data => {
+                if let Some(metadata) = self.metadata.as_mut() {
+                    metadata
+                        .add_piece(metadata_msg.piece, metadata_msg.data)
+                        .map_err(PeerSessionError::InvalidMetadata)?;
+                }
+            }
+            MetadataMessageType::Reject => {
+                if self.metadata.is_some() {
+                    return Err(PeerSessionError::MetadataRejected(metadata_msg.piece));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Handles a piece message received from the peer.
     fn handle_request(
         &mut self,
         message: Message,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
-        let mut index: [u8; 4] = [0; 4];
-        let mut begin: [u8; 4] = [0; 4];
-        let mut length: [u8; 4] = [0; 4];
-        index.copy_from_slice(&message.payload[0..4]);
-        begin.copy_from_slice(&message.payload[4..8]);
-        length.copy_from_slice(&message.payload[8..12]);
-
-        let index = u32::from_be_bytes(index);
-        let begin = u32::from_be_bytes(begin);
-        let length = u32::from_be_bytes(length);
+        let request = self
+            .message_handler
+            .handle_request(message)
+            .map_err(PeerSessionError::MessageHandlerError)?;
+        let (index, begin, length) = (request.index(), request.begin(), request.length());
 
-        let offset = index * self.torrent.piece_length() + begin;
+        let offset = index as u64 * self.torrent.piece_length() as u64 + begin as u64;
 
         let upload_start_time = Local::now();
 
-        let block = self
+        let block = match self
             .torrent_status
-            .get_piece(index, offset as u64, length as usize)
-            .map_err(PeerSessionError::ErrorGettingPiece)?;
+            .get_piece(index, offset, length as usize)
+        {
+            Ok(block) => block,
+            // With the fast extension, requests that can't be served are rejected instead of closing the connection.
+            Err(
+                AtomicTorrentStatusError::PieceWasNotFinished
+                | AtomicTorrentStatusError::InvalidPieceIndex,
+            ) if self.peer.supports_fast_extension => {
+                return self
+                    .message_handler
+                    .send_reject_request(&request, stream)
+                    .map_err(PeerSessionError::MessageHandlerError);
+            }
+            Err(err) => return Err(PeerSessionError::ErrorGettingPiece(err)),
+        };
 
         self.message_handler
             .send_piece(index, begin, &block, stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
+        self.torrent_status.metrics().bytes_uploaded(block.len());
+        // The torrent handler tells the tracker that the torrent stopped once its status is stopped.
+        if self.torrent_status.take_seed_ratio_reached() {
+            self.logger_sender.info(&format!(
+                "Seed ratio reached for torrent: {}",
+                self.torrent.name()
+            ));
+            self.torrent_status.stop();
+        }
 
         // Calculate upload speed
         let upload_speed = self.calculate_kilobits_per_second(upload_start_time, (length).into());
-        self.status.upload_speed = upload_speed;
+        self.status.upload_speed = self.upload_speed.update(upload_speed);
         self.update_peer_status()?;
         Ok(())
     }
@@ -644,3 +1190,1175 @@ impl PeerSession {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        fs,
+        io::Write as IOWrite,
+        net::TcpListener,
+        sync::mpsc,
+        thread::{self, JoinHandle},
+    };
+
+    use bencoder::bencode::Bencode;
+
+    use crate::{
+        peer::{
+            handshake::Handshake,
+            mse::CRYPTO_PLAINTEXT,
+            peer_message::{ExtendedMessage, MetadataMessage, RequestError, METADATA_PIECE_SIZE},
+        },
+        torrent_parser::info::Info,
+    };
+
+    use super::*;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    /// The `(index, begin, length)` of a request.
+    type TestRequest = (u32, u32, u32);
+
+    #[test]
+    fn test_last_piece_shorter_than_a_block_is_requested_as_a_single_block() {
+        let last_piece = vec![7; 100];
+        let torrent = create_test_torrent(
+            "test_last_piece_shorter_than_a_block",
+            &[vec![1; 2 * BLOCK_SIZE as usize], last_piece.clone()],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, move |listener| {
+            answer_test_requests(listener, last_piece)
+        });
+
+        session.download_piece(&mut stream, 1).unwrap();
+        drop(stream);
+
+        assert_eq!(seeder.join().unwrap(), vec![(1, 0, 100)]);
+        assert_eq!(session.piece, vec![7; 100]);
+    }
+
+    #[test]
+    fn test_last_piece_shorter_than_a_block_is_validated() {
+        let torrent = create_test_torrent(
+            "test_last_piece_shorter_than_a_block_is_validated",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            answer_test_requests(listener, vec![8; 100])
+        });
+
+        assert!(matches!(
+            session.download_piece(&mut stream, 1),
+            Err(PeerSessionError::PieceHashDoesNotMatch)
+        ));
+        drop(stream);
+        assert_eq!(seeder.join().unwrap(), vec![(1, 0, 100)]);
+    }
+
+    #[test]
+    fn test_last_piece_with_a_partial_block() {
+        let last_piece = vec![7; BLOCK_SIZE as usize + 100];
+        let torrent = create_test_torrent(
+            "test_last_piece_with_a_partial_block",
+            &[vec![1; 2 * BLOCK_SIZE as usize], last_piece.clone()],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, move |listener| {
+            answer_test_requests(listener, last_piece)
+        });
+
+        session.download_piece(&mut stream, 1).unwrap();
+        drop(stream);
+
+        assert_eq!(
+            seeder.join().unwrap(),
+            vec![(1, 0, BLOCK_SIZE), (1, BLOCK_SIZE, 100)]
+        );
+    }
+
+    #[test]
+    fn test_aborted_piece_is_resumed_from_its_downloaded_blocks() {
+        let piece: Vec<u8> = (0..4 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+        let torrent = create_test_torrent(
+            "test_aborted_piece_is_resumed_from_its_downloaded_blocks",
+            &[piece.clone(), vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let piece = piece.clone();
+            move |listener| answer_test_requests(listener, piece)
+        });
+        let bitfield = Bitfield::new(vec![0b11000000]);
+        let status = session.torrent_status.clone();
+        let index = status.select_piece(&bitfield).unwrap().unwrap();
+        let first_blocks = 2 * BLOCK_SIZE as usize;
+        status
+            .block_downloaded(index, 0, &piece[..BLOCK_SIZE as usize])
+            .unwrap();
+        status
+            .block_downloaded(index, BLOCK_SIZE, &piece[BLOCK_SIZE as usize..first_blocks])
+            .unwrap();
+        status.piece_aborted(index).unwrap();
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(index));
+
+        session.download_piece(&mut stream, index).unwrap();
+        drop(stream);
+
+        assert_eq!(
+            seeder.join().unwrap(),
+            vec![
+                (0, 2 * BLOCK_SIZE, BLOCK_SIZE),
+                (0, 3 * BLOCK_SIZE, BLOCK_SIZE)
+            ]
+        );
+        assert_eq!(session.piece, piece);
+    }
+
+    #[test]
+    fn test_dropped_block_is_downloaded_from_a_second_attempt() {
+        let piece: Vec<u8> = (0..4 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+        let torrent = create_test_torrent(
+            "test_dropped_block_is_downloaded_from_a_second_attempt",
+            &[piece.clone(), vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let piece = piece.clone();
+            move |listener| {
+                answer_test_requests_failing(listener, piece, vec![], vec![2 * BLOCK_SIZE])
+            }
+        });
+        session.config.piece_request_seconds_timeout = 1;
+        session.bitfield = Bitfield::new(vec![0b10000000]);
+        let status = session.torrent_status.clone();
+
+        assert!(matches!(
+            session.request_pieces(&mut stream),
+            Err(PeerSessionError::PieceRequestTimeout)
+        ));
+        drop(stream);
+        seeder.join().unwrap();
+        let downloaded_blocks = status.downloaded_blocks_by_offset(0).unwrap();
+        assert_eq!(
+            downloaded_blocks.keys().copied().collect::<Vec<u32>>(),
+            vec![0, BLOCK_SIZE, 3 * BLOCK_SIZE]
+        );
+
+        let (mut second_session, mut second_stream, second_seeder) =
+            create_test_session_with_status(&torrent, status.clone(), {
+                let piece = piece.clone();
+                move |listener| answer_test_requests(listener, piece)
+            });
+        assert_eq!(status.select_piece(&session.bitfield).unwrap(), Some(0));
+        second_session
+            .download_piece(&mut second_stream, 0)
+            .unwrap();
+        drop(second_stream);
+
+        assert_eq!(
+            second_seeder.join().unwrap(),
+            vec![(0, 2 * BLOCK_SIZE, BLOCK_SIZE)]
+        );
+        assert_eq!(second_session.piece, piece);
+    }
+
+    #[test]
+    fn test_piece_is_aborted_when_its_blocks_do_not_arrive_in_time() {
+        let torrent = create_test_torrent(
+            "test_piece_is_aborted_when_its_blocks_do_not_arrive_in_time",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) =
+            create_test_session(&torrent, answer_test_requests_with_keep_alives);
+        session.config.piece_request_seconds_timeout = 1;
+        session.bitfield = Bitfield::new(vec![0b11000000]);
+        let status = session.torrent_status.clone();
+
+        assert!(matches!(
+            session.request_pieces(&mut stream),
+            Err(PeerSessionError::PieceRequestTimeout)
+        ));
+        drop(stream);
+        seeder.join().unwrap();
+
+        assert_eq!(status.downloading_pieces(), 0);
+        assert_eq!(status.missing_pieces().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_pieces_announced_only_with_have_messages_are_selectable() {
+        let torrent = create_test_torrent(
+            "test_pieces_announced_only_with_have_messages_are_selectable",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            for index in [1u32, 7] {
+                let have = Message::new(MessageId::Have, index.to_be_bytes().to_vec());
+                stream.write_all(&have.as_bytes()).unwrap();
+            }
+        });
+        let status = session.torrent_status.clone();
+        assert_eq!(status.select_piece(&session.bitfield).unwrap(), None);
+
+        session.read_message_from_stream(&mut stream).unwrap();
+        session.read_message_from_stream(&mut stream).unwrap();
+        seeder.join().unwrap();
+
+        assert!(session.bitfield.has_piece(1));
+        assert!(!session.bitfield.has_piece(7));
+        assert_eq!(status.select_piece(&session.bitfield).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_have_all_makes_every_piece_selectable() {
+        let torrent = create_test_torrent(
+            "test_have_all_makes_every_piece_selectable",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let have_all = Message::new(MessageId::HaveAll, vec![]);
+            stream.write_all(&have_all.as_bytes()).unwrap();
+        });
+        session.peer.supports_fast_extension = true;
+        let status = session.torrent_status.clone();
+
+        session.read_message_from_stream(&mut stream).unwrap();
+        seeder.join().unwrap();
+
+        assert!(session.bitfield.has_piece(0));
+        assert!(session.bitfield.has_piece(1));
+        assert_eq!(status.select_piece(&session.bitfield).unwrap(), Some(0));
+        assert_eq!(status.select_piece(&session.bitfield).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_have_none_clears_the_pieces_of_the_peer() {
+        let torrent = create_test_torrent(
+            "test_have_none_clears_the_pieces_of_the_peer",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            for id in [MessageId::HaveAll, MessageId::HaveNone] {
+                stream
+                    .write_all(&Message::new(id, vec![]).as_bytes())
+                    .unwrap();
+            }
+        });
+        session.peer.supports_fast_extension = true;
+        let status = session.torrent_status.clone();
+
+        session.read_message_from_stream(&mut stream).unwrap();
+        session.read_message_from_stream(&mut stream).unwrap();
+        seeder.join().unwrap();
+
+        assert_eq!(session.bitfield.count_pieces(torrent.total_pieces()), 0);
+        assert_eq!(status.select_piece(&session.bitfield).unwrap(), None);
+    }
+
+    #[test]
+    fn test_fast_messages_without_negotiating_the_fast_extension() {
+        let torrent = create_test_torrent(
+            "test_fast_messages_without_negotiating_the_fast_extension",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let have_all = Message::new(MessageId::HaveAll, vec![]);
+            stream.write_all(&have_all.as_bytes()).unwrap();
+        });
+
+        let result = session.read_message_from_stream(&mut stream);
+        seeder.join().unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PeerSessionError::FastExtensionNotNegotiated(
+                MessageId::HaveAll
+            ))
+        ));
+        assert_eq!(session.bitfield.count_pieces(torrent.total_pieces()), 0);
+    }
+
+    #[test]
+    fn test_piece_with_a_short_payload_is_an_error() {
+        let torrent = create_test_torrent(
+            "test_piece_with_a_short_payload_is_an_error",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let piece = Message::new(MessageId::Piece, vec![0, 0, 0, 0, 0, 0, 0]);
+            stream.write_all(&piece.as_bytes()).unwrap();
+        });
+
+        let result = session.read_message_from_stream(&mut stream);
+        seeder.join().unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PeerSessionError::MessageHandlerError(
+                MessageHandlerError::InvalidPayloadLength(MessageId::Piece, 7)
+            ))
+        ));
+        assert!(session.piece_blocks.is_empty());
+    }
+
+    #[test]
+    fn test_request_with_a_short_payload_is_an_error() {
+        let torrent = create_test_torrent(
+            "test_request_with_a_short_payload_is_an_error",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = Message::new(MessageId::Request, vec![0, 0, 0, 0, 0, 0, 0, 0]);
+            stream.write_all(&request.as_bytes()).unwrap();
+        });
+
+        let result = session.read_message_from_stream(&mut stream);
+        seeder.join().unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PeerSessionError::MessageHandlerError(
+                MessageHandlerError::InvalidRequest(RequestError::InvalidLength(8))
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_have_with_a_short_payload_is_an_error() {
+        let torrent = create_test_torrent(
+            "test_have_with_a_short_payload_is_an_error",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let have = Message::new(MessageId::Have, vec![0, 1]);
+            stream.write_all(&have.as_bytes()).unwrap();
+        });
+
+        let result = session.read_message_from_stream(&mut stream);
+        seeder.join().unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PeerSessionError::MessageHandlerError(
+                MessageHandlerError::InvalidPayloadLength(MessageId::Have, 2)
+            ))
+        ));
+        assert_eq!(session.bitfield.count_pieces(torrent.total_pieces()), 0);
+    }
+
+    #[test]
+    fn test_rejected_block_is_not_requested_again() {
+        let piece: Vec<u8> = (0..2 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+        let torrent = create_test_torrent(
+            "test_rejected_block_is_not_requested_again",
+            &[piece.clone(), vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let piece = piece.clone();
+            move |listener| answer_test_requests_failing(listener, piece, vec![BLOCK_SIZE], vec![])
+        });
+        session.peer.supports_fast_extension = true;
+        let status = session.torrent_status.clone();
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b10000000]))
+            .unwrap()
+            .unwrap();
+        session.current_piece = index;
+
+        assert!(matches!(
+            session.download_piece(&mut stream, index),
+            Err(PeerSessionError::BlockRejected(0, BLOCK_SIZE))
+        ));
+        drop(stream);
+
+        assert_eq!(
+            seeder.join().unwrap(),
+            vec![(0, 0, BLOCK_SIZE), (0, BLOCK_SIZE, BLOCK_SIZE)]
+        );
+        let downloaded_blocks = status.downloaded_blocks_by_offset(0).unwrap();
+        assert_eq!(
+            downloaded_blocks.keys().copied().collect::<Vec<u32>>(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_piece_with_a_rejected_block_is_left_for_other_peers() {
+        let piece: Vec<u8> = (0..2 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+        let torrent = create_test_torrent(
+            "test_piece_with_a_rejected_block_is_left_for_other_peers",
+            &[piece.clone(), piece[..100].to_vec()],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let piece = piece.clone();
+            move |listener| answer_test_requests_failing(listener, piece, vec![BLOCK_SIZE], vec![])
+        });
+        session.peer.supports_fast_extension = true;
+        session.bitfield = Bitfield::full(2);
+        session.status.choked = false;
+        let status = session.torrent_status.clone();
+
+        assert!(matches!(
+            session.request_pieces(&mut stream),
+            Err(PeerSessionError::NoPiecesLeftToDownloadInThisPeer)
+        ));
+        drop(stream);
+        let _ = fs::remove_file(format!(
+            "{}/{}",
+            session.config.download_directory, torrent.info.name
+        ));
+
+        assert_eq!(
+            seeder.join().unwrap(),
+            vec![(0, 0, BLOCK_SIZE), (0, BLOCK_SIZE, BLOCK_SIZE), (1, 0, 100)]
+        );
+        assert_eq!(status.select_piece(&Bitfield::full(2)).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_out_of_order_blocks_are_assembled_by_offset() {
+        let piece: Vec<u8> = (0..4 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+        let torrent = create_test_torrent(
+            "test_out_of_order_blocks_are_assembled_by_offset",
+            &[piece.clone(), vec![7; 100]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let piece = piece.clone();
+            move |listener| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let requests: Vec<TestRequest> =
+                    (0..4).map(|_| read_test_request(&mut stream)).collect();
+                for request in requests.iter().rev() {
+                    send_test_block(&mut stream, *request, &piece);
+                }
+                requests
+            }
+        });
+        session.config.pipelining_size = 4;
+
+        session.download_piece(&mut stream, 0).unwrap();
+        drop(stream);
+
+        assert_eq!(seeder.join().unwrap().len(), 4);
+        assert_eq!(session.piece, piece);
+    }
+
+    #[test]
+    fn test_piece_is_assembled_from_blocks_in_reverse_order() {
+        let piece: Vec<u8> = (0..2 * BLOCK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let torrent = create_test_torrent(
+            "test_piece_is_assembled_from_blocks_in_reverse_order",
+            &[piece.clone(), vec![7; 100]],
+        );
+        let (session, _stream, seeder) = create_test_session(&torrent, |listener| {
+            listener.accept().unwrap();
+        });
+        seeder.join().unwrap();
+        let blocks: Vec<(u32, Vec<u8>)> = session
+            .blocks_in_torrent_piece(0)
+            .into_iter()
+            .rev()
+            .map(|(begin, length)| {
+                let range = begin as usize..(begin + length) as usize;
+                (begin, piece[range].to_vec())
+            })
+            .collect();
+
+        let assembled = session.assemble_piece(0, blocks);
+
+        assert_eq!(assembled, piece);
+        assert!(session.validate_piece(&assembled, 0).is_ok());
+    }
+
+    #[test]
+    fn test_piece_with_a_missing_block_fails_its_hash() {
+        let piece: Vec<u8> = (0..2 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        let torrent = create_test_torrent(
+            "test_piece_with_a_missing_block_fails_its_hash",
+            &[piece.clone(), vec![7; 100]],
+        );
+        let (session, _stream, seeder) = create_test_session(&torrent, |listener| {
+            listener.accept().unwrap();
+        });
+        seeder.join().unwrap();
+
+        let assembled =
+            session.assemble_piece(0, [(BLOCK_SIZE, piece[BLOCK_SIZE as usize..].to_vec())]);
+
+        assert_eq!(assembled.len(), piece.len());
+        assert!(matches!(
+            session.validate_piece(&assembled, 0),
+            Err(PeerSessionError::PieceHashDoesNotMatch)
+        ));
+    }
+
+    #[test]
+    fn test_unrequested_and_mismatched_blocks_are_not_kept() {
+        let piece: Vec<u8> = (0..2 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+        let torrent = create_test_torrent(
+            "test_unrequested_and_mismatched_blocks_are_not_kept",
+            &[piece.clone(), vec![7; 2 * BLOCK_SIZE as usize]],
+        );
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let piece = piece.clone();
+            move |listener| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut requests: Vec<TestRequest> =
+                    (0..2).map(|_| read_test_request(&mut stream)).collect();
+                // A block of another piece, and a block shorter than requested.
+                send_test_block(
+                    &mut stream,
+                    (1, 0, BLOCK_SIZE),
+                    &[9; 2 * BLOCK_SIZE as usize],
+                );
+                send_test_block(&mut stream, (0, 0, 10), &piece);
+                requests.push(read_test_request(&mut stream));
+                for request in &requests {
+                    send_test_block(&mut stream, *request, &piece);
+                }
+                requests
+            }
+        });
+        session.config.pipelining_size = 2;
+        let status = session.torrent_status.clone();
+
+        session.download_piece(&mut stream, 0).unwrap();
+        drop(stream);
+
+        assert_eq!(
+            seeder.join().unwrap(),
+            vec![
+                (0, 0, BLOCK_SIZE),
+                (0, BLOCK_SIZE, BLOCK_SIZE),
+                (0, 0, BLOCK_SIZE)
+            ]
+        );
+        assert_eq!(session.piece, piece);
+        assert!(status.downloaded_blocks_by_offset(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_connecting_to_an_unreachable_peer_fails_within_the_connect_timeout() {
+        let torrent = create_test_torrent(
+            "test_connecting_to_an_unreachable_peer_fails_within_the_connect_timeout",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, stream, seeder) = create_test_session(&torrent, |_| ());
+        drop(stream);
+        seeder.join().unwrap();
+        let (listener, _connections) = create_full_backlog_listener();
+        session.peer = BtPeer::new(
+            "127.0.0.1".to_string(),
+            listener.local_addr().unwrap().port() as i64,
+        );
+        session.config.connect_seconds_timeout = Some(1);
+
+        // Without the connect timeout, the system one takes minutes.
+        let start = Instant::now();
+        assert!(matches!(
+            session.set_up_peer_session(),
+            Err(PeerSessionError::CouldNotConnectToPeer)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_fast_peer_is_told_we_have_no_pieces_right_after_the_handshake() {
+        let torrent = create_test_torrent(
+            "test_fast_peer_is_told_we_have_no_pieces_right_after_the_handshake",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, listener) = create_test_session_with_listener(&torrent);
+        session.config.encryption_mode = EncryptionMode::Disabled;
+        let seeder = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = PeerStream::new(stream);
+            let info_hash = BtPeer::new("127.0.0.1".to_string(), 6881)
+                .receive_handshake(&mut stream)
+                .unwrap();
+            stream
+                .write_all(&Handshake::new(info_hash, vec![1; 20]).as_bytes())
+                .unwrap();
+            let mut first_message = [0; 5];
+            stream.read_exact(&mut first_message).unwrap();
+            first_message
+        });
+
+        let stream = session.set_up_peer_session().unwrap();
+        let first_message = seeder.join().unwrap();
+        drop(stream);
+
+        assert!(session.peer.supports_fast_extension);
+        assert_eq!(
+            first_message.to_vec(),
+            Message::new(MessageId::HaveNone, vec![]).as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_incoming_fast_leecher_gets_the_pieces_we_have_before_any_other_message() {
+        let torrent = create_test_torrent(
+            "test_incoming_fast_leecher_gets_the_pieces_we_have_before_any_other_message",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, leecher) = create_test_session(&torrent, |listener| {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = PeerStream::new(stream);
+            BtPeer::new("127.0.0.1".to_string(), 6881)
+                .receive_handshake(&mut stream)
+                .unwrap();
+            let mut first_message = [0; 5];
+            stream.read_exact(&mut first_message).unwrap();
+            first_message
+        });
+        session.peer.supports_fast_extension = true;
+
+        session.handshake_incoming_leecher(&mut stream).unwrap();
+        let first_message = leecher.join().unwrap();
+
+        assert_eq!(
+            first_message.to_vec(),
+            Message::new(MessageId::HaveNone, vec![]).as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_incoming_fast_leecher_gets_the_pieces_we_have_only_once() {
+        let torrent = create_test_torrent(
+            "test_incoming_fast_leecher_gets_the_pieces_we_have_only_once",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, leecher) = create_test_session(&torrent, |listener| {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = PeerStream::new(stream);
+            BtPeer::new("127.0.0.1".to_string(), 6881)
+                .receive_handshake(&mut stream)
+                .unwrap();
+            stream
+                .write_all(&Message::new(MessageId::Interested, vec![]).as_bytes())
+                .unwrap();
+            for _ in 0..3 {
+                stream.write_all(&[0; 4]).unwrap();
+            }
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+        session.peer.supports_fast_extension = true;
+
+        session.handshake_incoming_leecher(&mut stream).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        assert!(session.unchoke_incoming_leecher(&mut stream).is_err());
+        drop(stream);
+
+        let mut expected = Message::new(MessageId::HaveNone, vec![]).as_bytes();
+        expected.extend(Message::new(MessageId::Unchoke, vec![]).as_bytes());
+        assert_eq!(leecher.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_request_of_a_missing_piece_is_rejected_with_the_fast_extension() {
+        let torrent = create_test_torrent(
+            "test_request_of_a_missing_piece_is_rejected_with_the_fast_extension",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let request = Request::new(0, 0, BLOCK_SIZE);
+        let request_bytes = request.as_bytes();
+        let (mut session, mut stream, leecher) = create_test_session(&torrent, move |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(&Message::new(MessageId::Request, request_bytes).as_bytes())
+                .unwrap();
+            let mut answer = [0; 17];
+            stream.read_exact(&mut answer).unwrap();
+            answer
+        });
+        session.peer.supports_fast_extension = true;
+
+        session.read_message_from_stream(&mut stream).unwrap();
+        let answer = leecher.join().unwrap();
+
+        assert_eq!(
+            answer.to_vec(),
+            Message::new(MessageId::RejectRequest, request.as_bytes()).as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_request_of_a_missing_piece_fails_without_the_fast_extension() {
+        let torrent = create_test_torrent(
+            "test_request_of_a_missing_piece_fails_without_the_fast_extension",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, mut stream, leecher) = create_test_session(&torrent, |listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = Request::new(0, 0, BLOCK_SIZE).as_bytes();
+            stream
+                .write_all(&Message::new(MessageId::Request, request).as_bytes())
+                .unwrap();
+        });
+
+        let result = session.read_message_from_stream(&mut stream);
+        leecher.join().unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PeerSessionError::ErrorGettingPiece(
+                AtomicTorrentStatusError::PieceWasNotFinished
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_metadata_is_fetched_from_the_peer() {
+        let (torrent, info_bytes) = create_test_magnet_torrent("test_metadata_is_fetched");
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, {
+            let info_bytes = info_bytes.clone();
+            move |listener| answer_test_metadata_requests(listener, info_bytes, false)
+        });
+        session.peer.supports_extensions = true;
+
+        let fetched = session.request_metadata(&mut stream).unwrap();
+        drop(stream);
+
+        assert_eq!(seeder.join().unwrap(), vec![0, 1]);
+        assert_eq!(fetched.info_hash, torrent.info_hash);
+        assert_eq!(fetched.announce_url, torrent.announce_url);
+        assert_eq!(fetched.name(), "test_metadata_is_fetched");
+        assert_eq!(fetched.info_bytes, info_bytes);
+    }
+
+    #[test]
+    fn test_metadata_that_does_not_match_the_info_hash_is_rejected() {
+        let (mut torrent, info_bytes) = create_test_magnet_torrent("test_metadata_wrong_hash");
+        torrent.info_hash = [1; 20];
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, move |listener| {
+            answer_test_metadata_requests(listener, info_bytes, false)
+        });
+        session.peer.supports_extensions = true;
+
+        assert!(matches!(
+            session.request_metadata(&mut stream),
+            Err(PeerSessionError::InvalidMetadata(
+                MetadataError::HashDoesNotMatch
+            ))
+        ));
+        drop(stream);
+        seeder.join().unwrap();
+    }
+
+    #[test]
+    fn test_rejected_metadata_request_fails_the_fetch() {
+        let (torrent, info_bytes) = create_test_magnet_torrent("test_rejected_metadata");
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, move |listener| {
+            answer_test_metadata_requests(listener, info_bytes, true)
+        });
+        session.peer.supports_extensions = true;
+
+        assert!(matches!(
+            session.request_metadata(&mut stream),
+            Err(PeerSessionError::MetadataRejected(0))
+        ));
+        drop(stream);
+        seeder.join().unwrap();
+    }
+
+    #[test]
+    fn test_metadata_is_not_fetched_without_the_extension_protocol() {
+        let (torrent, _) = create_test_magnet_torrent("test_metadata_without_extensions");
+        let (mut session, mut stream, seeder) = create_test_session(&torrent, |_| ());
+
+        assert!(matches!(
+            session.request_metadata(&mut stream),
+            Err(PeerSessionError::MetadataNotSupported)
+        ));
+        drop(stream);
+        seeder.join().unwrap();
+    }
+
+    #[test]
+    fn test_connection_is_encrypted_if_required() {
+        let torrent = create_test_torrent(
+            "test_connection_is_encrypted_if_required",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, listener) = create_test_session_with_listener(&torrent);
+        session.config.encryption_mode = EncryptionMode::Required;
+        let seeder = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = PeerStream::new(stream);
+            MseHandshake::new()
+                .respond(&mut stream, &[vec![0; 20]], CRYPTO_RC4)
+                .unwrap();
+            answer_test_handshake(&mut stream);
+        });
+
+        let stream = session.set_up_peer_session().unwrap();
+
+        assert!(stream.is_encrypted());
+        drop(stream);
+        seeder.join().unwrap();
+    }
+
+    #[test]
+    fn test_enabled_encryption_falls_back_to_plaintext() {
+        let torrent = create_test_torrent(
+            "test_enabled_encryption_falls_back_to_plaintext",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, listener) = create_test_session_with_listener(&torrent);
+        session.config.encryption_mode = EncryptionMode::Enabled;
+        let seeder = thread::spawn(move || {
+            // A peer without encryption closes the connection, as it isn't a BitTorrent handshake.
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut [0; 68]).unwrap();
+            drop(stream);
+
+            let (stream, _) = listener.accept().unwrap();
+            answer_test_handshake(&mut PeerStream::new(stream));
+        });
+
+        let stream = session.set_up_peer_session().unwrap();
+
+        assert!(!stream.is_encrypted());
+        drop(stream);
+        seeder.join().unwrap();
+    }
+
+    #[test]
+    fn test_required_encryption_does_not_fall_back_to_plaintext() {
+        let torrent = create_test_torrent(
+            "test_required_encryption_does_not_fall_back_to_plaintext",
+            &[vec![1; 2 * BLOCK_SIZE as usize], vec![7; 100]],
+        );
+        let (mut session, listener) = create_test_session_with_listener(&torrent);
+        session.config.encryption_mode = EncryptionMode::Required;
+        let seeder = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = PeerStream::new(stream);
+            MseHandshake::new().respond(&mut stream, &[vec![0; 20]], CRYPTO_PLAINTEXT)
+        });
+
+        assert!(matches!(
+            session.set_up_peer_session(),
+            Err(PeerSessionError::EncryptionError(_))
+        ));
+        assert!(matches!(
+            seeder.join().unwrap(),
+            Err(MseError::NoCommonCrypto(CRYPTO_RC4))
+        ));
+    }
+
+    #[test]
+    fn test_error_display_and_source() {
+        let io_error = io::Error::new(io::ErrorKind::UnexpectedEof, "early eof");
+        let err = PeerSessionError::ErrorReadingMessage(io_error);
+
+        assert_eq!(err.to_string(), "couldn't read a message from the peer");
+        assert_eq!(err.source().unwrap().to_string(), "early eof");
+    }
+
+    #[test]
+    fn test_error_without_source() {
+        let err = PeerSessionError::PieceHashDoesNotMatch;
+
+        assert_eq!(err.to_string(), "the piece hash does not match");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn test_error_source_is_the_wrapped_error() {
+        let err = PeerSessionError::InvalidBitfield(BitfieldError::SpareBitsSet);
+
+        assert_eq!(err.to_string(), "the peer sent an invalid bitfield");
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            "bitfield has spare bits set"
+        );
+    }
+
+    // Auxiliary functions
+
+    /// Returns a listener that never accepts connections, with its backlog filled by the returned connections, so new connections are never answered.
+    fn create_full_backlog_listener() -> (TcpListener, Vec<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut connections = Vec::new();
+        while let Ok(stream) = TcpStream::connect_timeout(&addr, Duration::from_millis(100)) {
+            connections.push(stream);
+            assert!(connections.len() < 4096, "the backlog is never full");
+        }
+        (listener, connections)
+    }
+
+    /// Creates a torrent with the given pieces, where every piece but the last one has the same length.
+    fn create_test_torrent(name: &str, pieces: &[Vec<u8>]) -> Torrent {
+        let info = Info {
+            length: pieces.iter().map(|piece| piece.len() as i64).sum(),
+            name: name.to_string(),
+            piece_length: pieces[0].len() as i64,
+            pieces: pieces
+                .iter()
+                .flat_map(|piece| Sha1::digest(piece).to_vec())
+                .collect(),
+            files: None,
+        };
+
+        Torrent {
+            announce_url: "announce".to_string(),
+            info,
+            info_hash: [0; 20],
+            info_bytes: Vec::new(),
+        }
+    }
+
+    /// Creates a torrent known only by its info hash, returning it with the metadata of the info hash, which needs two metadata pieces.
+    fn create_test_magnet_torrent(name: &str) -> (Torrent, Vec<u8>) {
+        let info = Info {
+            length: 20000 * 16384,
+            name: name.to_string(),
+            piece_length: 16384,
+            pieces: vec![7; 20000],
+            files: None,
+        };
+        let info_bytes = Bencode::encode(&info);
+
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            info: Info {
+                length: 0,
+                name: String::new(),
+                piece_length: 0,
+                pieces: vec![],
+                files: None,
+            },
+            info_hash: Torrent::create_info_hash(&info_bytes),
+            info_bytes: Vec::new(),
+        };
+        (torrent, info_bytes)
+    }
+
+    /// Sends an extended handshake advertising `ut_metadata` and the size of `info_bytes`, and answers every metadata request with its piece, or rejects it if `reject` is set.
+    ///
+    /// It returns the requested pieces once the stream has been dropped.
+    fn answer_test_metadata_requests(
+        listener: TcpListener,
+        info_bytes: Vec<u8>,
+        reject: bool,
+    ) -> Vec<u32> {
+        const PEER_UT_METADATA_ID: u8 = 3;
+        let (mut stream, _) = listener.accept().unwrap();
+        let handshake = ExtendedMessage::handshake(
+            &[(UT_METADATA, PEER_UT_METADATA_ID)],
+            Some(info_bytes.len()),
+        );
+        stream
+            .write_all(&Message::new(MessageId::Extended, handshake.as_bytes()).as_bytes())
+            .unwrap();
+
+        let mut requested = Vec::new();
+        let mut length = [0; 4];
+        while stream.read_exact(&mut length).is_ok() {
+            let mut payload = vec![0; u32::from_be_bytes(length) as usize];
+            stream.read_exact(&mut payload).unwrap();
+            assert_eq!(
+                payload[..2],
+                [MessageId::Extended as u8, PEER_UT_METADATA_ID]
+            );
+            let piece = MetadataMessage::from_bytes(&payload[2..]).unwrap().piece;
+            requested.push(piece);
+
+            let answer = if reject {
+                MetadataMessage::reject(piece)
+            } else {
+                let start = piece as usize * METADATA_PIECE_SIZE;
+                let end = (start + METADATA_PIECE_SIZE).min(info_bytes.len());
+                MetadataMessage::data(
+                    piece,
+                    info_bytes.len() as u32,
+                    info_bytes[start..end].to_vec(),
+                )
+            };
+            let mut answer_payload = vec![UT_METADATA_ID];
+            answer_payload.extend(answer.as_bytes());
+            if stream
+                .write_all(&Message::new(MessageId::Extended, answer_payload).as_bytes())
+                .is_err()
+            {
+                break;
+            }
+        }
+        requested
+    }
+
+    /// Creates a session connected to a seeder that runs `seeder` on its listener.
+    fn create_test_session<T, F>(
+        torrent: &Torrent,
+        seeder: F,
+    ) -> (PeerSession, PeerStream, JoinHandle<T>)
+    where
+        T: Send + 'static,
+        F: FnOnce(TcpListener) -> T + Send + 'static,
+    {
+        let (status, _) = AtomicTorrentStatus::new(torrent, Cfg::new(CONFIG_PATH).unwrap());
+        create_test_session_with_status(torrent, Arc::new(status), seeder)
+    }
+
+    /// Same as `create_test_session`, but the session uses the given torrent status.
+    fn create_test_session_with_status<T, F>(
+        torrent: &Torrent,
+        status: Arc<AtomicTorrentStatus>,
+        seeder: F,
+    ) -> (PeerSession, PeerStream, JoinHandle<T>)
+    where
+        T: Send + 'static,
+        F: FnOnce(TcpListener) -> T + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = PeerStream::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        let seeder = thread::spawn(move || seeder(listener));
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let (sender, _) = mpsc::channel();
+        let session = PeerSession::new(
+            BtPeer::new("127.0.0.1".to_string(), 6881),
+            torrent.clone(),
+            status,
+            config,
+            LoggerSender::new(sender),
+            "-DT0001-000000000000".to_string(),
+        )
+        .unwrap();
+
+        (session, stream, seeder)
+    }
+
+    /// Creates a session connected to nothing yet, whose peer is the returned listener.
+    fn create_test_session_with_listener(torrent: &Torrent) -> (PeerSession, TcpListener) {
+        let (mut session, stream, seeder) = create_test_session(torrent, |_| ());
+        drop(stream);
+        seeder.join().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        session.peer = BtPeer::new("127.0.0.1".to_string(), port as i64);
+        (session, listener)
+    }
+
+    /// Answers the handshake of the session, reading what it sends next until the stream has been dropped.
+    fn answer_test_handshake(stream: &mut PeerStream) {
+        let info_hash = BtPeer::new("127.0.0.1".to_string(), 6881)
+            .receive_handshake(stream)
+            .unwrap();
+        stream
+            .write_all(&Handshake::new(info_hash, vec![1; 20]).as_bytes())
+            .unwrap();
+        let _ = stream.read_to_end(&mut Vec::new());
+    }
+
+    /// Answers every request with the requested range of `piece`, returning every request received once the stream has been dropped.
+    fn answer_test_requests(listener: TcpListener, piece: Vec<u8>) -> Vec<TestRequest> {
+        answer_test_requests_failing(listener, piece, vec![], vec![])
+    }
+
+    /// Same as `answer_test_requests`, but the first request of each of the `rejected` offsets is rejected, and the requests of the `dropped` offsets are never answered, sending keep-alives meanwhile.
+    fn answer_test_requests_failing(
+        listener: TcpListener,
+        piece: Vec<u8>,
+        mut rejected: Vec<u32>,
+        dropped: Vec<u32>,
+    ) -> Vec<TestRequest> {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut requests = Vec::new();
+        let mut request = [0; 17];
+        if !dropped.is_empty() {
+            stream
+                .set_read_timeout(Some(Duration::from_millis(100)))
+                .unwrap();
+        }
+        loop {
+            match stream.read_exact(&mut request) {
+                Ok(()) => (),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if stream.write_all(&[0; 4]).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Err(_) => break,
+            }
+            let index = u32::from_be_bytes(request[5..9].try_into().unwrap());
+            let begin = u32::from_be_bytes(request[9..13].try_into().unwrap());
+            let length = u32::from_be_bytes(request[13..17].try_into().unwrap());
+            requests.push((index, begin, length));
+
+            if let Some(position) = rejected.iter().position(|offset| *offset == begin) {
+                rejected.remove(position);
+                let reject = Message::new(MessageId::RejectRequest, request[5..17].to_vec());
+                stream.write_all(&reject.as_bytes()).unwrap();
+                continue;
+            }
+            if dropped.contains(&begin) {
+                continue;
+            }
+            let mut payload = request[5..13].to_vec();
+            payload.extend(&piece[begin as usize..(begin + length) as usize]);
+            stream
+                .write_all(&Message::new(MessageId::Piece, payload).as_bytes())
+                .unwrap();
+        }
+        requests
+    }
+
+    /// Reads a request message sent by the session.
+    fn read_test_request(stream: &mut TcpStream) -> TestRequest {
+        let mut request = [0; 17];
+        stream.read_exact(&mut request).unwrap();
+        (
+            u32::from_be_bytes(request[5..9].try_into().unwrap()),
+            u32::from_be_bytes(request[9..13].try_into().unwrap()),
+            u32::from_be_bytes(request[13..17].try_into().unwrap()),
+        )
+    }
+
+    /// Sends the block of the request with the given range of `piece`.
+    fn send_test_block(stream: &mut TcpStream, (index, begin, length): TestRequest, piece: &[u8]) {
+        let mut payload = index.to_be_bytes().to_vec();
+        payload.extend(begin.to_be_bytes());
+        payload.extend(&piece[begin as usize..(begin + length) as usize]);
+        stream
+            .write_all(&Message::new(MessageId::Piece, payload).as_bytes())
+            .unwrap();
+    }
+
+    /// Sends keep-alives without ever answering a request, until the stream has been dropped.
+    fn answer_test_requests_with_keep_alives(listener: TcpListener) {
+        let (mut stream, _) = listener.accept().unwrap();
+        while stream.write_all(&[0; 4]).is_ok() {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use super::mse::rc4::Rc4;
+
+/// A connection to a peer, encrypted once Message Stream Encryption negotiates RC4 and in plaintext otherwise.
+///
+/// To create a new `PeerStream` use the method builder `new()`, and `set_cipher()` to encrypt everything sent and received afterwards.
+#[derive(Debug)]
+pub struct PeerStream {
+    stream: TcpStream,
+    cipher: Option<(Rc4, Rc4)>,
+    pending: Vec<u8>,
+}
+
+impl PeerStream {
+    /// Creates a new plaintext `PeerStream` over the given connection.
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            cipher: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Encrypts what is written with `encryptor` and decrypts what is read with `decryptor` from now on.
+    ///
+    /// Bytes already read and given back with `unread()` are not decrypted.
+    pub fn set_cipher(&mut self, encryptor: Rc4, decryptor: Rc4) {
+        self.cipher = Some((encryptor, decryptor));
+    }
+
+    /// Returns whether the connection is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Gives back bytes that were already read, so they are the first ones read next.
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.pending.splice(0..0, bytes.iter().copied());
+    }
+
+    /// Sets the read timeout of the connection.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// Sets the write timeout of the connection.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+}
+
+impl Read for PeerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.pending.is_empty() {
+            let length = buf.len().min(self.pending.len());
+            buf[..length].copy_from_slice(&self.pending[..length]);
+            self.pending.drain(..length);
+            return Ok(length);
+        }
+
+        let length = self.stream.read(buf)?;
+        if let Some((_, decryptor)) = &mut self.cipher {
+            decryptor.apply(&mut buf[..length]);
+        }
+        Ok(length)
+    }
+}
+
+impl Write for PeerStream {
+    /// Writes the whole buffer, as the keystream can't be rewound if only part of it were written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.cipher {
+            Some((encryptor, _)) => {
+                let mut encrypted = buf.to_vec();
+                encryptor.apply(&mut encrypted);
+                self.stream.write_all(&encrypted)?;
+            }
+            None => self.stream.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_plaintext_stream() {
+        let (mut client, stream) = create_test_connection();
+        let mut stream = PeerStream::new(stream);
+
+        client.write_all(b"hello").unwrap();
+        stream.write_all(b"world").unwrap();
+
+        let mut received = [0; 5];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello");
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"world");
+        assert!(!stream.is_encrypted());
+    }
+
+    #[test]
+    fn test_encrypted_stream() {
+        let (client, stream) = create_test_connection();
+        let mut client = PeerStream::new(client);
+        let mut stream = PeerStream::new(stream);
+        client.set_cipher(Rc4::new(b"a"), Rc4::new(b"b"));
+        stream.set_cipher(Rc4::new(b"b"), Rc4::new(b"a"));
+
+        client.write_all(b"hello").unwrap();
+        stream.write_all(b"world").unwrap();
+
+        let mut received = [0; 5];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello");
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"world");
+        assert!(stream.is_encrypted());
+    }
+
+    #[test]
+    fn test_unread_bytes_are_read_first() {
+        let (mut client, stream) = create_test_connection();
+        let mut stream = PeerStream::new(stream);
+
+        client.write_all(b"world").unwrap();
+        stream.unread(b"hello ");
+
+        let mut received = [0; 11];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello world");
+    }
+
+    // Auxiliary functions
+
+    /// Returns both ends of a local connection: the client and the accepted stream.
+    fn create_test_connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (client, stream)
+    }
+}
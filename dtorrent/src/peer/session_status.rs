@@ -1,22 +1,32 @@
-use super::peer_message::Bitfield;
+use super::peer_message::{Bitfield, MessageId};
 
 /// Represents our status in the peer session.
+///
+/// It keeps the choke and interest state in both directions, as defined in the protocol:
+/// - `choked` and `interested` are our state towards the peer, changed by the messages the peer sends us and the ones we send.
+/// - `peer_choked` and `peer_interested` are the peer state towards us.
+///
+/// A session starts with both sides choked and not interested, which is what `Default` returns.
 #[derive(Debug, Clone)]
 pub struct SessionStatus {
-    /// We are choked
+    /// We are choked by the other peer.
     pub choked: bool,
-    /// We are interested
+    /// We are interested in the other peer.
     pub interested: bool,
-    /// The other peer is choked by us
+    /// The other peer is choked by us.
     pub peer_choked: bool,
-    /// The other peer is interested in us
+    /// The other peer is interested in us.
     pub peer_interested: bool,
+    /// The pieces we have, as last sent to the peer.
     pub bitfield: Bitfield,
+    /// Download speed from the peer, in kilobits per second.
     pub download_speed: f64,
+    /// Upload speed to the peer, in kilobits per second.
     pub upload_speed: f64,
 }
 
 impl SessionStatus {
+    /// Creates the status of a new session, with the given bitfield.
     pub fn new(bitfield: Bitfield) -> Self {
         Self {
             choked: true,
@@ -28,4 +38,122 @@ impl SessionStatus {
             upload_speed: 0.0,
         }
     }
+
+    /// Updates the status after receiving a message from the peer.
+    ///
+    /// Only choke, unchoke, interested and not interested messages change the status.
+    pub fn message_received(&mut self, id: &MessageId) {
+        match id {
+            MessageId::Choke => self.choked = true,
+            MessageId::Unchoke => self.choked = false,
+            MessageId::Interested => self.peer_interested = true,
+            MessageId::NotInterested => self.peer_interested = false,
+            _ => (),
+        }
+    }
+
+    /// Updates the status after sending a message to the peer.
+    ///
+    /// Only choke, unchoke, interested and not interested messages change the status.
+    pub fn message_sent(&mut self, id: &MessageId) {
+        match id {
+            MessageId::Choke => self.peer_choked = true,
+            MessageId::Unchoke => self.peer_choked = false,
+            MessageId::Interested => self.interested = true,
+            MessageId::NotInterested => self.interested = false,
+            _ => (),
+        }
+    }
+}
+
+impl Default for SessionStatus {
+    fn default() -> Self {
+        Self::new(Bitfield::new(vec![]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_status() {
+        let status = SessionStatus::default();
+
+        assert!(status.choked);
+        assert!(!status.interested);
+        assert!(status.peer_choked);
+        assert!(!status.peer_interested);
+        assert_eq!(status.download_speed, 0.0);
+        assert_eq!(status.upload_speed, 0.0);
+    }
+
+    #[test]
+    fn test_receive_unchoke_and_choke() {
+        let mut status = SessionStatus::default();
+
+        status.message_received(&MessageId::Unchoke);
+        assert_only_changed(&status, |expected| expected.choked = false);
+
+        status.message_received(&MessageId::Choke);
+        assert_only_changed(&status, |_| ());
+    }
+
+    #[test]
+    fn test_receive_interested_and_not_interested() {
+        let mut status = SessionStatus::default();
+
+        status.message_received(&MessageId::Interested);
+        assert_only_changed(&status, |expected| expected.peer_interested = true);
+
+        status.message_received(&MessageId::NotInterested);
+        assert_only_changed(&status, |_| ());
+    }
+
+    #[test]
+    fn test_send_unchoke_and_choke() {
+        let mut status = SessionStatus::default();
+
+        status.message_sent(&MessageId::Unchoke);
+        assert_only_changed(&status, |expected| expected.peer_choked = false);
+
+        status.message_sent(&MessageId::Choke);
+        assert_only_changed(&status, |_| ());
+    }
+
+    #[test]
+    fn test_send_interested_and_not_interested() {
+        let mut status = SessionStatus::default();
+
+        status.message_sent(&MessageId::Interested);
+        assert_only_changed(&status, |expected| expected.interested = true);
+
+        status.message_sent(&MessageId::NotInterested);
+        assert_only_changed(&status, |_| ());
+    }
+
+    #[test]
+    fn test_other_messages_do_not_change_the_status() {
+        let mut status = SessionStatus::default();
+
+        status.message_received(&MessageId::Have);
+        status.message_received(&MessageId::Piece);
+        status.message_sent(&MessageId::Request);
+        status.message_sent(&MessageId::Bitfield);
+
+        assert_only_changed(&status, |_| ());
+    }
+
+    // Auxiliary functions
+
+    /// Asserts the status equals the default one with the changes applied by `change`.
+    fn assert_only_changed(status: &SessionStatus, change: impl Fn(&mut SessionStatus)) {
+        let mut expected = SessionStatus::default();
+        change(&mut expected);
+
+        assert_eq!(status.choked, expected.choked);
+        assert_eq!(status.interested, expected.interested);
+        assert_eq!(status.peer_choked, expected.peer_choked);
+        assert_eq!(status.peer_interested, expected.peer_interested);
+    }
 }
@@ -0,0 +1,104 @@
+use chrono::Duration;
+
+/// Minimum elapsed time considered when calculating a rate, in microseconds.
+///
+/// Chunks that complete within the same instant would otherwise give an infinite rate.
+const MIN_ELAPSED_MICROSECONDS: i64 = 1000;
+
+/// Weight of the newest rate in the moving average.
+const SMOOTHING_FACTOR: f64 = 0.3;
+
+/// Exponential moving average of the transfer rate of a peer session.
+///
+/// Every new rate moves the average by `SMOOTHING_FACTOR` of the difference, so a single slow or fast chunk doesn't make the speed jump.
+#[derive(Debug, Clone, Default)]
+pub struct SpeedAverage {
+    average: Option<f64>,
+}
+
+impl SpeedAverage {
+    /// Creates a new `SpeedAverage` without any rate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a new rate to the average and returns the smoothed value.
+    ///
+    /// The first rate is taken as the average.
+    pub fn update(&mut self, rate: f64) -> f64 {
+        let average = match self.average {
+            Some(average) => average + SMOOTHING_FACTOR * (rate - average),
+            None => rate,
+        };
+        self.average = Some(average);
+        average
+    }
+}
+
+/// Returns the instantaneous rate in kilobits per second of transferring `size` bytes in `elapsed_time`.
+///
+/// The elapsed time is clamped to at least `MIN_ELAPSED_MICROSECONDS`, so the rate is always finite.
+pub fn kilobits_per_second(size: u64, elapsed_time: Duration) -> f64 {
+    let elapsed_time_in_seconds = match elapsed_time.num_microseconds() {
+        Some(x) => x.max(MIN_ELAPSED_MICROSECONDS) as f64 / 1_000_000.0,
+        None => return 0.0,
+    };
+    (size as f64 / elapsed_time_in_seconds) * 8.0 / 1024.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_rate_is_the_average() {
+        let mut speed = SpeedAverage::new();
+
+        assert_eq!(speed.update(100.0), 100.0);
+    }
+
+    #[test]
+    fn test_single_slow_chunk_does_not_tank_the_average() {
+        let mut speed = SpeedAverage::new();
+        speed.update(100.0);
+
+        assert_eq!(speed.update(0.0), 70.0);
+    }
+
+    #[test]
+    fn test_average_converges_to_a_constant_rate() {
+        let mut speed = SpeedAverage::new();
+        speed.update(0.0);
+
+        let rates: Vec<f64> = (0..30).map(|_| speed.update(200.0)).collect();
+
+        // It increases monotonically towards the new rate, without overshooting it.
+        assert!(rates.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(rates.iter().all(|rate| *rate < 200.0));
+        assert!((rates[0] - 60.0).abs() < 1e-9);
+        assert!(200.0 - rates[29] < 0.01);
+    }
+
+    #[test]
+    fn test_kilobits_per_second() {
+        let rate = kilobits_per_second(1024, Duration::seconds(1));
+
+        assert_eq!(rate, 8.0);
+    }
+
+    #[test]
+    fn test_kilobits_per_second_with_zero_elapsed_time() {
+        let rate = kilobits_per_second(16384, Duration::zero());
+
+        assert!(rate.is_finite());
+        assert_eq!(rate, kilobits_per_second(16384, Duration::milliseconds(1)));
+    }
+
+    #[test]
+    fn test_kilobits_per_second_with_negative_elapsed_time() {
+        let rate = kilobits_per_second(16384, Duration::milliseconds(-5));
+
+        assert!(rate.is_finite());
+        assert!(rate > 0.0);
+    }
+}
@@ -0,0 +1,58 @@
+use std::{fs, io, net::Ipv4Addr};
+
+const ROUTES_PATH: &str = "/proc/net/route";
+
+/// Returns the IPv4 address of the default gateway, from the routing table of the system.
+///
+/// # Errors
+/// - The routing table couldn't be read. It is only available on Linux.
+/// - `NotFound` if there's no default route.
+pub fn default_gateway() -> io::Result<Ipv4Addr> {
+    let routes = fs::read_to_string(ROUTES_PATH)?;
+    parse_default_gateway(&routes)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no default route found"))
+}
+
+/// Returns the gateway of the default route of a routing table in the format of `/proc/net/route`.
+///
+/// Addresses are written in hex, in the byte order of the host.
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    routes.lines().skip(1).find_map(|route| {
+        let fields: Vec<&str> = route.split_whitespace().collect();
+        match fields[..] {
+            [_, "00000000", gateway, ..] => u32::from_str_radix(gateway, 16)
+                .ok()
+                .map(|gateway| Ipv4Addr::from(gateway.to_ne_bytes())),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The routing table was written by a little endian host.
+    #[cfg(target_endian = "little")]
+    #[test]
+    fn test_parse_default_gateway() {
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+            eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n";
+
+        assert_eq!(
+            parse_default_gateway(routes),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+    }
+
+    #[test]
+    fn test_parse_routes_without_default_gateway() {
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n";
+
+        assert_eq!(parse_default_gateway(routes), None);
+    }
+}
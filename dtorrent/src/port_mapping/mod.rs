@@ -0,0 +1,174 @@
+pub mod gateway;
+pub mod nat_pmp;
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use logger::logger_sender::LoggerSender;
+
+use self::nat_pmp::{NatPmpClient, NatPmpError};
+
+/// Seconds a mapping is requested for, as recommended by RFC 6886. It is renewed at half its lifetime.
+const MAPPING_LIFETIME_SECONDS: u32 = 7200;
+/// The shortest time between two renewals, so a gateway granting very short lifetimes isn't flooded. It's also waited before retrying a failed renewal.
+const MIN_RENEWAL_DELAY: Duration = Duration::from_secs(30);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A TCP port of the gateway mapped to a port of this host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortMapping {
+    pub external_ip: Ipv4Addr,
+    pub external_port: u16,
+    pub lifetime: u32,
+}
+
+/// Keeps a TCP port of this host reachable from outside the local network, by mapping a port of the gateway to it with NAT-PMP.
+///
+/// To create a new `PortMapper` use `PortMapper::map()`, and keep the port mapped with `keep_mapped()` until its stop signal is set.
+#[derive(Debug)]
+pub struct PortMapper {
+    client: NatPmpClient,
+    internal_port: u16,
+    mapping: PortMapping,
+    stop: Arc<AtomicBool>,
+}
+
+impl PortMapper {
+    /// Maps a TCP port of the given gateway to the `internal_port` of this host. The same port is requested, but the gateway may choose another one.
+    ///
+    /// # Errors
+    /// - Any `NatPmpError` if the gateway couldn't be reached or refused the mapping.
+    pub fn map(gateway: SocketAddr, internal_port: u16) -> Result<Self, NatPmpError> {
+        let client = NatPmpClient::new(gateway)?;
+        let external_ip = client.external_address()?;
+        let (external_port, lifetime) =
+            client.map_tcp(internal_port, internal_port, MAPPING_LIFETIME_SECONDS)?;
+
+        Ok(Self {
+            client,
+            internal_port,
+            mapping: PortMapping {
+                external_ip,
+                external_port,
+                lifetime,
+            },
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Returns the external address the port is reachable at.
+    pub fn mapping(&self) -> PortMapping {
+        self.mapping
+    }
+
+    /// Returns the signal that makes `keep_mapped()` remove the mapping and return once it is set to true.
+    pub fn stop_signal(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Renews the mapping at half its lifetime until the stop signal is set, and then removes it.
+    ///
+    /// Renewals that fail are logged and retried after `MIN_RENEWAL_DELAY`, so the mapping is always removed once stopped.
+    ///
+    /// # Errors
+    /// - Any `NatPmpError` if the mapping couldn't be removed.
+    pub fn keep_mapped(mut self, logger_sender: LoggerSender) -> Result<(), NatPmpError> {
+        let mut renew_at = Instant::now() + renewal_delay(self.mapping.lifetime);
+        while !self.stop.load(Ordering::Relaxed) {
+            if Instant::now() >= renew_at {
+                renew_at = match self.client.map_tcp(
+                    self.internal_port,
+                    self.mapping.external_port,
+                    MAPPING_LIFETIME_SECONDS,
+                ) {
+                    Ok((external_port, lifetime)) => {
+                        self.mapping.external_port = external_port;
+                        self.mapping.lifetime = lifetime;
+                        Instant::now() + renewal_delay(lifetime)
+                    }
+                    Err(err) => {
+                        logger_sender.warn(&format!(
+                            "Couldn't renew the port mapping, retrying: {:?}",
+                            err
+                        ));
+                        Instant::now() + MIN_RENEWAL_DELAY
+                    }
+                };
+            }
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+        self.client.unmap_tcp(self.internal_port)
+    }
+}
+
+/// Returns the time to wait before renewing a mapping granted for `lifetime` seconds: half of it, but at least `MIN_RENEWAL_DELAY`.
+fn renewal_delay(lifetime: u32) -> Duration {
+    Duration::from_secs(lifetime as u64 / 2).max(MIN_RENEWAL_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, sync::mpsc};
+
+    use super::*;
+
+    #[test]
+    fn test_mapping_is_removed_once_stopped() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        let responses = vec![
+            vec![0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7],
+            vec![
+                0, 130, 0, 0, 0, 0, 0, 1, 0x1a, 0xe1, 0x1b, 0x3a, 0, 0, 0x1c, 0x20,
+            ],
+            vec![0, 130, 0, 0, 0, 0, 0, 1, 0x1a, 0xe1, 0, 0, 0, 0, 0, 0],
+        ];
+        let requests = thread::spawn(move || {
+            let mut requests = Vec::new();
+            let mut request = [0; 12];
+            for response in responses {
+                let (length, client) = gateway.recv_from(&mut request).unwrap();
+                requests.push(request[..length].to_vec());
+                gateway.send_to(&response, client).unwrap();
+            }
+            requests
+        });
+
+        let mapper = PortMapper::map(gateway_addr, 6881).unwrap();
+        assert_eq!(
+            mapper.mapping(),
+            PortMapping {
+                external_ip: Ipv4Addr::new(203, 0, 113, 7),
+                external_port: 6970,
+                lifetime: 7200,
+            }
+        );
+        mapper.stop_signal().store(true, Ordering::Relaxed);
+        let (sender, _) = mpsc::channel();
+        mapper.keep_mapped(LoggerSender::new(sender)).unwrap();
+
+        let requests = requests.join().unwrap();
+        assert_eq!(
+            requests.last().unwrap(),
+            &vec![0, 2, 0, 0, 0x1a, 0xe1, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_renewal_is_at_half_the_lifetime() {
+        assert_eq!(renewal_delay(7200), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_renewal_of_short_lifetimes_waits_the_minimum_delay() {
+        assert_eq!(renewal_delay(0), MIN_RENEWAL_DELAY);
+        assert_eq!(renewal_delay(1), MIN_RENEWAL_DELAY);
+    }
+}
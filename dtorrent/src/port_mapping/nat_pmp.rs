@@ -0,0 +1,420 @@
+use std::{
+    fmt, io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+/// Port of the gateway that NAT-PMP requests are sent to.
+pub const NAT_PMP_PORT: u16 = 5351;
+
+const VERSION: u8 = 0;
+const EXTERNAL_ADDRESS_OPCODE: u8 = 0;
+const MAP_TCP_OPCODE: u8 = 2;
+// The opcode of a response is the one of its request plus 128.
+const EXTERNAL_ADDRESS_RESPONSE_OPCODE: u8 = 128;
+const MAP_TCP_RESPONSE_OPCODE: u8 = 130;
+const EXTERNAL_ADDRESS_RESPONSE_LENGTH: usize = 12;
+const MAP_TCP_RESPONSE_LENGTH: usize = 16;
+
+// Requests are sent again if unanswered, doubling the time waited each time.
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const MAX_ATTEMPTS: u32 = 4;
+
+/// A NAT-PMP request (RFC 6886) sent to the gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatPmpRequest {
+    /// Asks for the external IP address of the gateway.
+    ExternalAddress,
+    /// Asks to map a TCP port of the gateway, preferably `external_port`, to the `internal_port` of this host for `lifetime` seconds.
+    ///
+    /// A `lifetime` of zero removes the mapping.
+    MapTcp {
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    },
+}
+
+/// A successful NAT-PMP response received from the gateway.
+///
+/// The `epoch` is the number of seconds since the gateway started, or lost its mappings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatPmpResponse {
+    ExternalAddress {
+        epoch: u32,
+        external_ip: Ipv4Addr,
+    },
+    MapTcp {
+        epoch: u32,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    },
+}
+
+/// Posible NAT-PMP errors.
+#[derive(Debug)]
+pub enum NatPmpError {
+    IoError(io::Error),
+    NoResponse,
+    InvalidLength(usize),
+    UnsupportedVersion(u8),
+    UnexpectedOpcode(u8),
+    ResultCode(u16),
+}
+
+impl fmt::Display for NatPmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(_) => write!(f, "couldn't communicate with the gateway"),
+            Self::NoResponse => write!(f, "the gateway didn't answer"),
+            Self::InvalidLength(length) => write!(f, "invalid response length: {}", length),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported NAT-PMP version: {}", version)
+            }
+            Self::UnexpectedOpcode(opcode) => write!(f, "unexpected response opcode: {}", opcode),
+            Self::ResultCode(code) => write!(f, "the gateway refused the request: {}", code),
+        }
+    }
+}
+
+impl std::error::Error for NatPmpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl NatPmpRequest {
+    /// Returns the request as it is sent to the gateway.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::ExternalAddress => vec![VERSION, EXTERNAL_ADDRESS_OPCODE],
+            Self::MapTcp {
+                internal_port,
+                external_port,
+                lifetime,
+            } => {
+                // The opcode is followed by two reserved bytes.
+                let mut bytes = vec![VERSION, MAP_TCP_OPCODE, 0, 0];
+                bytes.extend(internal_port.to_be_bytes());
+                bytes.extend(external_port.to_be_bytes());
+                bytes.extend(lifetime.to_be_bytes());
+                bytes
+            }
+        }
+    }
+}
+
+impl NatPmpResponse {
+    /// Parses a response received from the gateway.
+    ///
+    /// # Errors
+    /// - `InvalidLength` if the response is too short for its opcode.
+    /// - `UnsupportedVersion` if the response is not of NAT-PMP version 0.
+    /// - `UnexpectedOpcode` if the response is not for an external address or TCP mapping request.
+    /// - `ResultCode` if the gateway refused the request, with the code of the reason.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NatPmpError> {
+        if bytes.len() < 8 {
+            return Err(NatPmpError::InvalidLength(bytes.len()));
+        }
+        if bytes[0] != VERSION {
+            return Err(NatPmpError::UnsupportedVersion(bytes[0]));
+        }
+        let opcode = bytes[1];
+        let expected_length = match opcode {
+            EXTERNAL_ADDRESS_RESPONSE_OPCODE => EXTERNAL_ADDRESS_RESPONSE_LENGTH,
+            MAP_TCP_RESPONSE_OPCODE => MAP_TCP_RESPONSE_LENGTH,
+            _ => return Err(NatPmpError::UnexpectedOpcode(opcode)),
+        };
+        let result_code = u16::from_be_bytes([bytes[2], bytes[3]]);
+        if result_code != 0 {
+            return Err(NatPmpError::ResultCode(result_code));
+        }
+        if bytes.len() < expected_length {
+            return Err(NatPmpError::InvalidLength(bytes.len()));
+        }
+
+        let epoch = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if opcode == EXTERNAL_ADDRESS_RESPONSE_OPCODE {
+            return Ok(Self::ExternalAddress {
+                epoch,
+                external_ip: Ipv4Addr::new(bytes[8], bytes[9], bytes[10], bytes[11]),
+            });
+        }
+        Ok(Self::MapTcp {
+            epoch,
+            internal_port: u16::from_be_bytes([bytes[8], bytes[9]]),
+            external_port: u16::from_be_bytes([bytes[10], bytes[11]]),
+            lifetime: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        })
+    }
+}
+
+/// Client that sends NAT-PMP requests to a gateway.
+#[derive(Debug)]
+pub struct NatPmpClient {
+    socket: UdpSocket,
+}
+
+impl NatPmpClient {
+    /// Creates a client for the gateway at the given address, usually its `NAT_PMP_PORT`.
+    ///
+    /// # Errors
+    /// - `IoError` if the socket couldn't be opened.
+    pub fn new(gateway: SocketAddr) -> Result<Self, NatPmpError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(NatPmpError::IoError)?;
+        // Only the responses of the gateway are received.
+        socket.connect(gateway).map_err(NatPmpError::IoError)?;
+        Ok(Self { socket })
+    }
+
+    /// Returns the external IP address of the gateway.
+    ///
+    /// It returns the same errors as `send()`.
+    pub fn external_address(&self) -> Result<Ipv4Addr, NatPmpError> {
+        match self.send(NatPmpRequest::ExternalAddress)? {
+            NatPmpResponse::ExternalAddress { external_ip, .. } => Ok(external_ip),
+            NatPmpResponse::MapTcp { .. } => {
+                Err(NatPmpError::UnexpectedOpcode(MAP_TCP_RESPONSE_OPCODE))
+            }
+        }
+    }
+
+    /// Maps a TCP port of the gateway, preferably `external_port`, to the `internal_port` of this host for `lifetime` seconds.
+    ///
+    /// On success it returns the external port mapped and the seconds the mapping lasts, which the gateway may have changed.
+    ///
+    /// It returns the same errors as `send()`.
+    pub fn map_tcp(
+        &self,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: u32,
+    ) -> Result<(u16, u32), NatPmpError> {
+        let request = NatPmpRequest::MapTcp {
+            internal_port,
+            external_port,
+            lifetime,
+        };
+        match self.send(request)? {
+            NatPmpResponse::MapTcp {
+                external_port,
+                lifetime,
+                ..
+            } => Ok((external_port, lifetime)),
+            NatPmpResponse::ExternalAddress { .. } => Err(NatPmpError::UnexpectedOpcode(
+                EXTERNAL_ADDRESS_RESPONSE_OPCODE,
+            )),
+        }
+    }
+
+    /// Removes the mapping of the TCP `internal_port` of this host.
+    ///
+    /// It returns the same errors as `send()`.
+    pub fn unmap_tcp(&self, internal_port: u16) -> Result<(), NatPmpError> {
+        self.map_tcp(internal_port, 0, 0).map(|_| ())
+    }
+
+    /// Sends a request to the gateway and returns its response.
+    ///
+    /// # Errors
+    /// - `IoError` if the request couldn't be sent or the response received.
+    /// - `NoResponse` if the gateway didn't answer after `MAX_ATTEMPTS` attempts.
+    /// - Any error of `NatPmpResponse::from_bytes()` if the response is invalid or the request was refused.
+    fn send(&self, request: NatPmpRequest) -> Result<NatPmpResponse, NatPmpError> {
+        let mut timeout = INITIAL_TIMEOUT;
+        let mut response = [0; MAP_TCP_RESPONSE_LENGTH];
+        for _ in 0..MAX_ATTEMPTS {
+            self.socket
+                .send(&request.as_bytes())
+                .map_err(NatPmpError::IoError)?;
+            self.socket
+                .set_read_timeout(Some(timeout))
+                .map_err(NatPmpError::IoError)?;
+            match self.socket.recv(&mut response) {
+                Ok(length) => return NatPmpResponse::from_bytes(&response[..length]),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    timeout *= 2;
+                }
+                Err(err) => return Err(NatPmpError::IoError(err)),
+            }
+        }
+        Err(NatPmpError::NoResponse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_external_address_request_as_bytes() {
+        assert_eq!(NatPmpRequest::ExternalAddress.as_bytes(), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_map_tcp_request_as_bytes() {
+        let request = NatPmpRequest::MapTcp {
+            internal_port: 6881,
+            external_port: 6969,
+            lifetime: 7200,
+        };
+
+        assert_eq!(
+            request.as_bytes(),
+            vec![0, 2, 0, 0, 0x1a, 0xe1, 0x1b, 0x39, 0, 0, 0x1c, 0x20]
+        );
+    }
+
+    #[test]
+    fn test_external_address_response_from_bytes() {
+        let bytes = [0, 128, 0, 0, 0, 0, 0x01, 0x2c, 203, 0, 113, 7];
+
+        assert_eq!(
+            NatPmpResponse::from_bytes(&bytes).unwrap(),
+            NatPmpResponse::ExternalAddress {
+                epoch: 300,
+                external_ip: Ipv4Addr::new(203, 0, 113, 7),
+            }
+        );
+    }
+
+    #[test]
+    fn test_map_tcp_response_from_bytes() {
+        let bytes = [
+            0, 130, 0, 0, 0, 0, 0x01, 0x2c, 0x1a, 0xe1, 0x1b, 0x3a, 0, 0, 0x0e, 0x10,
+        ];
+
+        assert_eq!(
+            NatPmpResponse::from_bytes(&bytes).unwrap(),
+            NatPmpResponse::MapTcp {
+                epoch: 300,
+                internal_port: 6881,
+                external_port: 6970,
+                lifetime: 3600,
+            }
+        );
+    }
+
+    #[test]
+    fn test_refused_response_from_bytes() {
+        // Result code 2: not authorized.
+        let bytes = [0, 130, 0, 2, 0, 0, 0x01, 0x2c, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert!(matches!(
+            NatPmpResponse::from_bytes(&bytes),
+            Err(NatPmpError::ResultCode(2))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_responses_from_bytes() {
+        assert!(matches!(
+            NatPmpResponse::from_bytes(&[0, 128, 0, 0]),
+            Err(NatPmpError::InvalidLength(4))
+        ));
+        assert!(matches!(
+            NatPmpResponse::from_bytes(&[0, 130, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(NatPmpError::InvalidLength(12))
+        ));
+        assert!(matches!(
+            NatPmpResponse::from_bytes(&[1, 128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(NatPmpError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            NatPmpResponse::from_bytes(&[0, 129, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(NatPmpError::UnexpectedOpcode(129))
+        ));
+    }
+
+    #[test]
+    fn test_client_maps_a_port_with_the_gateway() {
+        let (gateway, requests) = spawn_test_gateway(vec![
+            vec![0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7],
+            vec![
+                0, 130, 0, 0, 0, 0, 0, 1, 0x1a, 0xe1, 0x1b, 0x3a, 0, 0, 0x0e, 0x10,
+            ],
+            vec![0, 130, 0, 0, 0, 0, 0, 1, 0x1a, 0xe1, 0, 0, 0, 0, 0, 0],
+        ]);
+        let client = NatPmpClient::new(gateway).unwrap();
+
+        assert_eq!(
+            client.external_address().unwrap(),
+            Ipv4Addr::new(203, 0, 113, 7)
+        );
+        assert_eq!(client.map_tcp(6881, 6881, 7200).unwrap(), (6970, 3600));
+        client.unmap_tcp(6881).unwrap();
+
+        assert_eq!(
+            requests.join().unwrap(),
+            vec![
+                NatPmpRequest::ExternalAddress.as_bytes(),
+                NatPmpRequest::MapTcp {
+                    internal_port: 6881,
+                    external_port: 6881,
+                    lifetime: 7200,
+                }
+                .as_bytes(),
+                NatPmpRequest::MapTcp {
+                    internal_port: 6881,
+                    external_port: 0,
+                    lifetime: 0,
+                }
+                .as_bytes(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_without_a_gateway_answering() {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = NatPmpClient::new(gateway.local_addr().unwrap()).unwrap();
+
+        assert!(matches!(
+            client.external_address(),
+            Err(NatPmpError::NoResponse)
+        ));
+    }
+
+    #[test]
+    fn test_error_display_and_source() {
+        let err = NatPmpError::IoError(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "connection refused",
+        ));
+
+        assert_eq!(err.to_string(), "couldn't communicate with the gateway");
+        assert_eq!(err.source().unwrap().to_string(), "connection refused");
+    }
+
+    // Auxiliary functions
+
+    /// Spawns a gateway that answers each request with the next of the given responses, returning every request received.
+    fn spawn_test_gateway(
+        responses: Vec<Vec<u8>>,
+    ) -> (SocketAddr, thread::JoinHandle<Vec<Vec<u8>>>) {
+        let gateway = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = gateway.local_addr().unwrap();
+        let requests = thread::spawn(move || {
+            let mut requests = Vec::new();
+            let mut request = [0; 12];
+            for response in responses {
+                let (length, client) = gateway.recv_from(&mut request).unwrap();
+                requests.push(request[..length].to_vec());
+                gateway.send_to(&response, client).unwrap();
+            }
+            requests
+        });
+        (addr, requests)
+    }
+}
@@ -0,0 +1,106 @@
+use super::torrent_stats::TorrentStats;
+
+/// The statistics of every torrent added together, for a summary of the whole client.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AggregateStats {
+    pub download_speed: f64,
+    pub upload_speed: f64,
+    pub connected_peers: usize,
+    pub total_peers: usize,
+    pub downloaded_pieces: usize,
+    pub total_pieces: u64,
+}
+
+impl AggregateStats {
+    /// Adds together the statistics of the given torrents.
+    pub fn for_torrents(torrents: &[TorrentStats]) -> Self {
+        torrents
+            .iter()
+            .fold(Self::default(), |aggregate, torrent| Self {
+                download_speed: aggregate.download_speed + torrent.download_speed,
+                upload_speed: aggregate.upload_speed + torrent.upload_speed,
+                connected_peers: aggregate.connected_peers + torrent.peers_amount,
+                total_peers: aggregate.total_peers + torrent.total_peers,
+                downloaded_pieces: aggregate.downloaded_pieces + torrent.downloaded_pieces_amount,
+                total_pieces: aggregate.total_pieces + torrent.pieces_amount as u64,
+            })
+    }
+
+    /// Returns the percentage of the pieces of every torrent already downloaded, from 0 to 100.
+    ///
+    /// Every piece weighs the same, so larger torrents weigh more than smaller ones.
+    pub fn percentage(&self) -> f64 {
+        if self.total_pieces == 0 {
+            return 0.0;
+        }
+        self.downloaded_pieces as f64 / self.total_pieces as f64 * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_handler::state::TorrentState;
+
+    #[test]
+    fn test_aggregate_without_torrents() {
+        let aggregate = AggregateStats::for_torrents(&[]);
+
+        assert_eq!(aggregate, AggregateStats::default());
+        assert_eq!(aggregate.percentage(), 0.0);
+    }
+
+    #[test]
+    fn test_aggregate_adds_every_torrent() {
+        let torrents = vec![
+            create_test_stats(100.0, 10.0, 2, 1, 4),
+            create_test_stats(50.5, 0.0, 3, 3, 4),
+        ];
+
+        let aggregate = AggregateStats::for_torrents(&torrents);
+
+        assert_eq!(aggregate.download_speed, 150.5);
+        assert_eq!(aggregate.upload_speed, 10.0);
+        assert_eq!(aggregate.connected_peers, 5);
+        assert_eq!(aggregate.total_peers, 50);
+        assert_eq!(aggregate.percentage(), 50.0);
+    }
+
+    #[test]
+    fn test_percentage_is_weighted_by_pieces() {
+        let torrents = vec![
+            create_test_stats(0.0, 0.0, 0, 0, 1),
+            create_test_stats(0.0, 0.0, 0, 3, 3),
+        ];
+
+        assert_eq!(AggregateStats::for_torrents(&torrents).percentage(), 75.0);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_stats(
+        download_speed: f64,
+        upload_speed: f64,
+        peers_amount: usize,
+        downloaded_pieces_amount: usize,
+        pieces_amount: u32,
+    ) -> TorrentStats {
+        TorrentStats {
+            torrent_name: "test.iso".to_string(),
+            info_hash: "2c6b6858d61da9543d4231a71db4b1c9264b0685".to_string(),
+            length: 100,
+            pieces_amount,
+            peers_amount,
+            downloaded_pieces_amount,
+            peers: vec![],
+            total_peers: 25,
+            seeders: 20,
+            leechers: 5,
+            download_speed,
+            upload_speed,
+            eta: "-".to_string(),
+            files: vec![],
+            state: TorrentState::Downloading,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use serde::Serialize;
+
+use crate::{peer::peer_message::Bitfield, torrent_parser::info::InfoFile};
+
+/// The progress of a file of a torrent: how many of its bytes are in finished pieces.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileProgress {
+    pub path: String,
+    pub downloaded: u64,
+    pub length: u64,
+}
+
+impl FileProgress {
+    /// Returns the progress of each file, given in the order their content is laid out in the torrent.
+    ///
+    /// A piece that spans the boundary between two files counts for both, only with the bytes that belong to each one.
+    pub fn for_files(files: &[InfoFile], piece_length: u64, finished: &Bitfield) -> Vec<Self> {
+        let mut file_start = 0;
+        files
+            .iter()
+            .map(|file| {
+                let length = file.length as u64;
+                let file_end = file_start + length;
+                let downloaded =
+                    Self::downloaded_bytes(file_start, file_end, piece_length, finished);
+                file_start = file_end;
+
+                Self {
+                    path: file.path.join("/"),
+                    downloaded,
+                    length,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the bytes between `start` and `end` of the torrent content that are in finished pieces.
+    fn downloaded_bytes(start: u64, end: u64, piece_length: u64, finished: &Bitfield) -> u64 {
+        if piece_length == 0 || start == end {
+            return 0;
+        }
+        let first_piece = start / piece_length;
+        let last_piece = (end - 1) / piece_length;
+
+        (first_piece..=last_piece)
+            .filter(|piece| finished.has_piece(*piece as u32))
+            .map(|piece| {
+                let piece_start = piece * piece_length;
+                let piece_end = piece_start + piece_length;
+                piece_end.min(end) - piece_start.max(start)
+            })
+            .sum()
+    }
+
+    /// Returns the ratio between the downloaded bytes and the length of the file, from 0 to 1.
+    pub fn completed(&self) -> f32 {
+        if self.length == 0 {
+            return 1.0;
+        }
+        self.downloaded as f32 / self.length as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_file_complete_and_the_other_partial() {
+        // Pieces of 10 bytes: the first file takes pieces 0 and 1, and the second one pieces 2 to 4.
+        let files = vec![
+            create_test_file("first", 20),
+            create_test_file("second", 30),
+        ];
+        let finished = Bitfield::new(vec![0b11100000]);
+
+        let progress = FileProgress::for_files(&files, 10, &finished);
+
+        assert_eq!(
+            progress,
+            vec![
+                FileProgress {
+                    path: "dir/first".to_string(),
+                    downloaded: 20,
+                    length: 20
+                },
+                FileProgress {
+                    path: "dir/second".to_string(),
+                    downloaded: 10,
+                    length: 30
+                },
+            ]
+        );
+        assert_eq!(progress[0].completed(), 1.0);
+        assert!((progress[1].completed() - 1.0 / 3.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_piece_spanning_two_files_counts_for_both() {
+        // Pieces of 10 bytes: piece 1 has the last 5 bytes of the first file and the first 5 of the second one.
+        let files = vec![
+            create_test_file("first", 15),
+            create_test_file("second", 15),
+        ];
+        let finished = Bitfield::new(vec![0b01000000]);
+
+        let progress = FileProgress::for_files(&files, 10, &finished);
+
+        assert_eq!(progress[0].downloaded, 5);
+        assert_eq!(progress[1].downloaded, 5);
+    }
+
+    #[test]
+    fn test_files_without_finished_pieces() {
+        let files = vec![
+            create_test_file("first", 15),
+            create_test_file("second", 15),
+        ];
+        let finished = Bitfield::empty(3);
+
+        let progress = FileProgress::for_files(&files, 10, &finished);
+
+        assert!(progress.iter().all(|file| file.downloaded == 0));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_file(name: &str, length: i64) -> InfoFile {
+        InfoFile {
+            length,
+            path: vec!["dir".to_string(), name.to_string()],
+        }
+    }
+}
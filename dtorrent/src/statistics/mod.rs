@@ -1,3 +1,6 @@
+pub mod aggregate_stats;
+pub mod file_progress;
 pub mod peer_stats;
+pub mod statistics_sender;
 pub mod statistics_updater;
 pub mod torrent_stats;
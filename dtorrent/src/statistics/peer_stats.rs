@@ -1,6 +1,7 @@
 use crate::peer::{bt_peer::BtPeer, session_status::SessionStatus};
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PeerStats {
     pub port: i64,
     pub ip: String,
@@ -0,0 +1,48 @@
+use super::torrent_stats::TorrentStats;
+use gtk::glib;
+use std::sync::mpsc;
+
+#[derive(Debug)]
+pub struct StatisticsSenderError;
+
+/// Destination of the statistics periodically sent by the `StatisticsUpdater`.
+///
+/// It's implemented for the GTK channel used by the UI and for the standard library channel, so the client can run without a UI.
+pub trait StatisticsSender: Send {
+    /// Sends the current statistics of every torrent.
+    fn send_statistics(&self, statistics: Vec<TorrentStats>) -> Result<(), StatisticsSenderError>;
+}
+
+impl StatisticsSender for glib::Sender<Vec<TorrentStats>> {
+    fn send_statistics(&self, statistics: Vec<TorrentStats>) -> Result<(), StatisticsSenderError> {
+        self.send(statistics).map_err(|_| StatisticsSenderError)
+    }
+}
+
+impl StatisticsSender for mpsc::Sender<Vec<TorrentStats>> {
+    fn send_statistics(&self, statistics: Vec<TorrentStats>) -> Result<(), StatisticsSenderError> {
+        self.send(statistics).map_err(|_| StatisticsSenderError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_statistics_through_mpsc_channel() {
+        let (sender, receiver) = mpsc::channel();
+
+        sender.send_statistics(vec![]).unwrap();
+
+        assert!(receiver.recv().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_send_statistics_with_closed_channel() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+
+        assert!(sender.send_statistics(vec![]).is_err());
+    }
+}
@@ -1,48 +1,275 @@
-use super::torrent_stats::TorrentStats;
+use super::{statistics_sender::StatisticsSender, torrent_stats::TorrentStats};
 use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
-use core::time;
-use gtk::glib;
-use std::{sync::Arc, thread::sleep};
+use std::{
+    fs, io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::Duration,
+};
+
+/// Unchanged statistics are still sent once every this many update intervals, so the receivers know the client is alive.
+const HEARTBEAT_INTERVALS: u32 = 10;
 
 #[derive(Debug)]
 pub enum StatisticsUpdaterError {
     SenderError,
     TorrentStatisticsError,
+    JsonSerializationError(serde_json::Error),
+    JsonFileError(io::Error),
 }
 pub struct StatisticsUpdater {
     torrent_status_list: Vec<Arc<AtomicTorrentStatus>>,
-    sender: glib::Sender<Vec<TorrentStats>>,
+    sender: Box<dyn StatisticsSender>,
+    json_file: Option<String>,
+    update_interval: Duration,
+    stop: Arc<AtomicBool>,
 }
 
 impl StatisticsUpdater {
+    /// Creates a new `StatisticsUpdater`.
+    ///
+    /// If a `json_file` path is given, the statistics are also written to it as JSON on every update.
+    ///
+    /// The statistics are checked for changes every `update_interval`.
     pub fn new(
         torrent_status_list: Vec<Arc<AtomicTorrentStatus>>,
-        sender: glib::Sender<Vec<TorrentStats>>,
+        sender: Box<dyn StatisticsSender>,
+        json_file: Option<String>,
+        update_interval: Duration,
     ) -> StatisticsUpdater {
         Self {
             torrent_status_list,
             sender,
+            json_file,
+            update_interval,
+            stop: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Returns the signal that stops `run()` once it is set to true.
+    pub fn stop_signal(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Periodically sends the statistics of every torrent, until the stop signal is set.
+    ///
+    /// The statistics are only computed and sent again when some torrent changed, or once every `HEARTBEAT_INTERVALS` update intervals otherwise.
     pub fn run(&self) -> Result<(), StatisticsUpdaterError> {
-        loop {
+        // The first statistics are always sent.
+        let mut idle_intervals = HEARTBEAT_INTERVALS;
+        while !self.stop.load(Ordering::Relaxed) {
+            if !self.take_changed() && idle_intervals < HEARTBEAT_INTERVALS {
+                idle_intervals += 1;
+                sleep(self.update_interval);
+                continue;
+            }
+            idle_intervals = 1;
+
+            let statistics = self
+                .torrent_statistics()
+                .map_err(|_| StatisticsUpdaterError::TorrentStatisticsError)?;
+
+            if let Some(json_file) = &self.json_file {
+                Self::write_json_file(json_file, &statistics)?;
+            }
+
             self.sender
-                .send(
-                    self.torrent_statistics()
-                        .map_err(|_| StatisticsUpdaterError::TorrentStatisticsError)?,
-                )
+                .send_statistics(statistics)
                 .map_err(|_| StatisticsUpdaterError::SenderError)?;
 
-            sleep(time::Duration::from_millis(300)); //Only update the UI every 300ms
+            sleep(self.update_interval);
         }
+        Ok(())
+    }
+
+    /// Returns true if some torrent changed since the last call. Every torrent is checked, so all of them are cleared.
+    fn take_changed(&self) -> bool {
+        self.torrent_status_list
+            .iter()
+            .filter(|torrent_status| torrent_status.take_changed())
+            .count()
+            > 0
     }
 
+    /// Returns the statistics of every torrent, except the ones that were stopped.
     pub fn torrent_statistics(&self) -> Result<Vec<TorrentStats>, AtomicTorrentStatusError> {
         let mut statistics = Vec::new();
-        for torrent_status in &self.torrent_status_list {
+        for torrent_status in self
+            .torrent_status_list
+            .iter()
+            .filter(|torrent_status| !torrent_status.is_stopped())
+        {
             statistics.push(TorrentStats::for_torrent(torrent_status)?);
         }
         Ok(statistics)
     }
+
+    /// Writes the given statistics as a JSON array to the given path.
+    ///
+    /// The content is first written to a temporary file and then renamed, so readers never see a partially written file.
+    pub fn write_json_file(
+        path: &str,
+        statistics: &[TorrentStats],
+    ) -> Result<(), StatisticsUpdaterError> {
+        let values = statistics
+            .iter()
+            .map(|stats| stats.to_json_value())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(StatisticsUpdaterError::JsonSerializationError)?;
+        let json = serde_json::to_string(&values)
+            .map_err(StatisticsUpdaterError::JsonSerializationError)?;
+
+        let temp_path = format!("{}.tmp", path);
+        fs::write(&temp_path, json).map_err(StatisticsUpdaterError::JsonFileError)?;
+        fs::rename(&temp_path, path).map_err(StatisticsUpdaterError::JsonFileError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::cfg::Cfg,
+        torrent_handler::state::TorrentState,
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+    use serde_json::Value;
+    use std::{sync::mpsc, thread};
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_write_json_file_with_no_torrents() {
+        let path = "./test_write_json_file_with_no_torrents.json";
+
+        StatisticsUpdater::write_json_file(path, &[]).unwrap();
+
+        let json: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(json, Value::Array(vec![]));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_write_json_file_with_torrents() {
+        let path = "./test_write_json_file_with_torrents.json";
+        let stats = create_test_stats("test.iso");
+
+        StatisticsUpdater::write_json_file(path, &[stats]).unwrap();
+
+        let json: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let torrents = json.as_array().unwrap();
+        assert_eq!(torrents.len(), 1);
+        assert_eq!(torrents[0]["torrent_name"], "test.iso");
+        assert_eq!(torrents[0]["percentage"], 50.0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_stopped_torrents_are_not_in_the_statistics() {
+        let running = create_test_status("test_running_torrent_statistics");
+        let stopped = create_test_status("test_stopped_torrent_statistics");
+        stopped.stop();
+        let (sender, _receiver) = mpsc::channel::<Vec<TorrentStats>>();
+        let updater = StatisticsUpdater::new(
+            vec![running, stopped],
+            Box::new(sender),
+            None,
+            Duration::from_millis(300),
+        );
+
+        let statistics = updater.torrent_statistics().unwrap();
+
+        assert_eq!(statistics.len(), 1);
+        assert_eq!(
+            statistics[0].torrent_name,
+            "test_running_torrent_statistics"
+        );
+    }
+
+    #[test]
+    fn test_unchanged_statistics_are_sent_at_the_heartbeat_cadence() {
+        let status = create_test_status("test_unchanged_statistics_heartbeat");
+        let (sender, receiver) = mpsc::channel::<Vec<TorrentStats>>();
+        let updater = StatisticsUpdater::new(
+            vec![status],
+            Box::new(sender),
+            None,
+            Duration::from_millis(10),
+        );
+        let stop = updater.stop_signal();
+
+        let runner = thread::spawn(move || updater.run());
+        // Enough for 15 updates, but only 2 heartbeats.
+        thread::sleep(Duration::from_millis(150));
+        stop.store(true, Ordering::Relaxed);
+        runner.join().unwrap().unwrap();
+
+        let sent = receiver.try_iter().count();
+        assert!((1..=2).contains(&sent), "{} statistics sent", sent);
+    }
+
+    #[test]
+    fn test_changed_statistics_are_sent_on_the_next_update() {
+        let status = create_test_status("test_changed_statistics_are_sent");
+        let (sender, receiver) = mpsc::channel::<Vec<TorrentStats>>();
+        let updater = StatisticsUpdater::new(
+            vec![status.clone()],
+            Box::new(sender),
+            None,
+            Duration::from_millis(10),
+        );
+        let stop = updater.stop_signal();
+
+        let runner = thread::spawn(move || updater.run());
+        assert_eq!(receiver.recv().unwrap()[0].total_peers, 0);
+        status.update_total_peers(3, 2);
+        let statistics = receiver.recv_timeout(Duration::from_millis(50));
+        stop.store(true, Ordering::Relaxed);
+        runner.join().unwrap().unwrap();
+
+        assert_eq!(statistics.unwrap()[0].total_peers, 5);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_status(name: &str) -> Arc<AtomicTorrentStatus> {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            files: None,
+        };
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            info,
+            info_hash: [0; 20],
+            info_bytes: Vec::new(),
+        };
+        let (status, _) = AtomicTorrentStatus::new(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+        Arc::new(status)
+    }
+
+    fn create_test_stats(name: &str) -> TorrentStats {
+        TorrentStats {
+            torrent_name: name.to_string(),
+            info_hash: "2c6b6858d61da9543d4231a71db4b1c9264b0685".to_string(),
+            length: 100,
+            pieces_amount: 2,
+            peers_amount: 0,
+            downloaded_pieces_amount: 1,
+            peers: vec![],
+            total_peers: 0,
+            seeders: 0,
+            leechers: 0,
+            download_speed: 0.0,
+            upload_speed: 0.0,
+            eta: "-".to_string(),
+            files: vec![],
+            state: TorrentState::Downloading,
+        }
+    }
 }
@@ -1,9 +1,14 @@
-use super::peer_stats::PeerStats;
-use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
+use super::{file_progress::FileProgress, peer_stats::PeerStats};
+use crate::torrent_handler::{
+    state::TorrentState,
+    status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+};
 use core::time;
+use serde::Serialize;
+use serde_json::json;
 use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TorrentStats {
     pub torrent_name: String,
     pub info_hash: String,
@@ -13,9 +18,13 @@ pub struct TorrentStats {
     pub downloaded_pieces_amount: usize,
     pub peers: Vec<PeerStats>,
     pub total_peers: usize,
+    pub seeders: usize,
+    pub leechers: usize,
     pub download_speed: f64,
     pub upload_speed: f64,
     pub eta: String,
+    pub files: Vec<FileProgress>,
+    pub state: TorrentState,
 }
 
 impl TorrentStats {
@@ -23,41 +32,51 @@ impl TorrentStats {
         torrent_status: &Arc<AtomicTorrentStatus>,
     ) -> Result<Self, AtomicTorrentStatusError> {
         let torrent = torrent_status.torrent.clone(); //TODO: no romper encap
-        let mut peers = Vec::new();
-        let peers_hashmap = torrent_status.get_connected_peers()?;
-        for (peer, session) in peers_hashmap {
-            peers.push(PeerStats::for_peer(peer, session));
-        }
+
+        // Every peer derived value comes from the same snapshot, so the sessions are locked only once.
+        let snapshot = torrent_status.peers_snapshot()?;
+        let peers: Vec<PeerStats> = snapshot
+            .peers
+            .into_iter()
+            .map(|(peer, session)| PeerStats::for_peer(peer, session))
+            .collect();
 
         let (seeders, leechers) = torrent_status.get_total_peers();
         let total_peers = seeders + leechers;
 
         Ok(Self {
             torrent_name: torrent.name(),
-            info_hash: torrent.info_hash(),
+            info_hash: torrent.info_hash_hex(),
             length: torrent.length(),
             pieces_amount: torrent.total_pieces(),
-            peers_amount: torrent_status.current_peers(),
+            peers_amount: peers.len(),
             downloaded_pieces_amount: torrent_status.downloaded_pieces(),
             peers,
             total_peers,
-            download_speed: torrent_status.torrent_download_speed()?,
-            upload_speed: torrent_status.torrent_upload_speed()?,
-            eta: Self::format_eta(torrent_status)?,
+            seeders,
+            leechers,
+            download_speed: snapshot.download_speed,
+            upload_speed: snapshot.upload_speed,
+            eta: Self::format_eta(torrent_status, snapshot.download_speed),
+            files: FileProgress::for_files(
+                &torrent.files(),
+                torrent.piece_length() as u64,
+                &torrent_status.get_bitfield()?,
+            ),
+            state: torrent_status.state()?,
         })
     }
 
-    fn format_eta(
-        torrent_status: &Arc<AtomicTorrentStatus>,
-    ) -> Result<String, AtomicTorrentStatusError> {
-        let down_speed = torrent_status.torrent_download_speed()? / 8_f64;
+    /// Formats the time left to download the torrent at the given download speed, in kilobits per second.
+    fn format_eta(torrent_status: &Arc<AtomicTorrentStatus>, download_speed: f64) -> String {
+        let down_speed = download_speed / 8_f64;
         let remaining_bytes =
             torrent_status.remaining_pieces() as u64 * torrent_status.torrent.piece_length() as u64;
 
         let remaining_kb = remaining_bytes / 1024;
 
         if down_speed == 0.0 {
-            return Ok("-".to_string());
+            return "-".to_string();
         }
 
         let eta = (remaining_kb as f64 / down_speed).ceil() as u64;
@@ -65,7 +84,7 @@ impl TorrentStats {
         let seconds = eta.as_secs() % 60;
         let minutes = (eta.as_secs() / 60) % 60;
         let hours = ((eta.as_secs() / 60) / 60) % 60;
-        Ok(format!("{:#02}:{:#02}:{:#02}", hours, minutes, seconds))
+        format!("{:#02}:{:#02}:{:#02}", hours, minutes, seconds)
     }
 
     pub fn download_percentage(&self) -> f32 {
@@ -75,4 +94,192 @@ impl TorrentStats {
     pub fn torrent_name(&self) -> &str {
         &self.torrent_name
     }
+
+    /// Returns the progress of each file of the torrent.
+    pub fn file_progress(&self) -> Vec<FileProgress> {
+        self.files.clone()
+    }
+
+    /// Serializes the statistics as a JSON string for external monitoring.
+    ///
+    /// Along with every field of the struct it includes the `percentage` of the torrent already downloaded.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.to_json_value()?)
+    }
+
+    /// Builds the JSON value used by `to_json`.
+    pub fn to_json_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        value["percentage"] = json!(self.download_percentage() * 100.0);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::cfg::Cfg,
+        peer::{bt_peer::BtPeer, peer_message::Bitfield, session_status::SessionStatus},
+        torrent_parser::{info::Info, torrent::Torrent},
+        tracker::tracker_response::TrackerResponse,
+    };
+    use serde_json::Value;
+    use std::thread;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_to_json_contains_torrent_fields() {
+        let stats = create_test_stats();
+
+        let json: Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+
+        assert_eq!(json["torrent_name"], "test.iso");
+        assert_eq!(
+            json["info_hash"],
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685"
+        );
+        assert_eq!(json["percentage"], 25.0);
+        assert_eq!(json["download_speed"], 1500.5);
+        assert_eq!(json["upload_speed"], 200.0);
+        assert_eq!(json["peers_amount"], 1);
+        assert_eq!(json["total_peers"], 10);
+        assert_eq!(json["seeders"], 7);
+        assert_eq!(json["leechers"], 3);
+        assert_eq!(json["state"], "Downloading");
+    }
+
+    #[test]
+    fn test_to_json_contains_peers() {
+        let stats = create_test_stats();
+
+        let json: Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+        let peers = json["peers"].as_array().unwrap();
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0]["ip"], "127.0.0.1");
+        assert_eq!(peers[0]["port"], 6881);
+        assert_eq!(peers[0]["download_speed"], 1500.5);
+        assert_eq!(peers[0]["choked"], false);
+    }
+
+    #[test]
+    fn test_seeders_and_leechers_from_tracker_response() {
+        let (status, _) = AtomicTorrentStatus::new(
+            &create_test_torrent("test_seeders_and_leechers_from_tracker_response"),
+            Cfg::new(CONFIG_PATH).unwrap(),
+        );
+        let tracker_response = TrackerResponse::from(
+            b"d8:completei7e10:incompletei3e8:intervali1e5:peerslee".to_vec(),
+        )
+        .unwrap();
+
+        status.update_total_peers_from_response(&tracker_response);
+        let stats = TorrentStats::for_torrent(&Arc::new(status)).unwrap();
+
+        assert_eq!(stats.seeders, 7);
+        assert_eq!(stats.leechers, 3);
+        assert_eq!(stats.total_peers, 10);
+    }
+
+    #[test]
+    fn test_peers_amount_matches_peers_while_peers_connect() {
+        let (status, _) = AtomicTorrentStatus::new(
+            &create_test_torrent("test_peers_amount_matches_peers_while_peers_connect"),
+            Cfg::new(CONFIG_PATH).unwrap(),
+        );
+        let status = Arc::new(status);
+        let peers_status = status.clone();
+
+        let connector = thread::spawn(move || {
+            for port in 0..200 {
+                let peer = create_test_peer(port);
+                peers_status.peer_connected(&peer).unwrap();
+                let mut session_status = SessionStatus::new(Bitfield::new(vec![]));
+                session_status.download_speed = 8.0;
+                peers_status
+                    .update_peer_session_status(&peer, &session_status)
+                    .unwrap();
+                if port % 2 == 0 {
+                    peers_status.peer_disconnected(&peer).unwrap();
+                }
+            }
+        });
+        while !connector.is_finished() {
+            let stats = TorrentStats::for_torrent(&status).unwrap();
+
+            assert_eq!(stats.peers_amount, stats.peers.len());
+            let peers_download_speed: f64 =
+                stats.peers.iter().map(|peer| peer.download_speed).sum();
+            assert_eq!(stats.download_speed, peers_download_speed);
+        }
+        connector.join().unwrap();
+
+        let stats = TorrentStats::for_torrent(&status).unwrap();
+        assert_eq!(stats.peers_amount, 100);
+        assert_eq!(stats.peers.len(), 100);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_torrent(name: &str) -> Torrent {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            files: None,
+        };
+
+        Torrent {
+            announce_url: "announce".to_string(),
+            info,
+            info_hash: [0; 20],
+            info_bytes: Vec::new(),
+        }
+    }
+
+    fn create_test_peer(port: i64) -> BtPeer {
+        BtPeer {
+            peer_id: None,
+            ip: "127.0.0.1".to_string(),
+            port,
+            info_hash: None,
+            supports_extensions: false,
+            supports_fast_extension: false,
+        }
+    }
+
+    fn create_test_stats() -> TorrentStats {
+        let peer = PeerStats {
+            port: 6881,
+            ip: "127.0.0.1".to_string(),
+            download_speed: 1500.5,
+            upload_speed: 200.0,
+            choked: false,
+            interested: true,
+            client_choked: true,
+            client_interested: false,
+            peer_id: "".to_string(),
+        };
+
+        TorrentStats {
+            torrent_name: "test.iso".to_string(),
+            info_hash: "2c6b6858d61da9543d4231a71db4b1c9264b0685".to_string(),
+            length: 100,
+            pieces_amount: 4,
+            peers_amount: 1,
+            downloaded_pieces_amount: 1,
+            peers: vec![peer],
+            total_peers: 10,
+            seeders: 7,
+            leechers: 3,
+            download_speed: 1500.5,
+            upload_speed: 200.0,
+            eta: "00:00:10".to_string(),
+            files: vec![],
+            state: TorrentState::Downloading,
+        }
+    }
 }
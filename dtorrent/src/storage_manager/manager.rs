@@ -1,7 +1,7 @@
 use crate::config::cfg::Cfg;
 use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 trait WriteWithOffset {
     fn write_all_at(&mut self, buf: &[u8], offset: u64) -> Result<(), std::io::Error>;
@@ -32,6 +32,7 @@ pub fn save_piece(
     config: Cfg,
 ) -> Result<(), std::io::Error> {
     let save_directory = config.download_directory;
+    let path = file_path(&save_directory, &name)?;
     if !Path::new(&save_directory).exists() {
         fs::create_dir_all(save_directory.clone())?;
     }
@@ -39,7 +40,7 @@ pub fn save_piece(
         .read(true)
         .write(true)
         .create(true)
-        .open(save_directory + "/" + &name)?;
+        .open(path)?;
 
     file.write_all_at(piece, piece_offset)?;
 
@@ -63,7 +64,7 @@ pub fn retrieve_block(
 
     let mut file = OpenOptions::new()
         .read(true)
-        .open(file_directory + "/" + &filename)?;
+        .open(file_path(&file_directory, &filename)?)?;
 
     let mut buffer = vec![0; length];
     file.read_exact_at(&mut buffer, offset)?;
@@ -71,6 +72,49 @@ pub fn retrieve_block(
     Ok(buffer)
 }
 
+/// Builds the path of a file inside the given directory, sanitizing its name so it can't escape the directory.
+///
+/// It returns an `InvalidInput` error if the name is an absolute path or nothing is left after sanitizing it.
+pub fn file_path(directory: &str, name: &str) -> io::Result<PathBuf> {
+    Ok(Path::new(directory).join(sanitize_file_name(name)?))
+}
+
+/// Sanitizes a torrent name to be used as a file name.
+///
+/// Path separators are replaced by `_`, and empty, `.` and `..` components are removed.
+///
+/// It returns an `InvalidInput` error if the name is an absolute path or nothing is left after sanitizing it.
+pub fn sanitize_file_name(name: &str) -> io::Result<String> {
+    if is_absolute(name) {
+        return Err(invalid_name_error(name));
+    }
+
+    let sanitized = name
+        .split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .collect::<Vec<&str>>()
+        .join("_");
+
+    if sanitized.is_empty() {
+        return Err(invalid_name_error(name));
+    }
+    Ok(sanitized)
+}
+
+fn is_absolute(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    // Also rejects Windows paths like `C:\file` or `C:file`.
+    let has_drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    name.starts_with('/') || name.starts_with('\\') || has_drive || Path::new(name).is_absolute()
+}
+
+fn invalid_name_error(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid file name: {}", name),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs::File;
@@ -378,4 +422,61 @@ mod tests {
             fs::create_dir_all(path).unwrap();
         }
     }
+
+    // -------------------------------------------------------------------------------------
+
+    #[test]
+    fn sanitize_name_with_parent_directories() {
+        assert_eq!(
+            sanitize_file_name("../../etc/something").unwrap(),
+            "etc_something"
+        );
+    }
+
+    #[test]
+    fn sanitize_name_with_separators() {
+        assert_eq!(
+            sanitize_file_name("folder/sub\\file.txt").unwrap(),
+            "folder_sub_file.txt"
+        );
+    }
+
+    #[test]
+    fn sanitize_valid_name_does_not_change_it() {
+        assert_eq!(sanitize_file_name("ubuntu.iso").unwrap(), "ubuntu.iso");
+    }
+
+    #[test]
+    fn sanitize_absolute_name_is_rejected() {
+        assert!(sanitize_file_name("/etc/passwd").is_err());
+        assert!(sanitize_file_name("C:\\Windows\\file").is_err());
+    }
+
+    #[test]
+    fn sanitize_name_with_only_parent_directories_is_rejected() {
+        assert!(sanitize_file_name("../..").is_err());
+    }
+
+    #[test]
+    fn save_piece_with_parent_directory_name_stays_in_download_directory() {
+        let file_name = "../test_file_06.txt".to_string();
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let inside_path = format!("{}/test_file_06.txt", config.download_directory);
+        let outside_path = format!("{}/../test_file_06.txt", config.download_directory);
+
+        assert!(save_piece(file_name.clone(), &[0x50u8, 0x65u8], 0, config.clone()).is_ok());
+
+        assert!(Path::new(&inside_path).exists());
+        assert!(!Path::new(&outside_path).exists());
+        read_file_and_assert_its_content_equals_expected_content(
+            vec![0x50u8, 0x65u8],
+            &inside_path,
+        );
+        assert_eq!(
+            retrieve_block(file_name, 0, 2, config).unwrap(),
+            vec![0x50u8, 0x65u8]
+        );
+
+        fs::remove_file(inside_path).unwrap();
+    }
 }
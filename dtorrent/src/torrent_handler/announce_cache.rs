@@ -0,0 +1,209 @@
+use crate::{peer::bt_peer::BtPeer, tracker::tracker_response::TrackerResponse};
+use std::time::{Duration, Instant};
+
+/// The time to wait before announcing again for lack of peers, after an announce made once the interval passed.
+///
+/// It's also the shortest interval honoured, so a tracker asking for a shorter one isn't announced to in a loop.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// The longest time to wait before announcing again for lack of peers.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30 * 60);
+
+/// The peers of the last announce to the tracker, kept to avoid announcing more often than the tracker asks for.
+#[derive(Debug)]
+pub struct AnnounceCache {
+    peers: Vec<BtPeer>,
+    announced_at: Instant,
+    interval: Duration,
+    // The minimum time the tracker asks to wait between announces.
+    min_interval: Duration,
+    // The time to wait since the last announce before announcing again because too few peers are connected.
+    retry_delay: Duration,
+}
+
+impl AnnounceCache {
+    /// Caches the peers of a tracker response received now, until its `interval` passes.
+    ///
+    /// The interval is at least the `min interval` of the response and `MIN_RETRY_DELAY`, and so is the time to wait before announcing for lack of peers.
+    pub fn new(tracker_response: &TrackerResponse) -> Self {
+        let min_interval =
+            Duration::from_secs(tracker_response.min_interval.unwrap_or(0).max(0) as u64);
+        let interval = Duration::from_secs(tracker_response.interval.max(0) as u64);
+        Self {
+            peers: tracker_response.peers.clone(),
+            announced_at: Instant::now(),
+            interval: interval.max(min_interval).max(MIN_RETRY_DELAY),
+            min_interval,
+            retry_delay: MIN_RETRY_DELAY.max(min_interval),
+        }
+    }
+
+    /// Caches the peers of a tracker response received now, announced again while this cache was in use.
+    ///
+    /// If it was announced before the interval passed, for lack of peers, the time to wait before doing it again doubles, up to `MAX_RETRY_DELAY`.
+    pub fn renew(&self, tracker_response: &TrackerResponse) -> Self {
+        let mut cache = Self::new(tracker_response);
+        if self.announced_at.elapsed() < self.interval {
+            cache.retry_delay = self.next_retry_delay().max(cache.min_interval);
+        }
+        cache
+    }
+
+    /// Keeps the peers of this cache after announcing again failed, waiting as if the announce succeeded before doing it again.
+    ///
+    /// The time to wait before announcing again for lack of peers doubles, up to `MAX_RETRY_DELAY`.
+    pub fn renew_failed(&self) -> Self {
+        Self {
+            peers: self.peers.clone(),
+            announced_at: Instant::now(),
+            interval: self.interval,
+            min_interval: self.min_interval,
+            retry_delay: self.next_retry_delay().max(self.min_interval),
+        }
+    }
+
+    /// Returns true if the tracker must be announced to again: once the interval of the last response passed, or if fewer than `min_peers` peers are connected and the retry delay passed.
+    pub fn needs_announce(&self, connected_peers: usize, min_peers: usize) -> bool {
+        let elapsed = self.announced_at.elapsed();
+        elapsed >= self.interval || (connected_peers < min_peers && elapsed >= self.retry_delay)
+    }
+
+    /// Returns the peers of the last response.
+    pub fn peers(&self) -> Vec<BtPeer> {
+        self.peers.clone()
+    }
+
+    fn next_retry_delay(&self) -> Duration {
+        (self.retry_delay * 2).min(MAX_RETRY_DELAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_cache_with_enough_peers_does_not_announce() {
+        let cache = AnnounceCache::new(&create_test_response(1800));
+
+        assert!(!cache.needs_announce(5, 5));
+    }
+
+    #[test]
+    fn test_fresh_cache_with_a_single_peer_connected_does_not_announce() {
+        let cache = AnnounceCache::new(&create_test_response(1800));
+
+        assert!(!cache.needs_announce(1, 5));
+    }
+
+    #[test]
+    fn test_cache_with_few_peers_announces_once_the_retry_delay_passes() {
+        let mut cache = AnnounceCache::new(&create_test_response(1800));
+        cache.announced_at = Instant::now() - MIN_RETRY_DELAY;
+
+        assert!(cache.needs_announce(1, 5));
+        assert!(!cache.needs_announce(5, 5));
+    }
+
+    #[test]
+    fn test_announces_for_lack_of_peers_back_off() {
+        let mut cache = AnnounceCache::new(&create_test_response(3600));
+
+        for retry_delay in [60, 120, 240, 480, 960, 1800, 1800] {
+            cache.announced_at = Instant::now() - cache.retry_delay;
+            assert!(cache.needs_announce(1, 5));
+
+            cache = cache.renew(&create_test_response(3600));
+
+            assert_eq!(cache.retry_delay, Duration::from_secs(retry_delay));
+            assert!(!cache.needs_announce(1, 5));
+        }
+    }
+
+    #[test]
+    fn test_announce_once_the_interval_passes_resets_the_retry_delay() {
+        let mut cache = AnnounceCache::new(&create_test_response(1800));
+        cache.retry_delay = MAX_RETRY_DELAY;
+        cache.announced_at = Instant::now() - Duration::from_secs(1800);
+
+        let cache = cache.renew(&create_test_response(1800));
+
+        assert_eq!(cache.retry_delay, MIN_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_expired_cache_announces() {
+        let mut cache = AnnounceCache::new(&create_test_response(1800));
+        cache.announced_at = Instant::now() - Duration::from_secs(1800);
+
+        assert!(cache.needs_announce(10, 5));
+    }
+
+    #[test]
+    fn test_zero_interval_is_raised_to_the_min_retry_delay() {
+        for interval in [0, -1] {
+            let mut cache = AnnounceCache::new(&create_test_response(interval));
+
+            assert!(!cache.needs_announce(0, 5));
+
+            cache.announced_at = Instant::now() - MIN_RETRY_DELAY;
+            assert!(cache.needs_announce(10, 5));
+        }
+    }
+
+    #[test]
+    fn test_min_interval_is_honoured() {
+        let mut response = create_test_response(10);
+        response.min_interval = Some(120);
+        let mut cache = AnnounceCache::new(&response);
+
+        cache.announced_at = Instant::now() - Duration::from_secs(60);
+        assert!(!cache.needs_announce(0, 5));
+
+        cache.announced_at = Instant::now() - Duration::from_secs(120);
+        assert!(cache.needs_announce(0, 5));
+
+        let cache = cache.renew(&response);
+        assert_eq!(cache.retry_delay, Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_failed_announce_keeps_the_peers_and_backs_off() {
+        let mut cache = AnnounceCache::new(&create_test_response(1800));
+        cache.announced_at = Instant::now() - MIN_RETRY_DELAY;
+
+        let cache = cache.renew_failed();
+
+        assert_eq!(cache.peers().len(), 1);
+        assert_eq!(cache.retry_delay, 2 * MIN_RETRY_DELAY);
+        assert!(!cache.needs_announce(0, 5));
+    }
+
+    #[test]
+    fn test_cached_peers() {
+        let cache = AnnounceCache::new(&create_test_response(1800));
+
+        let peers = cache.peers();
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].port, 6881);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_response(interval: i64) -> TrackerResponse {
+        TrackerResponse {
+            interval,
+            min_interval: None,
+            complete: 1,
+            incomplete: 0,
+            peers: vec![BtPeer {
+                peer_id: None,
+                ip: "127.0.0.1".to_string(),
+                port: 6881,
+                info_hash: None,
+                supports_extensions: false,
+                supports_fast_extension: false,
+            }],
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// Limits how many connections to peers are being established at the same time.
+///
+/// Every dial holds a `DialPermit` until the connection is set up, and dropping it lets a waiting dial start.
+#[derive(Debug)]
+pub struct DialLimiter {
+    max_dials: usize,
+    current_dials: Mutex<usize>,
+    dial_finished: Condvar,
+}
+
+/// Permission to dial a peer, given by `DialLimiter::acquire`. The dial finishes when it is dropped.
+#[derive(Debug)]
+pub struct DialPermit<'a> {
+    limiter: &'a DialLimiter,
+}
+
+impl DialLimiter {
+    /// Creates a limiter that allows `max_dials` dials at the same time.
+    pub fn new(max_dials: usize) -> Self {
+        Self {
+            max_dials,
+            current_dials: Mutex::new(0),
+            dial_finished: Condvar::new(),
+        }
+    }
+
+    /// Waits until there are fewer than `max_dials` dials in progress, and returns the permit for a new one.
+    pub fn acquire(&self) -> DialPermit<'_> {
+        let mut current_dials = self.lock_current_dials();
+        while *current_dials >= self.max_dials {
+            current_dials = self
+                .dial_finished
+                .wait(current_dials)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *current_dials += 1;
+        DialPermit { limiter: self }
+    }
+
+    /// Returns the number of dials in progress.
+    pub fn current_dials(&self) -> usize {
+        *self.lock_current_dials()
+    }
+
+    // The count is only changed while holding the lock, so it's still valid if another thread panicked.
+    fn lock_current_dials(&self) -> MutexGuard<'_, usize> {
+        self.current_dials
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for DialPermit<'_> {
+    fn drop(&mut self) {
+        *self.limiter.lock_current_dials() -= 1;
+        self.limiter.dial_finished.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_dials_are_limited_to_the_max_dials() {
+        let limiter = Arc::new(DialLimiter::new(3));
+        let active_dials = Arc::new(AtomicUsize::new(0));
+        let max_active_dials = Arc::new(AtomicUsize::new(0));
+
+        let dials: Vec<_> = (0..10)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let active_dials = active_dials.clone();
+                let max_active_dials = max_active_dials.clone();
+                thread::spawn(move || {
+                    let _permit = limiter.acquire();
+                    let active = active_dials.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active_dials.fetch_max(active, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    active_dials.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for dial in dials {
+            dial.join().unwrap();
+        }
+
+        assert!(max_active_dials.load(Ordering::SeqCst) <= 3);
+        assert_eq!(limiter.current_dials(), 0);
+    }
+
+    #[test]
+    fn test_dial_waits_until_a_permit_is_dropped() {
+        let limiter = Arc::new(DialLimiter::new(1));
+        let permit = limiter.acquire();
+
+        let (sender, receiver) = mpsc::channel();
+        let waiting_limiter = limiter.clone();
+        let dial = thread::spawn(move || {
+            let _permit = waiting_limiter.acquire();
+            sender.send(()).unwrap();
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(permit);
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        dial.join().unwrap();
+        assert_eq!(limiter.current_dials(), 0);
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::{mpsc::Sender, Mutex};
+
+/// Events of a torrent that can be subscribed to, identified by the torrent info hash.
+///
+/// Peers are identified by their `ip:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorrentEvent {
+    PieceCompleted { info_hash: String, index: u32 },
+    TorrentCompleted { info_hash: String },
+    PeerConnected { info_hash: String, peer: String },
+    PeerDisconnected { info_hash: String, peer: String },
+}
+
+/// The subscribers to the events of a torrent.
+///
+/// Subscribers whose receiver was dropped are removed on the next published event.
+#[derive(Debug, Default)]
+pub struct EventSubscribers {
+    senders: Mutex<Vec<Sender<TorrentEvent>>>,
+}
+
+impl EventSubscribers {
+    /// Adds a subscriber that will receive every event published from now on.
+    pub fn subscribe(&self, sender: Sender<TorrentEvent>) {
+        if let Ok(mut senders) = self.senders.lock() {
+            senders.push(sender);
+        }
+    }
+
+    /// Sends the event to every subscriber.
+    pub fn publish(&self, event: TorrentEvent) {
+        if let Ok(mut senders) = self.senders.lock() {
+            senders.retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_every_subscriber_receives_the_event() {
+        let subscribers = EventSubscribers::default();
+        let (first_sender, first_receiver) = mpsc::channel();
+        let (second_sender, second_receiver) = mpsc::channel();
+        subscribers.subscribe(first_sender);
+        subscribers.subscribe(second_sender);
+
+        subscribers.publish(create_test_event());
+
+        assert_eq!(first_receiver.try_recv(), Ok(create_test_event()));
+        assert_eq!(second_receiver.try_recv(), Ok(create_test_event()));
+    }
+
+    #[test]
+    fn test_dropped_subscriber_is_removed() {
+        let subscribers = EventSubscribers::default();
+        let (sender, receiver) = mpsc::channel();
+        subscribers.subscribe(sender);
+        drop(receiver);
+
+        subscribers.publish(create_test_event());
+
+        assert!(subscribers.senders.lock().unwrap().is_empty());
+    }
+
+    // Auxiliary functions
+
+    fn create_test_event() -> TorrentEvent {
+        TorrentEvent::TorrentCompleted {
+            info_hash: "info_hash".to_string(),
+        }
+    }
+}
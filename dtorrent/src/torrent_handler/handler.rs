@@ -1,4 +1,9 @@
-use super::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
+use super::{
+    announce_cache::AnnounceCache,
+    dial_limiter::DialLimiter,
+    state::TorrentState,
+    status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+};
 use crate::{
     config::cfg::Cfg,
     peer::{
@@ -6,13 +11,11 @@ use crate::{
         peer_session::{PeerSession, PeerSessionError},
     },
     torrent_parser::torrent::Torrent,
-    tracker::{
-        tracker_handler::{TrackerHandler, TrackerHandlerError},
-        tracker_response::TrackerResponse,
-    },
+    tracker::tracker_handler::{TrackerHandler, TrackerHandlerError},
 };
 use logger::logger_sender::LoggerSender;
 use std::{
+    fmt,
     sync::{
         mpsc::{self, Receiver},
         Arc,
@@ -21,6 +24,15 @@ use std::{
     time::Duration,
 };
 
+/// The maximum number of peers being connected to at the same time, so a long peer list doesn't open every connection at once.
+const MAX_CONCURRENT_DIALS: usize = 5;
+/// The time waited between starting connections to peers.
+const DIAL_STAGGER: Duration = Duration::from_millis(20);
+/// The minimum number of peers connected to keep using the peers of the last announce, instead of announcing again before the tracker interval.
+const MIN_CONNECTED_PEERS: usize = 5;
+/// The time waited for a peer to disconnect before going through the cached peer list again.
+const CACHED_PEERS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Struct for handling the torrent download.
 ///
 /// To create a new `TorrentHandler`, use TorrentHandler::new(torrent, config, logger_sender).
@@ -32,6 +44,7 @@ pub struct TorrentHandler {
     torrent_status: Arc<AtomicTorrentStatus>,
     torrent_status_receiver: Receiver<usize>,
     client_peer_id: String,
+    dial_limiter: Arc<DialLimiter>,
 }
 
 /// Posible torrent handler errors.
@@ -43,14 +56,48 @@ pub enum TorrentHandlerError {
     TorrentStatusRecvError(mpsc::RecvError),
 }
 
+impl fmt::Display for TorrentHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TrackerError(_) => write!(f, "couldn't get the peers from the tracker"),
+            Self::TorrentStatusError(_) => write!(f, "couldn't use the torrent status"),
+            Self::PeerSessionError(_) => write!(f, "couldn't start a peer session"),
+            Self::TorrentStatusRecvError(_) => {
+                write!(f, "couldn't receive from the torrent status")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TorrentHandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::TrackerError(err) => Some(err),
+            Self::TorrentStatusError(err) => Some(err),
+            Self::PeerSessionError(err) => Some(err),
+            Self::TorrentStatusRecvError(err) => Some(err),
+        }
+    }
+}
+
 impl TorrentHandler {
     /// Creates a new `TorrentHandler` from a torrent, a config and a logger sender.
+    ///
+    /// If a `download_directory` is given, the torrent is saved there instead of the download directory of the config.
     pub fn new(
         torrent: Torrent,
         config: Cfg,
         logger_sender: LoggerSender,
         client_peer_id: String,
+        download_directory: Option<String>,
     ) -> Self {
+        let config = match download_directory {
+            Some(download_directory) => Cfg {
+                download_directory,
+                ..config
+            },
+            None => config,
+        };
         let (torrent_status, torrent_status_receiver) =
             AtomicTorrentStatus::new(&torrent, config.clone());
 
@@ -61,6 +108,7 @@ impl TorrentHandler {
             logger_sender,
             torrent_status_receiver,
             client_peer_id,
+            dial_limiter: Arc::new(DialLimiter::new(MAX_CONCURRENT_DIALS)),
         }
     }
 
@@ -68,23 +116,80 @@ impl TorrentHandler {
     ///
     /// First it connects to the tracker and gets the peers. Then it connects to each peer and starts the download.
     ///
+    /// The tracker is only announced to again once its interval passed or if too few peers are connected. Otherwise the peers of the last announce are reused.
+    ///
+    /// If a seed ratio limit is configured, it keeps running after the download finishes until the torrent is stopped, such as once the limit is reached, and then announces the `stopped` event.
+    ///
     /// # Errors
     ///
     /// - `TrackerErr` if there was a problem connecting to the tracker or getting the peers.
     /// - `TorrentStatusError` if there was a problem using the `Torrent Status`.
     /// - `TorrentStatusRecvError` if there was a problem receiving from the receiver of `Torrent Status`.
+    ///
+    /// If an error is returned, the state of the torrent is set to `Error`.
     pub fn handle(&mut self) -> Result<(), TorrentHandlerError> {
+        let result = self.handle_wrap();
+        if result.is_err() {
+            // The original error is more useful than a poisoned lock on the state.
+            let _ = self.torrent_status.set_state(TorrentState::Error);
+        }
+        result
+    }
+
+    fn handle_wrap(&mut self) -> Result<(), TorrentHandlerError> {
         let tracker_handler = TrackerHandler::new(
             self.torrent.clone(),
             self.config.tcp_port.into(),
             self.client_peer_id.clone(),
         )
-        .map_err(TorrentHandlerError::TrackerError)?;
+        .map_err(TorrentHandlerError::TrackerError)?
+        .with_announce_ip(self.config.announce_ip.clone());
         self.logger_sender.info("Connected to tracker.");
 
-        while !self.torrent_status.is_finished() {
-            let peer_list = self.get_peers_list(&tracker_handler)?;
-            self.logger_sender.info("Tracker peer list obtained.");
+        let mut announce_cache: Option<AnnounceCache> = None;
+        while !self.torrent_status.is_finished() && !self.torrent_status.is_stopped() {
+            let needs_announce = match &announce_cache {
+                Some(cache) => cache.needs_announce(
+                    self.torrent_status.all_current_peers(),
+                    self.min_connected_peers(),
+                ),
+                None => true,
+            };
+
+            let peer_list = if needs_announce {
+                let cache = match self.announce(&tracker_handler, announce_cache.as_ref()) {
+                    Ok(cache) => {
+                        self.logger_sender.info("Tracker peer list obtained.");
+                        self.torrent_status
+                            .peers_obtained(cache.peers().len())
+                            .map_err(TorrentHandlerError::TorrentStatusError)?;
+                        cache
+                    }
+                    // Once the tracker answered, its peers are kept if announcing again fails, such as when it refuses an announce.
+                    Err(err) => match &announce_cache {
+                        Some(cache) => {
+                            self.logger_sender.warn(&format!(
+                                "Couldn't announce again, keeping the previous peers: {:?}",
+                                err
+                            ));
+                            cache.renew_failed()
+                        }
+                        None => return Err(err),
+                    },
+                };
+                let peer_list = cache.peers();
+                announce_cache = Some(cache);
+                peer_list
+            } else {
+                // Wait for a peer to disconnect instead of going through the same peers again right away.
+                let _ = self
+                    .torrent_status_receiver
+                    .recv_timeout(CACHED_PEERS_POLL_INTERVAL);
+                announce_cache
+                    .as_ref()
+                    .map(AnnounceCache::peers)
+                    .unwrap_or_default()
+            };
 
             // Start connection with each peer
             for peer in peer_list {
@@ -104,7 +209,7 @@ impl TorrentHandler {
                         continue;
                     }
                 }
-                if self.torrent_status.is_finished() {
+                if self.torrent_status.is_finished() || self.torrent_status.is_stopped() {
                     break;
                 }
 
@@ -121,44 +226,76 @@ impl TorrentHandler {
                 let current_peers = self.torrent_status.all_current_peers();
                 if current_peers < self.config.max_peers_per_torrent as usize {
                     self.connect_to_peer(peer)?;
+                    thread::sleep(DIAL_STAGGER);
                 }
             }
         }
-        self.logger_sender.info("Torrent download finished.");
+        if !self.torrent_status.is_stopped() {
+            self.logger_sender.info("Torrent download finished.");
+            if self.config.seed_ratio_limit.is_none() {
+                return Ok(());
+            }
+            // The torrent seeds until its peer sessions reach the seed ratio limit and stop its status.
+            self.wait_until_stopped()?;
+        }
+        self.announce_stopped(&tracker_handler);
+        self.logger_sender.info("Torrent stopped.");
+        Ok(())
+    }
+
+    /// Waits until the torrent status is stopped, as it tells the torrent handler when it is.
+    fn wait_until_stopped(&self) -> Result<(), TorrentHandlerError> {
+        while !self.torrent_status.is_stopped() {
+            self.torrent_status_receiver
+                .recv()
+                .map_err(TorrentHandlerError::TorrentStatusRecvError)?;
+        }
         Ok(())
     }
 
+    /// Tells the tracker that the torrent stopped. As the client is shutting down, errors are only logged.
+    fn announce_stopped(&self, tracker_handler: &TrackerHandler) {
+        let downloaded = (self.torrent_status.downloaded_pieces() as i64
+            * self.torrent.info.piece_length)
+            .min(self.torrent.info.length);
+        let left = self.torrent.info.length - downloaded;
+        let uploaded = self.torrent_status.metrics().snapshot().bytes_uploaded;
+
+        if let Err(err) = tracker_handler.announce_stopped(uploaded, downloaded as u64, left) {
+            self.logger_sender.warn(&format!(
+                "Couldn't announce the stopped event to the tracker: {:?}",
+                err
+            ));
+        }
+    }
+
     /// Gets the status of the torrent.
     pub fn status(&self) -> Arc<AtomicTorrentStatus> {
         self.torrent_status.clone()
     }
 
-    fn get_peers_list(
+    /// The number of connected peers under which the tracker is announced to again, bounded by the maximum number of peers allowed.
+    fn min_connected_peers(&self) -> usize {
+        MIN_CONNECTED_PEERS.min(self.config.max_peers_per_torrent as usize)
+    }
+
+    /// Announces to the tracker and caches its response, renewing the previous cache if there is one.
+    fn announce(
         &self,
         tracker_handler: &TrackerHandler,
-    ) -> Result<Vec<BtPeer>, TorrentHandlerError> {
+        previous_cache: Option<&AnnounceCache>,
+    ) -> Result<AnnounceCache, TorrentHandlerError> {
         let tracker_response = tracker_handler
             .get_peers_list()
             .map_err(TorrentHandlerError::TrackerError)?;
 
-        self.update_total_peers(&tracker_response);
+        self.torrent_status
+            .update_total_peers_from_response(&tracker_response);
 
-        Ok(tracker_response.peers)
-    }
-
-    /// Updates the torrent status with the number of total peers.
-    ///
-    /// If the tracker response did not contain the number of total peers, it will be set to the number of peers in the response.
-    fn update_total_peers(&self, tracker_response: &TrackerResponse) {
-        if tracker_response.complete == 0 && tracker_response.incomplete == 0 {
-            self.torrent_status
-                .update_total_peers(tracker_response.peers.len(), 0);
-        } else {
-            self.torrent_status.update_total_peers(
-                tracker_response.complete as usize,
-                tracker_response.incomplete as usize,
-            );
-        }
+        Ok(match previous_cache {
+            Some(cache) => cache.renew(&tracker_response),
+            None => AnnounceCache::new(&tracker_response),
+        })
     }
 
     fn connect_to_peer(&mut self, peer: BtPeer) -> Result<(), TorrentHandlerError> {
@@ -180,13 +317,16 @@ impl TorrentHandler {
             self.torrent.info.name, peer_name
         ));
         let peer_logger_sender = self.logger_sender.clone();
+        let dial_limiter = self.dial_limiter.clone();
 
-        let join = builder.spawn(move || match peer_session.start_outgoing_seeder() {
-            Ok(_) => (),
-            Err(err) => {
-                peer_logger_sender.warn(&format!("{:?}", err));
-            }
-        });
+        let join = builder.spawn(
+            move || match peer_session.start_outgoing_seeder(&dial_limiter) {
+                Ok(_) => (),
+                Err(err) => {
+                    peer_logger_sender.warn(&format!("{:?}", err));
+                }
+            },
+        );
         match join {
             Ok(_) => (),
             Err(err) => {
@@ -199,3 +339,235 @@ impl TorrentHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{peer::peer_message::Bitfield, torrent_parser::info::Info};
+    use std::{
+        error::Error,
+        fs,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        path::Path,
+    };
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_torrent_with_custom_download_directory() {
+        let torrent = create_test_torrent("test_torrent_with_custom_download_directory");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let custom_directory = format!(
+            "{}/custom_directory_test",
+            config.download_directory.clone()
+        );
+
+        let handler = create_test_handler(&torrent, config.clone(), Some(custom_directory.clone()));
+        download_first_piece(&handler);
+
+        let custom_path = format!("{}/{}", custom_directory, torrent.info.name);
+        let default_path = format!("{}/{}", config.download_directory, torrent.info.name);
+        assert!(Path::new(&custom_path).exists());
+        assert!(!Path::new(&default_path).exists());
+
+        fs::remove_dir_all(custom_directory).unwrap();
+    }
+
+    #[test]
+    fn test_torrent_with_default_download_directory() {
+        let torrent = create_test_torrent("test_torrent_with_default_download_directory");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+
+        let handler = create_test_handler(&torrent, config.clone(), None);
+        download_first_piece(&handler);
+
+        let default_path = format!("{}/{}", config.download_directory, torrent.info.name);
+        assert!(Path::new(&default_path).exists());
+
+        fs::remove_file(default_path).unwrap();
+    }
+
+    #[test]
+    fn test_handshake_uses_the_peer_id_announced_to_the_tracker() {
+        let client_peer_id = "-DT0001-abcdefghijkl";
+        let peer_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer_port = peer_listener.local_addr().unwrap().port();
+        let tracker_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut torrent =
+            create_test_torrent("test_handshake_uses_the_peer_id_announced_to_the_tracker");
+        torrent.announce_url = format!(
+            "http://127.0.0.1:{}/announce",
+            tracker_listener.local_addr().unwrap().port()
+        );
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let (sender, _receiver) = mpsc::channel();
+        let mut handler = TorrentHandler::new(
+            torrent.clone(),
+            config.clone(),
+            LoggerSender::new(sender),
+            client_peer_id.to_string(),
+            None,
+        );
+
+        let status = handler.status();
+
+        // The tracker answers a single announce with the test peer, and then stops listening.
+        let tracker = thread::spawn(move || answer_test_announce(tracker_listener, peer_port));
+        let handler = thread::spawn(move || handler.handle());
+
+        let (mut peer_stream, _) = peer_listener.accept().unwrap();
+        let mut handshake = [0; 68];
+        peer_stream.read_exact(&mut handshake).unwrap();
+        drop(peer_stream);
+
+        let announced_peer_id = tracker.join().unwrap();
+        status.stop();
+        assert!(handler.join().unwrap().is_ok());
+        assert_eq!(announced_peer_id, client_peer_id);
+        assert_eq!(&handshake[48..], client_peer_id.as_bytes());
+
+        let _ = fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ));
+    }
+
+    #[test]
+    fn test_seeding_torrent_announces_stopped_once_its_status_is_stopped() {
+        let tracker_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut torrent = create_test_torrent(
+            "test_seeding_torrent_announces_stopped_once_its_status_is_stopped",
+        );
+        torrent.announce_url = format!(
+            "http://127.0.0.1:{}/announce",
+            tracker_listener.local_addr().unwrap().port()
+        );
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.seed_ratio_limit = Some(1.0);
+        let mut handler = create_test_handler(&torrent, config.clone(), None);
+        for _ in 0..torrent.total_pieces() {
+            download_first_piece(&handler);
+        }
+
+        let status = handler.status();
+        let tracker = thread::spawn(move || answer_stopped_announce(tracker_listener));
+        let handler = thread::spawn(move || handler.handle());
+
+        // A finished torrent keeps seeding until it is stopped, as a peer session does once the seed ratio is reached.
+        assert!(!handler.is_finished());
+        status.stop();
+        assert!(handler.join().unwrap().is_ok());
+        assert!(tracker.join().unwrap().contains("event=stopped"));
+
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_error_display_and_source() {
+        let err =
+            TorrentHandlerError::TorrentStatusError(AtomicTorrentStatusError::NoPeersConnected);
+
+        assert_eq!(err.to_string(), "couldn't use the torrent status");
+        assert_eq!(err.source().unwrap().to_string(), "no peers connected");
+    }
+
+    #[test]
+    fn test_error_source_chain() {
+        let err = TorrentHandlerError::PeerSessionError(PeerSessionError::ErrorSelectingPiece(
+            AtomicTorrentStatusError::PoisonedPiecesStatusLock,
+        ));
+
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "couldn't select a piece to download");
+        assert_eq!(
+            source.source().unwrap().to_string(),
+            "pieces status lock poisoned"
+        );
+    }
+
+    // Auxiliary functions
+
+    /// Answers an announce with a peer on the given port, and returns the announced peer_id.
+    fn answer_test_announce(tracker_listener: TcpListener, peer_port: u16) -> String {
+        let (mut stream, _) = tracker_listener.accept().unwrap();
+        let request = read_test_request(&mut stream);
+        let peer_id = request
+            .split(['?', '&', ' '])
+            .find_map(|param| param.strip_prefix("peer_id="))
+            .unwrap()
+            .to_string();
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\n\r\nd8:intervali1e5:peersld2:ip9:127.0.0.14:porti{}eeee",
+            peer_port
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+        peer_id
+    }
+
+    /// Answers an announce without peers, and returns its request.
+    fn answer_stopped_announce(tracker_listener: TcpListener) -> String {
+        let (mut stream, _) = tracker_listener.accept().unwrap();
+        let request = read_test_request(&mut stream);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\n\r\nd8:intervali1e5:peerslee")
+            .unwrap();
+        request
+    }
+
+    fn read_test_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let bytes_read = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..bytes_read]);
+        }
+        String::from_utf8(request).unwrap()
+    }
+
+    fn create_test_torrent(name: &str) -> Torrent {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            files: None,
+        };
+
+        Torrent {
+            announce_url: "announce".to_string(),
+            info,
+            info_hash: [0; 20],
+            info_bytes: Vec::new(),
+        }
+    }
+
+    fn create_test_handler(
+        torrent: &Torrent,
+        config: Cfg,
+        download_directory: Option<String>,
+    ) -> TorrentHandler {
+        let (sender, _) = mpsc::channel();
+        TorrentHandler::new(
+            torrent.clone(),
+            config,
+            LoggerSender::new(sender),
+            "peer_id".to_string(),
+            download_directory,
+        )
+    }
+
+    fn download_first_piece(handler: &TorrentHandler) {
+        let status = handler.status();
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(index, &[1]).unwrap();
+    }
+}
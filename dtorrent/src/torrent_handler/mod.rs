@@ -1,2 +1,8 @@
+pub mod announce_cache;
+pub mod dial_limiter;
+pub mod event;
 pub mod handler;
+pub mod priority;
+pub mod selection_strategy;
+pub mod state;
 pub mod status;
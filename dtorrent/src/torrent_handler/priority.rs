@@ -0,0 +1,27 @@
+/// The priority of a piece when selecting the free pieces to download.
+///
+/// The free pieces with the highest priority a peer has are always selected first, and the `SelectionStrategy` chooses between them.
+/// Every piece starts with `Normal` priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priorities_are_ordered() {
+        assert!(Priority::Low < Priority::Normal);
+        assert!(Priority::Normal < Priority::High);
+    }
+
+    #[test]
+    fn test_default_priority_is_normal() {
+        assert_eq!(Priority::default(), Priority::Normal);
+    }
+}
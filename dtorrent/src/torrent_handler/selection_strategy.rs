@@ -0,0 +1,52 @@
+use std::str::FromStr;
+
+/// How the next free piece to download from a peer is chosen, among the ones the peer has.
+///
+/// - `Rarest`: the piece the fewest connected peers have, so rare pieces spread before their peers leave.
+/// - `Random`: any of them, chosen at random.
+/// - `Sequential`: the one with the lowest index, so the content can be used while it downloads, such as streaming media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionStrategy {
+    Rarest,
+    Random,
+    #[default]
+    Sequential,
+}
+
+/// The setting is not one of the strategies.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidSelectionStrategy;
+
+impl FromStr for SelectionStrategy {
+    type Err = InvalidSelectionStrategy;
+
+    /// Parses a strategy from its lowercase name: `rarest`, `random` or `sequential`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rarest" => Ok(Self::Rarest),
+            "random" => Ok(Self::Random),
+            "sequential" => Ok(Self::Sequential),
+            _ => Err(InvalidSelectionStrategy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_strategies() {
+        assert_eq!("rarest".parse(), Ok(SelectionStrategy::Rarest));
+        assert_eq!("random".parse(), Ok(SelectionStrategy::Random));
+        assert_eq!("sequential".parse(), Ok(SelectionStrategy::Sequential));
+    }
+
+    #[test]
+    fn test_parse_invalid_strategy() {
+        assert_eq!(
+            "Sequential".parse::<SelectionStrategy>(),
+            Err(InvalidSelectionStrategy)
+        );
+    }
+}
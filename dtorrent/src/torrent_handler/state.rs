@@ -0,0 +1,37 @@
+use serde::Serialize;
+use std::fmt;
+
+/// The state of a torrent, as shown to the user.
+///
+/// - `Connecting`: getting peers from the tracker and connecting to them, before any block arrived.
+/// - `Downloading`: blocks are arriving from the peers.
+/// - `Stalled`: there are no peers to download from, neither connected nor from the tracker.
+/// - `Seeding`: the download finished and the pieces are being uploaded to other peers.
+/// - `Finished`: the download finished.
+/// - `Paused`: the torrent was stopped.
+/// - `Error`: the torrent stopped because of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum TorrentState {
+    #[default]
+    Connecting,
+    Downloading,
+    Stalled,
+    Seeding,
+    Finished,
+    Paused,
+    Error,
+}
+
+impl fmt::Display for TorrentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "Connecting"),
+            Self::Downloading => write!(f, "Downloading"),
+            Self::Stalled => write!(f, "Stalled"),
+            Self::Seeding => write!(f, "Seeding"),
+            Self::Finished => write!(f, "Finished"),
+            Self::Paused => write!(f, "Paused"),
+            Self::Error => write!(f, "Error"),
+        }
+    }
+}
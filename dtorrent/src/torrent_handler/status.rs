@@ -1,19 +1,31 @@
+use super::{
+    event::{EventSubscribers, TorrentEvent},
+    priority::Priority,
+    selection_strategy::SelectionStrategy,
+    state::TorrentState,
+};
 use crate::{
     config::cfg::Cfg,
+    metrics::torrent_metrics::TorrentMetrics,
     peer::{bt_peer::BtPeer, peer_message::Bitfield, session_status::SessionStatus},
     storage_manager::manager::{retrieve_block, save_piece},
     torrent_parser::torrent::Torrent,
+    tracker::tracker_response::TrackerResponse,
 };
-use rand::{self, prelude::IteratorRandom};
+use rand::{prelude::IteratorRandom, rngs::StdRng, SeedableRng};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    fmt,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        mpsc::{sync_channel, Receiver, SyncSender},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{sync_channel, Receiver, Sender, SyncSender},
         {Mutex, MutexGuard},
     },
 };
 
+/// The blocks downloaded of a piece, by offset.
+type PieceBlocks = BTreeMap<u32, Vec<u8>>;
+
 /// A Struct that represents the current status of a torrent.
 ///
 /// It contains the following information:
@@ -27,10 +39,26 @@ use std::{
 ///
 /// The `new()` method also returns a `Receiver` that can be used to know when a peer disconnects. This is useful if there is a limit for how many peers can be created,
 /// so the thread can be blocked until the status notifies that a peer has disconnected.
+///
+/// The progress of the torrent can be followed by subscribing to its `TorrentEvent`s with `subscribe()`.
 #[derive(Debug)]
 pub struct AtomicTorrentStatus {
     pub torrent: Torrent, //TODO: resolver encqapsulamiento en statistics.rs
-    pieces_status: Mutex<HashMap<u32, PieceStatus>>,
+    // Indexed by piece number, so each piece takes a single byte instead of a map entry:
+    // about 500KB for a torrent with 500,000 pieces, where a `HashMap<u32, PieceStatus>` took around 8MB.
+    pieces_status: Mutex<Vec<PieceStatus>>,
+    // Number of sessions downloading each piece. Always locked after `pieces_status`.
+    piece_downloaders: Mutex<HashMap<u32, usize>>,
+    // Blocks already downloaded of the pieces that are not finished, by piece and then by offset. Always locked after `pieces_status`.
+    downloaded_blocks: Mutex<HashMap<u32, PieceBlocks>>,
+    // Priority of the pieces that don't have the default `Normal` priority, by piece number. Always locked after `piece_downloaders` and before `rng` and `piece_availability`.
+    piece_priorities: Mutex<BTreeMap<u32, Priority>>,
+    // Chooses between the pieces with the fewest downloaders in the endgame. Always locked after `piece_downloaders`.
+    rng: Mutex<StdRng>,
+    // Number of connected peers that have each piece, indexed by piece number. Always locked after `piece_downloaders`.
+    piece_availability: Mutex<Vec<usize>>,
+    // Always locked last.
+    state: Mutex<TorrentState>,
     current_peers: AtomicUsize,
     config: Cfg,
     torrent_status_sender: SyncSender<usize>,
@@ -41,6 +69,23 @@ pub struct AtomicTorrentStatus {
     total_seeders_count: AtomicUsize,
     total_leechers_count: AtomicUsize,
     all_current_peers: AtomicUsize,
+    subscribers: EventSubscribers,
+    metrics: TorrentMetrics,
+    // Set when something shown in the statistics changes, and cleared by `take_changed()`.
+    changed: AtomicBool,
+    stopped: AtomicBool,
+    // Set once the seed ratio limit is reached, by `take_seed_ratio_reached()`.
+    seeding_stopped: AtomicBool,
+}
+
+/// The connected peers of a torrent and their total speeds, taken at the same time.
+#[derive(Debug, Clone, Default)]
+pub struct PeersSnapshot {
+    pub peers: HashMap<BtPeer, SessionStatus>,
+    /// Sum of the download speeds of the peers, in kilobits per second.
+    pub download_speed: f64,
+    /// Sum of the upload speeds of the peers, in kilobits per second.
+    pub upload_speed: f64,
 }
 
 /// Possible states of a piece.
@@ -57,6 +102,12 @@ pub enum AtomicTorrentStatusError {
     PoisonedPiecesStatusLock,
     PoisonedCurrentPeersLock,
     PoisonedSessionsStatusLock,
+    PoisonedPieceDownloadersLock,
+    PoisonedDownloadedBlocksLock,
+    PoisonedRngLock,
+    PoisonedPieceAvailabilityLock,
+    PoisonedPiecePrioritiesLock,
+    PoisonedStateLock,
     InvalidPieceIndex,
     NoPeersConnected,
     PieceWasNotDownloading,
@@ -65,28 +116,61 @@ pub enum AtomicTorrentStatusError {
     PieceWasNotFinished,
 }
 
+impl fmt::Display for AtomicTorrentStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PoisonedPiecesStatusLock => write!(f, "pieces status lock poisoned"),
+            Self::PoisonedCurrentPeersLock => write!(f, "current peers lock poisoned"),
+            Self::PoisonedSessionsStatusLock => write!(f, "sessions status lock poisoned"),
+            Self::PoisonedPieceDownloadersLock => write!(f, "piece downloaders lock poisoned"),
+            Self::PoisonedDownloadedBlocksLock => write!(f, "downloaded blocks lock poisoned"),
+            Self::PoisonedRngLock => write!(f, "piece selection rng lock poisoned"),
+            Self::PoisonedPieceAvailabilityLock => write!(f, "piece availability lock poisoned"),
+            Self::PoisonedPiecePrioritiesLock => write!(f, "piece priorities lock poisoned"),
+            Self::PoisonedStateLock => write!(f, "torrent state lock poisoned"),
+            Self::InvalidPieceIndex => write!(f, "invalid piece index"),
+            Self::NoPeersConnected => write!(f, "no peers connected"),
+            Self::PieceWasNotDownloading => write!(f, "piece was not downloading"),
+            Self::SavePieceError(_) => write!(f, "couldn't save the piece"),
+            Self::RetrievingPieceError(_) => write!(f, "couldn't retrieve the piece"),
+            Self::PieceWasNotFinished => write!(f, "piece was not finished"),
+        }
+    }
+}
+
+impl std::error::Error for AtomicTorrentStatusError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SavePieceError(err) | Self::RetrievingPieceError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl AtomicTorrentStatus {
     /// Creates a new `AtomicTorrentStatus` from a `Torrent` and a `config`.
     ///
     /// Returns a tuple with the `AtomicTorrentStatus` and a channel `Receiver` that can be used optionally to receive when a peer disconects from the torrent status.
     /// The value sent on the channel is the current number of peers connected.
     pub fn new(torrent: &Torrent, config: Cfg) -> (Self, Receiver<usize>) {
-        let mut pieces_status: HashMap<u32, PieceStatus> = HashMap::new();
         let sessions_status: HashMap<BtPeer, SessionStatus> = HashMap::new();
 
         let (torrent_status_sender, torrent_status_receiver): (SyncSender<usize>, Receiver<usize>) =
             sync_channel((config.max_peers_per_torrent * 100) as usize);
 
         let total_pieces = torrent.total_pieces();
-
-        for index in 0..total_pieces {
-            pieces_status.insert(index, PieceStatus::Free);
-        }
+        let pieces_status = vec![PieceStatus::Free; total_pieces as usize];
 
         (
             Self {
                 torrent: torrent.clone(),
                 pieces_status: Mutex::new(pieces_status),
+                piece_downloaders: Mutex::new(HashMap::new()),
+                downloaded_blocks: Mutex::new(HashMap::new()),
+                piece_priorities: Mutex::new(BTreeMap::new()),
+                rng: Mutex::new(StdRng::from_entropy()),
+                piece_availability: Mutex::new(vec![0; total_pieces as usize]),
+                state: Mutex::new(TorrentState::default()),
                 current_peers: AtomicUsize::new(0),
                 config,
                 torrent_status_sender,
@@ -97,16 +181,125 @@ impl AtomicTorrentStatus {
                 total_seeders_count: AtomicUsize::new(0),
                 total_leechers_count: AtomicUsize::new(0),
                 all_current_peers: AtomicUsize::new(0),
+                subscribers: EventSubscribers::default(),
+                metrics: TorrentMetrics::default(),
+                changed: AtomicBool::new(true),
+                stopped: AtomicBool::new(false),
+                seeding_stopped: AtomicBool::new(false),
             },
             torrent_status_receiver,
         )
     }
 
+    /// Seeds the random number generator used to select pieces, so the selection is reproducible.
+    ///
+    /// By default it's seeded from the OS entropy.
+    ///
+    /// # Errors
+    /// - `PoisonedRngLock` if the lock on the `rng` field is poisoned.
+    pub fn seed_rng(&self, seed: u64) -> Result<(), AtomicTorrentStatusError> {
+        *self.lock_rng()? = StdRng::seed_from_u64(seed);
+        Ok(())
+    }
+
+    /// Subscribes to the events of the torrent, which will be sent through the given `sender`.
+    pub fn subscribe(&self, sender: Sender<TorrentEvent>) {
+        self.subscribers.subscribe(sender);
+    }
+
+    /// Returns the metrics of the torrent, such as the pieces and bytes downloaded.
+    pub fn metrics(&self) -> &TorrentMetrics {
+        &self.metrics
+    }
+
+    /// Returns true if something shown in the statistics of the torrent changed since the last call, such as its peers, pieces or state.
+    ///
+    /// It returns true on the first call.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+
+    fn mark_changed(&self) {
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
     /// Returns true if the torrent download finished.
     pub fn is_finished(&self) -> bool {
         self.finished_pieces.load(Ordering::Relaxed) == self.torrent.total_pieces() as usize
     }
 
+    /// Signals the torrent handler and its peer sessions to stop.
+    ///
+    /// The torrent handler is woken up if it is waiting for a peer to disconnect.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Ok(mut state) = self.state.lock() {
+            *state = TorrentState::Paused;
+        }
+        self.mark_changed();
+        // If the channel is full the handler will be woken up anyway.
+        let _ = self
+            .torrent_status_sender
+            .try_send(self.all_current_peers.load(Ordering::Relaxed));
+    }
+
+    /// Returns the current state of the torrent.
+    ///
+    /// # Errors
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
+    pub fn state(&self) -> Result<TorrentState, AtomicTorrentStatusError> {
+        Ok(*self.lock_state()?)
+    }
+
+    /// Sets the state of the torrent, such as `Error` once its download can't continue.
+    ///
+    /// The rest of the states are set by the status itself as the download goes on.
+    ///
+    /// # Errors
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
+    pub fn set_state(&self, state: TorrentState) -> Result<(), AtomicTorrentStatusError> {
+        *self.lock_state()? = state;
+        self.mark_changed();
+        Ok(())
+    }
+
+    /// Marks the torrent as `Stalled` if the tracker returned no peers and there are none connected.
+    ///
+    /// # Errors
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
+    pub fn peers_obtained(&self, peers_count: usize) -> Result<(), AtomicTorrentStatusError> {
+        if peers_count == 0 && self.all_current_peers() == 0 {
+            self.update_state(|state| match state {
+                TorrentState::Connecting | TorrentState::Downloading => TorrentState::Stalled,
+                state => state,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the torrent was signaled to stop.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the torrent finished downloading and uploaded at least `seed_ratio_limit` times its size, after which it stops seeding.
+    ///
+    /// It is always false if no seed ratio limit is configured.
+    pub fn seed_ratio_reached(&self) -> bool {
+        match self.config.seed_ratio_limit {
+            Some(limit) => {
+                let uploaded = self.metrics.snapshot().bytes_uploaded as f64;
+                self.is_finished() && uploaded >= limit * self.torrent.total_size() as f64
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true only the first time it is called once the seed ratio limit is reached, so the torrent stops seeding once.
+    pub fn take_seed_ratio_reached(&self) -> bool {
+        self.seed_ratio_reached() && !self.seeding_stopped.swap(true, Ordering::Relaxed)
+    }
+
     /// Returns the number of ramaining pieces to download.
     pub fn remaining_pieces(&self) -> usize {
         self.torrent.total_pieces() as usize - self.finished_pieces.load(Ordering::Relaxed)
@@ -127,9 +320,15 @@ impl AtomicTorrentStatus {
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
     pub fn peer_connected(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
-        self.current_peers.fetch_add(1, Ordering::Relaxed);
+        let current_peers = self.current_peers.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.set_active_peers(current_peers);
+        self.mark_changed();
         let mut peer_status = self.lock_session_status()?;
         peer_status.insert(peer.clone(), SessionStatus::new(Bitfield::new(vec![])));
+        self.subscribers.publish(TorrentEvent::PeerConnected {
+            info_hash: self.torrent.info_hash_hex(),
+            peer: format!("{}:{}", peer.ip, peer.port),
+        });
         Ok(())
     }
 
@@ -142,18 +341,31 @@ impl AtomicTorrentStatus {
     ///
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
     /// - `NoPeersConnected` if there are no peers connected.
     pub fn peer_disconnected(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
         let mut peer_status = self.lock_session_status()?;
         if self.current_peers.load(Ordering::Relaxed) == 0 {
             return Err(AtomicTorrentStatusError::NoPeersConnected);
         }
-        self.current_peers.fetch_sub(1, Ordering::Relaxed);
+        let current_peers = self.current_peers.fetch_sub(1, Ordering::Relaxed) - 1;
         self.all_current_peers.fetch_sub(1, Ordering::Relaxed);
+        self.metrics.set_active_peers(current_peers);
+        self.mark_changed();
 
         peer_status.remove(peer);
+        if current_peers == 0 {
+            self.update_state(|state| match state {
+                TorrentState::Downloading => TorrentState::Stalled,
+                state => state,
+            })?;
+        }
 
         self.notify_peer_disconnected();
+        self.subscribers.publish(TorrentEvent::PeerDisconnected {
+            info_hash: self.torrent.info_hash_hex(),
+            peer: format!("{}:{}", peer.ip, peer.port),
+        });
         Ok(())
     }
 
@@ -195,6 +407,7 @@ impl AtomicTorrentStatus {
         let mut peer_status = self.lock_session_status()?;
         peer_status.remove(peer);
         peer_status.insert(peer.clone(), status.clone());
+        self.mark_changed();
         Ok(())
     }
 
@@ -204,6 +417,21 @@ impl AtomicTorrentStatus {
             .store(seeders_count, Ordering::Relaxed);
         self.total_leechers_count
             .store(leechers_count, Ordering::Relaxed);
+        self.mark_changed();
+    }
+
+    /// Updates the count of seeders and leechers with the `complete` and `incomplete` of a tracker response.
+    ///
+    /// If the tracker response did not contain the number of total peers, it will be set to the number of peers in the response.
+    pub fn update_total_peers_from_response(&self, tracker_response: &TrackerResponse) {
+        if tracker_response.complete == 0 && tracker_response.incomplete == 0 {
+            self.update_total_peers(tracker_response.peers.len(), 0);
+        } else {
+            self.update_total_peers(
+                tracker_response.complete as usize,
+                tracker_response.incomplete as usize,
+            );
+        }
     }
 
     /// Returns a tuple containing the number of seeders and leechers.
@@ -225,6 +453,21 @@ impl AtomicTorrentStatus {
         Ok(self.lock_session_status()?.clone())
     }
 
+    /// Returns the connected peers along with the download and upload speeds of the torrent, all from a single lock on the sessions, so they are consistent with each other.
+    ///
+    /// # Errors
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    pub fn peers_snapshot(&self) -> Result<PeersSnapshot, AtomicTorrentStatusError> {
+        let peers = self.lock_session_status()?.clone();
+        let download_speed = peers.values().map(|session| session.download_speed).sum();
+        let upload_speed = peers.values().map(|session| session.upload_speed).sum();
+        Ok(PeersSnapshot {
+            peers,
+            download_speed,
+            upload_speed,
+        })
+    }
+
     /// Returns the current download speed of the torrent in kilobits per second.
     ///
     /// # Errors
@@ -249,43 +492,112 @@ impl AtomicTorrentStatus {
             .sum())
     }
 
+    /// Returns the strategy used to select the free pieces, from the config.
+    pub fn selection_strategy(&self) -> SelectionStrategy {
+        self.config.piece_selection_strategy
+    }
+
+    /// Adds the pieces of a peer to the availability of the pieces, used by the `Rarest` selection strategy.
+    ///
+    /// # Errors
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    pub fn peer_has_pieces(&self, bitfield: &Bitfield) -> Result<(), AtomicTorrentStatusError> {
+        let mut piece_availability = self.lock_piece_availability()?;
+        for (index, availability) in piece_availability.iter_mut().enumerate() {
+            if bitfield.has_piece(index as u32) {
+                *availability += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a piece announced by a peer to the availability of the pieces.
+    ///
+    /// # Errors
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    pub fn peer_has_piece(&self, index: u32) -> Result<(), AtomicTorrentStatusError> {
+        if let Some(availability) = self.lock_piece_availability()?.get_mut(index as usize) {
+            *availability += 1;
+        }
+        Ok(())
+    }
+
+    /// Removes the pieces of a peer from the availability of the pieces, once it disconnects.
+    ///
+    /// # Errors
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    pub fn peer_lost_pieces(&self, bitfield: &Bitfield) -> Result<(), AtomicTorrentStatusError> {
+        let mut piece_availability = self.lock_piece_availability()?;
+        for (index, availability) in piece_availability.iter_mut().enumerate() {
+            if bitfield.has_piece(index as u32) {
+                *availability = availability.saturating_sub(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the priority of the given pieces, such as the first and last pieces of a media file so it can be played early.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecePrioritiesLock` if the lock on the `piece_priorities` field is poisoned.
+    /// - `InvalidPieceIndex` if any of the piece indexes is invalid, in which case no priority is changed.
+    pub fn set_priority(
+        &self,
+        pieces: &[u32],
+        level: Priority,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        let mut piece_priorities = self.lock_piece_priorities()?;
+        if pieces
+            .iter()
+            .any(|index| *index >= self.torrent.total_pieces())
+        {
+            return Err(AtomicTorrentStatusError::InvalidPieceIndex);
+        }
+        for index in pieces {
+            if level == Priority::Normal {
+                piece_priorities.remove(index);
+            } else {
+                piece_priorities.insert(*index, level);
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the index of a piece that can be downloaded from a peer `Bitfield` passed by parameter.
     ///
+    /// The free pieces with the highest `Priority` are preferred, and chosen between with the `SelectionStrategy` of the config.
+    ///
+    /// If there are no free pieces left the 'EndGame' strategy is used, returning a piece that is already downloading.
+    /// The downloading pieces with the fewest sessions downloading them are preferred, so the remaining pieces are spread between peers.
+    ///
     /// If none of the pieces can be downloaded, returns `None`.
     ///
     /// # Errors
     /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedPieceDownloadersLock` if the lock on the `piece_downloaders` field is poisoned.
+    /// - `PoisonedPiecePrioritiesLock` if the lock on the `piece_priorities` field is poisoned.
+    /// - `PoisonedRngLock` if the lock on the `rng` field is poisoned.
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
     pub fn select_piece(
         &self,
         bitfield: &Bitfield,
     ) -> Result<Option<u32>, AtomicTorrentStatusError> {
         let mut pieces_status = self.lock_pieces_status()?;
+        let mut piece_downloaders = self.lock_piece_downloaders()?;
+
+        if self.free_pieces.load(Ordering::Relaxed) == 0 {
+            return Ok(Self::select_endgame_piece(
+                &pieces_status,
+                &mut piece_downloaders,
+                bitfield,
+                &mut *self.lock_rng()?,
+            ));
+        }
 
-        // If there are no free pieces do the 'EndGame' strategy, otherwise do the normal piece selection.
-        let index = if pieces_status
-            .values()
-            .filter(|status| **status == PieceStatus::Free)
-            .count()
-            == 0
-        {
-            pieces_status
-                .clone()
-                .iter()
-                .filter(|(_, status)| **status == PieceStatus::Downloading)
-                .choose(&mut rand::thread_rng())
-                .map(|(index, _)| *index)
-        } else {
-            pieces_status
-                .clone()
-                .iter()
-                .filter(|(_, status)| **status == PieceStatus::Free)
-                .find(|(index, _)| bitfield.has_piece(**index))
-                .map(|(index, _)| *index)
-        };
-
-        Ok(match index {
+        Ok(match self.select_free_piece(&pieces_status, bitfield)? {
             Some(index) => {
-                pieces_status.insert(index, PieceStatus::Downloading);
+                pieces_status[index as usize] = PieceStatus::Downloading;
+                piece_downloaders.insert(index, 1);
                 self.downloading_pieces.fetch_add(1, Ordering::Relaxed);
                 self.free_pieces.fetch_sub(1, Ordering::Relaxed);
                 Some(index)
@@ -294,10 +606,95 @@ impl AtomicTorrentStatus {
         })
     }
 
+    /// Returns a free piece the peer has, chosen with the selection strategy over the locked pieces status.
+    ///
+    /// Only the pieces with the highest priority among them are considered.
+    fn select_free_piece(
+        &self,
+        pieces_status: &[PieceStatus],
+        bitfield: &Bitfield,
+    ) -> Result<Option<u32>, AtomicTorrentStatusError> {
+        let piece_priorities = self.lock_piece_priorities()?;
+        let is_free = |index: &u32| {
+            pieces_status[*index as usize] == PieceStatus::Free && bitfield.has_piece(*index)
+        };
+
+        let mut levels: Vec<Priority> = piece_priorities.values().copied().collect();
+        levels.push(Priority::Normal);
+        levels.sort_unstable_by(|a, b| b.cmp(a));
+        levels.dedup();
+
+        for level in levels {
+            let selected = if level == Priority::Normal {
+                self.choose_free_piece(
+                    (0..pieces_status.len() as u32)
+                        .filter(|index| is_free(index) && !piece_priorities.contains_key(index)),
+                )?
+            } else {
+                self.choose_free_piece(
+                    piece_priorities
+                        .iter()
+                        .filter(|(_, priority)| **priority == level)
+                        .map(|(index, _)| *index)
+                        .filter(is_free),
+                )?
+            };
+            if selected.is_some() {
+                return Ok(selected);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Chooses one of the candidate pieces, given in ascending order, with the selection strategy.
+    ///
+    /// The `Rarest` strategy breaks ties by choosing the lowest index.
+    fn choose_free_piece(
+        &self,
+        mut candidates: impl Iterator<Item = u32>,
+    ) -> Result<Option<u32>, AtomicTorrentStatusError> {
+        Ok(match self.selection_strategy() {
+            SelectionStrategy::Sequential => candidates.next(),
+            SelectionStrategy::Random => candidates.choose(&mut *self.lock_rng()?),
+            SelectionStrategy::Rarest => {
+                let piece_availability = self.lock_piece_availability()?;
+                candidates.min_by_key(|index| piece_availability[*index as usize])
+            }
+        })
+    }
+
+    fn select_endgame_piece(
+        pieces_status: &[PieceStatus],
+        piece_downloaders: &mut HashMap<u32, usize>,
+        bitfield: &Bitfield,
+        rng: &mut StdRng,
+    ) -> Option<u32> {
+        let downloaders = |index: &u32| piece_downloaders.get(index).copied().unwrap_or(0);
+
+        let candidates = || {
+            (0..pieces_status.len() as u32).filter(|index| {
+                pieces_status[*index as usize] == PieceStatus::Downloading
+                    && bitfield.has_piece(*index)
+            })
+        };
+
+        let fewest_downloaders = candidates().map(|index| downloaders(&index)).min()?;
+        let index = candidates()
+            .filter(|index| downloaders(index) == fewest_downloaders)
+            .choose(rng)?;
+
+        *piece_downloaders.entry(index).or_insert(0) += 1;
+        Some(index)
+    }
+
     /// Saves a downlaoded piece to the disk.
     ///
+    /// If the piece was already downloaded by another session during the 'EndGame', it's not saved again.
+    ///
     /// # Errors
     /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedPieceDownloadersLock` if the lock on the `piece_downloaders` field is poisoned.
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
     /// - `InvalidPieceIndex` if the piece index is invalid.
     /// - `PieceWasNotDownloading` if the piece was not downloading.
     /// - `SavePieceError` if the piece could not be saved.
@@ -307,12 +704,14 @@ impl AtomicTorrentStatus {
         piece: &[u8],
     ) -> Result<(), AtomicTorrentStatusError> {
         let mut piece_status = self.lock_pieces_status()?;
-        match piece_status.get(&index) {
-            Some(value) => {
-                if *value != PieceStatus::Downloading {
-                    return Err(AtomicTorrentStatusError::PieceWasNotDownloading);
-                }
+        let mut piece_downloaders = self.lock_piece_downloaders()?;
+        match piece_status.get(index as usize) {
+            Some(PieceStatus::Downloading) => (),
+            Some(PieceStatus::Finished) if piece_downloaders.contains_key(&index) => {
+                Self::remove_downloader(&mut piece_downloaders, index);
+                return Ok(());
             }
+            Some(_) => return Err(AtomicTorrentStatusError::PieceWasNotDownloading),
             None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
         }
         save_piece(
@@ -323,9 +722,27 @@ impl AtomicTorrentStatus {
         )
         .map_err(AtomicTorrentStatusError::SavePieceError)?;
 
-        piece_status.insert(index, PieceStatus::Finished);
+        Self::remove_downloader(&mut piece_downloaders, index);
+        self.lock_downloaded_blocks()?.remove(&index);
+        piece_status[index as usize] = PieceStatus::Finished;
         self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
-        self.finished_pieces.fetch_add(1, Ordering::Relaxed);
+        let finished_pieces = self.finished_pieces.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.piece_downloaded();
+        self.mark_changed();
+
+        self.subscribers.publish(TorrentEvent::PieceCompleted {
+            info_hash: self.torrent.info_hash_hex(),
+            index,
+        });
+        if finished_pieces == self.torrent.total_pieces() as usize {
+            self.update_state(|state| match state {
+                TorrentState::Paused | TorrentState::Error => state,
+                _ => TorrentState::Finished,
+            })?;
+            self.subscribers.publish(TorrentEvent::TorrentCompleted {
+                info_hash: self.torrent.info_hash_hex(),
+            });
+        }
         Ok(())
     }
 
@@ -333,6 +750,7 @@ impl AtomicTorrentStatus {
     ///
     /// # Errors
     /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
     /// - `InvalidPieceIndex` if the piece index is invalid.
     /// - `PieceWasNotFinished` if the piece was not donwloaded.
     pub fn get_piece(
@@ -343,7 +761,7 @@ impl AtomicTorrentStatus {
     ) -> Result<Vec<u8>, AtomicTorrentStatusError> {
         let pieces_status = self.lock_pieces_status()?;
 
-        match pieces_status.get(&index) {
+        match pieces_status.get(index as usize) {
             Some(value) => {
                 if *value != PieceStatus::Finished {
                     return Err(AtomicTorrentStatusError::PieceWasNotFinished);
@@ -352,39 +770,142 @@ impl AtomicTorrentStatus {
             None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
         }
 
-        retrieve_block(
+        let block = retrieve_block(
             self.torrent.info.name.clone(),
             offset,
             length,
             self.config.clone(),
         )
-        .map_err(AtomicTorrentStatusError::RetrievingPieceError)
+        .map_err(AtomicTorrentStatusError::RetrievingPieceError)?;
+        self.update_state(|state| match state {
+            TorrentState::Finished => TorrentState::Seeding,
+            state => state,
+        })?;
+        Ok(block)
     }
 
     /// Aborts a piece download.
     ///
     /// This must be called when a piece obteined from `select_piece` can not longer be downloaded.
+    /// The piece is only freed once every session downloading it aborted it.
     ///
     /// # Errors
     /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedPieceDownloadersLock` if the lock on the `piece_downloaders` field is poisoned.
     /// - `InvalidPieceIndex` if the piece index is invalid.
     /// - `PieceWasNotDownloading` if the piece was not downloading.
     pub fn piece_aborted(&self, index: u32) -> Result<(), AtomicTorrentStatusError> {
         let mut piece_status = self.lock_pieces_status()?;
-        match piece_status.get(&index) {
-            Some(value) => {
-                if *value != PieceStatus::Downloading {
-                    return Err(AtomicTorrentStatusError::PieceWasNotDownloading);
-                }
+        let mut piece_downloaders = self.lock_piece_downloaders()?;
+        match piece_status.get(index as usize) {
+            Some(PieceStatus::Downloading) => (),
+            Some(PieceStatus::Finished) if piece_downloaders.contains_key(&index) => {
+                Self::remove_downloader(&mut piece_downloaders, index);
+                return Ok(());
             }
+            Some(_) => return Err(AtomicTorrentStatusError::PieceWasNotDownloading),
             None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
         }
-        piece_status.insert(index, PieceStatus::Free);
+
+        if Self::remove_downloader(&mut piece_downloaders, index) > 0 {
+            // Other sessions are still downloading the piece.
+            return Ok(());
+        }
+        piece_status[index as usize] = PieceStatus::Free;
         self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
         self.free_pieces.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
+    /// Keeps a block of a downloading piece, so the piece can be resumed from it if it's aborted.
+    ///
+    /// Blocks of pieces that aren't downloading, such as the ones finished by another session during the 'EndGame', are ignored, but still counted in the downloaded bytes.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedDownloadedBlocksLock` if the lock on the `downloaded_blocks` field is poisoned.
+    /// - `PoisonedStateLock` if the lock on the `state` field is poisoned.
+    /// - `InvalidPieceIndex` if the piece index is invalid.
+    pub fn block_downloaded(
+        &self,
+        index: u32,
+        begin: u32,
+        block: &[u8],
+    ) -> Result<(), AtomicTorrentStatusError> {
+        self.metrics.bytes_downloaded(block.len());
+        let piece_status = self.lock_pieces_status()?;
+        match piece_status.get(index as usize) {
+            Some(PieceStatus::Downloading) => (),
+            Some(_) => return Ok(()),
+            None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
+        }
+        self.lock_downloaded_blocks()?
+            .entry(index)
+            .or_default()
+            .insert(begin, block.to_vec());
+        self.update_state(|state| match state {
+            TorrentState::Connecting | TorrentState::Stalled => TorrentState::Downloading,
+            state => state,
+        })
+    }
+
+    /// Returns the blocks already downloaded from the start of a piece, up to the first missing one.
+    ///
+    /// # Errors
+    /// - `PoisonedDownloadedBlocksLock` if the lock on the `downloaded_blocks` field is poisoned.
+    pub fn downloaded_blocks(&self, index: u32) -> Result<Vec<u8>, AtomicTorrentStatusError> {
+        let downloaded_blocks = self.lock_downloaded_blocks()?;
+        let mut piece = Vec::new();
+        if let Some(blocks) = downloaded_blocks.get(&index) {
+            for (begin, block) in blocks {
+                if *begin as usize != piece.len() {
+                    break;
+                }
+                piece.extend_from_slice(block);
+            }
+        }
+        Ok(piece)
+    }
+
+    /// Returns every block already downloaded of a piece, by offset, even if there are missing blocks before them.
+    ///
+    /// # Errors
+    /// - `PoisonedDownloadedBlocksLock` if the lock on the `downloaded_blocks` field is poisoned.
+    pub fn downloaded_blocks_by_offset(
+        &self,
+        index: u32,
+    ) -> Result<BTreeMap<u32, Vec<u8>>, AtomicTorrentStatusError> {
+        Ok(self
+            .lock_downloaded_blocks()?
+            .get(&index)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// Discards the blocks downloaded of a piece, such as when the downloaded piece doesn't match its hash.
+    ///
+    /// # Errors
+    /// - `PoisonedDownloadedBlocksLock` if the lock on the `downloaded_blocks` field is poisoned.
+    pub fn discard_blocks(&self, index: u32) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_downloaded_blocks()?.remove(&index);
+        Ok(())
+    }
+
+    /// Removes a session from the ones downloading a piece, returning how many are left.
+    fn remove_downloader(piece_downloaders: &mut HashMap<u32, usize>, index: u32) -> usize {
+        let remaining = match piece_downloaders.get_mut(&index) {
+            Some(downloaders) => {
+                *downloaders = downloaders.saturating_sub(1);
+                *downloaders
+            }
+            None => 0,
+        };
+        if remaining == 0 {
+            piece_downloaders.remove(&index);
+        }
+        remaining
+    }
+
     /// Returns the current bitfield of the torrent.
     ///
     /// # Errors
@@ -394,14 +915,97 @@ impl AtomicTorrentStatus {
         Ok(Bitfield::from(&pieces_status))
     }
 
-    fn lock_pieces_status(
+    /// Returns the indices of the pieces that are not downloaded yet, either free or downloading.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    pub fn missing_pieces(&self) -> Result<Vec<u32>, AtomicTorrentStatusError> {
+        self.pieces_with_status(|status| status != &PieceStatus::Finished)
+    }
+
+    /// Returns the indices of the pieces already downloaded.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    pub fn finished_pieces_list(&self) -> Result<Vec<u32>, AtomicTorrentStatusError> {
+        self.pieces_with_status(|status| status == &PieceStatus::Finished)
+    }
+
+    /// Returns the indices of the pieces whose status matches, from a snapshot taken under the lock.
+    fn pieces_with_status(
         &self,
-    ) -> Result<MutexGuard<HashMap<u32, PieceStatus>>, AtomicTorrentStatusError> {
+        matches: impl Fn(&PieceStatus) -> bool,
+    ) -> Result<Vec<u32>, AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+        Ok((0..pieces_status.len() as u32)
+            .filter(|index| matches(&pieces_status[*index as usize]))
+            .collect())
+    }
+
+    fn lock_pieces_status(&self) -> Result<MutexGuard<Vec<PieceStatus>>, AtomicTorrentStatusError> {
         self.pieces_status
             .lock()
             .map_err(|_| AtomicTorrentStatusError::PoisonedPiecesStatusLock)
     }
 
+    fn lock_piece_downloaders(
+        &self,
+    ) -> Result<MutexGuard<'_, HashMap<u32, usize>>, AtomicTorrentStatusError> {
+        self.piece_downloaders
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPieceDownloadersLock)
+    }
+
+    fn lock_downloaded_blocks(
+        &self,
+    ) -> Result<MutexGuard<'_, HashMap<u32, PieceBlocks>>, AtomicTorrentStatusError> {
+        self.downloaded_blocks
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedDownloadedBlocksLock)
+    }
+
+    /// Replaces the state of the torrent with the one returned by `update`, given the current one.
+    fn update_state(
+        &self,
+        update: impl FnOnce(TorrentState) -> TorrentState,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        let mut state = self.lock_state()?;
+        let updated = update(*state);
+        if updated != *state {
+            *state = updated;
+            self.mark_changed();
+        }
+        Ok(())
+    }
+
+    fn lock_state(&self) -> Result<MutexGuard<'_, TorrentState>, AtomicTorrentStatusError> {
+        self.state
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedStateLock)
+    }
+
+    fn lock_piece_priorities(
+        &self,
+    ) -> Result<MutexGuard<'_, BTreeMap<u32, Priority>>, AtomicTorrentStatusError> {
+        self.piece_priorities
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPiecePrioritiesLock)
+    }
+
+    fn lock_piece_availability(
+        &self,
+    ) -> Result<MutexGuard<'_, Vec<usize>>, AtomicTorrentStatusError> {
+        self.piece_availability
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPieceAvailabilityLock)
+    }
+
+    fn lock_rng(&self) -> Result<MutexGuard<'_, StdRng>, AtomicTorrentStatusError> {
+        self.rng
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedRngLock)
+    }
+
     fn lock_session_status(
         &self,
     ) -> Result<MutexGuard<HashMap<BtPeer, SessionStatus>>, AtomicTorrentStatusError> {
@@ -413,13 +1017,21 @@ impl AtomicTorrentStatus {
 
 #[cfg(test)]
 mod tests {
-    use std::{fs, sync::Arc, thread};
+    use std::{
+        collections::HashSet,
+        fs,
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
 
     use crate::torrent_parser::info::Info;
+    use bencoder::bencode::Bencode;
 
     use super::*;
 
     const CONFIG_PATH: &str = "config.cfg";
+    const SINGLE_FILE_IN_FILES_LIST_TORRENT: &[u8] = b"d8:announce8:announce4:infod5:filesld6:lengthi2e4:pathl3:dir9:other.txteee4:name34:test_single_file_in_the_files_list12:piece lengthi1e6:pieces0:ee";
 
     #[test]
     fn test_is_not_finished() {
@@ -429,6 +1041,37 @@ mod tests {
         assert!(!status.is_finished());
     }
 
+    #[test]
+    fn test_seed_ratio_reached_once_finished_and_uploaded() {
+        let torrent = create_test_torrent("test_seed_ratio_reached_once_finished_and_uploaded");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.seed_ratio_limit = Some(1.5);
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+
+        status
+            .metrics()
+            .bytes_uploaded((torrent.info.length * 2) as usize);
+        // The ratio only stops seeding, not downloading.
+        assert!(!status.seed_ratio_reached());
+
+        for _ in 0..torrent.total_pieces() {
+            let index = status
+                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                .unwrap()
+                .unwrap();
+            status.piece_downloaded(index, &[]).unwrap();
+        }
+
+        assert!(status.seed_ratio_reached());
+        assert!(status.take_seed_ratio_reached());
+        assert!(!status.take_seed_ratio_reached());
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
     #[test]
     fn test_is_finished() {
         let torrent = create_test_torrent("test_is_finished");
@@ -451,19 +1094,94 @@ mod tests {
     }
 
     #[test]
-    fn test_starting_current_peers() {
-        let torrent = create_test_torrent("test_starting_current_peers");
-
+    fn test_stop_wakes_up_the_handler() {
+        let torrent = create_test_torrent("test_stop_wakes_up_the_handler");
         let config = Cfg::new(CONFIG_PATH).unwrap();
-        let status = create_status_whitout_receiver(&torrent, config.clone());
-        assert_eq!(0, status.current_peers());
+        let (status, receiver) = AtomicTorrentStatus::new(&torrent, config);
+        assert!(!status.is_stopped());
+
+        status.stop();
+
+        assert!(status.is_stopped());
+        assert!(receiver.recv_timeout(Duration::from_secs(1)).is_ok());
     }
 
     #[test]
-    fn test_peer_connected() {
-        let torrent = create_test_torrent("test_peer_connected");
-        let peer = create_test_peer("192.0".to_string());
-
+    fn test_subscriber_receives_torrent_completed() {
+        let torrent = create_test_torrent("test_subscriber_receives_torrent_completed");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let (sender, receiver) = mpsc::channel();
+        status.subscribe(sender);
+
+        for _ in 0..torrent.total_pieces() {
+            let index = status
+                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                .unwrap()
+                .unwrap();
+            status.piece_downloaded(index, &[]).unwrap();
+        }
+
+        let events: Vec<TorrentEvent> = receiver.try_iter().collect();
+        let pieces_completed = events
+            .iter()
+            .filter(|event| matches!(event, TorrentEvent::PieceCompleted { .. }))
+            .count();
+        assert_eq!(pieces_completed, torrent.total_pieces() as usize);
+        assert_eq!(
+            events.last(),
+            Some(&TorrentEvent::TorrentCompleted {
+                info_hash: torrent.info_hash_hex()
+            })
+        );
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_subscriber_receives_peer_events() {
+        let torrent = create_test_torrent("test_subscriber_receives_peer_events");
+        let peer = create_test_peer("192.0".to_string());
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let (sender, receiver) = mpsc::channel();
+        status.subscribe(sender);
+
+        status.peer_connected(&peer).unwrap();
+        status.peer_disconnected(&peer).unwrap();
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<TorrentEvent>>(),
+            vec![
+                TorrentEvent::PeerConnected {
+                    info_hash: torrent.info_hash_hex(),
+                    peer: "192.0:0".to_string()
+                },
+                TorrentEvent::PeerDisconnected {
+                    info_hash: torrent.info_hash_hex(),
+                    peer: "192.0:0".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_starting_current_peers() {
+        let torrent = create_test_torrent("test_starting_current_peers");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert_eq!(0, status.current_peers());
+    }
+
+    #[test]
+    fn test_peer_connected() {
+        let torrent = create_test_torrent("test_peer_connected");
+        let peer = create_test_peer("192.0".to_string());
+
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         status.peer_connected(&peer).unwrap();
@@ -504,7 +1222,7 @@ mod tests {
             .unwrap()
             .unwrap();
         assert_eq!(
-            *status.pieces_status.lock().unwrap().get(&index).unwrap(),
+            status.pieces_status.lock().unwrap()[index as usize],
             PieceStatus::Downloading
         );
     }
@@ -533,7 +1251,7 @@ mod tests {
             .unwrap();
         status.piece_downloaded(index as u32, &[]).unwrap();
         assert_eq!(
-            *status.pieces_status.lock().unwrap().get(&index).unwrap(),
+            status.pieces_status.lock().unwrap()[index as usize],
             PieceStatus::Finished
         );
         fs::remove_file(format!(
@@ -543,6 +1261,74 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_peers_snapshot() {
+        let torrent = create_test_torrent("test_peers_snapshot");
+        let first_peer = create_test_peer("192.0".to_string());
+        let second_peer = create_test_peer("192.1".to_string());
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        for (peer, speed) in [(&first_peer, 10.0), (&second_peer, 20.0)] {
+            status.peer_connected(peer).unwrap();
+            let mut session_status = create_test_peer_session_status();
+            session_status.download_speed = speed;
+            session_status.upload_speed = speed / 2.0;
+            status
+                .update_peer_session_status(peer, &session_status)
+                .unwrap();
+        }
+
+        let snapshot = status.peers_snapshot().unwrap();
+        assert_eq!(snapshot.peers.len(), 2);
+        assert_eq!(snapshot.download_speed, 30.0);
+        assert_eq!(snapshot.upload_speed, 15.0);
+    }
+
+    #[test]
+    fn test_take_changed() {
+        let torrent = create_test_torrent("test_take_changed");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        assert!(status.take_changed());
+        assert!(!status.take_changed());
+
+        status.peer_connected(&peer).unwrap();
+        assert!(status.take_changed());
+        assert!(!status.take_changed());
+    }
+
+    #[test]
+    fn test_metrics_after_download() {
+        let torrent = create_test_torrent("test_metrics_after_download");
+        let peer = create_test_peer("192.0".to_string());
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.peer_connected(&peer).unwrap();
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.block_downloaded(index, 0, &[1]).unwrap();
+        status.piece_downloaded(index, &[1]).unwrap();
+        // Blocks of finished pieces are still counted as downloaded.
+        status.block_downloaded(index, 0, &[1]).unwrap();
+        status.peer_disconnected(&peer).unwrap();
+
+        let metrics = status.metrics().snapshot();
+        assert_eq!(metrics.pieces_downloaded, 1);
+        assert_eq!(metrics.bytes_downloaded, 2);
+        assert_eq!(metrics.active_peers, 0);
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
     #[test]
     fn test_piece_aborted() {
         let torrent = create_test_torrent("test_piece_aborted");
@@ -555,11 +1341,149 @@ mod tests {
             .unwrap();
         status.piece_aborted(index).unwrap();
         assert_eq!(
-            *status.pieces_status.lock().unwrap().get(&index).unwrap(),
+            status.pieces_status.lock().unwrap()[index as usize],
             PieceStatus::Free
         );
     }
 
+    #[test]
+    fn test_aborted_piece_keeps_its_downloaded_blocks() {
+        let torrent = create_test_torrent("test_aborted_piece_keeps_its_downloaded_blocks");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        let index = status.select_piece(&bitfield).unwrap().unwrap();
+        status.block_downloaded(index, 0, &[1, 2]).unwrap();
+        status.block_downloaded(index, 2, &[3, 4]).unwrap();
+        status.piece_aborted(index).unwrap();
+
+        let resumed_index = status.select_piece(&bitfield).unwrap().unwrap();
+
+        assert_eq!(resumed_index, index);
+        assert_eq!(status.downloaded_blocks(index).unwrap(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_downloaded_blocks_by_offset_skip_missing_blocks() {
+        let torrent = create_test_torrent("test_downloaded_blocks_by_offset_skip_missing_blocks");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.block_downloaded(index, 0, &[1, 2]).unwrap();
+        status.block_downloaded(index, 4, &[5, 6]).unwrap();
+
+        let blocks = status.downloaded_blocks_by_offset(index).unwrap();
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks.get(&0), Some(&vec![1, 2]));
+        assert_eq!(blocks.get(&4), Some(&vec![5, 6]));
+    }
+
+    #[test]
+    fn test_downloaded_blocks_stop_at_the_first_missing_block() {
+        let torrent = create_test_torrent("test_downloaded_blocks_stop_at_the_first_missing_block");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.block_downloaded(index, 0, &[1, 2]).unwrap();
+        status.block_downloaded(index, 4, &[5, 6]).unwrap();
+
+        assert_eq!(status.downloaded_blocks(index).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_discarded_blocks_are_not_resumed() {
+        let torrent = create_test_torrent("test_discarded_blocks_are_not_resumed");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .unwrap()
+            .unwrap();
+        status.block_downloaded(index, 0, &[1, 2]).unwrap();
+        status.discard_blocks(index).unwrap();
+
+        assert!(status.downloaded_blocks(index).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_blocks_of_a_free_piece_are_ignored() {
+        let torrent = create_test_torrent("test_blocks_of_a_free_piece_are_ignored");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        status.block_downloaded(0, 0, &[1, 2]).unwrap();
+
+        assert!(status.downloaded_blocks(0).unwrap().is_empty());
+        assert!(status.block_downloaded(1000, 0, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_single_file_in_the_files_list_downloads_to_a_file_named_after_the_info() {
+        let torrent_bencode = Bencode::decode(SINGLE_FILE_IN_FILES_LIST_TORRENT).unwrap();
+        let torrent = Torrent::from(torrent_bencode).unwrap();
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let bitfield = Bitfield::new(vec![0b11000000]);
+        for _ in 0..torrent.total_pieces() {
+            let index = status.select_piece(&bitfield).unwrap().unwrap();
+            status.piece_downloaded(index, b"a").unwrap();
+        }
+
+        let path = format!("{}/{}", config.download_directory, torrent.info.name);
+        assert!(status.is_finished());
+        assert_eq!(fs::read(&path).unwrap(), b"aa");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_pieces_exclude_the_finished_ones() {
+        let torrent = create_test_torrent("test_missing_pieces_exclude_the_finished_ones");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        for _ in 0..3 {
+            let index = status.select_piece(&bitfield).unwrap().unwrap();
+            status.piece_downloaded(index, &[1]).unwrap();
+        }
+        // A downloading piece is still missing.
+        status.select_piece(&bitfield).unwrap().unwrap();
+
+        assert_eq!(status.finished_pieces_list().unwrap(), vec![0, 1, 2]);
+        assert_eq!(status.missing_pieces().unwrap(), vec![3, 4, 5, 6, 7, 8, 9]);
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_every_piece_is_missing_before_downloading() {
+        let torrent = create_test_torrent("test_every_piece_is_missing_before_downloading");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        assert_eq!(
+            status.missing_pieces().unwrap(),
+            (0..10).collect::<Vec<u32>>()
+        );
+        assert!(status.finished_pieces_list().unwrap().is_empty());
+    }
+
     #[test]
     fn test_bad_index() {
         let torrent = create_test_torrent("test_bad_index");
@@ -620,6 +1544,475 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_select_piece_only_selects_pieces_the_peer_has() {
+        let torrent = create_test_torrent("test_select_piece_only_selects_pieces_the_peer_has");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b00100000, 0b01000000]);
+
+        let mut selected = vec![
+            status.select_piece(&bitfield).unwrap().unwrap(),
+            status.select_piece(&bitfield).unwrap().unwrap(),
+        ];
+        selected.sort();
+
+        assert_eq!(selected, vec![2, 9]);
+        assert_eq!(status.downloading_pieces(), 2);
+        // The peer has no other free pieces, and the torrent is not in endgame yet.
+        assert_eq!(status.select_piece(&bitfield).unwrap(), None);
+    }
+
+    #[test]
+    fn test_select_piece_with_many_pieces() {
+        let total_pieces: u32 = 200_000;
+        let mut torrent = create_test_torrent("test_select_piece_with_many_pieces");
+        torrent.info.length = total_pieces as i64;
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111; total_pieces as usize / 8]);
+
+        let mut selected = HashSet::new();
+        for _ in 0..5000 {
+            selected.insert(status.select_piece(&bitfield).unwrap().unwrap());
+        }
+
+        // Every call selects a different piece, and only the selected pieces stop being free.
+        assert_eq!(selected.len(), 5000);
+        assert_eq!(status.downloading_pieces(), 5000);
+        let pieces_status = status.pieces_status.lock().unwrap();
+        assert!(selected
+            .iter()
+            .all(|index| pieces_status[*index as usize] == PieceStatus::Downloading));
+        assert_eq!(
+            pieces_status
+                .iter()
+                .filter(|piece_status| **piece_status == PieceStatus::Free)
+                .count(),
+            total_pieces as usize - 5000
+        );
+    }
+
+    #[test]
+    fn test_get_bitfield() {
+        let torrent = create_test_torrent("test_get_bitfield");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+
+        status
+            .select_piece(&Bitfield::new(vec![0b00000000, 0b01000000]))
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(9, &[1]).unwrap();
+        status
+            .select_piece(&Bitfield::new(vec![0b10000000, 0b00000000]))
+            .unwrap()
+            .unwrap();
+
+        // Only finished pieces are set, and the bits past the last piece are left clear.
+        assert_eq!(
+            status.get_bitfield().unwrap().get_vec(),
+            vec![0b00000000, 0b01000000]
+        );
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_pieces_status_memory_for_large_torrent() {
+        let total_pieces: u32 = 500_000;
+        let mut torrent = create_test_torrent("test_pieces_status_memory_for_large_torrent");
+        torrent.info.length = total_pieces as i64;
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        let pieces_status = status.pieces_status.lock().unwrap();
+
+        // One byte per piece, where the previous `HashMap<u32, PieceStatus>` took at least 8 bytes per entry
+        // plus the hashing overhead.
+        assert_eq!(pieces_status.len(), total_pieces as usize);
+        assert_eq!(std::mem::size_of::<PieceStatus>(), 1);
+        assert!(pieces_status.capacity() * std::mem::size_of::<PieceStatus>() < 1024 * 1024);
+    }
+
+    #[test]
+    fn test_endgame_selects_a_downloading_piece() {
+        let torrent = create_test_torrent("test_endgame_selects_a_downloading_piece");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        for _ in 0..torrent.total_pieces() {
+            status.select_piece(&bitfield).unwrap().unwrap();
+        }
+
+        let index = status.select_piece(&bitfield).unwrap().unwrap();
+
+        assert_eq!(
+            status.pieces_status.lock().unwrap()[index as usize],
+            PieceStatus::Downloading
+        );
+        assert_eq!(status.downloading_pieces(), torrent.total_pieces() as usize);
+        assert_eq!(status.free_pieces.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_endgame_selection_is_reproducible_with_a_seed() {
+        let torrent = create_test_torrent("test_endgame_selection_is_reproducible_with_a_seed");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        let endgame_selection = |seed: u64| {
+            let status = create_status_whitout_receiver(&torrent, config.clone());
+            status.seed_rng(seed).unwrap();
+            for _ in 0..torrent.total_pieces() {
+                status.select_piece(&bitfield).unwrap().unwrap();
+            }
+            (0..2 * torrent.total_pieces())
+                .map(|_| status.select_piece(&bitfield).unwrap().unwrap())
+                .collect::<Vec<u32>>()
+        };
+
+        let selection = endgame_selection(42);
+
+        assert_eq!(selection, endgame_selection(42));
+        // Every round selects each piece once, as the pieces with the fewest downloaders are chosen first.
+        let mut first_round = selection[..torrent.total_pieces() as usize].to_vec();
+        first_round.sort();
+        assert_eq!(
+            first_round,
+            (0..torrent.total_pieces()).collect::<Vec<u32>>()
+        );
+    }
+
+    #[test]
+    fn test_sequential_selection_returns_pieces_in_ascending_order() {
+        let torrent = create_test_torrent("test_sequential_selection_returns_pieces_in_order");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+
+        let selection: Vec<u32> = (0..torrent.total_pieces())
+            .map(|_| status.select_piece(&bitfield).unwrap().unwrap())
+            .collect();
+
+        assert_eq!(selection, (0..torrent.total_pieces()).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_sequential_selection_skips_pieces_the_peer_does_not_have() {
+        let torrent = create_test_torrent("test_sequential_selection_skips_pieces");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b01010000, 0b01000000]);
+
+        let selection: Vec<u32> = (0..3)
+            .map(|_| status.select_piece(&bitfield).unwrap().unwrap())
+            .collect();
+
+        assert_eq!(selection, vec![1, 3, 9]);
+        assert_eq!(status.select_piece(&bitfield).unwrap(), None);
+    }
+
+    #[test]
+    fn test_rarest_selection_prefers_the_pieces_fewer_peers_have() {
+        let torrent = create_test_torrent("test_rarest_selection_prefers_the_rarest_pieces");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Rarest;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+        // Pieces 7 and 9 are the only ones a single peer has.
+        status.peer_has_pieces(&bitfield).unwrap();
+        status
+            .peer_has_pieces(&Bitfield::new(vec![0b11111110, 0b00000000]))
+            .unwrap();
+        status.peer_has_piece(8).unwrap();
+
+        let selection: Vec<u32> = (0..3)
+            .map(|_| status.select_piece(&bitfield).unwrap().unwrap())
+            .collect();
+
+        assert_eq!(selection, vec![7, 9, 0]);
+    }
+
+    #[test]
+    fn test_rarest_selection_forgets_the_pieces_of_disconnected_peers() {
+        let torrent = create_test_torrent("test_rarest_selection_forgets_disconnected_peers");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Rarest;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+        let partial_bitfield = Bitfield::new(vec![0b11111110, 0b00000000]);
+        status.peer_has_pieces(&bitfield).unwrap();
+        status.peer_has_pieces(&partial_bitfield).unwrap();
+
+        status.peer_lost_pieces(&partial_bitfield).unwrap();
+
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_random_selection_is_reproducible_with_a_seed() {
+        let torrent = create_test_torrent("test_random_selection_is_reproducible_with_a_seed");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Random;
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+        let random_selection = |seed: u64| {
+            let status = create_status_whitout_receiver(&torrent, config.clone());
+            status.seed_rng(seed).unwrap();
+            (0..torrent.total_pieces())
+                .map(|_| status.select_piece(&bitfield).unwrap().unwrap())
+                .collect::<Vec<u32>>()
+        };
+
+        let selection = random_selection(7);
+
+        assert_eq!(selection, random_selection(7));
+        let mut pieces = selection.clone();
+        pieces.sort();
+        assert_eq!(pieces, (0..torrent.total_pieces()).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_high_priority_piece_is_selected_before_normal_pieces() {
+        let torrent = create_test_torrent("test_high_priority_piece_is_selected_first");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+        let last_piece = torrent.total_pieces() - 1;
+
+        status.set_priority(&[last_piece], Priority::High).unwrap();
+
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(last_piece));
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_high_priority_pieces_the_peer_does_not_have_are_skipped() {
+        let torrent = create_test_torrent("test_high_priority_pieces_the_peer_does_not_have");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b00000000]);
+
+        status.set_priority(&[9], Priority::High).unwrap();
+
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_low_priority_pieces_are_selected_last() {
+        let torrent = create_test_torrent("test_low_priority_pieces_are_selected_last");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11000000, 0b00000000]);
+
+        status.set_priority(&[0], Priority::Low).unwrap();
+
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(1));
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_pieces_set_back_to_normal_priority_are_selected_in_order() {
+        let torrent = create_test_torrent("test_pieces_set_back_to_normal_priority");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+
+        status.set_priority(&[5, 9], Priority::High).unwrap();
+        status.set_priority(&[9], Priority::Normal).unwrap();
+
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(5));
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_set_priority_of_invalid_piece() {
+        let torrent = create_test_torrent("test_set_priority_of_invalid_piece");
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.piece_selection_strategy = SelectionStrategy::Sequential;
+        let status = create_status_whitout_receiver(&torrent, config);
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+
+        let result = status.set_priority(&[9, torrent.total_pieces()], Priority::High);
+
+        assert!(matches!(
+            result,
+            Err(AtomicTorrentStatusError::InvalidPieceIndex)
+        ));
+        assert_eq!(status.select_piece(&bitfield).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_new_torrent_is_connecting() {
+        let torrent = create_test_torrent("test_new_torrent_is_connecting");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        status.peers_obtained(3).unwrap();
+
+        assert_eq!(status.state().unwrap(), TorrentState::Connecting);
+    }
+
+    #[test]
+    fn test_torrent_without_peers_is_stalled_until_a_block_arrives() {
+        let torrent = create_test_torrent("test_torrent_without_peers_is_stalled");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        status.peers_obtained(0).unwrap();
+        assert_eq!(status.state().unwrap(), TorrentState::Stalled);
+
+        let index = status
+            .select_piece(&Bitfield::new(vec![0b11111111, 0b11000000]))
+            .unwrap()
+            .unwrap();
+        status.block_downloaded(index, 0, &[1]).unwrap();
+        assert_eq!(status.state().unwrap(), TorrentState::Downloading);
+    }
+
+    #[test]
+    fn test_torrent_is_stalled_when_every_peer_disconnects() {
+        let torrent = create_test_torrent("test_torrent_is_stalled_when_every_peer_disconnects");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let peer = create_test_peer("127.0.0.1".to_string());
+        status.peer_connecting();
+        status.peer_connected(&peer).unwrap();
+        status.set_state(TorrentState::Downloading).unwrap();
+
+        status.peer_disconnected(&peer).unwrap();
+
+        assert_eq!(status.state().unwrap(), TorrentState::Stalled);
+    }
+
+    #[test]
+    fn test_stopped_torrent_is_paused() {
+        let torrent = create_test_torrent("test_stopped_torrent_is_paused");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        status.stop();
+        status.peers_obtained(0).unwrap();
+
+        assert_eq!(status.state().unwrap(), TorrentState::Paused);
+    }
+
+    #[test]
+    fn test_endgame_piece_downloaded_twice() {
+        let torrent = create_test_torrent("test_endgame_piece_downloaded_twice");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        for _ in 0..torrent.total_pieces() {
+            status.select_piece(&bitfield).unwrap().unwrap();
+        }
+        let index = status.select_piece(&bitfield).unwrap().unwrap();
+
+        status.piece_downloaded(index, &[1]).unwrap();
+        status.piece_downloaded(index, &[1]).unwrap();
+
+        assert_eq!(status.downloaded_pieces(), 1);
+        assert!(status.piece_downloaded(index, &[1]).is_err());
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_endgame_piece_aborted_by_one_session() {
+        let torrent = create_test_torrent("test_endgame_piece_aborted_by_one_session");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        for _ in 0..torrent.total_pieces() {
+            status.select_piece(&bitfield).unwrap().unwrap();
+        }
+        let index = status.select_piece(&bitfield).unwrap().unwrap();
+
+        status.piece_aborted(index).unwrap();
+        assert_eq!(
+            status.pieces_status.lock().unwrap()[index as usize],
+            PieceStatus::Downloading
+        );
+
+        status.piece_aborted(index).unwrap();
+        assert_eq!(
+            status.pieces_status.lock().unwrap()[index as usize],
+            PieceStatus::Free
+        );
+    }
+
+    #[test]
+    fn test_multiple_threads_endgame_distributes_pieces() {
+        let torrent = create_test_torrent("test_multiple_threads_endgame_distributes_pieces");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = Arc::new(create_status_whitout_receiver(&torrent, config.clone()));
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11111111]);
+        let total_pieces = torrent.total_pieces() as usize;
+        for _ in 0..total_pieces {
+            status.select_piece(&bitfield).unwrap().unwrap();
+        }
+
+        // Every piece is downloading, so each of these threads is an endgame selection.
+        let sessions_per_piece = 5;
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..total_pieces * sessions_per_piece {
+            let status_cloned = status.clone();
+            let bitfield_cloned = bitfield.clone();
+            let sender_cloned = sender.clone();
+            thread::spawn(move || {
+                let index = status_cloned.select_piece(&bitfield_cloned).unwrap();
+                sender_cloned.send(index).unwrap();
+            });
+        }
+
+        let mut assigned = HashMap::new();
+        for _ in 0..total_pieces * sessions_per_piece {
+            let index = receiver
+                .recv_timeout(Duration::from_secs(5))
+                .expect("endgame selection deadlocked")
+                .unwrap();
+            *assigned.entry(index).or_insert(0) += 1;
+        }
+
+        // The pieces are spread evenly instead of being handed randomly.
+        assert_eq!(assigned.len(), total_pieces);
+        assert!(assigned.values().all(|count| *count == sessions_per_piece));
+
+        // Every session downloads its piece concurrently, and only the first one of each is saved.
+        let mut joins = Vec::new();
+        for (index, count) in assigned {
+            for _ in 0..=count {
+                let status_cloned = status.clone();
+                joins.push(thread::spawn(move || {
+                    status_cloned.piece_downloaded(index, &[1]).unwrap();
+                }));
+            }
+        }
+        for join in joins {
+            join.join().unwrap();
+        }
+
+        assert!(status.is_finished());
+        assert_eq!(status.downloading_pieces(), 0);
+        assert!(status.piece_downloaders.lock().unwrap().is_empty());
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
     #[test]
     fn test_bad_downloaded() {
         let torrent = create_test_torrent("test_bad_downloaded");
@@ -772,12 +2165,14 @@ mod tests {
             name: name.to_string(),
             piece_length: 1,
             pieces: vec![],
+            files: None,
         };
 
         Torrent {
             announce_url: "announce".to_string(),
             info,
-            info_hash: "info_hash".to_string(),
+            info_hash: [0; 20],
+            info_bytes: Vec::new(),
         }
     }
 
@@ -787,6 +2182,8 @@ mod tests {
             ip,
             port: 0,
             info_hash: None,
+            supports_extensions: false,
+            supports_fast_extension: false,
         }
     }
 
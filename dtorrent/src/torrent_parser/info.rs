@@ -8,6 +8,17 @@ pub struct Info {
     pub name: String,
     pub piece_length: i64,
     pub pieces: Vec<u8>,
+    /// The `files` list, for single-file torrents described with one entry in it instead of a top-level `length`.
+    ///
+    /// It's kept so the info is encoded back as it was received, which keeps its info hash.
+    pub files: Option<Vec<InfoFile>>,
+}
+
+/// An entry of the `files` list of the info.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InfoFile {
+    pub length: i64,
+    pub path: Vec<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -18,6 +29,7 @@ pub enum FromInfoError {
     MissingPieces,
     NotADict,
     MultipleFilesNotSupported,
+    InvalidFiles,
 }
 
 impl Info {
@@ -26,6 +38,7 @@ impl Info {
         let mut length = 0;
         let mut piece_length = 0;
         let mut pieces = Vec::new();
+        let mut files = None;
 
         let d = match bencode {
             Bencode::BDict(s) => s,
@@ -42,7 +55,15 @@ impl Info {
             } else if k == b"pieces" {
                 pieces = Info::create_pieces(v)?;
             } else if k == b"files" {
-                return Err(FromInfoError::MultipleFilesNotSupported);
+                files = Some(Info::create_files(v)?);
+            }
+        }
+
+        // A single entry in the files list is the same as a single-file torrent, stored in a file named after the info name.
+        if let Some(files) = &files {
+            match files.as_slice() {
+                [file] => length = file.length,
+                _ => return Err(FromInfoError::MultipleFilesNotSupported),
             }
         }
 
@@ -51,9 +72,45 @@ impl Info {
             name,
             piece_length,
             pieces,
+            files,
         })
     }
 
+    fn create_files(bencode: &Bencode) -> Result<Vec<InfoFile>, FromInfoError> {
+        let list = match bencode {
+            Bencode::BList(l) => l,
+            _ => return Err(FromInfoError::InvalidFiles),
+        };
+
+        list.iter().map(Info::create_file).collect()
+    }
+
+    fn create_file(bencode: &Bencode) -> Result<InfoFile, FromInfoError> {
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(FromInfoError::InvalidFiles),
+        };
+
+        let length = match d.get(b"length".as_slice()) {
+            Some(Bencode::BNumber(length)) => *length,
+            _ => return Err(FromInfoError::InvalidFiles),
+        };
+        let path = match d.get(b"path".as_slice()) {
+            Some(Bencode::BList(path)) => path
+                .iter()
+                .map(|component| match component {
+                    Bencode::BString(s) => {
+                        String::from_utf8(s.to_vec()).map_err(|_| FromInfoError::InvalidFiles)
+                    }
+                    _ => Err(FromInfoError::InvalidFiles),
+                })
+                .collect::<Result<Vec<String>, FromInfoError>>()?,
+            _ => return Err(FromInfoError::InvalidFiles),
+        };
+
+        Ok(InfoFile { length, path })
+    }
+
     fn create_name(bencode: &Bencode) -> Result<String, FromInfoError> {
         let c = match bencode {
             Bencode::BString(s) => s,
@@ -96,7 +153,10 @@ impl Info {
 impl ToBencode for Info {
     fn to_bencode(&self) -> Bencode {
         let mut info = BTreeMap::new();
-        info.insert(b"length".to_vec(), self.length.to_bencode());
+        match &self.files {
+            Some(files) => info.insert(b"files".to_vec(), files.to_bencode()),
+            None => info.insert(b"length".to_vec(), self.length.to_bencode()),
+        };
         info.insert(b"name".to_vec(), self.name.to_bencode());
         info.insert(b"piece length".to_vec(), self.piece_length.to_bencode());
         info.insert(b"pieces".to_vec(), self.pieces.to_bencode());
@@ -104,6 +164,15 @@ impl ToBencode for Info {
     }
 }
 
+impl ToBencode for InfoFile {
+    fn to_bencode(&self) -> Bencode {
+        let mut file = BTreeMap::new();
+        file.insert(b"length".to_vec(), self.length.to_bencode());
+        file.insert(b"path".to_vec(), self.path.to_bencode());
+        Bencode::BDict(file)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,7 +184,7 @@ mod tests {
         assert_eq!(info.length, 0);
         assert_eq!(info.name, String::new());
         assert_eq!(info.piece_length, 0);
-        assert_eq!(info.pieces, Vec::new());
+        assert_eq!(info.pieces, Vec::<u8>::new());
     }
 
     #[test]
@@ -146,4 +215,70 @@ mod tests {
         let response = Info::from(&bencode).unwrap_err();
         assert_eq!(response, FromInfoError::MultipleFilesNotSupported);
     }
+
+    #[test]
+    fn test_from_info_with_a_single_file_in_the_files_list() {
+        let bencode = build_info_with_files(vec![build_file_bencode(10, &["dir", "test1.txt"])]);
+
+        let info = Info::from(&bencode).unwrap();
+        assert_eq!(info.length, 10);
+        assert_eq!(info.name, "test1");
+        assert_eq!(
+            info.files,
+            Some(vec![InfoFile {
+                length: 10,
+                path: vec!["dir".to_string(), "test1.txt".to_string()],
+            }])
+        );
+    }
+
+    #[test]
+    fn test_info_with_a_single_file_in_the_files_list_is_encoded_back_as_received() {
+        let bencode = build_info_with_files(vec![build_file_bencode(10, &["test1.txt"])]);
+
+        let info = Info::from(&bencode).unwrap();
+        assert_eq!(Bencode::encode(&info), Bencode::encode(&bencode));
+    }
+
+    #[test]
+    fn test_from_info_with_two_files_in_the_files_list() {
+        let bencode = build_info_with_files(vec![
+            build_file_bencode(10, &["test1.txt"]),
+            build_file_bencode(20, &["test2.txt"]),
+        ]);
+
+        let response = Info::from(&bencode).unwrap_err();
+        assert_eq!(response, FromInfoError::MultipleFilesNotSupported);
+    }
+
+    #[test]
+    fn test_from_info_with_an_invalid_file() {
+        let bencode = build_info_with_files(vec![Bencode::BNumber(10)]);
+
+        let response = Info::from(&bencode).unwrap_err();
+        assert_eq!(response, FromInfoError::InvalidFiles);
+    }
+
+    fn build_info_with_files(files: Vec<Bencode>) -> Bencode {
+        let mut info = BTreeMap::new();
+        info.insert(b"name".to_vec(), Bencode::BString(b"test1".to_vec()));
+        info.insert(b"piece length".to_vec(), Bencode::BNumber(2));
+        info.insert(b"pieces".to_vec(), Bencode::BString(b"test2".to_vec()));
+        info.insert(b"files".to_vec(), Bencode::BList(files));
+        Bencode::BDict(info)
+    }
+
+    fn build_file_bencode(length: i64, path: &[&str]) -> Bencode {
+        let mut file = BTreeMap::new();
+        file.insert(b"length".to_vec(), Bencode::BNumber(length));
+        file.insert(
+            b"path".to_vec(),
+            Bencode::BList(
+                path.iter()
+                    .map(|component| Bencode::BString(component.as_bytes().to_vec()))
+                    .collect(),
+            ),
+        );
+        Bencode::BDict(file)
+    }
 }
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bencoder::bencode::{Bencode, BencodeError};
+use sha1::{Digest, Sha1};
+
+use crate::peer::peer_message::METADATA_PIECE_SIZE;
+
+use super::torrent::{FromTorrentError, Torrent};
+
+/// Maximum metadata size accepted from a peer, to avoid allocating absurd amounts of memory.
+const MAX_METADATA_SIZE: usize = 10 * 1024 * 1024;
+
+/// Posible `Metadata` errors.
+#[derive(Debug, PartialEq)]
+pub enum MetadataError {
+    InvalidSize,
+    InvalidPieceIndex(u32),
+    InvalidPieceSize(u32),
+    Incomplete,
+    HashDoesNotMatch,
+    InvalidInfo(BencodeError),
+    FromTorrentError(FromTorrentError),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize => write!(f, "invalid metadata size"),
+            Self::InvalidPieceIndex(index) => write!(f, "invalid metadata piece index: {}", index),
+            Self::InvalidPieceSize(index) => write!(f, "invalid size of metadata piece: {}", index),
+            Self::Incomplete => write!(f, "the metadata is incomplete"),
+            Self::HashDoesNotMatch => write!(f, "the metadata hash does not match"),
+            Self::InvalidInfo(_) => write!(f, "the metadata is not valid bencode"),
+            Self::FromTorrentError(_) => write!(f, "the metadata is not a valid info dictionary"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+/// Reassembles the `info` dictionary of a torrent from the pieces received through `ut_metadata` (BEP 9).
+///
+/// Once every piece is received, `into_torrent` verifies the metadata against the expected info hash and builds the `Torrent`.
+#[derive(Debug)]
+pub struct Metadata {
+    info_hash: Vec<u8>,
+    total_size: usize,
+    pieces: Vec<Option<Vec<u8>>>,
+}
+
+impl Metadata {
+    /// Creates a new `Metadata` for the given info hash and size in bytes, as advertised by a peer.
+    ///
+    /// It returns `MetadataError::InvalidSize` if the size is zero or too big.
+    pub fn new(info_hash: Vec<u8>, total_size: usize) -> Result<Self, MetadataError> {
+        if total_size == 0 || total_size > MAX_METADATA_SIZE {
+            return Err(MetadataError::InvalidSize);
+        }
+        let total_pieces = total_size.div_ceil(METADATA_PIECE_SIZE);
+
+        Ok(Self {
+            info_hash,
+            total_size,
+            pieces: vec![None; total_pieces],
+        })
+    }
+
+    /// Returns the number of pieces the metadata is split in.
+    pub fn total_pieces(&self) -> u32 {
+        self.pieces.len() as u32
+    }
+
+    /// Returns the indexes of the pieces not received yet.
+    pub fn missing_pieces(&self) -> Vec<u32> {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, piece)| piece.is_none())
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+
+    /// Returns true if every piece of the metadata was received.
+    pub fn is_complete(&self) -> bool {
+        self.pieces.iter().all(|piece| piece.is_some())
+    }
+
+    /// Stores a received metadata piece.
+    ///
+    /// It returns an error if the index is out of range or the piece doesn't have the expected size.
+    pub fn add_piece(&mut self, index: u32, data: Vec<u8>) -> Result<(), MetadataError> {
+        if index >= self.total_pieces() {
+            return Err(MetadataError::InvalidPieceIndex(index));
+        }
+        if data.len() != self.expected_piece_size(index) {
+            return Err(MetadataError::InvalidPieceSize(index));
+        }
+        self.pieces[index as usize] = Some(data);
+        Ok(())
+    }
+
+    /// Builds the `Torrent` from the complete metadata.
+    ///
+    /// It returns an error if:
+    /// - There are pieces missing.
+    /// - The SHA-1 of the metadata doesn't match the info hash.
+    /// - The metadata is not a valid info dictionary.
+    pub fn into_torrent(self, announce_url: String) -> Result<Torrent, MetadataError> {
+        if !self.is_complete() {
+            return Err(MetadataError::Incomplete);
+        }
+        let info_bytes: Vec<u8> = self.pieces.into_iter().flatten().flatten().collect();
+
+        let hash = Sha1::digest(&info_bytes);
+        if hash.as_slice() != self.info_hash.as_slice() {
+            return Err(MetadataError::HashDoesNotMatch);
+        }
+
+        let info = Bencode::decode(&info_bytes).map_err(MetadataError::InvalidInfo)?;
+
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"announce".to_vec(),
+            Bencode::BString(announce_url.into_bytes()),
+        );
+        dict.insert(b"info".to_vec(), info);
+
+        let mut torrent =
+            Torrent::from(Bencode::BDict(dict)).map_err(MetadataError::FromTorrentError)?;
+        // The verified metadata is kept as received, as re-encoding it may not give back the same bytes.
+        torrent.set_info_bytes(info_bytes);
+        Ok(torrent)
+    }
+
+    fn expected_piece_size(&self, index: u32) -> usize {
+        if index + 1 < self.total_pieces() {
+            METADATA_PIECE_SIZE
+        } else {
+            self.total_size - (self.total_pieces() as usize - 1) * METADATA_PIECE_SIZE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_parser::info::Info;
+
+    #[test]
+    fn test_reassemble_two_pieces() {
+        let info = create_test_info();
+        let info_bytes = Bencode::encode(&info);
+        let info_hash = Sha1::digest(&info_bytes).to_vec();
+
+        let mut metadata = Metadata::new(info_hash.clone(), info_bytes.len()).unwrap();
+        assert_eq!(metadata.total_pieces(), 2);
+
+        metadata
+            .add_piece(1, info_bytes[METADATA_PIECE_SIZE..].to_vec())
+            .unwrap();
+        assert!(!metadata.is_complete());
+        assert_eq!(metadata.missing_pieces(), vec![0]);
+
+        metadata
+            .add_piece(0, info_bytes[..METADATA_PIECE_SIZE].to_vec())
+            .unwrap();
+        assert!(metadata.is_complete());
+
+        let torrent = metadata
+            .into_torrent("http://tracker/announce".to_string())
+            .unwrap();
+
+        assert_eq!(torrent.info, info);
+        assert_eq!(torrent.announce_url, "http://tracker/announce");
+        assert_eq!(torrent.get_info_hash_as_bytes(), info_hash.as_slice());
+        assert_eq!(torrent.info_bytes, info_bytes);
+    }
+
+    #[test]
+    fn test_reject_metadata_with_wrong_hash() {
+        let info_bytes = Bencode::encode(&create_test_info());
+
+        let mut metadata = Metadata::new(vec![0; 20], info_bytes.len()).unwrap();
+        metadata
+            .add_piece(0, info_bytes[..METADATA_PIECE_SIZE].to_vec())
+            .unwrap();
+        metadata
+            .add_piece(1, info_bytes[METADATA_PIECE_SIZE..].to_vec())
+            .unwrap();
+
+        assert_eq!(
+            metadata
+                .into_torrent("http://tracker/announce".to_string())
+                .unwrap_err(),
+            MetadataError::HashDoesNotMatch
+        );
+    }
+
+    #[test]
+    fn test_incomplete_metadata() {
+        let metadata = Metadata::new(vec![0; 20], METADATA_PIECE_SIZE + 1).unwrap();
+
+        assert_eq!(
+            metadata
+                .into_torrent("http://tracker/announce".to_string())
+                .unwrap_err(),
+            MetadataError::Incomplete
+        );
+    }
+
+    #[test]
+    fn test_add_piece_with_invalid_index() {
+        let mut metadata = Metadata::new(vec![0; 20], 100).unwrap();
+
+        assert_eq!(
+            metadata.add_piece(1, vec![0; 100]),
+            Err(MetadataError::InvalidPieceIndex(1))
+        );
+    }
+
+    #[test]
+    fn test_add_piece_with_invalid_size() {
+        let mut metadata = Metadata::new(vec![0; 20], 100).unwrap();
+
+        assert_eq!(
+            metadata.add_piece(0, vec![0; 99]),
+            Err(MetadataError::InvalidPieceSize(0))
+        );
+    }
+
+    #[test]
+    fn test_invalid_total_size() {
+        assert_eq!(
+            Metadata::new(vec![0; 20], 0).unwrap_err(),
+            MetadataError::InvalidSize
+        );
+    }
+
+    // Auxiliary functions
+
+    /// Creates an info dictionary that needs two metadata pieces.
+    fn create_test_info() -> Info {
+        Info {
+            length: 20000 * 16384,
+            name: "test.iso".to_string(),
+            piece_length: 16384,
+            pieces: vec![7; 20000],
+            files: None,
+        }
+    }
+}
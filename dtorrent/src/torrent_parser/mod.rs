@@ -1,3 +1,4 @@
 pub mod info;
+pub mod metadata;
 pub mod parser;
 pub mod torrent;
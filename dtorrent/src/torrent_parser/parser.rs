@@ -3,14 +3,17 @@ use std::{
     io::{BufReader, Error, Read},
 };
 
-use super::torrent::{FromTorrentError, Torrent};
+use super::torrent::{FromTorrentError, Torrent, TorrentSummary};
 use bencoder::bencode::{Bencode, BencodeError};
 
+/// Posible errors while parsing a torrent file, so each kind of failure can be told apart.
 #[derive(Debug)]
-pub enum ParseError {
-    IoError(Error),
-    BencodeError(BencodeError),
+pub enum TorrentParserError {
+    FileReadError(Error),
+    BencodeDecodeError(BencodeError),
     FromTorrentError(FromTorrentError),
+    InvalidPieceLength,
+    InvalidPiecesHashes,
 }
 
 pub struct TorrentParser;
@@ -20,28 +23,55 @@ impl TorrentParser {
     ///
     /// # Errors
     ///
-    /// * `ParseError::IoError` - An error occurred while reading the file
-    /// * `ParseError::BencodeError` - An error occurred while parsing the bencode
-    /// * `ParseError::FromTorrentError` - An error occurred while creating the Torrent struct
-    pub fn parse(filepath: String) -> Result<Torrent, ParseError> {
+    /// * `TorrentParserError::FileReadError` - An error occurred while reading the file
+    /// * `TorrentParserError::BencodeDecodeError` - An error occurred while parsing the bencode
+    /// * `TorrentParserError::FromTorrentError` - An error occurred while creating the Torrent struct
+    pub fn parse(filepath: String) -> Result<Torrent, TorrentParserError> {
         let buffer = match TorrentParser::read_file(filepath) {
             Ok(buffer) => buffer,
-            Err(e) => return Err(ParseError::IoError(e)),
+            Err(e) => return Err(TorrentParserError::FileReadError(e)),
         };
 
         let bencode = match Bencode::decode(&buffer) {
             Ok(bencode) => bencode,
-            Err(e) => return Err(ParseError::BencodeError(e)),
+            Err(e) => return Err(TorrentParserError::BencodeDecodeError(e)),
         };
 
-        let torrent = match Torrent::from(bencode) {
+        let mut torrent = match Torrent::from(bencode) {
             Ok(torrent) => torrent,
-            Err(e) => return Err(ParseError::FromTorrentError(e)),
+            Err(e) => return Err(TorrentParserError::FromTorrentError(e)),
         };
 
+        // The info hash must be taken from the info dictionary as found in the file, which may not be encoded canonically.
+        if let Some(info_bytes) = Torrent::find_info_bytes(&buffer) {
+            torrent.set_info_bytes(info_bytes.to_vec());
+        }
+
         Ok(torrent)
     }
 
+    /// Parses a torrent file without downloading it, and checks that its pieces can be downloaded and verified.
+    ///
+    /// On success it returns a summary of the torrent.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as `parse`, and:
+    /// * `TorrentParserError::InvalidPieceLength` - The piece length is not positive
+    /// * `TorrentParserError::InvalidPiecesHashes` - There isn't a 20 bytes hash for every piece
+    pub fn validate(filepath: &str) -> Result<TorrentSummary, TorrentParserError> {
+        let torrent = TorrentParser::parse(filepath.to_string())?;
+
+        if torrent.info.piece_length <= 0 {
+            return Err(TorrentParserError::InvalidPieceLength);
+        }
+        if torrent.info.pieces.len() != torrent.total_pieces() as usize * 20 {
+            return Err(TorrentParserError::InvalidPiecesHashes);
+        }
+
+        Ok(torrent.summary())
+    }
+
     fn read_file(filepath: String) -> Result<Vec<u8>, Error> {
         let file = File::open(filepath)?;
         let mut reader = BufReader::new(file);
@@ -58,6 +88,8 @@ mod tests {
     use super::*;
     use std::{fs, io::Write};
 
+    const VALID_TORRENT: &[u8] = b"d8:announce28:http://tracker.test/announce4:infod6:lengthi10e4:name8:test.txt12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+
     #[test]
     fn test_parse_torrent() {
         let filepath = "./test_parse_torrent.torrent";
@@ -78,12 +110,115 @@ mod tests {
         assert_eq!(torrent.info.name, "ubuntu-22.04-desktop-amd64.iso");
         assert_eq!(torrent.info.piece_length, 262144);
         assert_eq!(
-            torrent.info_hash,
+            torrent.info_hash_hex(),
             "48442ddee1900ed8c8101bb8b2bd955060f1eabc"
         );
         remove_file(filepath);
     }
 
+    #[test]
+    fn test_parse_torrent_keeps_the_info_dict_as_found_in_the_file() {
+        let filepath = "./test_parse_torrent_keeps_the_info_dict_as_found_in_the_file.torrent";
+        let info: &[u8] =
+            b"d4:name8:test.txt6:lengthi10e6:pieces20:aaaaaaaaaaaaaaaaaaaa12:piece lengthi16384ee";
+        let mut contents = b"d8:announce28:http://tracker.test/announce4:info".to_vec();
+        contents.extend(info);
+        contents.push(b'e');
+        create_and_write_file(filepath, &contents);
+
+        let torrent = TorrentParser::parse(filepath.to_string());
+        remove_file(filepath);
+
+        let torrent = torrent.unwrap();
+        assert_eq!(torrent.info_bytes, info);
+        assert_eq!(torrent.info_hash, Torrent::create_info_hash(info));
+    }
+
+    #[test]
+    fn test_parse_missing_file() {
+        let result = TorrentParser::parse("./test_parse_missing_file.torrent".to_string());
+
+        assert!(matches!(result, Err(TorrentParserError::FileReadError(_))));
+    }
+
+    #[test]
+    fn test_parse_invalid_bencode() {
+        let filepath = "./test_parse_invalid_bencode.torrent";
+        create_and_write_file(filepath, b"not bencode");
+
+        let result = TorrentParser::parse(filepath.to_string());
+        remove_file(filepath);
+
+        assert!(matches!(
+            result,
+            Err(TorrentParserError::BencodeDecodeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_torrent_without_announce() {
+        let filepath = "./test_parse_torrent_without_announce.torrent";
+        create_and_write_file(filepath, b"d4:infod6:lengthi10e4:name8:test.txtee");
+
+        let result = TorrentParser::parse(filepath.to_string());
+        remove_file(filepath);
+
+        assert!(matches!(
+            result,
+            Err(TorrentParserError::FromTorrentError(
+                FromTorrentError::MissingAnnounce
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_validate_torrent() {
+        let filepath = "./test_validate_torrent.torrent";
+        create_and_write_file(filepath, VALID_TORRENT);
+
+        let summary = TorrentParser::validate(filepath);
+        remove_file(filepath);
+
+        let summary = summary.unwrap();
+        assert_eq!(summary.name, "test.txt");
+        assert_eq!(summary.total_size, 10);
+        assert_eq!(summary.total_pieces, 1);
+        assert_eq!(summary.piece_length, 16384);
+        assert_eq!(summary.trackers, vec!["http://tracker.test/announce"]);
+        assert_eq!(summary.info_hash.len(), 40);
+    }
+
+    #[test]
+    fn test_validate_truncated_torrent() {
+        let filepath = "./test_validate_truncated_torrent.torrent";
+        create_and_write_file(
+            filepath,
+            b"d8:announce28:http://tracker.test/announce4:info",
+        );
+
+        let result = TorrentParser::validate(filepath);
+        remove_file(filepath);
+
+        assert!(matches!(
+            result,
+            Err(TorrentParserError::BencodeDecodeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_torrent_with_missing_piece_hashes() {
+        let filepath = "./test_validate_torrent_with_missing_piece_hashes.torrent";
+        create_and_write_file(filepath, b"d8:announce28:http://tracker.test/announce4:infod6:lengthi10e4:name8:test.txt12:piece lengthi16384e6:pieces4:abcdee");
+
+        let result = TorrentParser::validate(filepath);
+        remove_file(filepath);
+
+        assert!(matches!(
+            result,
+            Err(TorrentParserError::InvalidPiecesHashes)
+        ));
+    }
+
     fn create_and_write_file(path: &str, contents: &[u8]) {
         let mut file = File::create(path).unwrap();
         file.write_all(contents).unwrap();
@@ -1,17 +1,31 @@
-use std::fmt::Write;
-use std::{collections::BTreeMap, num::ParseIntError};
+use std::collections::BTreeMap;
+use std::fmt;
 
 use sha1::{Digest, Sha1};
 
 use bencoder::bencode::{Bencode, ToBencode};
 
-use super::info::{FromInfoError, Info};
+use super::info::{FromInfoError, Info, InfoFile};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Torrent {
     pub announce_url: String,
     pub info: Info,
+    /// SHA-1 hash of the bencoded info dictionary.
+    pub info_hash: [u8; 20],
+    /// The bencoded info dictionary as found in the torrent file or in the received metadata, including the keys `info` leaves out.
+    pub info_bytes: Vec<u8>,
+}
+
+/// A summary of a torrent, with the information shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentSummary {
+    pub name: String,
     pub info_hash: String,
+    pub total_size: u64,
+    pub total_pieces: u32,
+    pub piece_length: u32,
+    pub trackers: Vec<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -19,14 +33,17 @@ pub enum FromTorrentError {
     MissingAnnounce,
     MissingInfo,
     FromInfoError(FromInfoError),
-    InfoHashError,
     NotADict,
 }
 
 impl Torrent {
+    /// Creates a torrent from its decoded bencode.
+    ///
+    /// The info dictionary is re-encoded canonically, so `set_info_bytes` should be called with the bytes it was decoded from when they are at hand.
     pub fn from(bencode: Bencode) -> Result<Torrent, FromTorrentError> {
         let mut announce_url = String::new();
         let mut info: Option<Info> = None;
+        let mut info_bytes = Vec::new();
 
         let d = match bencode {
             Bencode::BDict(s) => s,
@@ -38,6 +55,7 @@ impl Torrent {
                 announce_url = Torrent::create_announce(v)?;
             } else if k == b"info" {
                 info = Some(Torrent::create_info(v)?);
+                info_bytes = Bencode::encode(v);
             }
         }
 
@@ -50,12 +68,13 @@ impl Torrent {
             None => return Err(FromTorrentError::MissingInfo),
         };
 
-        let info_hash = Torrent::create_info_hash(&info)?;
+        let info_hash = Torrent::create_info_hash(&info_bytes);
 
         Ok(Torrent {
             announce_url,
             info,
             info_hash,
+            info_bytes,
         })
     }
 
@@ -82,31 +101,49 @@ impl Torrent {
         Ok(info)
     }
 
-    pub fn create_info_hash(info: &Info) -> Result<String, FromTorrentError> {
-        let bencoded_info = Bencode::encode(info);
-        let hash = Sha1::digest(bencoded_info);
-
-        let mut hex_string = String::with_capacity(hash.len() * 2);
-
-        for b in hash {
-            match write!(&mut hex_string, "{:02x}", b) {
-                Ok(_) => (),
-                Err(_) => return Err(FromTorrentError::InfoHashError),
+    /// Returns the bytes of the `info` value of a bencoded torrent, as found in it.
+    ///
+    /// It returns `None` if the data is not a bencoded dictionary with an `info` key.
+    pub fn find_info_bytes(data: &[u8]) -> Option<&[u8]> {
+        if data.first() != Some(&b'd') {
+            return None;
+        }
+        let mut i = 1;
+        while data.get(i) != Some(&b'e') {
+            let (key, key_size) = Bencode::decode_with_length(data.get(i..)?).ok()?;
+            i += key_size;
+            let (_, value_size) = Bencode::decode_with_length(data.get(i..)?).ok()?;
+            if key == Bencode::BString(b"info".to_vec()) {
+                return data.get(i..i + value_size);
             }
+            i += value_size;
         }
+        None
+    }
 
-        Ok(hex_string)
+    /// Replaces the bencoded info dictionary of the torrent, along with the info hash taken from it.
+    pub fn set_info_bytes(&mut self, info_bytes: Vec<u8>) {
+        self.info_hash = Torrent::create_info_hash(&info_bytes);
+        self.info_bytes = info_bytes;
     }
 
-    /// Returns the info hash of the torrent as a byte array.
-    pub fn get_info_hash_as_bytes(&self) -> Result<Vec<u8>, ParseIntError> {
-        Self::decode_hex(self.info_hash.as_str())
+    /// Returns the SHA-1 hash of the given bencoded info dictionary.
+    pub fn create_info_hash(info_bytes: &[u8]) -> [u8; 20] {
+        let mut info_hash = [0; 20];
+        info_hash.copy_from_slice(&Sha1::digest(info_bytes));
+        info_hash
     }
 
-    fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
-        (0..s.len())
-            .step_by(2)
-            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+    /// Returns the info hash of the torrent as a byte array, as sent in handshakes.
+    pub fn get_info_hash_as_bytes(&self) -> &[u8] {
+        &self.info_hash
+    }
+
+    /// Returns the info hash of the torrent in hex, as shown to the user and sent to the tracker.
+    pub fn info_hash_hex(&self) -> String {
+        self.info_hash
+            .iter()
+            .map(|b| format!("{:02x}", b))
             .collect()
     }
 
@@ -135,8 +172,56 @@ impl Torrent {
         self.info.length as u32 % self.info.piece_length as u32
     }
 
-    pub fn info_hash(&self) -> String {
-        self.info_hash.clone()
+    /// Returns the size in bytes of the piece with the given index.
+    ///
+    /// Every piece has the piece length of the torrent, except for the last one, which may be shorter.
+    pub fn piece_size(&self, piece_index: u32) -> u32 {
+        let piece_start = piece_index as i64 * self.info.piece_length;
+        (self.info.length - piece_start).clamp(0, self.info.piece_length) as u32
+    }
+
+    /// Returns the total size in bytes of the content of the torrent.
+    ///
+    /// As multi-file torrents are rejected when parsing the info, this is the length of its single file.
+    pub fn total_size(&self) -> u64 {
+        self.info.length as u64
+    }
+
+    /// Returns the files of the torrent, in the order their content is laid out in the pieces.
+    ///
+    /// A torrent without a `files` list has a single file, named after the torrent.
+    pub fn files(&self) -> Vec<InfoFile> {
+        match &self.info.files {
+            Some(files) => files.clone(),
+            None => vec![InfoFile {
+                length: self.info.length,
+                path: vec![self.name()],
+            }],
+        }
+    }
+
+    /// Returns a summary of the torrent, with its name, info hash, total size, number of pieces, piece length and trackers.
+    ///
+    /// It is displayed in a human-readable form.
+    pub fn summary(&self) -> TorrentSummary {
+        TorrentSummary {
+            name: self.name(),
+            info_hash: self.info_hash_hex(),
+            total_size: self.total_size(),
+            total_pieces: self.total_pieces(),
+            piece_length: self.piece_length(),
+            trackers: vec![self.announce_url.clone()],
+        }
+    }
+}
+
+impl fmt::Display for TorrentSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} bytes in {} pieces of {} bytes",
+            self.name, self.total_size, self.total_pieces, self.piece_length
+        )
     }
 }
 
@@ -170,8 +255,7 @@ mod tests {
         let torrent_bencode =
             build_torrent_bencode(announce.clone().into_bytes(), info_bencode.clone());
 
-        let info = Info::from(&Bencode::BDict(info_bencode)).unwrap();
-        let info_hash = Torrent::create_info_hash(&info).unwrap();
+        let info_hash = Torrent::create_info_hash(&Bencode::encode(&Bencode::BDict(info_bencode)));
 
         let torrent = Torrent::from(torrent_bencode).unwrap();
 
@@ -229,8 +313,7 @@ mod tests {
     }
 
     #[test]
-    fn test_get_info_hash_as_bytes() {
-        let info_hash = String::from("2c6b6858d61da9543d4231a71db4b1c9264b0685");
+    fn test_info_hash_hex_and_bytes_are_consistent() {
         let info_hash_bytes = [
             44, 107, 104, 88, 214, 29, 169, 84, 61, 66, 49, 167, 29, 180, 177, 201, 38, 75, 6, 133,
         ];
@@ -242,11 +325,111 @@ mod tests {
                 name: String::from("example"),
                 piece_length: 20,
                 pieces: String::from("test").into_bytes(),
+                files: None,
             },
-            info_hash,
+            info_hash: info_hash_bytes,
+            info_bytes: Vec::new(),
         };
 
-        assert_eq!(torrent.get_info_hash_as_bytes().unwrap(), info_hash_bytes);
+        assert_eq!(torrent.get_info_hash_as_bytes(), info_hash_bytes);
+        assert_eq!(
+            torrent.info_hash_hex(),
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685"
+        );
+    }
+
+    #[test]
+    fn test_info_hash_is_the_sha1_of_the_raw_info_dict() {
+        let info_bencode = build_info_bencode(
+            10,
+            b"example".to_vec(),
+            20,
+            String::from("test").into_bytes(),
+        );
+        let raw_info = Bencode::encode(&Bencode::BDict(info_bencode.clone()));
+        let torrent_bencode =
+            build_torrent_bencode(b"http://example.com/announce".to_vec(), info_bencode);
+
+        let torrent = Torrent::from(torrent_bencode).unwrap();
+
+        assert_eq!(
+            torrent.get_info_hash_as_bytes(),
+            Sha1::digest(raw_info).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_info_hash_covers_the_keys_info_leaves_out() {
+        let mut info_bencode = build_info_bencode(
+            10,
+            b"example".to_vec(),
+            20,
+            String::from("test").into_bytes(),
+        );
+        info_bencode.insert(b"private".to_vec(), Bencode::BNumber(1));
+        let raw_info = Bencode::encode(&Bencode::BDict(info_bencode.clone()));
+        let torrent_bencode =
+            build_torrent_bencode(b"http://example.com/announce".to_vec(), info_bencode);
+
+        let torrent = Torrent::from(torrent_bencode).unwrap();
+
+        assert_eq!(
+            torrent.get_info_hash_as_bytes(),
+            Sha1::digest(raw_info).as_slice()
+        );
+        assert_ne!(
+            torrent.get_info_hash_as_bytes(),
+            Sha1::digest(Bencode::encode(&torrent.info)).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_info_bytes_keep_the_keys_info_leaves_out() {
+        let mut info_bencode = build_info_bencode(
+            10,
+            b"example".to_vec(),
+            20,
+            String::from("test").into_bytes(),
+        );
+        info_bencode.insert(b"source".to_vec(), Bencode::BString(b"tracker".to_vec()));
+        let raw_info = Bencode::encode(&Bencode::BDict(info_bencode.clone()));
+        let torrent_bencode =
+            build_torrent_bencode(b"http://example.com/announce".to_vec(), info_bencode);
+
+        let torrent = Torrent::from(torrent_bencode).unwrap();
+
+        assert_eq!(torrent.info_bytes, raw_info);
+    }
+
+    #[test]
+    fn test_find_info_bytes() {
+        let data = b"d8:announce3:url4:infod4:name7:example6:lengthi10ee7:comment4:teste";
+
+        assert_eq!(
+            Torrent::find_info_bytes(data),
+            Some(&b"d4:name7:example6:lengthi10ee"[..])
+        );
+    }
+
+    #[test]
+    fn test_find_info_bytes_without_info() {
+        assert_eq!(Torrent::find_info_bytes(b"d8:announce3:urle"), None);
+        assert_eq!(Torrent::find_info_bytes(b"l4:infoe"), None);
+        assert_eq!(Torrent::find_info_bytes(b"d8:announce3:url4:info"), None);
+    }
+
+    #[test]
+    fn test_set_info_bytes_replaces_the_info_hash() {
+        let mut torrent = build_test_torrent();
+        let info_bytes = b"d4:name7:example6:lengthi10ee".to_vec();
+
+        torrent.set_info_bytes(info_bytes.clone());
+
+        assert_eq!(
+            torrent.get_info_hash_as_bytes(),
+            Sha1::digest(&info_bytes).as_slice()
+        );
+        assert_eq!(torrent.info_bytes, info_bytes);
     }
 
     #[test]
@@ -279,6 +462,72 @@ mod tests {
         assert_eq!(torrent.last_piece_size(), 5);
     }
 
+    #[test]
+    fn test_piece_size() {
+        let torrent = build_test_torrent();
+        assert_eq!(torrent.piece_size(0), 10);
+        assert_eq!(torrent.piece_size(9), 10);
+        assert_eq!(torrent.piece_size(10), 5);
+    }
+
+    #[test]
+    fn test_piece_size_when_the_length_is_a_multiple_of_the_piece_length() {
+        let mut torrent = build_test_torrent();
+        torrent.info.length = 100;
+        assert_eq!(torrent.piece_size(9), 10);
+    }
+
+    #[test]
+    fn test_total_size() {
+        let torrent = build_test_torrent();
+        assert_eq!(torrent.total_size(), 105);
+    }
+
+    #[test]
+    fn test_total_size_over_4_gib() {
+        let mut torrent = build_test_torrent();
+        torrent.info.length = 5 * 1024 * 1024 * 1024;
+        assert_eq!(torrent.total_size(), 5 * 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_summary() {
+        let torrent = build_test_torrent();
+        let summary = torrent.summary();
+
+        assert_eq!(summary.info_hash, "ab".repeat(20));
+        assert_eq!(summary.trackers, vec!["http://example.com/announce"]);
+        assert_eq!(
+            summary.to_string(),
+            "example: 105 bytes in 11 pieces of 10 bytes"
+        );
+    }
+
+    #[test]
+    fn test_files_of_a_torrent_without_files_list() {
+        let torrent = build_test_torrent();
+
+        assert_eq!(
+            torrent.files(),
+            vec![InfoFile {
+                length: 105,
+                path: vec!["example".to_string()]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_files_of_a_torrent_with_files_list() {
+        let mut torrent = build_test_torrent();
+        let file = InfoFile {
+            length: 105,
+            path: vec!["dir".to_string(), "example.txt".to_string()],
+        };
+        torrent.info.files = Some(vec![file.clone()]);
+
+        assert_eq!(torrent.files(), vec![file]);
+    }
+
     fn build_info_bencode(
         length: i64,
         name: Vec<u8>,
@@ -311,8 +560,10 @@ mod tests {
                 name: String::from("example"),
                 piece_length: 10,
                 pieces: String::from("test").into_bytes(),
+                files: None,
             },
-            info_hash: "info_hash".to_string(),
+            info_hash: [0xab; 20],
+            info_bytes: Vec::new(),
         }
     }
 }
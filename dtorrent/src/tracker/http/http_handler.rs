@@ -1,6 +1,7 @@
 use native_tls::Error;
 use native_tls::HandshakeError;
 use native_tls::TlsConnector;
+use std::fmt;
 use std::io::Error as IOError;
 use std::io::{Read, Write};
 use std::net::TcpStream;
@@ -31,6 +32,32 @@ pub enum HttpHandlerError {
     ErrorReadingStream(IOError),
 }
 
+impl fmt::Display for HttpHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateTlsConnectorError(_) => write!(f, "couldn't create the TLS connector"),
+            Self::TcpStreamConnectError(_) => write!(f, "couldn't connect to the tracker"),
+            Self::TlsStreamConnectError(err) => {
+                write!(f, "couldn't establish the TLS connection: {:?}", err)
+            }
+            Self::ErrorWritingStream(_) => write!(f, "couldn't write the request to the tracker"),
+            Self::ErrorReadingStream(_) => write!(f, "couldn't read the response of the tracker"),
+        }
+    }
+}
+
+impl std::error::Error for HttpHandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CreateTlsConnectorError(err) => Some(err),
+            Self::TcpStreamConnectError(err)
+            | Self::ErrorWritingStream(err)
+            | Self::ErrorReadingStream(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 /// Posible `TlsStreamConnect` errors.
 ///
 /// `FatalError` is an error that should not continue the program.
@@ -1,43 +1,191 @@
+use std::fmt;
+
 use url_encoder::url_encoder::encode;
 
+/// The events a client can announce to a tracker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnounceEvent {
+    Started,
+    Stopped,
+    Completed,
+}
+
 /// `QueryParams` struct containing the query parameters information.
 ///
-/// To create a new `TrackerResponse` use the method builder `new()`.
+/// To create a new `QueryParams` use the method builder `new()`, or a `QueryParamsBuilder` to set the optional params.
 ///
 /// To build the Query params string use the method 'build()'.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct QueryParams {
     info_hash: String,
     client_port: u32,
-    info_length: i64,
     client_peer_id: String,
+    uploaded: Option<u64>,
+    downloaded: Option<u64>,
+    left: Option<i64>,
+    event: Option<AnnounceEvent>,
+    numwant: Option<u32>,
+    compact: Option<bool>,
+    key: Option<String>,
+    tracker_id: Option<String>,
+    ip: Option<String>,
+}
+
+/// `QueryParamsBuilder` struct for building a `QueryParams` with only the optional params that are set.
+///
+/// To create a new `QueryParamsBuilder` use the method builder `new()` with the required params.
+#[derive(Debug, Clone)]
+pub struct QueryParamsBuilder {
+    query_params: QueryParams,
 }
 
 impl QueryParams {
     /// Creates a new `QueryParams` from an **info_hash**, **client_port** and **info_lenght** passed by parameters.
+    ///
+    /// It announces a `started` event with nothing uploaded or downloaded yet.
     pub fn new(
         info_hash: String,
         client_port: u32,
         info_length: i64,
         client_peer_id: String,
     ) -> QueryParams {
-        QueryParams {
-            info_hash,
-            client_port,
-            info_length,
-            client_peer_id,
-        }
+        QueryParamsBuilder::new(info_hash, client_port, client_peer_id)
+            .uploaded(0)
+            .downloaded(0)
+            .left(info_length)
+            .event(AnnounceEvent::Started)
+            .build()
     }
 
     /// Builds the QueryParams string and returns it.
+    ///
+    /// Optional params are only included if they were set.
     pub fn build(&self) -> String {
-        format!(
-            "?info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&event=started",
+        let mut query = format!(
+            "?info_hash={}&peer_id={}&port={}",
             encode(self.info_hash.as_str()),
             self.client_peer_id,
-            self.client_port,
-            self.info_length
-        )
+            self.client_port
+        );
+
+        let optional_params = [
+            (
+                "uploaded",
+                self.uploaded.map(|uploaded| uploaded.to_string()),
+            ),
+            (
+                "downloaded",
+                self.downloaded.map(|downloaded| downloaded.to_string()),
+            ),
+            ("left", self.left.map(|left| left.to_string())),
+            ("event", self.event.map(|event| event.to_string())),
+            ("numwant", self.numwant.map(|numwant| numwant.to_string())),
+            (
+                "compact",
+                self.compact.map(|compact| (compact as u8).to_string()),
+            ),
+            ("key", self.key.clone()),
+            ("trackerid", self.tracker_id.clone()),
+            ("ip", self.ip.clone()),
+        ];
+        for (name, value) in optional_params {
+            if let Some(value) = value {
+                query.push_str(&format!("&{}={}", name, value));
+            }
+        }
+
+        query
+    }
+}
+
+impl QueryParamsBuilder {
+    /// Creates a new `QueryParamsBuilder` from the params every announce must have: an **info_hash**, **client_port** and **client_peer_id**.
+    pub fn new(info_hash: String, client_port: u32, client_peer_id: String) -> Self {
+        Self {
+            query_params: QueryParams {
+                info_hash,
+                client_port,
+                client_peer_id,
+                uploaded: None,
+                downloaded: None,
+                left: None,
+                event: None,
+                numwant: None,
+                compact: None,
+                key: None,
+                tracker_id: None,
+                ip: None,
+            },
+        }
+    }
+
+    /// Sets the total amount of bytes uploaded.
+    pub fn uploaded(mut self, uploaded: u64) -> Self {
+        self.query_params.uploaded = Some(uploaded);
+        self
+    }
+
+    /// Sets the total amount of bytes downloaded.
+    pub fn downloaded(mut self, downloaded: u64) -> Self {
+        self.query_params.downloaded = Some(downloaded);
+        self
+    }
+
+    /// Sets the amount of bytes left to download.
+    pub fn left(mut self, left: i64) -> Self {
+        self.query_params.left = Some(left);
+        self
+    }
+
+    /// Sets the event being announced.
+    pub fn event(mut self, event: AnnounceEvent) -> Self {
+        self.query_params.event = Some(event);
+        self
+    }
+
+    /// Sets the number of peers wanted from the tracker.
+    pub fn numwant(mut self, numwant: u32) -> Self {
+        self.query_params.numwant = Some(numwant);
+        self
+    }
+
+    /// Sets whether the peers should be sent in the compact format.
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.query_params.compact = Some(compact);
+        self
+    }
+
+    /// Sets the key that identifies the client between IP changes. It must be URL safe.
+    pub fn key(mut self, key: String) -> Self {
+        self.query_params.key = Some(key);
+        self
+    }
+
+    /// Sets the tracker id received in a previous announce. It must be URL safe.
+    pub fn tracker_id(mut self, tracker_id: String) -> Self {
+        self.query_params.tracker_id = Some(tracker_id);
+        self
+    }
+
+    /// Sets the address the client is reachable at, if it's not the one the tracker sees. It must be URL safe.
+    pub fn ip(mut self, ip: String) -> Self {
+        self.query_params.ip = Some(ip);
+        self
+    }
+
+    /// Returns the built `QueryParams`.
+    pub fn build(self) -> QueryParams {
+        self.query_params
+    }
+}
+
+impl fmt::Display for AnnounceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Started => write!(f, "started"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::Completed => write!(f, "completed"),
+        }
     }
 }
 
@@ -65,4 +213,50 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_builder_without_optional_params() {
+        let query_params =
+            QueryParamsBuilder::new("2c6b".to_string(), 6969, "LA_DEYMONETA_PAPA!!!".to_string())
+                .build();
+
+        assert_eq!(
+            query_params.build(),
+            "?info_hash=%2c%6b&peer_id=LA_DEYMONETA_PAPA!!!&port=6969"
+        );
+    }
+
+    #[test]
+    fn test_builder_with_optional_params() {
+        let query_params =
+            QueryParamsBuilder::new("2c6b".to_string(), 6969, "LA_DEYMONETA_PAPA!!!".to_string())
+                .tracker_id("abc".to_string())
+                .left(10)
+                .event(AnnounceEvent::Completed)
+                .numwant(50)
+                .compact(true)
+                .key("1234".to_string())
+                .ip("203.0.113.7".to_string())
+                .build();
+
+        assert_eq!(
+            query_params.build(),
+            "?info_hash=%2c%6b&peer_id=LA_DEYMONETA_PAPA!!!&port=6969&left=10&event=completed&numwant=50&compact=1&key=1234&trackerid=abc&ip=203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn test_new_is_the_same_as_the_builder() {
+        let built = QueryParamsBuilder::new("2c6b".to_string(), 6969, "peer".to_string())
+            .uploaded(0)
+            .downloaded(0)
+            .left(100)
+            .event(AnnounceEvent::Started)
+            .build();
+
+        assert_eq!(
+            QueryParams::new("2c6b".to_string(), 6969, 100, "peer".to_string()),
+            built
+        );
+    }
 }
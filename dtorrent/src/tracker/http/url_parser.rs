@@ -1,6 +1,10 @@
+use std::fmt;
+
 /// `TrackerUrl` struct containing a tracker url information.
 ///
 /// To create a new `TrackerUrl` use the method builder `parse()`.
+///
+/// The `endpoint` is the path of the url without its leading `/`. It can only be empty for `udp` urls, as they don't use it.
 #[derive(Debug, PartialEq, Clone)]
 pub struct TrackerUrl {
     pub protocol: ConnectionProtocol,
@@ -14,6 +18,7 @@ pub struct TrackerUrl {
 pub enum ConnectionProtocol {
     Http,
     Https,
+    Udp,
 }
 
 /// Posible `TrackerUrl` Errors.
@@ -22,8 +27,24 @@ pub enum TrackerUrlError {
     InvalidTrackerURL,
     UnsupportedConnectionProtocol,
     InvalidPortNumber,
+    MissingPortNumber,
 }
 
+impl fmt::Display for TrackerUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTrackerURL => write!(f, "invalid tracker url"),
+            Self::UnsupportedConnectionProtocol => {
+                write!(f, "unsupported tracker connection protocol")
+            }
+            Self::InvalidPortNumber => write!(f, "invalid tracker port number"),
+            Self::MissingPortNumber => write!(f, "missing tracker port number"),
+        }
+    }
+}
+
+impl std::error::Error for TrackerUrlError {}
+
 impl TrackerUrl {
     /// Builds a new `TrackerUrl` from a &str tracker url.
     ///
@@ -33,11 +54,12 @@ impl TrackerUrl {
     /// - the url format is invalid.
     /// - The url connection protocol is unsupported.
     /// - the url port number is not a number.
+    /// - the url port number is missing from an `udp` url, as it has no default port.
     pub fn parse(url: &str) -> Result<Self, TrackerUrlError> {
         let (url_without_protocol, protocol) = Self::identify_and_remove_protocol(url)?;
 
         let (url_without_endpoint, endpoint) =
-            Self::identify_and_remove_endpoint(&url_without_protocol)?;
+            Self::identify_and_remove_endpoint(&url_without_protocol, &protocol)?;
 
         let host = Self::identify_host(&url_without_endpoint)?;
 
@@ -47,6 +69,7 @@ impl TrackerUrl {
             match protocol {
                 ConnectionProtocol::Https => 443,
                 ConnectionProtocol::Http => 80,
+                ConnectionProtocol::Udp => return Err(TrackerUrlError::MissingPortNumber),
             }
         };
 
@@ -69,6 +92,8 @@ impl TrackerUrl {
                     ConnectionProtocol::Http
                 } else if protocol_name == "https" {
                     ConnectionProtocol::Https
+                } else if protocol_name == "udp" {
+                    ConnectionProtocol::Udp
                 } else {
                     return Err(TrackerUrlError::UnsupportedConnectionProtocol);
                 }
@@ -82,7 +107,10 @@ impl TrackerUrl {
         }
     }
 
-    fn identify_and_remove_endpoint(url: &str) -> Result<(String, String), TrackerUrlError> {
+    fn identify_and_remove_endpoint(
+        url: &str,
+        protocol: &ConnectionProtocol,
+    ) -> Result<(String, String), TrackerUrlError> {
         let mut splitted_url = url.split('/');
 
         let url_without_endpoint = match splitted_url.next() {
@@ -92,14 +120,17 @@ impl TrackerUrl {
 
         match splitted_url.next() {
             Some(endpoint) => Ok((url_without_endpoint.to_string(), endpoint.to_string())),
+            None if *protocol == ConnectionProtocol::Udp => {
+                Ok((url_without_endpoint.to_string(), String::new()))
+            }
             None => Err(TrackerUrlError::InvalidTrackerURL),
         }
     }
 
     fn identify_host(url: &str) -> Result<String, TrackerUrlError> {
         match url.split(':').next() {
-            Some(host) => Ok(host.to_string()),
-            None => Err(TrackerUrlError::InvalidTrackerURL),
+            Some(host) if !host.is_empty() => Ok(host.to_string()),
+            _ => Err(TrackerUrlError::InvalidTrackerURL),
         }
     }
 
@@ -162,9 +193,41 @@ mod tests {
         assert_eq!("ann", parsed_tracker_url.endpoint);
     }
 
+    #[test]
+    fn test_udp_with_path() {
+        let url = String::from("udp://tracker.example.org:1337/announce");
+        let parsed_tracker_url = TrackerUrl::parse(&url).unwrap();
+
+        assert_eq!(ConnectionProtocol::Udp, parsed_tracker_url.protocol);
+        assert_eq!("tracker.example.org", parsed_tracker_url.host);
+        assert_eq!(1337, parsed_tracker_url.port);
+        assert_eq!("announce", parsed_tracker_url.endpoint);
+    }
+
+    #[test]
+    fn test_udp_without_path() {
+        let url = String::from("udp://tracker.example.org:1337");
+        let parsed_tracker_url = TrackerUrl::parse(&url).unwrap();
+
+        assert_eq!(ConnectionProtocol::Udp, parsed_tracker_url.protocol);
+        assert_eq!("tracker.example.org", parsed_tracker_url.host);
+        assert_eq!(1337, parsed_tracker_url.port);
+        assert_eq!("", parsed_tracker_url.endpoint);
+    }
+
+    #[test]
+    fn test_udp_without_port() {
+        let url = String::from("udp://tracker.example.org/announce");
+
+        assert_eq!(
+            TrackerUrl::parse(&url),
+            Err(TrackerUrlError::MissingPortNumber)
+        );
+    }
+
     #[test]
     fn test_invalid_protocol() {
-        let url = String::from("udp://www.example.org:1337/ann");
+        let url = String::from("wss://www.example.org:1337/ann");
 
         assert_eq!(
             TrackerUrl::parse(&url),
@@ -181,6 +244,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_malformed_urls() {
+        for url in [
+            "www.example.org/ann",
+            "http://",
+            "http:///ann",
+            "http//example.org/ann",
+        ] {
+            assert!(TrackerUrl::parse(url).is_err(), "{} should be invalid", url);
+        }
+    }
+
     #[test]
     fn test_missing_path() {
         let url = String::from("https://www.example.org:123");
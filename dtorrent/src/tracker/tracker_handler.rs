@@ -1,9 +1,10 @@
 use super::http::http_handler::{HttpHandler, HttpHandlerError};
-use super::http::query_params::QueryParams;
+use super::http::query_params::{AnnounceEvent, QueryParams, QueryParamsBuilder};
 use super::http::url_parser::{ConnectionProtocol, TrackerUrl, TrackerUrlError};
 use super::tracker_response::FromTrackerResponseError;
 use crate::torrent_parser::torrent::Torrent;
 use crate::tracker::tracker_response::TrackerResponse;
+use std::fmt;
 
 /// `TrackerHandler` struct for communicating to a bt tracker.
 ///
@@ -16,6 +17,7 @@ pub struct TrackerHandler {
     pub tracker_url: TrackerUrl,
     pub client_port: u32,
     client_peer_id: String,
+    announce_ip: Option<String>,
 }
 /// Posible `TrackerHandler` errors.
 #[derive(Debug)]
@@ -23,6 +25,31 @@ pub enum TrackerHandlerError {
     HttpHandlerError(HttpHandlerError),
     FromTrackerResponseError(FromTrackerResponseError),
     UrlParseError(TrackerUrlError),
+    UnsupportedProtocol(ConnectionProtocol),
+}
+
+impl fmt::Display for TrackerHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HttpHandlerError(_) => write!(f, "couldn't announce to the tracker"),
+            Self::FromTrackerResponseError(_) => write!(f, "invalid tracker response"),
+            Self::UrlParseError(_) => write!(f, "invalid tracker url"),
+            Self::UnsupportedProtocol(protocol) => {
+                write!(f, "unsupported tracker protocol: {:?}", protocol)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrackerHandlerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::HttpHandlerError(err) => Some(err),
+            Self::FromTrackerResponseError(err) => Some(err),
+            Self::UrlParseError(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl TrackerHandler {
@@ -45,9 +72,16 @@ impl TrackerHandler {
             tracker_url,
             client_port,
             client_peer_id,
+            announce_ip: None,
         })
     }
 
+    /// Sets the address announced to the tracker, instead of the one it sees the client connecting from.
+    pub fn with_announce_ip(mut self, announce_ip: Option<String>) -> Self {
+        self.announce_ip = announce_ip;
+        self
+    }
+
     /// Gets the tracker's peers list.
     ///
     /// On success it returns a `TrackerResponse` struct cointaining the tracker's response.
@@ -56,25 +90,67 @@ impl TrackerHandler {
     /// - There was a problem writing to the tracker.
     /// - There was a problem reading the tracker's response.
     /// - There was a problem decoding the parser response.
+    /// - The tracker uses a protocol that is not supported yet, such as `udp`.
     pub fn get_peers_list(&self) -> Result<TrackerResponse, TrackerHandlerError> {
-        let query_params = QueryParams::new(
-            self.torrent.info_hash.clone(),
+        let query_params = self
+            .query_params_builder()
+            .uploaded(0)
+            .downloaded(0)
+            .left(self.torrent.info.length)
+            .event(AnnounceEvent::Started)
+            .build();
+
+        self.announce(query_params)
+    }
+
+    /// Tells the tracker that the client stopped sharing the torrent, with the given number of `uploaded`, `downloaded` and `left` bytes.
+    ///
+    /// It returns the same errors as `get_peers_list()`.
+    pub fn announce_stopped(
+        &self,
+        uploaded: u64,
+        downloaded: u64,
+        left: i64,
+    ) -> Result<TrackerResponse, TrackerHandlerError> {
+        let query_params = self
+            .query_params_builder()
+            .uploaded(uploaded)
+            .downloaded(downloaded)
+            .left(left)
+            .event(AnnounceEvent::Stopped)
+            .build();
+
+        self.announce(query_params)
+    }
+
+    fn query_params_builder(&self) -> QueryParamsBuilder {
+        let builder = QueryParamsBuilder::new(
+            self.torrent.info_hash_hex(),
             self.client_port,
-            self.torrent.info.length,
             self.client_peer_id.clone(),
         );
+        match &self.announce_ip {
+            Some(ip) => builder.ip(ip.clone()),
+            None => builder,
+        }
+    }
 
+    fn announce(&self, query_params: QueryParams) -> Result<TrackerResponse, TrackerHandlerError> {
         let http_handler = HttpHandler::new(self.tracker_url.clone(), query_params);
 
-        let response = if self.tracker_url.protocol == ConnectionProtocol::Https {
-            match http_handler.https_request() {
+        let response = match self.tracker_url.protocol {
+            ConnectionProtocol::Https => match http_handler.https_request() {
                 Ok(response) => response,
                 Err(err) => return Err(TrackerHandlerError::HttpHandlerError(err)),
-            }
-        } else {
-            match http_handler.http_request() {
+            },
+            ConnectionProtocol::Http => match http_handler.http_request() {
                 Ok(response) => response,
                 Err(err) => return Err(TrackerHandlerError::HttpHandlerError(err)),
+            },
+            ConnectionProtocol::Udp => {
+                return Err(TrackerHandlerError::UnsupportedProtocol(
+                    ConnectionProtocol::Udp,
+                ))
             }
         };
         match TrackerResponse::from(response) {
@@ -95,7 +171,10 @@ mod tests {
     fn test_get_peers_list() {
         let torrent = create_test_torrent(
             "https://torrent.ubuntu.com:443/announce",
-            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+            [
+                44, 107, 104, 88, 214, 29, 169, 84, 61, 66, 49, 167, 29, 180, 177, 201, 38, 75, 6,
+                133,
+            ],
         );
         let test_port = 6969;
         let test_peer_id = "LA_DEYMONETA_PAPA!!!".to_string();
@@ -110,7 +189,10 @@ mod tests {
     fn test_http_request() {
         let torrent = create_test_torrent(
             "http://vps02.net.orel.ru/announce",
-            "f834824904be1854c89ba007c01678ff797f8dc7",
+            [
+                248, 52, 130, 73, 4, 190, 24, 84, 200, 155, 160, 7, 192, 22, 120, 255, 121, 127,
+                141, 199,
+            ],
         );
         let test_port = 6969;
         let test_peer_id = "LA_DEYMONETA_PAPA!!!".to_string();
@@ -120,20 +202,37 @@ mod tests {
         assert!(!tracker_handler.get_peers_list().unwrap().peers.is_empty());
     }
 
+    #[test]
+    fn test_udp_tracker_is_not_supported_yet() {
+        let torrent = create_test_torrent("udp://tracker.example.org:1337/announce", [0; 20]);
+
+        let tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
+
+        assert!(matches!(
+            tracker_handler.get_peers_list(),
+            Err(TrackerHandlerError::UnsupportedProtocol(
+                ConnectionProtocol::Udp
+            ))
+        ));
+    }
+
     // Auxiliar
 
-    fn create_test_torrent(announce: &str, info_hash: &str) -> Torrent {
+    fn create_test_torrent(announce: &str, info_hash: [u8; 20]) -> Torrent {
         let info = Info {
             length: 100,
             name: "test".to_string(),
             piece_length: 100,
             pieces: vec![],
+            files: None,
         };
 
         Torrent {
             announce_url: announce.to_string(),
             info,
-            info_hash: info_hash.to_string(),
+            info_hash,
+            info_bytes: Vec::new(),
         }
     }
 }
@@ -1,13 +1,16 @@
-use bencoder::bencode::{Bencode, BencodeError};
+use bencoder::bencode::{Bencode, BencodeError, ToBencode};
+use std::collections::BTreeMap;
+use std::fmt;
 
-use crate::peer::bt_peer::{BtPeer, BtPeerError};
+use crate::peer::bt_peer::{BtPeer, BtPeerError, COMPACT_PEER6_LENGTH, COMPACT_PEER_LENGTH};
 
 /// `TrackerResponse` struct containing a tracker response.
 ///
-/// To create a new `TrackerResponse` use the method builder `from()`.
-#[derive(Debug)]
+/// To create a new `TrackerResponse` use the method builder `from()`, and to encode it as a tracker would use `to_bencode()`.
+#[derive(Debug, PartialEq, Eq)]
 pub struct TrackerResponse {
     pub interval: i64,
+    pub min_interval: Option<i64>,
     pub complete: i64,
     pub incomplete: i64,
     pub peers: Vec<BtPeer>,
@@ -18,11 +21,44 @@ pub struct TrackerResponse {
 pub enum FromTrackerResponseError {
     DecodeResponseError(BencodeError),
     InvalidInterval,
+    InvalidMinInterval,
     InvalidComplete,
     InvalidIncomplete,
     InvalidPeers(BtPeerError),
     NotADict,
     NotAList,
+    FailureReason(String),
+}
+
+impl fmt::Display for FromTrackerResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DecodeResponseError(err) => {
+                write!(f, "couldn't decode the tracker response: {:?}", err)
+            }
+            Self::InvalidInterval => write!(f, "invalid interval in the tracker response"),
+            Self::InvalidMinInterval => {
+                write!(f, "invalid min interval in the tracker response")
+            }
+            Self::InvalidComplete => write!(f, "invalid complete in the tracker response"),
+            Self::InvalidIncomplete => write!(f, "invalid incomplete in the tracker response"),
+            Self::InvalidPeers(_) => write!(f, "invalid peers in the tracker response"),
+            Self::NotADict => write!(f, "tracker response is not a dict"),
+            Self::NotAList => write!(f, "peers in the tracker response are not a list"),
+            Self::FailureReason(reason) => {
+                write!(f, "the tracker refused the announce: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromTrackerResponseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPeers(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl TrackerResponse {
@@ -32,15 +68,20 @@ impl TrackerResponse {
     /// - There was a problem decoding the parser response.
     /// - The bencoded response is not a dict.
     /// - The bencoded peers are not a list.
-    /// - The tracker response interval is invalid.
+    /// - The tracker response has a failure reason.
+    /// - The tracker response interval or min interval are invalid.
     /// - The tracker response complete is invalid.
     /// - The tracker response incomplete is invalid.
     /// - The tracker response peers are invalid.
+    ///
+    /// The IPv6 peers of a compact response, under `peers6`, are appended to the rest.
     pub fn from(response: Vec<u8>) -> Result<TrackerResponse, FromTrackerResponseError> {
         let mut interval = 0;
+        let mut min_interval = None;
         let mut complete = 0;
         let mut incomplete = 0;
         let mut peers = Vec::new();
+        let mut peers6 = Vec::new();
 
         let decoded_res = match Bencode::decode(&response) {
             Ok(decoded_res) => decoded_res,
@@ -52,20 +93,33 @@ impl TrackerResponse {
             _ => return Err(FromTrackerResponseError::NotADict),
         };
 
+        // No other keys may be present in a failed response.
+        if let Some(Bencode::BString(reason)) = d.get(b"failure reason".as_slice()) {
+            return Err(FromTrackerResponseError::FailureReason(
+                String::from_utf8_lossy(reason).to_string(),
+            ));
+        }
+
         for (k, v) in d.iter() {
             if k == b"interval" {
                 interval = Self::create_interval(v)?;
+            } else if k == b"min interval" {
+                min_interval = Some(Self::create_min_interval(v)?);
             } else if k == b"complete" {
                 complete = Self::create_complete(v)?;
             } else if k == b"incomplete" {
                 incomplete = Self::create_incomplete(v)?;
             } else if k == b"peers" {
                 peers = Self::create_peers(v)?;
+            } else if k == b"peers6" {
+                peers6 = Self::create_peers6(v)?;
             }
         }
+        peers.extend(peers6);
 
         Ok(TrackerResponse {
             interval,
+            min_interval,
             complete,
             incomplete,
             peers,
@@ -81,6 +135,13 @@ impl TrackerResponse {
         Ok(interval)
     }
 
+    fn create_min_interval(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
+        match bencode {
+            Bencode::BNumber(n) => Ok(*n),
+            _ => Err(FromTrackerResponseError::InvalidMinInterval),
+        }
+    }
+
     fn create_complete(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
         let complete = match bencode {
             Bencode::BNumber(n) => *n,
@@ -107,6 +168,18 @@ impl TrackerResponse {
         }
     }
 
+    fn create_peers6(bencode: &Bencode) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
+        match bencode {
+            Bencode::BString(str) => str
+                .chunks(COMPACT_PEER6_LENGTH)
+                .map(|chunk| {
+                    BtPeer::from_compact6(chunk).map_err(FromTrackerResponseError::InvalidPeers)
+                })
+                .collect(),
+            _ => Err(FromTrackerResponseError::NotAList),
+        }
+    }
+
     fn create_peers_from_dict(list: &[Bencode]) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
         let mut peers = Vec::new();
 
@@ -119,22 +192,36 @@ impl TrackerResponse {
     }
 
     fn create_peers_from_bstring(bstring: &[u8]) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
-        Ok(bstring
-            .chunks(6)
+        bstring
+            .chunks(COMPACT_PEER_LENGTH)
             .map(|chunk| {
-                let ip = format!("{}.{}.{}.{}", chunk[0], chunk[1], chunk[2], chunk[3]);
-                let port = u16::from_be_bytes([chunk[4], chunk[5]]) as i64;
-
-                BtPeer::new(ip, port)
+                BtPeer::from_compact(chunk).map_err(FromTrackerResponseError::InvalidPeers)
             })
-            .collect())
+            .collect()
+    }
+}
+
+impl ToBencode for TrackerResponse {
+    /// Encodes the response with its peers in the dictionary model, as read by `from()`.
+    fn to_bencode(&self) -> Bencode {
+        let mut response = BTreeMap::new();
+        response.insert(b"interval".to_vec(), self.interval.to_bencode());
+        if let Some(min_interval) = self.min_interval {
+            response.insert(b"min interval".to_vec(), min_interval.to_bencode());
+        }
+        response.insert(b"complete".to_vec(), self.complete.to_bencode());
+        response.insert(b"incomplete".to_vec(), self.incomplete.to_bencode());
+        response.insert(
+            b"peers".to_vec(),
+            Bencode::BList(self.peers.iter().map(|peer| peer.to_bencode()).collect()),
+        );
+        response.to_bencode()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::BTreeMap;
 
     #[test]
     fn test_from_tracker_response() {
@@ -158,6 +245,115 @@ mod tests {
         assert_eq!(response_decoded.peers.len(), 2);
     }
 
+    #[test]
+    fn test_to_bencode_round_trip() {
+        let response = TrackerResponse {
+            interval: 1800,
+            min_interval: Some(60),
+            complete: 3,
+            incomplete: 7,
+            peers: vec![
+                BtPeer::from(Bencode::BDict(build_peer_dict(
+                    b"id1".to_vec(),
+                    b"127.0.0.1".to_vec(),
+                    6868,
+                )))
+                .unwrap(),
+                BtPeer::from(Bencode::BDict(build_peer_dict(
+                    b"id2".to_vec(),
+                    b"::1".to_vec(),
+                    4242,
+                )))
+                .unwrap(),
+            ],
+        };
+
+        let decoded = TrackerResponse::from(Bencode::encode(&response)).unwrap();
+
+        assert_eq!(decoded, response);
+        let peer_ids: Vec<_> = decoded.peers.into_iter().map(|peer| peer.peer_id).collect();
+        assert_eq!(peer_ids, vec![Some(b"id1".to_vec()), Some(b"id2".to_vec())]);
+    }
+
+    #[test]
+    fn test_to_bencode_without_peers() {
+        let response = TrackerResponse {
+            interval: 10,
+            min_interval: None,
+            complete: 0,
+            incomplete: 0,
+            peers: Vec::new(),
+        };
+
+        assert_eq!(
+            Bencode::encode(&response),
+            b"d8:completei0e10:incompletei0e8:intervali10e5:peerslee".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_min_interval() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"interval".to_vec(), Bencode::BNumber(1800));
+        dict.insert(b"min interval".to_vec(), Bencode::BNumber(60));
+
+        let response = TrackerResponse::from(Bencode::encode(&dict)).unwrap();
+
+        assert_eq!(response.interval, 1800);
+        assert_eq!(response.min_interval, Some(60));
+    }
+
+    #[test]
+    fn test_failure_reason_is_an_error() {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"failure reason".to_vec(),
+            Bencode::BString(b"Announcing too fast".to_vec()),
+        );
+
+        assert!(matches!(
+            TrackerResponse::from(Bencode::encode(&dict)),
+            Err(FromTrackerResponseError::FailureReason(reason)) if reason == "Announcing too fast"
+        ));
+    }
+
+    #[test]
+    fn test_from_compact_tracker_response() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"interval".to_vec(), Bencode::BNumber(10));
+        dict.insert(
+            b"peers".to_vec(),
+            Bencode::BString(vec![127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x10, 0x92]),
+        );
+
+        let response = TrackerResponse::from(Bencode::encode(&dict)).unwrap();
+
+        assert_eq!(
+            response.peers,
+            vec![
+                BtPeer::new("127.0.0.1".to_string(), 6881),
+                BtPeer::new("10.0.0.2".to_string(), 4242)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_compact_peers() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"interval".to_vec(), Bencode::BNumber(10));
+        dict.insert(
+            b"peers".to_vec(),
+            Bencode::BString(vec![127, 0, 0, 1, 0x1a, 0xe1, 10, 0]),
+        );
+
+        assert!(matches!(
+            TrackerResponse::from(Bencode::encode(&dict)),
+            Err(FromTrackerResponseError::InvalidPeers(
+                BtPeerError::InvalidCompactLength(2)
+            ))
+        ));
+    }
+
     fn build_peer_dict(peer_id: Vec<u8>, ip: Vec<u8>, port: i64) -> BTreeMap<Vec<u8>, Bencode> {
         let mut peer_dict = BTreeMap::new();
         peer_dict.insert(b"peer id".to_vec(), Bencode::BString(peer_id));
@@ -3,8 +3,12 @@ use super::setup::UserInterfaceError;
 use crate::statistics::torrent_stats::TorrentStats;
 use gtk::glib::Receiver;
 use gtk::prelude::*;
-use gtk::{glib, Window};
+use gtk::{glib, Inhibit, Window};
 use std::rc::Rc;
+use std::sync::mpsc::Sender;
+
+/// The mouse button that opens the context menus.
+const SECONDARY_BUTTON: u32 = 3;
 
 pub struct ClientWindow {
     window: Window,
@@ -31,6 +35,7 @@ impl ClientWindow {
             window_data_clone.update_statistics(statistics);
             window_data_clone.update_torrent_liststore();
             window_data_clone.update_peer_liststore();
+            window_data_clone.update_status_bar();
             glib::Continue(true)
         });
 
@@ -52,13 +57,51 @@ impl ClientWindow {
 
         let window_data_clone = self.window_data.clone();
         treeview.connect_row_activated(move |_, row_path, _| {
-            window_data_clone.select_torrent(row_path.indices()[0]);
+            window_data_clone.select_torrent_row(row_path);
             window_data_clone.update_peer_liststore();
         });
 
         Ok(())
     }
 
+    /// Adds a context menu to the torrents list to remove the torrent of the row clicked.
+    ///
+    /// The info hash of the removed torrent is sent through the given `removal_sender`, so the client stops it.
+    pub fn remove_on_menu(&self, removal_sender: Sender<String>) -> Result<(), UserInterfaceError> {
+        let treeview: gtk::TreeView = self
+            .builder
+            .object("torrent_treeview")
+            .ok_or(UserInterfaceError::WindowBuildingError)?;
+
+        let window_data_clone = self.window_data.clone();
+        treeview.connect_button_press_event(move |treeview, event| {
+            if event.button() != SECONDARY_BUTTON {
+                return Inhibit(false);
+            }
+            let (x, y) = event.position();
+            let row_path = match treeview.path_at_pos(x as i32, y as i32) {
+                Some((Some(row_path), _, _, _)) => row_path,
+                _ => return Inhibit(false),
+            };
+
+            let menu = gtk::Menu::new();
+            let remove_item = gtk::MenuItem::with_label("Remove torrent");
+            let window_data = window_data_clone.clone();
+            let removal_sender = removal_sender.clone();
+            remove_item.connect_activate(move |_| {
+                window_data.remove_torrent_row(&row_path, |info_hash| {
+                    let _ = removal_sender.send(info_hash.to_string());
+                });
+            });
+            menu.append(&remove_item);
+            menu.show_all();
+            menu.popup_at_pointer(Some(event));
+            Inhibit(true)
+        });
+
+        Ok(())
+    }
+
     pub fn display_on(&self, app: &gtk::Application) {
         self.window.set_application(Some(app));
     }
@@ -1,22 +1,25 @@
 use super::setup::UserInterfaceError;
+use crate::config::sort_settings::{ColumnSort, SortSettings};
+use crate::statistics::aggregate_stats::AggregateStats;
 use crate::statistics::peer_stats::PeerStats;
 use crate::statistics::torrent_stats::TorrentStats;
-use gtk::{
-    glib::{self, FormatSizeFlags},
-    prelude::*,
-    ListStore,
-};
-use std::sync::{
-    atomic::{AtomicI32, Ordering},
-    Mutex,
-};
+use crate::util::format::{format_size, format_speed};
+use gtk::{prelude::*, ListStore, SortColumn, SortType, TreeIter};
+use std::sync::Mutex;
+
+/// File where the sorting of the torrents and peers lists is saved, to restore it on startup.
+const SORT_SETTINGS_PATH: &str = "./sort_settings.json";
+/// Column of the torrents liststore with the info hash, which identifies the row of each torrent.
+const INFO_HASH_COLUMN: i32 = 2;
 
 #[derive(Debug)]
 pub struct ClientWindowData {
     torrents_liststore: ListStore,
     peers_liststore: ListStore,
+    status_label: gtk::Label,
     last_torrents_statistics: Mutex<Vec<TorrentStats>>,
-    selected_torrent_index: AtomicI32,
+    // The info hash of the selected torrent, as the position of its row changes when the torrents are sorted or removed.
+    selected_info_hash: Mutex<Option<String>>,
 }
 
 impl ClientWindowData {
@@ -27,18 +30,25 @@ impl ClientWindowData {
         let peers_liststore: ListStore = builder
             .object("peers")
             .ok_or(UserInterfaceError::WindowDataError)?;
+        let status_label: gtk::Label = builder
+            .object("status_label")
+            .ok_or(UserInterfaceError::WindowDataError)?;
 
-        // Sort by torrent name
-        //torrents_liststore.set_sort_column_id(gtk::SortColumn::Index(0), gtk::SortType::Ascending);
+        let sort_settings = SortSettings::load(SORT_SETTINGS_PATH).unwrap_or_default();
+        Self::set_sort(&torrents_liststore, sort_settings.torrents);
+        Self::set_sort(&peers_liststore, sort_settings.peers);
 
-        // Sort by peer download speed
-        //peers_liststore.set_sort_column_id(gtk::SortColumn::Index(7), gtk::SortType::Descending);
+        let (torrents, peers) = (torrents_liststore.clone(), peers_liststore.clone());
+        torrents_liststore.connect_sort_column_changed(move |_| Self::save_sort(&torrents, &peers));
+        let (torrents, peers) = (torrents_liststore.clone(), peers_liststore.clone());
+        peers_liststore.connect_sort_column_changed(move |_| Self::save_sort(&torrents, &peers));
 
         Ok(Self {
             last_torrents_statistics: Mutex::new(Vec::new()),
             torrents_liststore,
             peers_liststore,
-            selected_torrent_index: AtomicI32::new(0),
+            status_label,
+            selected_info_hash: Mutex::new(None),
         })
     }
 
@@ -50,16 +60,26 @@ impl ClientWindowData {
 
     pub fn update_torrent_liststore(&self) {
         let torrent_stats = self.last_torrents_statistics.lock().unwrap();
-        for (index, statistics) in torrent_stats.iter().enumerate() {
-            self.update_torrent_store_row(index, statistics);
+        for statistics in torrent_stats.iter() {
+            self.update_torrent_store_row(statistics);
         }
     }
 
+    /// Shows the speeds, peers and progress of every torrent added together in the status bar.
+    pub fn update_status_bar(&self) {
+        let torrent_stats = self.last_torrents_statistics.lock().unwrap();
+        let aggregate = AggregateStats::for_torrents(&torrent_stats);
+        self.status_label.set_text(&status_bar_text(&aggregate));
+    }
+
+    /// Shows the peers of the selected torrent.
+    ///
+    /// If no torrent is selected, or the selected one is not in the statistics, the list is left empty.
     pub fn update_peer_liststore(&self) {
         let torrent_stats = self.last_torrents_statistics.lock().unwrap();
+        let selected = self.selected_torrent();
         self.peers_liststore.clear();
-        for (peer_index, peer_stats) in torrent_stats[self.selected_torrent() as usize]
-            .peers
+        for (peer_index, peer_stats) in selected_peers(&torrent_stats, selected.as_deref())
             .iter()
             .enumerate()
         {
@@ -67,38 +87,82 @@ impl ClientWindowData {
         }
     }
 
-    fn selected_torrent(&self) -> i32 {
-        self.selected_torrent_index.load(Ordering::Relaxed)
+    fn set_sort(liststore: &ListStore, sort: ColumnSort) {
+        let order = if sort.descending {
+            SortType::Descending
+        } else {
+            SortType::Ascending
+        };
+        liststore.set_sort_column_id(SortColumn::Index(sort.column), order);
     }
 
-    fn update_torrent_store_row(&self, row_num: usize, torrent_stats: &TorrentStats) {
-        let tl_iter = match self
-            .torrents_liststore
-            .iter_from_string(row_num.to_string().as_str())
-        {
-            Some(iter) => iter,
-            None => self.torrents_liststore.append(),
+    /// Saves the current sorting of both liststores, so it's restored the next time the client starts.
+    fn save_sort(torrents_liststore: &ListStore, peers_liststore: &ListStore) {
+        let default = SortSettings::default();
+        let sort_settings = SortSettings {
+            torrents: Self::current_sort(torrents_liststore).unwrap_or(default.torrents),
+            peers: Self::current_sort(peers_liststore).unwrap_or(default.peers),
         };
+        if let Err(err) = sort_settings.save(SORT_SETTINGS_PATH) {
+            eprintln!("Couldn't save the sort settings: {}", err);
+        }
+    }
+
+    fn current_sort(liststore: &ListStore) -> Option<ColumnSort> {
+        match liststore.sort_column_id()? {
+            (SortColumn::Index(column), order) => Some(ColumnSort {
+                column,
+                descending: order == SortType::Descending,
+            }),
+            _ => None,
+        }
+    }
+
+    fn selected_torrent(&self) -> Option<String> {
+        self.selected_info_hash.lock().unwrap().clone()
+    }
+
+    /// Returns the row of the torrent with the given info hash, appending it if there is none.
+    ///
+    /// As the liststore is sorted, the position of the rows doesn't match the order of the statistics.
+    fn torrent_store_row(&self, info_hash: &str) -> TreeIter {
+        if let Some(iter) = self.torrents_liststore.iter_first() {
+            loop {
+                let row_info_hash = self
+                    .torrents_liststore
+                    .value(&iter, INFO_HASH_COLUMN)
+                    .get::<String>()
+                    .ok();
+                if row_info_hash.as_deref() == Some(info_hash) {
+                    return iter;
+                }
+                if !self.torrents_liststore.iter_next(&iter) {
+                    break;
+                }
+            }
+        }
+        self.torrents_liststore.append()
+    }
+
+    fn update_torrent_store_row(&self, torrent_stats: &TorrentStats) {
+        let tl_iter = self.torrent_store_row(&torrent_stats.info_hash);
         self.torrents_liststore.set(
             &tl_iter,
             &[
                 (0u32, &torrent_stats.torrent_name),
                 (1u32, &(torrent_stats.download_percentage() * 100_f32)),
                 (2u32, &torrent_stats.info_hash),
-                (
-                    3u32,
-                    &(glib::format_size_full(
-                        torrent_stats.length as u64,
-                        FormatSizeFlags::IEC_UNITS,
-                    )),
-                ),
+                (3u32, &format_size(torrent_stats.length as u64)),
                 (4u32, &(torrent_stats.peers_amount as u32)),
                 (5u32, &torrent_stats.pieces_amount),
                 (6u32, &(torrent_stats.downloaded_pieces_amount as u32)),
                 (7u32, &(torrent_stats.total_peers as u32)),
-                (8u32, &self.format_speed(torrent_stats.download_speed)),
-                (9u32, &self.format_speed(torrent_stats.upload_speed)),
+                (8u32, &format_speed(torrent_stats.download_speed)),
+                (9u32, &format_speed(torrent_stats.upload_speed)),
                 (10u32, &torrent_stats.eta),
+                (11u32, &(torrent_stats.seeders as u32)),
+                (12u32, &(torrent_stats.leechers as u32)),
+                (13u32, &torrent_stats.state.to_string()),
             ],
         );
     }
@@ -116,8 +180,8 @@ impl ClientWindowData {
             &[
                 (0u32, &peer_stats.ip),
                 (1u32, &peer_stats.port),
-                (2u32, &self.format_speed(peer_stats.download_speed)),
-                (3u32, &self.format_speed(peer_stats.upload_speed)),
+                (2u32, &format_speed(peer_stats.download_speed)),
+                (3u32, &format_speed(peer_stats.upload_speed)),
                 (
                     4u32,
                     &self.format_state(peer_stats.choked, peer_stats.interested),
@@ -143,24 +207,206 @@ impl ClientWindowData {
         format!("{}/{}", choked_str, interested_str)
     }
 
-    fn format_speed(&self, speed: f64) -> String {
-        let speed_in_kilobytes = speed / 8_f64;
-        if speed_in_kilobytes < 1024_f64 {
-            format!("{:.2} KiB/s", speed_in_kilobytes)
-        } else if speed_in_kilobytes < 1024_f64 * 1024_f64 {
-            format!("{:.2} MiB/s", speed_in_kilobytes / 1024_f64)
-        } else if speed_in_kilobytes < 1024_f64 * 1024_f64 * 1024_f64 {
-            format!("{:.2} GiB/s", speed_in_kilobytes / 1024_f64 / 1024_f64)
-        } else {
-            format!(
-                "{:.2} TiB/s",
-                speed_in_kilobytes / 1024_f64 / 1024_f64 / 1024_f64
-            )
+    /// Selects the torrent with the given info hash, whose peers are shown in the peers list.
+    pub fn select_torrent(&self, info_hash: &str) {
+        *self.selected_info_hash.lock().unwrap() = Some(info_hash.to_string());
+    }
+
+    /// Removes the given row of the torrents liststore, calling `remove` with the info hash of its torrent so the client stops it.
+    ///
+    /// The torrent is also removed from the last statistics and, if it was selected, unselected, so the peers list stops showing its peers.
+    pub fn remove_torrent_row(&self, row_path: &gtk::TreePath, remove: impl FnOnce(&str)) {
+        let iter = match self.torrents_liststore.iter(row_path) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let info_hash = self
+            .torrents_liststore
+            .value(&iter, INFO_HASH_COLUMN)
+            .get::<String>();
+        self.torrents_liststore.remove(&iter);
+
+        if let Ok(info_hash) = info_hash {
+            self.last_torrents_statistics
+                .lock()
+                .unwrap()
+                .retain(|statistics| statistics.info_hash != info_hash);
+            unselect_removed(&mut self.selected_info_hash.lock().unwrap(), &info_hash);
+            self.update_peer_liststore();
+            remove(&info_hash);
         }
     }
 
-    pub fn select_torrent(&self, new_index: i32) {
-        self.selected_torrent_index
-            .store(new_index, Ordering::Relaxed);
+    /// Selects the torrent of the given row of the torrents liststore.
+    pub fn select_torrent_row(&self, row_path: &gtk::TreePath) {
+        let info_hash = self.torrents_liststore.iter(row_path).and_then(|iter| {
+            self.torrents_liststore
+                .value(&iter, INFO_HASH_COLUMN)
+                .get::<String>()
+                .ok()
+        });
+        if let Some(info_hash) = info_hash {
+            self.select_torrent(&info_hash);
+        }
+    }
+}
+
+/// Returns the summary of every torrent shown in the status bar.
+fn status_bar_text(aggregate: &AggregateStats) -> String {
+    format!(
+        "Down: {} | Up: {} | Peers: {} connected of {} | Progress: {:.2}%",
+        format_speed(aggregate.download_speed),
+        format_speed(aggregate.upload_speed),
+        aggregate.connected_peers,
+        aggregate.total_peers,
+        aggregate.percentage()
+    )
+}
+
+/// Returns the peers of the torrent with the `selected` info hash.
+///
+/// If no torrent is selected, or there is none with that info hash in `torrent_stats`, it returns no peers.
+fn selected_peers<'a>(
+    torrent_stats: &'a [TorrentStats],
+    selected: Option<&str>,
+) -> &'a [PeerStats] {
+    torrent_stats
+        .iter()
+        .find(|statistics| Some(statistics.info_hash.as_str()) == selected)
+        .map(|statistics| statistics.peers.as_slice())
+        .unwrap_or_default()
+}
+
+/// Clears the `selected` info hash if it's the one of the removed torrent.
+fn unselect_removed(selected: &mut Option<String>, removed_info_hash: &str) {
+    if selected.as_deref() == Some(removed_info_hash) {
+        *selected = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_handler::state::TorrentState;
+
+    const FIRST_INFO_HASH: &str = "2c6b6858d61da9543d4231a71db4b1c9264b0685";
+    const SECOND_INFO_HASH: &str = "d984f67af9917b214cd8b6048ab5624c7df6a07a";
+
+    #[test]
+    fn test_selected_peers_without_torrents() {
+        assert!(selected_peers(&[], Some(FIRST_INFO_HASH)).is_empty());
+    }
+
+    #[test]
+    fn test_selected_peers_of_a_torrent() {
+        let torrent_stats = vec![
+            create_test_stats(FIRST_INFO_HASH, 1),
+            create_test_stats(SECOND_INFO_HASH, 2),
+        ];
+
+        assert_eq!(
+            selected_peers(&torrent_stats, Some(FIRST_INFO_HASH)).len(),
+            1
+        );
+        assert_eq!(
+            selected_peers(&torrent_stats, Some(SECOND_INFO_HASH)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_selected_peers_of_a_reordered_torrent() {
+        let torrent_stats = vec![
+            create_test_stats(SECOND_INFO_HASH, 2),
+            create_test_stats(FIRST_INFO_HASH, 1),
+        ];
+
+        assert_eq!(
+            selected_peers(&torrent_stats, Some(FIRST_INFO_HASH)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_selected_peers_of_a_missing_torrent_are_empty() {
+        let torrent_stats = vec![create_test_stats(FIRST_INFO_HASH, 1)];
+
+        assert!(selected_peers(&torrent_stats, Some(SECOND_INFO_HASH)).is_empty());
+    }
+
+    #[test]
+    fn test_selected_peers_without_selection_are_empty() {
+        let torrent_stats = vec![create_test_stats(FIRST_INFO_HASH, 1)];
+
+        assert!(selected_peers(&torrent_stats, None).is_empty());
+    }
+
+    #[test]
+    fn test_removing_the_selected_torrent_unselects_it() {
+        let mut selected = Some(FIRST_INFO_HASH.to_string());
+
+        unselect_removed(&mut selected, FIRST_INFO_HASH);
+
+        assert_eq!(selected, None);
+    }
+
+    #[test]
+    fn test_removing_another_torrent_keeps_the_selection() {
+        let mut selected = Some(FIRST_INFO_HASH.to_string());
+
+        unselect_removed(&mut selected, SECOND_INFO_HASH);
+
+        assert_eq!(selected.as_deref(), Some(FIRST_INFO_HASH));
+    }
+
+    #[test]
+    fn test_status_bar_text_formats_the_speeds() {
+        let aggregate = AggregateStats {
+            download_speed: 8.0 * 2048.0,
+            upload_speed: 8.0 * 512.0,
+            connected_peers: 3,
+            total_peers: 40,
+            downloaded_pieces: 1,
+            total_pieces: 4,
+        };
+
+        assert_eq!(
+            status_bar_text(&aggregate),
+            "Down: 2.00 MiB/s | Up: 512.00 KiB/s | Peers: 3 connected of 40 | Progress: 25.00%"
+        );
+    }
+
+    // Auxiliary functions
+
+    fn create_test_stats(info_hash: &str, peers_amount: usize) -> TorrentStats {
+        let peer = PeerStats {
+            port: 6881,
+            ip: "127.0.0.1".to_string(),
+            download_speed: 0.0,
+            upload_speed: 0.0,
+            choked: true,
+            interested: false,
+            client_choked: true,
+            client_interested: false,
+            peer_id: "".to_string(),
+        };
+
+        TorrentStats {
+            torrent_name: "test.iso".to_string(),
+            info_hash: info_hash.to_string(),
+            length: 100,
+            pieces_amount: 4,
+            peers_amount,
+            downloaded_pieces_amount: 0,
+            peers: vec![peer; peers_amount],
+            total_peers: peers_amount,
+            seeders: 0,
+            leechers: 0,
+            download_speed: 0.0,
+            upload_speed: 0.0,
+            eta: "-".to_string(),
+            files: vec![],
+            state: TorrentState::Downloading,
+        }
     }
 }
@@ -3,6 +3,10 @@ use crate::bt_client::btclient::BtClient;
 use crate::bt_client::btclient_error::BtClientError;
 use crate::statistics::torrent_stats::TorrentStats;
 use gtk::glib;
+use std::sync::{
+    mpsc::{self, Receiver},
+    Arc,
+};
 use std::thread;
 
 pub enum UserInterfaceError {
@@ -17,22 +21,36 @@ pub fn start_dtorrent_application(
     torrents_directory: String,
 ) -> Result<(), UserInterfaceError> {
     let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let (removal_sender, removal_receiver) = mpsc::channel();
 
-    start_btclient(sender, torrents_directory).map_err(UserInterfaceError::ClientError)?;
+    start_btclient(sender, torrents_directory, removal_receiver)
+        .map_err(UserInterfaceError::ClientError)?;
 
     let client_window = ClientWindow::new(receiver)?;
     client_window.update_on_click()?;
+    client_window.remove_on_menu(removal_sender)?;
     client_window.display_on(app);
 
     Ok(())
 }
 
+/// Starts the client in a new thread.
+///
+/// The torrents whose info hashes are received through `removal_receiver` are removed from the client.
 pub fn start_btclient(
     sender: glib::Sender<Vec<TorrentStats>>,
     torrents_directory: String,
+    removal_receiver: Receiver<String>,
 ) -> Result<(), BtClientError> {
     thread::spawn(move || match BtClient::init(torrents_directory) {
-        Ok(client) => client.run(sender),
+        Ok(client) => {
+            let client = Arc::new(client);
+            let remover = client.clone();
+            thread::spawn(move || remover.remove_torrents_from(removal_receiver));
+            if let Err(btclient_error) = client.run(sender) {
+                eprintln!("{:?}", btclient_error)
+            }
+        }
         Err(btclient_error) => eprintln!("{:?}", btclient_error),
     });
     Ok(())
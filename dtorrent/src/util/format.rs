@@ -0,0 +1,86 @@
+const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+/// Formats a speed in kilobits per second, as the statistics measure it, in the largest binary unit per second below 1024 of it (from `KiB/s` to `TiB/s`).
+pub fn format_speed(speed: f64) -> String {
+    let speed_in_kilobytes = speed / 8_f64;
+    let (value, unit) = scale(speed_in_kilobytes, 2);
+    format!("{:.2} {}/s", value, unit)
+}
+
+/// Formats an amount of bytes in the largest binary unit below 1024 of it, such as `1.5 MiB`.
+///
+/// Less than 1024 bytes are shown as `bytes`.
+pub fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let (value, unit) = scale(bytes as f64 / 1024_f64, 1);
+    format!("{:.1} {}", value, unit)
+}
+
+/// Divides a value in kibibytes by 1024 until, rounded to `decimals`, it's below 1024, or it's in the largest unit.
+///
+/// Comparing the rounded value avoids showing `1024.00` of a unit instead of `1.00` of the next one.
+fn scale(kilobytes: f64, decimals: i32) -> (f64, &'static str) {
+    let precision = 10_f64.powi(decimals);
+    let mut value = kilobytes;
+    let mut unit = 0;
+    while (value * precision).round() / precision >= 1024_f64 && unit < UNITS.len() - 1 {
+        value /= 1024_f64;
+        unit += 1;
+    }
+    (value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: f64 = 1024.0;
+
+    #[test]
+    fn test_format_speed_in_kib() {
+        assert_eq!(format_speed(0.0), "0.00 KiB/s");
+        assert_eq!(format_speed(8.0 * 512.0), "512.00 KiB/s");
+    }
+
+    #[test]
+    fn test_format_speed_boundaries() {
+        assert_eq!(format_speed(8.0 * 1023.0), "1023.00 KiB/s");
+        assert_eq!(format_speed(8.0 * KIB), "1.00 MiB/s");
+        assert_eq!(format_speed(8.0 * (KIB * KIB - 1.0)), "1.00 GiB/s");
+        assert_eq!(format_speed(8.0 * KIB * KIB), "1.00 GiB/s");
+        assert_eq!(format_speed(8.0 * KIB * KIB * 1023.0), "1023.00 GiB/s");
+        assert_eq!(format_speed(8.0 * KIB * KIB * KIB), "1.00 TiB/s");
+    }
+
+    #[test]
+    fn test_format_speed_above_the_largest_unit() {
+        assert_eq!(
+            format_speed(8.0 * KIB * KIB * KIB * 2048.0),
+            "2048.00 TiB/s"
+        );
+    }
+
+    #[test]
+    fn test_format_size_in_bytes() {
+        assert_eq!(format_size(0), "0 bytes");
+        assert_eq!(format_size(1023), "1023 bytes");
+    }
+
+    #[test]
+    fn test_format_size_boundaries() {
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1024 * 1024 - 1024), "1023.0 KiB");
+        assert_eq!(format_size(1024 * 1024 - 1), "1.0 MiB");
+        assert_eq!(format_size(1024 * 1024), "1.0 MiB");
+        assert_eq!(format_size(1024 * 1024 * 1024 - 1024 * 1024), "1023.0 MiB");
+        assert_eq!(format_size(1024 * 1024 * 1024), "1.0 GiB");
+        assert_eq!(format_size(1024 * 1024 * 1024 * 1024), "1.0 TiB");
+    }
+
+    #[test]
+    fn test_format_size_with_decimals() {
+        assert_eq!(format_size(1536), "1.5 KiB");
+    }
+}
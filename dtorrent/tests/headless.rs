@@ -0,0 +1,274 @@
+use bencoder::bencode::Bencode;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+const PIECE_LENGTH: usize = 16384;
+const FILE_LENGTH: usize = PIECE_LENGTH * 2 + 100;
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[test]
+fn test_headless_client_downloads_from_local_seed() {
+    let test_dir = create_test_dir("test_headless_client_downloads_from_local_seed");
+    let content: Vec<u8> = (0..FILE_LENGTH).map(|i| (i % 251) as u8).collect();
+
+    let seed_port = spawn_seed(content.clone());
+    let tracker_port = spawn_tracker(seed_port);
+    let config_path = write_config(&test_dir, "");
+    write_torrent(&test_dir, tracker_port, &content);
+
+    let output = run_headless_client(&test_dir, &config_path);
+
+    assert!(
+        output.iter().any(|line| line.starts_with("test.bin: ")),
+        "no progress reported: {:?}",
+        output
+    );
+    assert_eq!(
+        output.last().map(String::as_str),
+        Some("All torrents finished downloading."),
+        "download did not finish: {:?}",
+        output
+    );
+    assert_eq!(
+        fs::read(test_dir.join("downloads").join("test.bin")).unwrap(),
+        content
+    );
+
+    let _ = fs::remove_dir_all(test_dir);
+}
+
+#[test]
+fn test_headless_client_saves_a_torrent_in_its_own_download_directory() {
+    let test_dir =
+        create_test_dir("test_headless_client_saves_a_torrent_in_its_own_download_directory");
+    let content: Vec<u8> = (0..FILE_LENGTH).map(|i| (i % 251) as u8).collect();
+
+    let seed_port = spawn_seed(content.clone());
+    let tracker_port = spawn_tracker(seed_port);
+    let config_path = write_config(
+        &test_dir,
+        &format!(
+            "TORRENT_DOWNLOAD_DIRECTORIES=test.bin:{}",
+            test_dir.join("custom").display()
+        ),
+    );
+    write_torrent(&test_dir, tracker_port, &content);
+
+    let output = run_headless_client(&test_dir, &config_path);
+
+    assert_eq!(
+        output.last().map(String::as_str),
+        Some("All torrents finished downloading."),
+        "download did not finish: {:?}",
+        output
+    );
+    assert_eq!(
+        fs::read(test_dir.join("custom").join("test.bin")).unwrap(),
+        content
+    );
+    assert!(!test_dir.join("downloads").join("test.bin").exists());
+
+    let _ = fs::remove_dir_all(test_dir);
+}
+
+// Auxiliary functions
+
+fn create_test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("torrents")).unwrap();
+    dir
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Runs the headless client until every torrent finished downloading, returning the lines it printed.
+fn run_headless_client(dir: &Path, config_path: &Path) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bt_headless"))
+        .arg(dir.join("torrents"))
+        .arg(config_path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let stdout = child.stdout.take().unwrap();
+    let (lines_sender, lines_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if lines_sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut output = vec![];
+    while let Ok(line) = lines_receiver.recv_timeout(TIMEOUT) {
+        let finished = line.starts_with("All torrents finished");
+        output.push(line);
+        if finished {
+            break;
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    output
+}
+
+/// Writes a config file storing the downloads in the test directory, followed by the given extra settings.
+fn write_config(dir: &Path, extra_settings: &str) -> PathBuf {
+    let path = dir.join("config.cfg");
+    let mut contents = format!(
+        "TCP_PORT={}\nLOG_DIRECTORY={}\nDOWNLOAD_DIRECTORY={}\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=10\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=1000",
+        free_port(),
+        dir.join("logs").display(),
+        dir.join("downloads").display()
+    );
+    if !extra_settings.is_empty() {
+        contents.push('\n');
+        contents.push_str(extra_settings);
+    }
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn write_torrent(dir: &Path, tracker_port: u16, content: &[u8]) {
+    let pieces: Vec<u8> = content
+        .chunks(PIECE_LENGTH)
+        .flat_map(|piece| Sha1::digest(piece).to_vec())
+        .collect();
+
+    let mut info = BTreeMap::new();
+    info.insert(b"length".to_vec(), Bencode::BNumber(content.len() as i64));
+    info.insert(b"name".to_vec(), Bencode::BString(b"test.bin".to_vec()));
+    info.insert(
+        b"piece length".to_vec(),
+        Bencode::BNumber(PIECE_LENGTH as i64),
+    );
+    info.insert(b"pieces".to_vec(), Bencode::BString(pieces));
+
+    let mut torrent = BTreeMap::new();
+    torrent.insert(
+        b"announce".to_vec(),
+        Bencode::BString(format!("http://127.0.0.1:{}/announce", tracker_port).into_bytes()),
+    );
+    torrent.insert(b"info".to_vec(), Bencode::BDict(info));
+
+    fs::write(
+        dir.join("torrents").join("test.torrent"),
+        Bencode::encode(&Bencode::BDict(torrent)),
+    )
+    .unwrap();
+}
+
+/// Spawns a tracker that always answers with the seed as the only peer.
+fn spawn_tracker(seed_port: u16) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let mut peer = BTreeMap::new();
+    peer.insert(b"ip".to_vec(), Bencode::BString(b"127.0.0.1".to_vec()));
+    peer.insert(b"peer id".to_vec(), Bencode::BString(vec![b's'; 20]));
+    peer.insert(b"port".to_vec(), Bencode::BNumber(seed_port.into()));
+    let mut response = BTreeMap::new();
+    response.insert(b"interval".to_vec(), Bencode::BNumber(1));
+    response.insert(
+        b"peers".to_vec(),
+        Bencode::BList(vec![Bencode::BDict(peer)]),
+    );
+    let body = Bencode::encode(&Bencode::BDict(response));
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n");
+            let _ = stream.write_all(&body);
+        }
+    });
+    port
+}
+
+/// Spawns a seed that has every piece of the content.
+fn spawn_seed(content: Vec<u8>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let content = content.clone();
+            thread::spawn(move || {
+                let _ = serve_seed_connection(stream, &content);
+            });
+        }
+    });
+    port
+}
+
+fn serve_seed_connection(mut stream: TcpStream, content: &[u8]) -> std::io::Result<()> {
+    let mut handshake = [0; 68];
+    stream.read_exact(&mut handshake)?;
+
+    // Answer with the same info hash, without extensions and with our own peer id.
+    let mut answer = handshake.to_vec();
+    answer[20..28].copy_from_slice(&[0; 8]);
+    answer[48..].copy_from_slice(&[b's'; 20]);
+    stream.write_all(&answer)?;
+
+    let total_pieces = content.len().div_ceil(PIECE_LENGTH);
+    let mut bitfield = vec![0u8; total_pieces.div_ceil(8)];
+    for piece in 0..total_pieces {
+        bitfield[piece / 8] |= 0b1000_0000 >> (piece % 8);
+    }
+    send_message(&mut stream, 5, &bitfield)?;
+
+    loop {
+        let mut length = [0; 4];
+        stream.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length == 0 {
+            continue;
+        }
+        let mut message = vec![0; length];
+        stream.read_exact(&mut message)?;
+
+        match message[0] {
+            // Interested
+            2 => send_message(&mut stream, 1, &[])?,
+            // Request
+            6 => {
+                let index = u32::from_be_bytes(message[1..5].try_into().unwrap()) as usize;
+                let begin = u32::from_be_bytes(message[5..9].try_into().unwrap()) as usize;
+                let length = u32::from_be_bytes(message[9..13].try_into().unwrap()) as usize;
+                let start = index * PIECE_LENGTH + begin;
+
+                let mut payload = message[1..9].to_vec();
+                payload.extend(&content[start..start + length]);
+                send_message(&mut stream, 7, &payload)?;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn send_message(stream: &mut TcpStream, id: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut message = ((payload.len() + 1) as u32).to_be_bytes().to_vec();
+    message.push(id);
+    message.extend(payload);
+    stream.write_all(&message)
+}
@@ -0,0 +1,108 @@
+//! The responses encoded by dtracker must be read back by the client as they were sent, as both share the bencoder crate.
+
+use bencoder::bencode::{Bencode, ToBencode};
+use dtorrent::{peer::bt_peer::BtPeer, tracker::tracker_response::TrackerResponse};
+use dtracker::{
+    announce::announce_response::AnnounceResponse,
+    tracker_peer::{peer::Peer, peer_status::PeerStatus},
+};
+
+#[test]
+fn test_dictionary_response_is_encoded_the_same_by_both_sides() {
+    let announce_response = create_announce_response(false);
+    let tracker_response = TrackerResponse {
+        interval: 1800,
+        min_interval: None,
+        complete: 1,
+        incomplete: 2,
+        peers: vec![
+            create_bt_peer([1; 20], "127.0.0.1", 6881),
+            create_bt_peer([2; 20], "::1", 6882),
+        ],
+    };
+
+    assert_eq!(
+        Bencode::encode(&announce_response),
+        Bencode::encode(&tracker_response)
+    );
+}
+
+#[test]
+fn test_dictionary_response_is_read_by_the_client() {
+    let response =
+        TrackerResponse::from(Bencode::encode(&create_announce_response(false))).unwrap();
+
+    assert_eq!(response.interval, 1800);
+    assert_eq!(response.complete, 1);
+    assert_eq!(response.incomplete, 2);
+    assert_eq!(
+        response.peers,
+        vec![
+            BtPeer::new("127.0.0.1".to_string(), 6881),
+            BtPeer::new("::1".to_string(), 6882)
+        ]
+    );
+    assert_eq!(response.peers[0].peer_id, Some(vec![1; 20]));
+    assert_eq!(response.peers[1].peer_id, Some(vec![2; 20]));
+}
+
+#[test]
+fn test_compact_response_is_read_by_the_client() {
+    let response = TrackerResponse::from(Bencode::encode(&create_announce_response(true))).unwrap();
+
+    assert_eq!(response.interval, 1800);
+    assert_eq!(
+        response.peers,
+        vec![
+            BtPeer::new("127.0.0.1".to_string(), 6881),
+            BtPeer::new("::1".to_string(), 6882)
+        ]
+    );
+}
+
+#[test]
+fn test_info_dict_is_encoded_the_same_by_both_sides() {
+    let info = Bencode::decode(b"d6:lengthi-42e4:name4:test12:piece lengthi16384ee").unwrap();
+
+    assert_eq!(
+        Bencode::encode(&info),
+        b"d6:lengthi-42e4:name4:test12:piece lengthi16384ee".to_vec()
+    );
+    assert_eq!(info.to_bencode(), info);
+}
+
+// Auxiliary functions
+
+fn create_announce_response(compact: bool) -> AnnounceResponse {
+    AnnounceResponse {
+        failure_reason: None,
+        warning_message: None,
+        interval: 1800,
+        min_interval: None,
+        tracker_id: None,
+        complete: 1,
+        incomplete: 2,
+        peers: vec![
+            create_peer([1; 20], "127.0.0.1", 6881),
+            create_peer([2; 20], "::1", 6882),
+        ],
+        no_peer_id: false,
+        compact,
+    }
+}
+
+fn create_peer(id: [u8; 20], ip: &str, port: u16) -> Peer {
+    Peer::new(
+        id,
+        ip.to_string(),
+        port,
+        None,
+        PeerStatus::new(0, 0, 0, None),
+    )
+}
+
+fn create_bt_peer(id: [u8; 20], ip: &str, port: i64) -> BtPeer {
+    let mut bt_peer = BtPeer::new(ip.to_string(), port);
+    bt_peer.peer_id = Some(id.to_vec());
+    bt_peer
+}
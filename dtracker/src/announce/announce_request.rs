@@ -1,14 +1,13 @@
-use std::{collections::HashMap, num::ParseIntError, str::FromStr};
+use std::str::FromStr;
 
 use super::announce_request_error::AnnounceRequestError;
-use crate::tracker_peer::event::PeerEvent;
-use url_encoder::url_encoder::decode;
+use crate::{http::query_params::QueryParams, tracker_peer::event::PeerEvent};
 
 /// Struct representing the announce request to a tracker.
 ///
 /// # Fields
-/// * `info_hash`: urlencoded 20-byte SHA1 hash of the value of the info key from the Metainfo file.
-/// * `peer_id`: urlencoded 20-byte string used as a unique ID for the client, generated by the client at startup.
+/// * `info_hash`: 20-byte SHA1 hash of the value of the info key from the Metainfo file.
+/// * `peer_id`: 20-byte string used as a unique ID for the client, generated by the client at startup.
 /// * `port`: The port number the client is listening on.
 /// * `uploaded`: The total amount of data uploaded by the client, in bytes.
 /// * `downloaded`: The total amount of data downloaded by the client, in bytes.
@@ -20,7 +19,7 @@ use url_encoder::url_encoder::decode;
 ///     * `stopped`: The client has just stopped.
 ///     * `completed`: The client has just successfully downloaded the file.
 /// * `ip`: *(Optional)* The IP address of the client. If not present, the IP address of the client will be determined automatically.
-/// * `numwant`: The number of peers that the client would like to receive in the response. If absent, the client requests a default number of peers.
+/// * `numwant`: The number of peers that the client would like to receive in the response. If absent, the client requests a default number of peers. It's capped at `MAX_NUMWANT`.
 /// * `key`: *(Optional)* The key used to identify the client. If absent, the client will be identified by its peer id.
 /// * `trackerid`: *(Optional)* The id of the tracker. If absent, the tracker will be identified by its IP address.
 #[derive(Debug, Clone)]
@@ -41,12 +40,14 @@ pub struct AnnounceRequest {
 }
 
 const DEFAULT_NUMWANT: &str = "50";
+/// The maximum number of peers returned in an announce response, whatever the client asks for.
+pub const MAX_NUMWANT: u32 = 200;
 
 impl AnnounceRequest {
-    /// Creates a new AnnounceRequest from a HashMap containing the query parameters of the announce request.
+    /// Creates a new AnnounceRequest from the query parameters of the announce request.
     ///
     /// If the request is invalid, an error is returned.
-    pub fn new_from(query_params: HashMap<String, String>) -> Result<Self, AnnounceRequestError> {
+    pub fn new_from(query_params: QueryParams) -> Result<Self, AnnounceRequestError> {
         // Obligatory params:
         let info_hash = Self::get_info_hash(&query_params)?;
         let peer_id = Self::get_peer_id(&query_params)?;
@@ -81,45 +82,22 @@ impl AnnounceRequest {
         })
     }
 
-    fn get_info_hash(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<[u8; 20], AnnounceRequestError> {
-        let info_hash = query_params_map.get("info_hash").map_or_else(
-            || Err(AnnounceRequestError::InvalidInfoHash),
-            |i| {
-                Self::decode_hex(&decode(i))
-                    .map_err(|_| AnnounceRequestError::InvalidInfoHash)?
-                    .try_into()
-                    .map_err(|_| AnnounceRequestError::InvalidInfoHash)
-            },
-        )?;
-        Ok(info_hash)
-    }
-
-    fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
-        (0..s.len())
-            .step_by(2)
-            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-            .collect()
+    fn get_info_hash(query_params_map: &QueryParams) -> Result<[u8; 20], AnnounceRequestError> {
+        query_params_map
+            .get("info_hash")
+            .and_then(|i| i.try_into().ok())
+            .ok_or(AnnounceRequestError::InvalidInfoHash)
     }
 
-    fn get_peer_id(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<[u8; 20], AnnounceRequestError> {
-        let peer_id = query_params_map.get("peer_id").map_or_else(
-            || Err(AnnounceRequestError::InvalidPeerId),
-            |i| {
-                Self::decode_hex(&decode(i))
-                    .map_err(|_| AnnounceRequestError::InvalidPeerId)?
-                    .try_into()
-                    .map_err(|_| AnnounceRequestError::InvalidPeerId)
-            },
-        )?;
-        Ok(peer_id)
+    fn get_peer_id(query_params_map: &QueryParams) -> Result<[u8; 20], AnnounceRequestError> {
+        query_params_map
+            .get("peer_id")
+            .and_then(|i| i.try_into().ok())
+            .ok_or(AnnounceRequestError::InvalidPeerId)
     }
 
-    fn get_port(query_params_map: &HashMap<String, String>) -> Result<u16, AnnounceRequestError> {
-        let port = query_params_map.get("port").map_or_else(
+    fn get_port(query_params_map: &QueryParams) -> Result<u16, AnnounceRequestError> {
+        let port = query_params_map.get_str("port").map_or_else(
             || Err(AnnounceRequestError::InvalidPort),
             |p| {
                 p.parse::<u16>()
@@ -129,10 +107,8 @@ impl AnnounceRequest {
         Ok(port)
     }
 
-    fn get_uploaded(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<u64, AnnounceRequestError> {
-        let uploaded = query_params_map.get("uploaded").map_or_else(
+    fn get_uploaded(query_params_map: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        let uploaded = query_params_map.get_str("uploaded").map_or_else(
             || Err(AnnounceRequestError::InvalidUploaded),
             |u| {
                 u.parse::<u64>()
@@ -142,10 +118,8 @@ impl AnnounceRequest {
         Ok(uploaded)
     }
 
-    fn get_downloaded(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<u64, AnnounceRequestError> {
-        let downloaded = query_params_map.get("downloaded").map_or_else(
+    fn get_downloaded(query_params_map: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        let downloaded = query_params_map.get_str("downloaded").map_or_else(
             || Err(AnnounceRequestError::InvalidDownloaded),
             |d| {
                 d.parse::<u64>()
@@ -155,8 +129,8 @@ impl AnnounceRequest {
         Ok(downloaded)
     }
 
-    fn get_left(query_params_map: &HashMap<String, String>) -> Result<u64, AnnounceRequestError> {
-        let left = query_params_map.get("left").map_or_else(
+    fn get_left(query_params_map: &QueryParams) -> Result<u64, AnnounceRequestError> {
+        let left = query_params_map.get_str("left").map_or_else(
             || Err(AnnounceRequestError::InvalidLeft),
             |l| {
                 l.parse::<u64>()
@@ -166,23 +140,20 @@ impl AnnounceRequest {
         Ok(left)
     }
 
-    fn get_compact(query_params_map: &HashMap<String, String>) -> bool {
-        query_params_map.get("compact").unwrap_or(&"0".to_string()) == "1"
+    fn get_compact(query_params_map: &QueryParams) -> bool {
+        query_params_map.get("compact") == Some(b"1".as_slice())
     }
 
-    fn get_no_peer_id(query_params_map: &HashMap<String, String>) -> bool {
-        query_params_map
-            .get("no_peer_id")
-            .unwrap_or(&"0".to_string())
-            == "1"
+    fn get_no_peer_id(query_params_map: &QueryParams) -> bool {
+        query_params_map.get("no_peer_id") == Some(b"1".as_slice())
     }
 
     fn get_event(
-        query_params_map: &HashMap<String, String>,
+        query_params_map: &QueryParams,
     ) -> Result<Option<PeerEvent>, AnnounceRequestError> {
         match query_params_map
-            .get("event")
-            .map(|e| PeerEvent::from_str(e).ok())
+            .get_str("event")
+            .map(|e| PeerEvent::from_str(&e).ok())
         {
             Some(ev) => match ev {
                 Some(ev) => Ok(Some(ev)),
@@ -192,25 +163,24 @@ impl AnnounceRequest {
         }
     }
 
-    fn get_ip(query_params_map: &HashMap<String, String>) -> Option<String> {
-        query_params_map.get("ip").map(|ip| ip.to_string())
+    fn get_ip(query_params_map: &QueryParams) -> Option<String> {
+        query_params_map.get_str("ip")
     }
 
-    fn get_numwant(
-        query_params_map: &HashMap<String, String>,
-    ) -> Result<u32, AnnounceRequestError> {
+    fn get_numwant(query_params_map: &QueryParams) -> Result<u32, AnnounceRequestError> {
         query_params_map
-            .get("numwant")
-            .unwrap_or(&DEFAULT_NUMWANT.to_string())
+            .get_str("numwant")
+            .unwrap_or_else(|| DEFAULT_NUMWANT.to_string())
             .parse::<u32>()
+            .map(|numwant| numwant.min(MAX_NUMWANT))
             .map_err(|_| AnnounceRequestError::InvalidNumwant)
     }
 
-    fn get_key(query_params_map: &HashMap<String, String>) -> Option<String> {
-        query_params_map.get("key").map(|s| s.to_string())
+    fn get_key(query_params_map: &QueryParams) -> Option<String> {
+        query_params_map.get_str("key")
     }
 
-    fn get_tracker_id(query_params_map: &HashMap<String, String>) -> Option<String> {
-        query_params_map.get("tracker_id").map(|s| s.to_string())
+    fn get_tracker_id(query_params_map: &QueryParams) -> Option<String> {
+        query_params_map.get_str("tracker_id")
     }
 }
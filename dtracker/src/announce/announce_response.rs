@@ -1,11 +1,12 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    sync::Arc,
-};
+use std::{collections::BTreeMap, sync::Arc};
 
-use bencoder::bencode::ToBencode;
+use bencoder::bencode::{Bencode, ToBencode};
 
-use crate::{tracker_peer::peer::Peer, tracker_status::atomic_tracker_status::AtomicTrackerStatus};
+use crate::{
+    http::query_params::QueryParams,
+    tracker_peer::{event::PeerEvent, peer::Peer},
+    tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+};
 
 use super::announce_request::AnnounceRequest;
 
@@ -20,10 +21,13 @@ use super::announce_request::AnnounceRequest;
 /// * `complete`: number of peers with the entire file, i.e. seeders.
 /// * `incomplete`: number of non-seeder peers, aka "leechers".
 /// * `peers`: (dictionary model) The value is a list of dictionaries, each with the following keys:
-///    - **peer_id**: peer's self-selected ID, as described above for the tracker request (string)
+///    - **peer id**: peer's self-selected ID, as described above for the tracker request (string)
 ///    - **ip**: peer's IP address either IPv6 (hexed) or IPv4 (dotted quad) or DNS name (string)
 ///    - **port**: peer's port number (integer)
-/// * `peers_binary`: peers: (binary model) Instead of using the dictionary model described above, the peers value may be a string consisting of multiples of 6 bytes. First 4 bytes are the IP address and last 2 bytes are the port number. All in network (big endian) notation.
+/// * `no_peer_id`: If true, the `peer id` key is left out of the dictionary model peers.
+/// * `compact`: If true, the peers are sent in the binary model instead of the dictionary model:
+///    - **peers**: a string consisting of multiples of 6 bytes. First 4 bytes are the IPv4 address and last 2 bytes are the port number. All in network (big endian) notation.
+///    - **peers6**: the same for IPv6 peers, with multiples of 18 bytes: 16 bytes for the address and 2 for the port.
 #[derive(Debug)]
 pub struct AnnounceResponse {
     pub failure_reason: Option<String>,
@@ -34,12 +38,14 @@ pub struct AnnounceResponse {
     pub complete: u32,
     pub incomplete: u32,
     pub peers: Vec<Peer>,
+    pub no_peer_id: bool,
+    pub compact: bool,
 }
 
 impl AnnounceResponse {
-    /// Creates a new AnnounceResponse from a HashMap containing the query parameters of the announce request.
+    /// Creates a new AnnounceResponse from the query parameters of the announce request.
     pub fn from(
-        query_params: HashMap<String, String>,
+        query_params: QueryParams,
         tracker_status: Arc<AtomicTrackerStatus>,
         peer_ip: String,
     ) -> Self {
@@ -50,6 +56,24 @@ impl AnnounceResponse {
             }
         };
 
+        let min_interval = tracker_status.min_announce_interval().num_seconds() as u32;
+
+        // Stopping or completing is always accepted, so the peer state is never left stale.
+        let is_regular_announce = !matches!(
+            announce_request.event,
+            Some(PeerEvent::Stopped) | Some(PeerEvent::Completed)
+        );
+        if is_regular_announce
+            && !tracker_status.allow_announce(&peer_ip, announce_request.info_hash)
+        {
+            let mut response = Self::create_error_response(format!(
+                "Announcing too fast, wait at least {} seconds between announces",
+                min_interval
+            ));
+            response.min_interval = Some(min_interval);
+            return response;
+        }
+
         let peer = Peer::from_request(announce_request.clone(), peer_ip);
 
         let active_peers = tracker_status.incoming_peer(
@@ -58,13 +82,33 @@ impl AnnounceResponse {
             announce_request.numwant,
         );
 
-        // TODO: Handle announce_request.compact == true case.
-
-        Self::create_success_response(
+        let mut response = Self::create_success_response(
             active_peers.peers,
             active_peers.seeders,
             active_peers.leechers,
-        )
+            min_interval,
+        );
+        response.no_peer_id = announce_request.no_peer_id;
+        response.compact = announce_request.compact;
+        response
+    }
+
+    /// Creates the response to a HEAD announce from its query parameters.
+    ///
+    /// Only the parameters are validated: the peer isn't registered nor counted for the rate limit, so the response has no peers.
+    pub fn from_head(query_params: QueryParams, tracker_status: Arc<AtomicTrackerStatus>) -> Self {
+        let announce_request = match AnnounceRequest::new_from(query_params) {
+            Ok(announce_request) => announce_request,
+            Err(announce_request_error) => {
+                return Self::create_error_response(announce_request_error.to_string())
+            }
+        };
+
+        let min_interval = tracker_status.min_announce_interval().num_seconds() as u32;
+        let mut response = Self::create_success_response(Vec::new(), 0, 0, min_interval);
+        response.no_peer_id = announce_request.no_peer_id;
+        response.compact = announce_request.compact;
+        response
     }
 
     fn create_error_response(failure_reason: String) -> Self {
@@ -77,28 +121,68 @@ impl AnnounceResponse {
             complete: 0,
             incomplete: 0,
             peers: Vec::new(),
+            no_peer_id: false,
+            compact: false,
         }
     }
 
-    fn create_success_response(peers_list: Vec<Peer>, complete: u32, incomplete: u32) -> Self {
+    /// Creates a successful response, asking clients to wait the `min_interval` before announcing again, so following the interval never gets them rate-limited.
+    fn create_success_response(
+        peers_list: Vec<Peer>,
+        complete: u32,
+        incomplete: u32,
+        min_interval: u32,
+    ) -> Self {
         Self {
             failure_reason: None,
             warning_message: None,
-            interval: 0,
-            min_interval: None,
+            interval: min_interval,
+            min_interval: Some(min_interval),
             tracker_id: None,
             complete,
             incomplete,
             peers: peers_list,
+            no_peer_id: false,
+            compact: false,
         }
     }
+
+    /// Returns the compact IPv4 and IPv6 peer strings, in that order. Peers without a valid IP address are left out.
+    fn compact_peers(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut peers = Vec::new();
+        let mut peers6 = Vec::new();
+        for compact_peer in self.peers.iter().filter_map(|peer| peer.to_compact()) {
+            if compact_peer.len() == 6 {
+                peers.extend(compact_peer);
+            } else {
+                peers6.extend(compact_peer);
+            }
+        }
+        (peers, peers6)
+    }
+
+    /// Returns the dictionary model peers, without their `peer id` if `no_peer_id` is set.
+    fn dictionary_peers(&self) -> Vec<Bencode> {
+        self.peers
+            .iter()
+            .map(|peer| {
+                let mut peer = peer.to_bencode();
+                if let (true, Bencode::BDict(peer)) = (self.no_peer_id, &mut peer) {
+                    peer.remove(b"peer id".as_slice());
+                }
+                peer
+            })
+            .collect()
+    }
 }
 
 impl ToBencode for AnnounceResponse {
-    fn to_bencode(&self) -> bencoder::bencode::Bencode {
+    fn to_bencode(&self) -> Bencode {
         let mut announce_response = BTreeMap::new();
         if let Some(failure_reason) = &self.failure_reason {
+            // No other keys may be present in a failed response.
             announce_response.insert(b"failure reason".to_vec(), failure_reason.to_bencode());
+            return announce_response.to_bencode();
         }
         if let Some(warning_message) = &self.warning_message {
             announce_response.insert(b"warning message".to_vec(), warning_message.to_bencode());
@@ -112,7 +196,323 @@ impl ToBencode for AnnounceResponse {
         }
         announce_response.insert(b"complete".to_vec(), self.complete.to_bencode());
         announce_response.insert(b"incomplete".to_vec(), self.incomplete.to_bencode());
-        announce_response.insert(b"peers".to_vec(), self.peers.to_bencode());
+        if self.compact {
+            let (peers, peers6) = self.compact_peers();
+            announce_response.insert(b"peers".to_vec(), peers.to_bencode());
+            if !peers6.is_empty() {
+                announce_response.insert(b"peers6".to_vec(), peers6.to_bencode());
+            }
+        } else {
+            announce_response.insert(b"peers".to_vec(), self.dictionary_peers().to_bencode());
+        }
         announce_response.to_bencode()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::tracker_status::atomic_tracker_status::{
+        DEFAULT_MAX_SWARM_PEERS, DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS, DEFAULT_PEER_HOURS_TIMEOUT,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_too_fast_reannounce_is_rejected() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+
+        let first = AnnounceResponse::from(
+            create_test_params(None),
+            tracker_status.clone(),
+            "127.0.0.1".to_string(),
+        );
+        let second = AnnounceResponse::from(
+            create_test_params(None),
+            tracker_status,
+            "127.0.0.1".to_string(),
+        );
+
+        assert!(first.failure_reason.is_none());
+        assert!(second.failure_reason.is_some());
+        assert_eq!(second.min_interval, first.min_interval);
+    }
+
+    #[test]
+    fn test_interval_is_at_least_the_min_interval() {
+        let response = AnnounceResponse::from(
+            create_test_params(None),
+            Arc::new(AtomicTrackerStatus::default()),
+            "127.0.0.1".to_string(),
+        );
+
+        assert!(response.failure_reason.is_none());
+        assert!(Some(response.interval) >= response.min_interval);
+        assert!(response.interval as i64 >= DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS);
+    }
+
+    #[test]
+    fn test_client_following_the_interval_is_not_rate_limited() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+
+        let mut response = AnnounceResponse::from(
+            create_test_params(None),
+            tracker_status.clone(),
+            "127.0.0.1".to_string(),
+        );
+        for _ in 0..2 {
+            assert!(response.failure_reason.is_none());
+            tracker_status.backdate_last_announces(Duration::seconds(response.interval.into()));
+            response = AnnounceResponse::from(
+                create_test_params(None),
+                tracker_status.clone(),
+                "127.0.0.1".to_string(),
+            );
+        }
+        assert!(response.failure_reason.is_none());
+    }
+
+    #[test]
+    fn test_compliant_reannounce_is_accepted() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::new(
+            Duration::hours(DEFAULT_PEER_HOURS_TIMEOUT),
+            Duration::zero(),
+            DEFAULT_MAX_SWARM_PEERS,
+        ));
+
+        AnnounceResponse::from(
+            create_test_params(None),
+            tracker_status.clone(),
+            "127.0.0.1".to_string(),
+        );
+        let response = AnnounceResponse::from(
+            create_test_params(None),
+            tracker_status,
+            "127.0.0.1".to_string(),
+        );
+
+        assert!(response.failure_reason.is_none());
+        assert_eq!(response.incomplete, 1);
+    }
+
+    #[test]
+    fn test_numwant_limits_the_peers_and_leaves_out_the_requester() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        for id in 0..10 {
+            let query_params = create_test_params_for_peer([id; 20], None);
+            AnnounceResponse::from(
+                query_params,
+                tracker_status.clone(),
+                format!("10.0.0.{}", id),
+            );
+        }
+        let mut query_params = create_test_params(None);
+        query_params.insert("numwant", b"5".to_vec());
+
+        let response =
+            AnnounceResponse::from(query_params, tracker_status, "127.0.0.1".to_string());
+
+        assert_eq!(response.incomplete, 11);
+        assert_eq!(response.peers.len(), 5);
+        assert!(response
+            .peers
+            .iter()
+            .all(|peer| peer.id != *b"-DT0001-000000000000"));
+    }
+
+    #[test]
+    fn test_stopped_announce_is_not_rate_limited() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+
+        AnnounceResponse::from(
+            create_test_params(None),
+            tracker_status.clone(),
+            "127.0.0.1".to_string(),
+        );
+        let response = AnnounceResponse::from(
+            create_test_params(Some("stopped")),
+            tracker_status,
+            "127.0.0.1".to_string(),
+        );
+
+        assert!(response.failure_reason.is_none());
+    }
+
+    #[test]
+    fn test_short_info_hash_is_rejected() {
+        let mut query_params = QueryParams::parse(
+            b"peer_id=-DT0001-000000000000&port=6881&uploaded=0&downloaded=0&left=100",
+        )
+        .unwrap();
+        query_params.insert("info_hash", vec![1; 19]);
+
+        let response = AnnounceResponse::from(
+            query_params,
+            Arc::new(AtomicTrackerStatus::default()),
+            "127.0.0.1".to_string(),
+        );
+
+        assert_eq!(
+            Bencode::encode(&response),
+            b"d14:failure reason17:Invalid info_hashe"
+        );
+    }
+
+    #[test]
+    fn test_long_info_hash_is_rejected() {
+        let mut query_params = QueryParams::parse(
+            b"peer_id=-DT0001-000000000000&port=6881&uploaded=0&downloaded=0&left=100",
+        )
+        .unwrap();
+        query_params.insert("info_hash", vec![1; 21]);
+
+        let response = AnnounceResponse::from(
+            query_params,
+            Arc::new(AtomicTrackerStatus::default()),
+            "127.0.0.1".to_string(),
+        );
+
+        assert_eq!(
+            response.failure_reason,
+            Some("Invalid info_hash".to_string())
+        );
+        assert!(response.peers.is_empty());
+    }
+
+    #[test]
+    fn test_compact_response_has_only_peer_strings() {
+        let tracker_status = create_test_tracker_status_with_peers();
+        let mut query_params = create_test_params(None);
+        query_params.insert("compact", b"1".to_vec());
+
+        let response =
+            AnnounceResponse::from(query_params, tracker_status, "127.0.0.1".to_string());
+        let decoded = decode_test_response(&response);
+
+        assert_eq!(
+            decoded.get(b"peers".as_slice()),
+            Some(&Bencode::BString(vec![10, 0, 0, 1, 0x1a, 0xe1]))
+        );
+        let mut peers6 = vec![0; 15];
+        peers6.extend([1, 0x1a, 0xe1]);
+        assert_eq!(
+            decoded.get(b"peers6".as_slice()),
+            Some(&Bencode::BString(peers6))
+        );
+    }
+
+    #[test]
+    fn test_non_compact_response_has_dictionary_peers() {
+        for compact in [None, Some("0")] {
+            let tracker_status = create_test_tracker_status_with_peers();
+            let mut query_params = create_test_params(None);
+            if let Some(compact) = compact {
+                query_params.insert("compact", compact.as_bytes().to_vec());
+            }
+
+            let response =
+                AnnounceResponse::from(query_params, tracker_status, "127.0.0.1".to_string());
+            let decoded = decode_test_response(&response);
+
+            let peers = match decoded.get(b"peers".as_slice()) {
+                Some(Bencode::BList(peers)) => peers,
+                other => panic!("Expected a list of peers, got {:?}", other),
+            };
+            assert_eq!(peers.len(), 2);
+            assert!(peers.iter().all(
+                |peer| matches!(peer, Bencode::BDict(peer) if peer.contains_key(b"ip".as_slice()))
+            ));
+            assert!(!decoded.contains_key(b"peers6".as_slice()));
+        }
+    }
+
+    #[test]
+    fn test_dictionary_peers_have_peer_ids() {
+        let response = AnnounceResponse::from(
+            create_test_params(None),
+            create_test_tracker_status_with_peers(),
+            "127.0.0.1".to_string(),
+        );
+
+        let peers = decode_test_dictionary_peers(&response);
+
+        assert_eq!(peers.len(), 2);
+        assert!(peers
+            .iter()
+            .all(|peer| peer.contains_key(b"peer id".as_slice())));
+    }
+
+    #[test]
+    fn test_no_peer_id_leaves_out_peer_ids() {
+        let mut query_params = create_test_params(None);
+        query_params.insert("no_peer_id", b"1".to_vec());
+
+        let response = AnnounceResponse::from(
+            query_params,
+            create_test_tracker_status_with_peers(),
+            "127.0.0.1".to_string(),
+        );
+
+        let peers = decode_test_dictionary_peers(&response);
+
+        assert_eq!(peers.len(), 2);
+        assert!(peers
+            .iter()
+            .all(|peer| !peer.contains_key(b"peer id".as_slice())
+                && peer.contains_key(b"ip".as_slice())));
+    }
+
+    // Auxiliary functions
+
+    /// Creates a tracker status with an IPv4 and an IPv6 peer on the test torrent.
+    fn create_test_tracker_status_with_peers() -> Arc<AtomicTrackerStatus> {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        AnnounceResponse::from(
+            create_test_params_for_peer([1; 20], None),
+            tracker_status.clone(),
+            "10.0.0.1".to_string(),
+        );
+        AnnounceResponse::from(
+            create_test_params_for_peer([2; 20], None),
+            tracker_status.clone(),
+            "::1".to_string(),
+        );
+        tracker_status
+    }
+
+    fn decode_test_response(response: &AnnounceResponse) -> BTreeMap<Vec<u8>, Bencode> {
+        match Bencode::decode(&Bencode::encode(response)).unwrap() {
+            Bencode::BDict(dict) => dict,
+            other => panic!("Expected a dictionary, got {:?}", other),
+        }
+    }
+
+    fn decode_test_dictionary_peers(
+        response: &AnnounceResponse,
+    ) -> Vec<BTreeMap<Vec<u8>, Bencode>> {
+        match decode_test_response(response).remove(b"peers".as_slice()) {
+            Some(Bencode::BList(peers)) => peers
+                .into_iter()
+                .map(|peer| match peer {
+                    Bencode::BDict(peer) => peer,
+                    other => panic!("Expected a peer dictionary, got {:?}", other),
+                })
+                .collect(),
+            other => panic!("Expected a list of peers, got {:?}", other),
+        }
+    }
+
+    fn create_test_params(event: Option<&str>) -> QueryParams {
+        create_test_params_for_peer(*b"-DT0001-000000000000", event)
+    }
+
+    fn create_test_params_for_peer(peer_id: [u8; 20], event: Option<&str>) -> QueryParams {
+        let mut query_params = QueryParams::parse(b"info_hash=%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7&port=6881&uploaded=0&downloaded=0&left=100").unwrap();
+        query_params.insert("peer_id", peer_id.to_vec());
+        if let Some(event) = event {
+            query_params.insert("event", event.as_bytes().to_vec());
+        }
+        query_params
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+
+use chrono::Duration;
+
+use crate::{
+    stats::stats_updater::DEFAULT_DAYS_TO_KEEP_STATS,
+    tracker_status::atomic_tracker_status::{
+        DEFAULT_MAX_SWARM_PEERS, DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS, DEFAULT_PEER_HOURS_TIMEOUT,
+    },
+};
+
+pub const DEFAULT_READ_SECONDS_TIMEOUT: u64 = 10;
+
+pub const TRUST_PROXY_FLAG: &str = "--trust-proxy";
+pub const STATS_DAYS_OPTION: &str = "--stats-days";
+pub const MIN_INTERVAL_OPTION: &str = "--min-interval";
+pub const PEER_TIMEOUT_OPTION: &str = "--peer-timeout";
+pub const MAX_SWARM_PEERS_OPTION: &str = "--max-swarm-peers";
+pub const READ_TIMEOUT_OPTION: &str = "--read-timeout";
+
+/// Posible `TrackerSettings` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrackerSettingsError {
+    MissingAddress,
+    InvalidAddress(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for TrackerSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAddress => write!(f, "Missing port number or address"),
+            Self::InvalidAddress(addr) => write!(f, "Invalid port number or address: {}", addr),
+            Self::UnknownOption(option) => write!(f, "Unknown option: {}", option),
+            Self::MissingValue(option) => write!(f, "Missing value for {}", option),
+            Self::InvalidValue(option, value) => {
+                write!(f, "Invalid value for {}: {}", option, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrackerSettingsError {}
+
+/// The settings of the tracker, taken from the command line.
+///
+/// ## Fields
+/// * `addr`: The address the tracker listens on, which can be either IPv4 or IPv6.
+/// * `trust_proxy`: Whether the peer IPs are taken from the `X-Forwarded-For` header or the `ip` param, as the tracker is behind a reverse proxy.
+/// * `days_to_keep_stats`: The days of stats history kept.
+/// * `min_announce_interval_seconds`: The minimum time a source IP must wait between announces for the same torrent.
+/// * `peer_hours_timeout`: The time after which a peer that didn't announce is removed from its swarm.
+/// * `max_swarm_peers`: The maximum amount of peers kept for each torrent.
+/// * `read_seconds_timeout`: The time a client has to send its request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackerSettings {
+    pub addr: SocketAddr,
+    pub trust_proxy: bool,
+    pub days_to_keep_stats: u64,
+    pub min_announce_interval_seconds: i64,
+    pub peer_hours_timeout: i64,
+    pub max_swarm_peers: usize,
+    pub read_seconds_timeout: u64,
+}
+
+impl TrackerSettings {
+    /// Creates the default settings for a tracker listening on the given address.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            trust_proxy: false,
+            days_to_keep_stats: DEFAULT_DAYS_TO_KEEP_STATS,
+            min_announce_interval_seconds: DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS,
+            peer_hours_timeout: DEFAULT_PEER_HOURS_TIMEOUT,
+            max_swarm_peers: DEFAULT_MAX_SWARM_PEERS,
+            read_seconds_timeout: DEFAULT_READ_SECONDS_TIMEOUT,
+        }
+    }
+
+    /// Creates the settings from the command line arguments, without the program name.
+    ///
+    /// The first argument is a port number, which listens on every IPv4 interface, or an address. It's followed by any of the options, and the settings not given keep their default value.
+    ///
+    /// It returns a `TrackerSettingsError` if:
+    /// - There is no address, or it isn't a port number nor an address.
+    /// - An option is unknown or is missing its value.
+    /// - The value of an option isn't a number.
+    /// - The min interval is negative, or the peer timeout or the read timeout aren't positive.
+    /// - The min interval or the peer timeout are too long to be represented.
+    pub fn from_args(args: &[String]) -> Result<Self, TrackerSettingsError> {
+        let mut args = args.iter();
+        let addr = match args.next() {
+            Some(addr) => Self::parse_addr(addr)?,
+            None => return Err(TrackerSettingsError::MissingAddress),
+        };
+
+        let mut settings = Self::new(addr);
+        while let Some(option) = args.next() {
+            match option.as_str() {
+                TRUST_PROXY_FLAG => settings.trust_proxy = true,
+                STATS_DAYS_OPTION => {
+                    settings.days_to_keep_stats = Self::parse_value(option, args.next())?
+                }
+                MIN_INTERVAL_OPTION => {
+                    let value = args.next();
+                    settings.min_announce_interval_seconds = Self::parse_value(option, value)?;
+                    if settings.min_announce_interval_seconds < 0
+                        || Duration::try_seconds(settings.min_announce_interval_seconds).is_none()
+                    {
+                        return Err(Self::invalid_value(option, value));
+                    }
+                }
+                PEER_TIMEOUT_OPTION => {
+                    let value = args.next();
+                    settings.peer_hours_timeout = Self::parse_value(option, value)?;
+                    if settings.peer_hours_timeout <= 0
+                        || Duration::try_hours(settings.peer_hours_timeout).is_none()
+                    {
+                        return Err(Self::invalid_value(option, value));
+                    }
+                }
+                MAX_SWARM_PEERS_OPTION => {
+                    settings.max_swarm_peers = Self::parse_value(option, args.next())?
+                }
+                READ_TIMEOUT_OPTION => {
+                    let value = args.next();
+                    settings.read_seconds_timeout = Self::parse_value(option, value)?;
+                    // A zero timeout is rejected when set on a connection.
+                    if settings.read_seconds_timeout == 0 {
+                        return Err(Self::invalid_value(option, value));
+                    }
+                }
+                _ => return Err(TrackerSettingsError::UnknownOption(option.to_string())),
+            }
+        }
+        Ok(settings)
+    }
+
+    fn parse_addr(addr: &str) -> Result<SocketAddr, TrackerSettingsError> {
+        // A port alone listens on every IPv4 interface.
+        if let Ok(port) = addr.parse::<u16>() {
+            return Ok(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)));
+        }
+        addr.parse::<SocketAddr>()
+            .map_err(|_| TrackerSettingsError::InvalidAddress(addr.to_string()))
+    }
+
+    fn parse_value<T: FromStr>(
+        option: &str,
+        value: Option<&String>,
+    ) -> Result<T, TrackerSettingsError> {
+        match value {
+            Some(v) => v.parse().map_err(|_| Self::invalid_value(option, value)),
+            None => Err(TrackerSettingsError::MissingValue(option.to_string())),
+        }
+    }
+
+    fn invalid_value(option: &str, value: Option<&String>) -> TrackerSettingsError {
+        TrackerSettingsError::InvalidValue(option.to_string(), value.cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_alone_uses_the_default_settings() {
+        let settings = TrackerSettings::from_args(&args(&["8080"])).unwrap();
+
+        assert_eq!(
+            settings,
+            TrackerSettings::new(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 8080)))
+        );
+    }
+
+    #[test]
+    fn test_address_with_every_option() {
+        let settings = TrackerSettings::from_args(&args(&[
+            "[::]:8080",
+            "--trust-proxy",
+            "--stats-days",
+            "7",
+            "--min-interval",
+            "60",
+            "--peer-timeout",
+            "2",
+            "--max-swarm-peers",
+            "500",
+            "--read-timeout",
+            "5",
+        ]))
+        .unwrap();
+
+        assert_eq!(settings.addr, "[::]:8080".parse().unwrap());
+        assert!(settings.trust_proxy);
+        assert_eq!(settings.days_to_keep_stats, 7);
+        assert_eq!(settings.min_announce_interval_seconds, 60);
+        assert_eq!(settings.peer_hours_timeout, 2);
+        assert_eq!(settings.max_swarm_peers, 500);
+        assert_eq!(settings.read_seconds_timeout, 5);
+    }
+
+    #[test]
+    fn test_missing_address() {
+        assert_eq!(
+            TrackerSettings::from_args(&[]).unwrap_err(),
+            TrackerSettingsError::MissingAddress
+        );
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["localhost"])).unwrap_err(),
+            TrackerSettingsError::InvalidAddress("localhost".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_option() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--verbose"])).unwrap_err(),
+            TrackerSettingsError::UnknownOption("--verbose".to_string())
+        );
+    }
+
+    #[test]
+    fn test_option_without_value() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--stats-days"])).unwrap_err(),
+            TrackerSettingsError::MissingValue("--stats-days".to_string())
+        );
+    }
+
+    #[test]
+    fn test_option_with_invalid_value() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--max-swarm-peers", "-1"])).unwrap_err(),
+            TrackerSettingsError::InvalidValue("--max-swarm-peers".to_string(), "-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_negative_min_interval_is_rejected() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--min-interval", "-1"])).unwrap_err(),
+            TrackerSettingsError::InvalidValue("--min-interval".to_string(), "-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_zero_peer_timeout_is_rejected() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--peer-timeout", "0"])).unwrap_err(),
+            TrackerSettingsError::InvalidValue("--peer-timeout".to_string(), "0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_peer_timeout_that_overflows_is_rejected() {
+        let hours = i64::MAX.to_string();
+
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--peer-timeout", &hours])).unwrap_err(),
+            TrackerSettingsError::InvalidValue("--peer-timeout".to_string(), hours)
+        );
+    }
+
+    #[test]
+    fn test_zero_read_timeout_is_rejected() {
+        assert_eq!(
+            TrackerSettings::from_args(&args(&["8080", "--read-timeout", "0"])).unwrap_err(),
+            TrackerSettingsError::InvalidValue("--read-timeout".to_string(), "0".to_string())
+        );
+    }
+
+    // Auxiliary functions
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+}
@@ -5,10 +5,13 @@ use chrono::Duration;
 use logger::{logger_error::LoggerError, logger_receiver::Logger, logger_sender::LoggerSender};
 
 use crate::{
-    http_server::server::Server, stats::stats_updater::StatsUpdater,
+    http_server::server::Server,
+    stats::stats_updater::{StatsUpdater, StatsUpdaterError},
     tracker_status::atomic_tracker_status::AtomicTrackerStatus,
 };
 
+use super::settings::TrackerSettings;
+
 /// Struct that represents the Tracker itself.
 ///
 /// Serves as a starting point for the application.
@@ -21,23 +24,41 @@ pub enum BtTrackerError {
     LoggerInitError(LoggerError),
     CreatingServerError(io::Error),
     StartingServerError(io::Error),
+    StatsUpdaterError(StatsUpdaterError),
 }
 
 const STATS_UPDATER_MINUTES_TIMEOUT: i64 = 1;
 
 impl BtTracker {
-    /// Creates a new BtTracker
-    pub fn init(port: u16) -> Result<Self, BtTrackerError> {
+    /// Creates a new BtTracker with the given settings, listening on their address, which can be either IPv4 or IPv6.
+    ///
+    /// If `trust_proxy` is set, the tracker is expected to be behind a reverse proxy, and the peer IPs are taken from the `X-Forwarded-For` header or the `ip` param.
+    pub fn init(settings: TrackerSettings) -> Result<Self, BtTrackerError> {
         let logger = Logger::new("./logs", 1000000).map_err(BtTrackerError::LoggerInitError)?; // TODO: Sacar de configs
         let logger_sender = logger.new_sender();
 
-        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let tracker_status = Arc::new(AtomicTrackerStatus::new(
+            Duration::hours(settings.peer_hours_timeout),
+            Duration::seconds(settings.min_announce_interval_seconds),
+            settings.max_swarm_peers,
+        ));
 
-        let stats_updater =
-            Self::spawn_stats_updater(tracker_status.clone(), logger_sender.clone());
+        let stats_updater = Self::spawn_stats_updater(
+            tracker_status.clone(),
+            settings.days_to_keep_stats,
+            logger_sender.clone(),
+        )
+        .map_err(BtTrackerError::StatsUpdaterError)?;
 
-        let server = Server::init(tracker_status, stats_updater, logger_sender.clone(), port)
-            .map_err(BtTrackerError::CreatingServerError)?;
+        let server = Server::init(
+            tracker_status,
+            stats_updater,
+            logger_sender.clone(),
+            settings.addr,
+            std::time::Duration::from_secs(settings.read_seconds_timeout),
+            settings.trust_proxy,
+        )
+        .map_err(BtTrackerError::CreatingServerError)?;
 
         logger_sender.info("Tracker started");
 
@@ -53,17 +74,19 @@ impl BtTracker {
 
     fn spawn_stats_updater(
         tracker_status: Arc<AtomicTrackerStatus>,
+        days_to_keep_stats: u64,
         logger_sender: LoggerSender,
-    ) -> Arc<StatsUpdater> {
+    ) -> Result<Arc<StatsUpdater>, StatsUpdaterError> {
         let stats_updater = Arc::new(StatsUpdater::new(
             tracker_status,
             Duration::minutes(STATS_UPDATER_MINUTES_TIMEOUT),
+            days_to_keep_stats,
             logger_sender,
-        ));
+        )?);
         let updater = stats_updater.clone();
         spawn(move || {
             updater.run();
         });
-        stats_updater
+        Ok(stats_updater)
     }
 }
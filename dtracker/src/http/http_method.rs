@@ -1,8 +1,23 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
+/// The method of a HTTP request.
+///
+/// Only `GET` and `HEAD` are supported by the tracker, any other valid method is kept as `Other` so it can be answered with a `405 Method Not Allowed`.
 #[derive(Debug, PartialEq)]
 pub enum HttpMethod {
     Get,
+    Head,
+    Other(String),
+}
+
+impl HttpMethod {
+    /// The methods supported by the tracker, as sent in the `Allow` header.
+    pub const ALLOWED: &'static str = "GET, HEAD";
+
+    /// Returns true if the tracker supports the method.
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, HttpMethod::Get | HttpMethod::Head)
+    }
 }
 
 impl FromStr for HttpMethod {
@@ -11,7 +26,21 @@ impl FromStr for HttpMethod {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "GET" => Ok(HttpMethod::Get),
+            "HEAD" => Ok(HttpMethod::Head),
+            _ if !s.is_empty() && s.chars().all(|c| c.is_ascii_uppercase()) => {
+                Ok(HttpMethod::Other(s.to_string()))
+            }
             _ => Err(()),
         }
     }
 }
+
+impl fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Get => write!(f, "GET"),
+            Self::Head => write!(f, "HEAD"),
+            Self::Other(method) => write!(f, "{}", method),
+        }
+    }
+}
@@ -1,27 +1,37 @@
 use std::{collections::HashMap, str::FromStr};
 
-use super::http_method::HttpMethod;
+use super::{http_method::HttpMethod, query_params::QueryParams};
 
 /// A struct that represents a HTTP request.
 ///
 /// # Fields
 /// * `method`: The HTTP method of the request.
 /// * `endpoint`: The endpoint of the request.
-/// * `params`: The parameters of the request.
+/// * `params`: The percent-decoded query parameters of the request.
+/// * `headers`: The headers of the request, by lowercase name.
+/// * `body`: The body of the request, as long as its `Content-Length` header says. Empty if there's no such header.
 pub struct Http {
     pub method: HttpMethod,
     pub endpoint: String,
-    pub params: HashMap<String, String>,
+    pub params: QueryParams,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub enum HttpError {
     ParseError,
     HttpMethodNotSupported,
+    InvalidContentLength,
+    IncompleteBody,
 }
 
+const HEADERS_END: &[u8] = b"\r\n\r\n";
+
 impl Http {
     /// Parses a HTTP request. If the request is invalid, returns an error.
+    ///
+    /// Any bytes after the body are ignored.
     pub fn parse(buffer: &[u8]) -> Result<Http, HttpError> {
         let mut lines = buffer.split(|&b| b == b'\r');
         let line = lines.next().ok_or(HttpError::ParseError)?;
@@ -39,31 +49,93 @@ impl Http {
         let endpoint = String::from_utf8_lossy(endpoint_split.next().ok_or(HttpError::ParseError)?)
             .to_string();
 
-        let query_params = endpoint_split.next().ok_or(HttpError::ParseError)?;
-        let params = parse_params(query_params).map_err(|_| HttpError::ParseError)?;
+        // The query is optional, as some endpoints don't take parameters.
+        let params = match endpoint_split.next() {
+            Some(query_params) => {
+                QueryParams::parse(query_params).map_err(|_| HttpError::ParseError)?
+            }
+            None => QueryParams::default(),
+        };
+
+        let headers = parse_headers(lines);
+
+        let body = match find_headers_end(buffer) {
+            Some(headers_end) => {
+                let body_start = headers_end + HEADERS_END.len();
+                let body_end = body_start
+                    .checked_add(content_length(&headers)?)
+                    .ok_or(HttpError::InvalidContentLength)?;
+                buffer
+                    .get(body_start..body_end)
+                    .ok_or(HttpError::IncompleteBody)?
+                    .to_vec()
+            }
+            None => Vec::new(),
+        };
 
         Ok(Http {
             method,
             endpoint,
             params,
+            headers,
+            body,
         })
     }
+
+    /// Returns the length in bytes of the request at the start of the buffer, including its body, or `None` if its headers are not complete yet.
+    ///
+    /// If the `Content-Length` header is invalid or too big to be added to the headers length, the body is not taken into account, and parsing the request fails.
+    pub fn request_length(buffer: &[u8]) -> Option<usize> {
+        let headers_end = find_headers_end(buffer)?;
+        let headers = parse_headers(buffer[..headers_end].split(|&b| b == b'\r').skip(1));
+
+        let headers_length = headers_end + HEADERS_END.len();
+        Some(
+            content_length(&headers)
+                .ok()
+                .and_then(|length| headers_length.checked_add(length))
+                .unwrap_or(headers_length),
+        )
+    }
+
+    /// Returns the value of the given header, ignoring the case of its name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_lowercase())
+            .map(|value| value.as_str())
+    }
+}
+
+fn find_headers_end(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(HEADERS_END.len())
+        .position(|window| window == HEADERS_END)
 }
 
-fn parse_params(query_params: &[u8]) -> Result<HashMap<String, String>, HttpError> {
-    let mut params = HashMap::new();
-    let query_params = query_params.split(|&b| b == b'&');
+fn content_length(headers: &HashMap<String, String>) -> Result<usize, HttpError> {
+    headers.get("content-length").map_or(Ok(0), |length| {
+        length
+            .parse::<usize>()
+            .map_err(|_| HttpError::InvalidContentLength)
+    })
+}
 
-    for param in query_params {
-        let mut param_split = param.split(|&b| b == b'=');
-        let key =
-            String::from_utf8_lossy(param_split.next().ok_or(HttpError::ParseError)?).to_string();
-        let value =
-            String::from_utf8_lossy(param_split.next().ok_or(HttpError::ParseError)?).to_string();
-        params.insert(key, value);
+/// Parses the header lines up to the empty line that ends them. Lines that are not a valid header are ignored.
+fn parse_headers<'a>(lines: impl Iterator<Item = &'a [u8]>) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+
+    for line in lines {
+        let line = line.strip_prefix(b"\n").unwrap_or(line);
+        if line.is_empty() {
+            break;
+        }
+        let line = String::from_utf8_lossy(line);
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
     }
 
-    Ok(params)
+    headers
 }
 
 #[cfg(test)]
@@ -74,17 +146,20 @@ mod tests {
     fn test_parse_valid_request() {
         let buffer = "GET /announce?info_hash=%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7&peer_id=DTorrent:02284204893&port=6969&uploaded=0&downloaded=0&left=396361728&event=started HTTP/1.1\r\nHost: bttracker.debian.org\r\nUser-Agent: LDTorrent/0.1\r\n\r\n".as_bytes();
         let http = Http::parse(buffer).unwrap();
-        let mut params = HashMap::new();
+        let mut params = QueryParams::default();
         params.insert(
-            "info_hash".to_string(),
-            "%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7".to_string(),
+            "info_hash",
+            vec![
+                0xb1, 0x11, 0x81, 0x3c, 0xe6, 0x0f, 0x42, 0x91, 0x97, 0x34, 0x82, 0x3d, 0xf5, 0xec,
+                0x20, 0xbd, 0x1e, 0x04, 0xe7, 0xf7,
+            ],
         );
-        params.insert("peer_id".to_string(), "DTorrent:02284204893".to_string());
-        params.insert("port".to_string(), "6969".to_string());
-        params.insert("uploaded".to_string(), "0".to_string());
-        params.insert("downloaded".to_string(), "0".to_string());
-        params.insert("left".to_string(), "396361728".to_string());
-        params.insert("event".to_string(), "started".to_string());
+        params.insert("peer_id", b"DTorrent:02284204893".to_vec());
+        params.insert("port", b"6969".to_vec());
+        params.insert("uploaded", b"0".to_vec());
+        params.insert("downloaded", b"0".to_vec());
+        params.insert("left", b"396361728".to_vec());
+        params.insert("event", b"started".to_vec());
 
         assert_eq!(http.method, HttpMethod::from_str("GET").unwrap());
         assert_eq!(http.endpoint, "/announce");
@@ -97,6 +172,98 @@ mod tests {
         assert!(Http::parse(buffer).is_err());
     }
 
+    #[test]
+    fn test_parse_request_with_repeated_params() {
+        let buffer = "GET /scrape?info_hash=%aa%bb&info_hash=%cc%dd HTTP/1.1\r\n\r\n".as_bytes();
+        let http = Http::parse(buffer).unwrap();
+
+        assert_eq!(
+            http.params.get_all("info_hash"),
+            &[vec![0xaa, 0xbb], vec![0xcc, 0xdd]]
+        );
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let buffer = "GET /stats.csv HTTP/1.1\r\nHost: localhost\r\nX-Forwarded-For: 10.0.0.1, 10.0.0.2\r\n\r\n".as_bytes();
+        let http = Http::parse(buffer).unwrap();
+
+        assert_eq!(http.headers.len(), 2);
+        assert_eq!(http.header("Host"), Some("localhost"));
+        assert_eq!(http.header("x-forwarded-for"), Some("10.0.0.1, 10.0.0.2"));
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let buffer = "POST /stats HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET".as_bytes();
+        let http = Http::parse(buffer).unwrap();
+
+        assert_eq!(http.body, b"hello");
+        assert_eq!(Http::request_length(buffer), Some(buffer.len() - 3));
+    }
+
+    #[test]
+    fn test_parse_without_content_length_has_no_body() {
+        let buffer = "GET /stats.csv HTTP/1.1\r\n\r\nhello".as_bytes();
+        let http = Http::parse(buffer).unwrap();
+
+        assert!(http.body.is_empty());
+        assert_eq!(Http::request_length(buffer), Some(buffer.len() - 5));
+    }
+
+    #[test]
+    fn test_parse_incomplete_body() {
+        let buffer = "POST /stats HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello".as_bytes();
+
+        assert!(matches!(
+            Http::parse(buffer),
+            Err(HttpError::IncompleteBody)
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid_content_length() {
+        let buffer = "POST /stats HTTP/1.1\r\nContent-Length: five\r\n\r\nhello".as_bytes();
+
+        assert!(matches!(
+            Http::parse(buffer),
+            Err(HttpError::InvalidContentLength)
+        ));
+    }
+
+    #[test]
+    fn test_parse_content_length_that_overflows() {
+        let buffer = format!(
+            "POST /stats HTTP/1.1\r\nContent-Length: {}\r\n\r\nhello",
+            usize::MAX
+        );
+
+        assert!(matches!(
+            Http::parse(buffer.as_bytes()),
+            Err(HttpError::InvalidContentLength)
+        ));
+        assert_eq!(
+            Http::request_length(buffer.as_bytes()),
+            Some(buffer.len() - 5)
+        );
+    }
+
+    #[test]
+    fn test_request_length_of_incomplete_headers() {
+        let buffer = "GET /stats.csv HTTP/1.1\r\nHost: local".as_bytes();
+
+        assert_eq!(Http::request_length(buffer), None);
+    }
+
+    #[test]
+    fn test_parse_request_without_query_params() {
+        let buffer = "GET /stats.csv HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes();
+        let http = Http::parse(buffer).unwrap();
+
+        assert_eq!(http.endpoint, "/stats.csv");
+        assert!(http.params.is_empty());
+    }
+
     #[test]
     fn test_parse_request_without_query_cannot_be_parsed() {
         let buffer =
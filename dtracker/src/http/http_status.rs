@@ -3,8 +3,10 @@ use std::str::FromStr;
 #[derive(Debug, PartialEq)]
 pub enum HttpStatus {
     Ok,
-    NotFound,
     BadRequest,
+    NotFound,
+    MethodNotAllowed,
+    InternalServerError,
 }
 
 impl FromStr for HttpStatus {
@@ -13,8 +15,10 @@ impl FromStr for HttpStatus {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "200 OK" => Ok(HttpStatus::Ok),
-            "404 NOT FOUND" => Ok(HttpStatus::NotFound),
-            "400 BAD REQUEST" => Ok(HttpStatus::BadRequest),
+            "400 Bad Request" => Ok(HttpStatus::BadRequest),
+            "404 Not Found" => Ok(HttpStatus::NotFound),
+            "405 Method Not Allowed" => Ok(HttpStatus::MethodNotAllowed),
+            "500 Internal Server Error" => Ok(HttpStatus::InternalServerError),
             _ => Err(()),
         }
     }
@@ -24,8 +28,43 @@ impl ToString for HttpStatus {
     fn to_string(&self) -> String {
         match self {
             Self::Ok => "200 OK".to_string(),
-            Self::NotFound => "404 NOT FOUND".to_string(),
-            Self::BadRequest => "400 BAD REQUEST".to_string(),
+            Self::BadRequest => "400 Bad Request".to_string(),
+            Self::NotFound => "404 Not Found".to_string(),
+            Self::MethodNotAllowed => "405 Method Not Allowed".to_string(),
+            Self::InternalServerError => "500 Internal Server Error".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_lines() {
+        assert_eq!(HttpStatus::Ok.to_string(), "200 OK");
+        assert_eq!(HttpStatus::BadRequest.to_string(), "400 Bad Request");
+        assert_eq!(HttpStatus::NotFound.to_string(), "404 Not Found");
+        assert_eq!(
+            HttpStatus::MethodNotAllowed.to_string(),
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            HttpStatus::InternalServerError.to_string(),
+            "500 Internal Server Error"
+        );
+    }
+
+    #[test]
+    fn test_status_line_round_trip() {
+        for status in [
+            HttpStatus::Ok,
+            HttpStatus::BadRequest,
+            HttpStatus::NotFound,
+            HttpStatus::MethodNotAllowed,
+            HttpStatus::InternalServerError,
+        ] {
+            assert_eq!(HttpStatus::from_str(&status.to_string()), Ok(status));
         }
     }
 }
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+/// Posible query parsing errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueryParamsError {
+    MissingValue,
+    InvalidPercentEncoding,
+}
+
+/// The percent-decoded parameters of a HTTP query string.
+///
+/// A key can be repeated, so every key keeps all of its values in the order they were received.
+/// Values are kept as bytes, as they may be binary (e.g. an `info_hash`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryParams {
+    params: HashMap<String, Vec<Vec<u8>>>,
+}
+
+impl QueryParams {
+    /// Parses a query string such as `info_hash=%aa%bb&info_hash=%cc%dd`.
+    ///
+    /// It returns an error if:
+    /// - A parameter doesn't have a value.
+    /// - A key or value has an invalid percent-encoded byte.
+    pub fn parse(query: &[u8]) -> Result<Self, QueryParamsError> {
+        let mut query_params = Self::default();
+
+        for param in query.split(|&b| b == b'&') {
+            let mut param_split = param.splitn(2, |&b| b == b'=');
+            let key = percent_decode(param_split.next().unwrap_or_default())?;
+            let value = percent_decode(param_split.next().ok_or(QueryParamsError::MissingValue)?)?;
+
+            query_params.insert(&String::from_utf8_lossy(&key), value);
+        }
+
+        Ok(query_params)
+    }
+
+    /// Adds a value for the given key, after any value it already has.
+    pub fn insert(&mut self, key: &str, value: Vec<u8>) {
+        self.params.entry(key.to_string()).or_default().push(value);
+    }
+
+    /// Returns the first value of the given key, for single-valued params.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.get_all(key).first().map(|value| value.as_slice())
+    }
+
+    /// Returns the first value of the given key as a string, for single-valued params.
+    ///
+    /// Invalid UTF-8 sequences are replaced.
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        self.get(key)
+            .map(|value| String::from_utf8_lossy(value).to_string())
+    }
+
+    /// Returns every value of the given key, in the order they were received.
+    pub fn get_all(&self, key: &str) -> &[Vec<u8>] {
+        self.params.get(key).map_or(&[], |values| values.as_slice())
+    }
+
+    /// Returns true if there are no params.
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+/// Decodes a percent-encoded string into bytes. A `+` is decoded as a space.
+fn percent_decode(encoded: &[u8]) -> Result<Vec<u8>, QueryParamsError> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.iter();
+
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'%' => {
+                let high = bytes.next().and_then(|&c| hex_value(c));
+                let low = bytes.next().and_then(|&c| hex_value(c));
+                match (high, low) {
+                    (Some(high), Some(low)) => decoded.push(high << 4 | low),
+                    _ => return Err(QueryParamsError::InvalidPercentEncoding),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            _ => decoded.push(b),
+        }
+    }
+
+    Ok(decoded)
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|digit| digit as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repeated_keys() {
+        let params = QueryParams::parse(b"info_hash=%aa%bb&info_hash=%cc%dd").unwrap();
+
+        assert_eq!(
+            params.get_all("info_hash"),
+            &[vec![0xaa, 0xbb], vec![0xcc, 0xdd]]
+        );
+        assert_eq!(params.get("info_hash"), Some([0xaa, 0xbb].as_slice()));
+    }
+
+    #[test]
+    fn test_parse_mixed_encoded_and_plain_bytes() {
+        let params = QueryParams::parse(b"peer_id=DTorrent%3a02284204893&port=6969").unwrap();
+
+        assert_eq!(
+            params.get_str("peer_id"),
+            Some("DTorrent:02284204893".to_string())
+        );
+        assert_eq!(params.get_str("port"), Some("6969".to_string()));
+    }
+
+    #[test]
+    fn test_parse_encoded_key_and_plus() {
+        let params = QueryParams::parse(b"my%20key=a+b").unwrap();
+
+        assert_eq!(params.get_str("my key"), Some("a b".to_string()));
+    }
+
+    #[test]
+    fn test_missing_key_has_no_values() {
+        let params = QueryParams::parse(b"port=6969").unwrap();
+
+        assert_eq!(params.get("info_hash"), None);
+        assert!(params.get_all("info_hash").is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_percent_encoding() {
+        assert_eq!(
+            QueryParams::parse(b"info_hash=%zz"),
+            Err(QueryParamsError::InvalidPercentEncoding)
+        );
+        assert_eq!(
+            QueryParams::parse(b"info_hash=%a"),
+            Err(QueryParamsError::InvalidPercentEncoding)
+        );
+    }
+
+    #[test]
+    fn test_parse_param_without_value() {
+        assert_eq!(
+            QueryParams::parse(b"info_hash"),
+            Err(QueryParamsError::MissingValue)
+        );
+    }
+}
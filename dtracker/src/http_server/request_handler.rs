@@ -1,21 +1,33 @@
 use std::{
-    io::{Read, Write},
-    net::TcpStream,
+    io::{self, Read, Write},
+    net::{IpAddr, TcpStream},
     sync::Arc,
 };
 
 use bencoder::bencode::Bencode;
+use logger::logger_sender::LoggerSender;
 
 use crate::{
     announce::announce_response::AnnounceResponse,
     http::{http_method::HttpMethod, http_parser::Http, http_status::HttpStatus},
+    scrape::scrape_response::ScrapeResponse,
     stats::{stats_response::StatsResponse, stats_updater::StatsUpdater},
     tracker_status::atomic_tracker_status::AtomicTrackerStatus,
 };
 
+const HEALTH_RESPONSE: &str = "OK";
+// Bencoded responses are sent as plain text, as there's no standard type for them.
+const TEXT_CONTENT_TYPE: &str = "text/plain";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const CSV_CONTENT_TYPE: &str = "text/csv";
+/// The maximum length in bytes of a request, including its body.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
 /// Struct that represents a connection capable of listening to requests and returning an answer.
 pub struct RequestHandler {
     pub stream: TcpStream,
+    logger_sender: LoggerSender,
+    trust_proxy: bool,
 }
 
 #[derive(Debug)]
@@ -28,6 +40,10 @@ pub enum RequestHandlerError {
     WritingResponseError,
     InvalidQueryParamError,
     InvalidStatsError,
+    SerializingStatsError,
+    InvalidScrapeError,
+    MethodNotAllowed,
+    ReadTimeout,
 }
 
 impl RequestHandler {
@@ -35,13 +51,25 @@ impl RequestHandler {
     ///
     /// ## Arguments
     /// * `stream`: a TcpStream responsible of reading HTTP requests and sending a response.
-    pub fn new(stream: TcpStream) -> RequestHandler {
-        RequestHandler { stream }
+    /// * `logger_sender`: To log every request handled.
+    /// * `trust_proxy`: Whether to take the peer IP from the `X-Forwarded-For` header or the `ip` param, instead of the connection.
+    pub fn new(
+        stream: TcpStream,
+        logger_sender: LoggerSender,
+        trust_proxy: bool,
+    ) -> RequestHandler {
+        RequestHandler {
+            stream,
+            logger_sender,
+            trust_proxy,
+        }
     }
 
     /// Handles a HTTP request and sends back a response in a successful scenario.
     /// Returns a RequestHandleError in the event of a request to an invalid endpoint.
     ///
+    /// Every request is logged with its method, endpoint, resulting status and peer IP, and the info hash for announces.
+    ///
     /// ## Arguments
     /// * `tracker_status`: The status of the tracker at the moment of handling the request.
     pub fn handle(
@@ -49,102 +77,221 @@ impl RequestHandler {
         tracker_status: Arc<AtomicTrackerStatus>,
         stats_updater: Arc<StatsUpdater>,
     ) -> Result<(), RequestHandlerError> {
-        // TODO: read HTTP message length correctly
-        let mut buf = [0; 1024];
-        let bytes_read = match self.stream.read(&mut buf) {
-            Ok(bytes_read) => bytes_read,
-            Err(_) => {
-                self.send_bad_request()?;
+        let request = match self.read_request() {
+            Ok(request) if !request.is_empty() => request,
+            // A client that doesn't send its request in time is dropped without an answer.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(RequestHandlerError::ReadTimeout);
+            }
+            _ => {
+                self.send_bad_request(None)?;
                 return Err(RequestHandlerError::BadRequest);
             }
         };
-        if bytes_read == 0 {
-            self.send_bad_request()?;
-            return Err(RequestHandlerError::BadRequest);
+
+        let http_request =
+            match Http::parse(&request).map_err(|_| RequestHandlerError::ParseHttpError) {
+                Ok(http_request) => http_request,
+                Err(_) => {
+                    self.send_bad_request(None)?;
+                    return Err(RequestHandlerError::BadRequest);
+                }
+            };
+
+        if !http_request.method.is_allowed() {
+            self.send_error(
+                Some(&http_request),
+                HttpStatus::MethodNotAllowed,
+                &[("Allow", HttpMethod::ALLOWED)],
+            )?;
+            return Err(RequestHandlerError::MethodNotAllowed);
         }
 
-        let http_request = match Http::parse(&buf).map_err(|_| RequestHandlerError::ParseHttpError)
-        {
-            Ok(http_request) => http_request,
-            Err(_) => {
-                self.send_bad_request()?;
-                return Err(RequestHandlerError::BadRequest);
+        let (response, content_type) = match http_request.endpoint.as_str() {
+            // Answered without touching the tracker status, so it's cheap for load balancers to poll.
+            "/health" => (HEALTH_RESPONSE.as_bytes().to_vec(), TEXT_CONTENT_TYPE),
+            // A HEAD announce only validates its params, so the peer isn't registered.
+            "/announce" if http_request.method == HttpMethod::Head => (
+                self.handle_head_announce(&http_request, tracker_status),
+                TEXT_CONTENT_TYPE,
+            ),
+            "/announce" => {
+                let peer_ip = self.get_request_ip(&http_request)?;
+                (
+                    self.handle_announce(&http_request, tracker_status, peer_ip),
+                    TEXT_CONTENT_TYPE,
+                )
             }
-        };
-
-        let (status_line, response) = if http_request.method.eq(&HttpMethod::Get) {
-            let response = match http_request.endpoint.as_str() {
-                "/announce" => {
-                    self.handle_announce(http_request, tracker_status, self.get_peer_ip()?)
+            "/scrape" => match self.handle_scrape(&http_request, tracker_status) {
+                Ok(response) => (response, TEXT_CONTENT_TYPE),
+                Err(_) => {
+                    self.send_bad_request(Some(&http_request))?;
+                    return Err(RequestHandlerError::BadRequest);
                 }
-                "/stats" => match self.handle_stats(http_request, stats_updater) {
-                    Ok(response) => response,
-                    Err(_) => {
-                        self.send_bad_request()?;
-                        return Err(RequestHandlerError::BadRequest);
-                    }
-                },
-                _ => {
-                    self.send_bad_request()?;
-                    return Err(RequestHandlerError::InvalidEndpointError);
+            },
+            "/stats" => match self.handle_stats(&http_request, stats_updater) {
+                Ok(response) => (response, JSON_CONTENT_TYPE),
+                Err(RequestHandlerError::SerializingStatsError) => {
+                    self.send_error(Some(&http_request), HttpStatus::InternalServerError, &[])?;
+                    return Err(RequestHandlerError::SerializingStatsError);
                 }
-            };
-            (HttpStatus::Ok, response)
-        } else {
-            (HttpStatus::NotFound, "".as_bytes().to_vec())
+                Err(_) => {
+                    self.send_bad_request(Some(&http_request))?;
+                    return Err(RequestHandlerError::BadRequest);
+                }
+            },
+            "/stats.csv" => (stats_updater.to_csv().into_bytes(), CSV_CONTENT_TYPE),
+            _ => {
+                self.send_error(Some(&http_request), HttpStatus::NotFound, &[])?;
+                return Err(RequestHandlerError::InvalidEndpointError);
+            }
         };
 
-        self.send_response(response, status_line)
-            .map_err(|_| RequestHandlerError::WritingResponseError)?;
+        // A HEAD request gets the same headers as a GET one, but without the body.
+        let include_body = http_request.method != HttpMethod::Head;
+        self.log_access(Some(&http_request), &HttpStatus::Ok);
+        self.send_response(
+            response,
+            HttpStatus::Ok,
+            &[("Content-Type", content_type)],
+            include_body,
+        )
+        .map_err(|_| RequestHandlerError::WritingResponseError)?;
 
         Ok(())
     }
 
-    fn send_bad_request(&mut self) -> Result<(), RequestHandlerError> {
-        self.send_response("".as_bytes().to_vec(), HttpStatus::BadRequest)
+    /// Reads a whole request from the stream: its headers and as many body bytes as its `Content-Length` header says.
+    ///
+    /// It stops early if the connection is closed, and fails if the request is longer than `MAX_REQUEST_SIZE`, or its headers say it is, without waiting for its body.
+    fn read_request(&mut self) -> io::Result<Vec<u8>> {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+
+        loop {
+            if let Some(length) = Http::request_length(&request) {
+                if length > MAX_REQUEST_SIZE {
+                    return Err(Self::request_too_long_error());
+                }
+                if request.len() >= length {
+                    request.truncate(length);
+                    return Ok(request);
+                }
+            }
+            if request.len() > MAX_REQUEST_SIZE {
+                return Err(Self::request_too_long_error());
+            }
+
+            let bytes_read = self.stream.read(&mut buf)?;
+            if bytes_read == 0 {
+                return Ok(request);
+            }
+            request.extend_from_slice(&buf[..bytes_read]);
+        }
+    }
+
+    fn request_too_long_error() -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, "the request is too long")
+    }
+
+    fn send_bad_request(&mut self, http_request: Option<&Http>) -> Result<(), RequestHandlerError> {
+        self.send_error(http_request, HttpStatus::BadRequest, &[])
+    }
+
+    /// Logs the request and answers it with the given error status and extra headers, and an empty body.
+    fn send_error(
+        &mut self,
+        http_request: Option<&Http>,
+        status: HttpStatus,
+        headers: &[(&str, &str)],
+    ) -> Result<(), RequestHandlerError> {
+        self.log_access(http_request, &status);
+        self.send_response("".as_bytes().to_vec(), status, headers, true)
             .map_err(|_| RequestHandlerError::WritingResponseError)?;
         Ok(())
     }
 
     fn handle_announce(
         &self,
-        http_request: Http,
+        http_request: &Http,
         tracker_status: Arc<AtomicTrackerStatus>,
         peer_ip: String,
     ) -> Vec<u8> {
-        let response = AnnounceResponse::from(http_request.params, tracker_status, peer_ip);
-        match response.failure_reason {
-            Some(failure) => Bencode::encode(&failure),
-            None => Bencode::encode(&response),
-        }
+        let response = AnnounceResponse::from(http_request.params.clone(), tracker_status, peer_ip);
+        Bencode::encode(&response)
+    }
+
+    fn handle_head_announce(
+        &self,
+        http_request: &Http,
+        tracker_status: Arc<AtomicTrackerStatus>,
+    ) -> Vec<u8> {
+        let response = AnnounceResponse::from_head(http_request.params.clone(), tracker_status);
+        Bencode::encode(&response)
+    }
+
+    fn handle_scrape(
+        &self,
+        http_request: &Http,
+        tracker_status: Arc<AtomicTrackerStatus>,
+    ) -> Result<Vec<u8>, RequestHandlerError> {
+        let response = ScrapeResponse::from(http_request.params.clone(), tracker_status)
+            .map_err(|_| RequestHandlerError::InvalidScrapeError)?;
+        Ok(Bencode::encode(&response))
     }
 
     fn handle_stats(
         &self,
-        http_request: Http,
+        http_request: &Http,
         stats_updater: Arc<StatsUpdater>,
     ) -> Result<Vec<u8>, RequestHandlerError> {
-        let response = StatsResponse::from(http_request.params, stats_updater)
+        let response = StatsResponse::from(http_request.params.clone(), stats_updater)
             .map_err(|_| RequestHandlerError::InvalidStatsError)?;
         Ok(serde_json::to_string(&response)
-            .map_err(|_| RequestHandlerError::InvalidStatsError)?
+            .map_err(|_| RequestHandlerError::SerializingStatsError)?
             .as_bytes()
             .to_vec())
     }
 
-    fn create_response(mut contents: Vec<u8>, status_line: HttpStatus) -> Vec<u8> {
-        let response = format!(
-            "HTTP/1.1 {}\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n",
+    /// Creates a HTTP response with the given status, extra headers and contents.
+    ///
+    /// The `Content-Length` header always reflects the contents, even if the body is left out.
+    fn create_response(
+        mut contents: Vec<u8>,
+        status_line: HttpStatus,
+        headers: &[(&str, &str)],
+        include_body: bool,
+    ) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n",
             status_line.to_string(),
             contents.len(),
         );
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("\r\n");
+
         let mut response = response.as_bytes().to_vec();
-        response.append(&mut contents);
+        if include_body {
+            response.append(&mut contents);
+        }
         response
     }
 
-    fn send_response(&mut self, contents: Vec<u8>, status_line: HttpStatus) -> std::io::Result<()> {
-        let response = Self::create_response(contents, status_line);
+    fn send_response(
+        &mut self,
+        contents: Vec<u8>,
+        status_line: HttpStatus,
+        headers: &[(&str, &str)],
+        include_body: bool,
+    ) -> std::io::Result<()> {
+        let response = Self::create_response(contents, status_line, headers, include_body);
 
         self.stream.write_all(&response)?;
         self.stream.flush()?;
@@ -152,12 +299,404 @@ impl RequestHandler {
         Ok(())
     }
 
+    /// Logs a handled request. If the request could not be parsed, its method and endpoint are logged as `-`.
+    fn log_access(&self, http_request: Option<&Http>, status: &HttpStatus) {
+        self.logger_sender.info(&Self::access_log_line(
+            http_request,
+            status,
+            self.get_peer_ip(),
+        ));
+    }
+
+    fn access_log_line(
+        http_request: Option<&Http>,
+        status: &HttpStatus,
+        peer_ip: Result<String, RequestHandlerError>,
+    ) -> String {
+        // IPv6 addresses are bracketed, so they can't be mistaken for a port.
+        let peer_ip = match peer_ip.as_deref().map(str::parse::<IpAddr>) {
+            Ok(Ok(IpAddr::V6(ip))) => format!("[{}]", ip),
+            Ok(_) => peer_ip.unwrap_or_default(),
+            Err(_) => "-".to_string(),
+        };
+        let (method, endpoint) = match http_request {
+            Some(http_request) => (
+                http_request.method.to_string(),
+                http_request.endpoint.as_str(),
+            ),
+            None => ("-".to_string(), "-"),
+        };
+
+        let mut line = format!(
+            "{} \"{} {}\" {}",
+            peer_ip,
+            method,
+            endpoint,
+            status.to_string()
+        );
+        if let Some(info_hash) = http_request
+            .filter(|http_request| http_request.endpoint == "/announce")
+            .and_then(|http_request| http_request.params.get("info_hash"))
+        {
+            let info_hash: String = info_hash.iter().map(|b| format!("{:02x}", b)).collect();
+            line.push_str(&format!(" info_hash={}", info_hash));
+        }
+        line
+    }
+
+    /// Returns the IP of the peer that made the request.
+    ///
+    /// When trusting a proxy, the first address of the `X-Forwarded-For` header or else the `ip` param is used, as long as it's a valid IP.
+    /// Otherwise, the IP of the connection is used.
+    fn get_request_ip(&self, http_request: &Http) -> Result<String, RequestHandlerError> {
+        if self.trust_proxy {
+            let forwarded_for = http_request
+                .header("X-Forwarded-For")
+                .and_then(|forwarded_for| forwarded_for.split(',').next())
+                .map(|ip| ip.trim().to_string());
+
+            if let Some(ip) = [forwarded_for, http_request.params.get_str("ip")]
+                .into_iter()
+                .flatten()
+                .find_map(|ip| ip.parse::<IpAddr>().ok())
+            {
+                return Ok(ip.to_string());
+            }
+        }
+        self.get_peer_ip()
+    }
+
+    /// Returns the IP of the connection. IPv4 clients of a dual-stack socket are seen as IPv4-mapped IPv6 addresses, so they are turned back into IPv4.
     fn get_peer_ip(&self) -> Result<String, RequestHandlerError> {
         Ok(self
             .stream
             .peer_addr()
             .map_err(|_| RequestHandlerError::GettingPeerIpError)?
             .ip()
+            .to_canonical()
             .to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::mpsc, thread, time::Duration};
+
+    use crate::{
+        stats::stats_updater::{CSV_HEADER, DEFAULT_DAYS_TO_KEEP_STATS},
+        tracker_peer::{peer::Peer, peer_status::PeerStatus},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_announce_is_logged() {
+        let request = "GET /announce?info_hash=%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7&peer_id=DTorrent%3a02284204893&port=6969&uploaded=0&downloaded=0&left=396361728&event=started HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+        let (_, logs) = handle_test_request(request.as_bytes());
+
+        let access_log = logs
+            .iter()
+            .find(|log| log.contains("/announce"))
+            .expect("no access log for the announce");
+        assert!(access_log.contains("[INFO]"));
+        assert!(access_log.contains(
+            "127.0.0.1 \"GET /announce\" 200 OK info_hash=b111813ce60f42919734823df5ec20bd1e04e7f7"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_endpoint_is_not_found() {
+        let (response, logs) = handle_test_request(b"GET /unknown HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("127.0.0.1 \"GET /unknown\" 404 Not Found")));
+    }
+
+    #[test]
+    fn test_unparseable_request_is_logged() {
+        let (_, logs) = handle_test_request(b"INVALID\r\n\r\n");
+
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("127.0.0.1 \"- -\" 400 Bad Request")));
+    }
+
+    #[test]
+    fn test_invalid_stats_query_is_a_bad_request() {
+        let (response, _) = handle_test_request(b"GET /stats?since=x HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_unsupported_method_is_not_allowed() {
+        let (response, logs) = handle_test_request(b"POST /announce HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.contains("\r\nAllow: GET, HEAD\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("127.0.0.1 \"POST /announce\" 405 Method Not Allowed")));
+    }
+
+    #[test]
+    fn test_head_request_has_no_body() {
+        let (response, _) = handle_test_request(b"HEAD /stats.csv HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let content_length = format!("\r\nContent-Length: {}\r\n", CSV_HEADER.len() + 1);
+        assert!(response.contains(&content_length));
+        assert!(response.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_head_announce_has_no_body_and_does_not_register_the_peer() {
+        let request = format!("HEAD /announce?{} HTTP/1.1\r\n\r\n", TEST_ANNOUNCE_QUERY);
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+
+        let (response, _) =
+            handle_test_request_with(request.as_bytes(), tracker_status.clone(), false);
+        let (headers, body) = split_test_response(&response);
+
+        assert!(headers.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(headers.contains("\r\nContent-Type: text/plain\r\n"));
+        assert!(headers.contains("\r\nContent-Length: "));
+        assert!(body.is_empty());
+        assert!(tracker_status.scrape(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_head_announce_does_not_rate_limit_the_next_announce() {
+        let head = format!("HEAD /announce?{} HTTP/1.1\r\n\r\n", TEST_ANNOUNCE_QUERY);
+        let get = format!("GET /announce?{} HTTP/1.1\r\n\r\n", TEST_ANNOUNCE_QUERY);
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+
+        handle_test_request_with(head.as_bytes(), tracker_status.clone(), false);
+        let (response, _) = handle_test_request_with(get.as_bytes(), tracker_status, false);
+        let (_, body) = split_test_response(&response);
+
+        assert!(!String::from_utf8_lossy(body).contains("failure reason"));
+    }
+
+    #[test]
+    fn test_get_request_has_body() {
+        let (response, _) = handle_test_request(b"GET /stats.csv HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{}\n", CSV_HEADER)));
+    }
+
+    #[test]
+    fn test_forwarded_ip_is_used_when_trusting_the_proxy() {
+        let request = format!(
+            "GET /announce?{}&ip=10.0.0.2 HTTP/1.1\r\nX-Forwarded-For: 10.0.0.1, 192.168.0.1\r\n\r\n",
+            TEST_ANNOUNCE_QUERY
+        );
+
+        let tracker_status = announce_test_request(request.as_bytes(), true);
+
+        assert_eq!(announced_peer_ip(&tracker_status), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_ip_param_is_used_when_trusting_the_proxy() {
+        let request = format!(
+            "GET /announce?{}&ip=10.0.0.2 HTTP/1.1\r\nX-Forwarded-For: invalid\r\n\r\n",
+            TEST_ANNOUNCE_QUERY
+        );
+
+        let tracker_status = announce_test_request(request.as_bytes(), true);
+
+        assert_eq!(announced_peer_ip(&tracker_status), "10.0.0.2");
+    }
+
+    #[test]
+    fn test_connection_ip_is_used_when_not_trusting_the_proxy() {
+        let request = format!(
+            "GET /announce?{}&ip=10.0.0.2 HTTP/1.1\r\nX-Forwarded-For: 10.0.0.1\r\n\r\n",
+            TEST_ANNOUNCE_QUERY
+        );
+
+        let tracker_status = announce_test_request(request.as_bytes(), false);
+
+        assert_eq!(announced_peer_ip(&tracker_status), "127.0.0.1");
+    }
+
+    #[test]
+    fn test_health_without_torrents() {
+        let (response, logs) = handle_test_request(b"GET /health HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nOK"));
+        assert!(logs
+            .iter()
+            .any(|log| log.contains("127.0.0.1 \"GET /health\" 200 OK")));
+    }
+
+    #[test]
+    fn test_request_sent_in_parts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let (sender, _receiver) = mpsc::channel();
+        let mut request_handler = RequestHandler::new(stream, LoggerSender::new(sender), false);
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let stats_updater = create_test_stats_updater(tracker_status.clone());
+
+        let handler = thread::spawn(move || request_handler.handle(tracker_status, stats_updater));
+        client.write_all(b"GET /hea").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.write_all(b"lth HTTP/1.1\r\n\r\n").unwrap();
+
+        assert!(handler.join().unwrap().is_ok());
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("\r\n\r\nOK"));
+    }
+
+    #[test]
+    fn test_request_with_a_body_too_long_is_rejected_before_reading_it() {
+        let request = format!(
+            "POST /stats HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_REQUEST_SIZE
+        );
+
+        let (response, _) = handle_test_request(request.as_bytes());
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_bencoded_response_headers() {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let leecher = Peer::new(
+            [0; 20],
+            "0".to_string(),
+            0,
+            None,
+            PeerStatus::new(0, 0, 100, None),
+        );
+        // An info hash that is not valid UTF-8, so the body is binary.
+        tracker_status.incoming_peer([0xff; 20], leecher, 50);
+
+        let (response, _) =
+            handle_test_request_with(b"GET /scrape HTTP/1.1\r\n\r\n", tracker_status, false);
+
+        let (headers, body) = split_test_response(&response);
+        assert!(headers.contains("\r\nContent-Type: text/plain\r\n"));
+        assert!(headers.contains(&format!("\r\nContent-Length: {}\r\n", body.len())));
+        assert!(body.starts_with(b"d5:filesd20:\xff"));
+    }
+
+    #[test]
+    fn test_stats_response_headers() {
+        let (response, _) = handle_test_request_with(
+            b"GET /stats?since=1 HTTP/1.1\r\n\r\n",
+            Arc::new(AtomicTrackerStatus::default()),
+            false,
+        );
+
+        let (headers, body) = split_test_response(&response);
+        assert!(headers.contains("\r\nContent-Type: application/json\r\n"));
+        assert!(headers.contains(&format!("\r\nContent-Length: {}\r\n", body.len())));
+    }
+
+    #[test]
+    fn test_scrape_without_torrents() {
+        let (response, _) = handle_test_request(b"GET /scrape HTTP/1.1\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nd5:filesdee"));
+    }
+
+    // Auxiliary functions
+
+    const TEST_ANNOUNCE_QUERY: &str = "info_hash=%b1%11%81%3c%e6%0f%42%91%97%34%82%3d%f5%ec%20%bd%1e%04%e7%f7&peer_id=DTorrent%3a02284204893&port=6969&uploaded=0&downloaded=0&left=0";
+
+    /// Handles the given request sent from a local client and returns the response and the logs.
+    fn handle_test_request(request: &[u8]) -> (String, Vec<String>) {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let (response, logs) = handle_test_request_with(request, tracker_status, false);
+        (String::from_utf8_lossy(&response).to_string(), logs)
+    }
+
+    /// Handles the given announce sent from a local client and returns the resulting tracker status.
+    fn announce_test_request(request: &[u8], trust_proxy: bool) -> Arc<AtomicTrackerStatus> {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        handle_test_request_with(request, tracker_status.clone(), trust_proxy);
+        tracker_status
+    }
+
+    /// Returns the IP of the only peer announced in the tracker, by announcing a leecher to its torrent.
+    fn announced_peer_ip(tracker_status: &AtomicTrackerStatus) -> String {
+        let info_hashes: Vec<[u8; 20]> = tracker_status.scrape(&[]).into_keys().collect();
+        assert_eq!(info_hashes.len(), 1);
+
+        let leecher = Peer::new(
+            [0xff; 20],
+            "0".to_string(),
+            0,
+            None,
+            PeerStatus::new(0, 0, 100, None),
+        );
+        let active_peers = tracker_status.incoming_peer(info_hashes[0], leecher, 50);
+        assert_eq!(active_peers.peers.len(), 1);
+        active_peers.peers[0].ip.clone()
+    }
+
+    fn handle_test_request_with(
+        request: &[u8],
+        tracker_status: Arc<AtomicTrackerStatus>,
+        trust_proxy: bool,
+    ) -> (Vec<u8>, Vec<String>) {
+        let (sender, receiver) = mpsc::channel();
+        let logger_sender = LoggerSender::new(sender);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let stats_updater = create_test_stats_updater(tracker_status.clone());
+        let _ = RequestHandler::new(stream, logger_sender, trust_proxy)
+            .handle(tracker_status, stats_updater);
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        let logs = receiver
+            .recv_timeout(Duration::from_secs(1))
+            .into_iter()
+            .chain(receiver.try_iter())
+            .collect();
+        (response, logs)
+    }
+
+    /// Splits a response into its status line and headers, and its body.
+    fn split_test_response(response: &[u8]) -> (String, &[u8]) {
+        let headers_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap()
+            + 4;
+        (
+            String::from_utf8_lossy(&response[..headers_end]).to_string(),
+            &response[headers_end..],
+        )
+    }
+
+    fn create_test_stats_updater(tracker_status: Arc<AtomicTrackerStatus>) -> Arc<StatsUpdater> {
+        let (sender, _) = mpsc::channel();
+        Arc::new(
+            StatsUpdater::new(
+                tracker_status,
+                chrono::Duration::minutes(1),
+                DEFAULT_DAYS_TO_KEEP_STATS,
+                LoggerSender::new(sender),
+            )
+            .unwrap(),
+        )
+    }
+}
@@ -1,4 +1,8 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
 
 use logger::logger_sender::LoggerSender;
 
@@ -16,44 +20,70 @@ use crate::{
 /// * `pool`: A thread pool that provides worker threads, in order to favor parallel execution.
 /// * `status`: Current status of the tracker.
 /// * `logger_sender`: To log using the Logger.
+/// * `addr`: The address the server is listening on. It can be either IPv4 or IPv6.
+/// * `read_timeout`: The time a connection has to send its request before being dropped.
+/// * `trust_proxy`: Whether to take the peer IPs from the `X-Forwarded-For` header or the `ip` param.
 pub struct Server {
     listener: TcpListener,
     pool: ThreadPool,
     status: Arc<AtomicTrackerStatus>,
     stats_updater: Arc<StatsUpdater>,
     logger_sender: LoggerSender,
-    port: u16,
+    addr: SocketAddr,
+    read_timeout: Duration,
+    trust_proxy: bool,
 }
 
 impl Server {
-    /// Creates a new `Server`.
+    /// Creates a new `Server` listening on the given address.
+    ///
+    /// To serve IPv6 clients, an IPv6 address such as `[::]:8080` must be used.
     pub fn init(
         status: Arc<AtomicTrackerStatus>,
         stats_updater: Arc<StatsUpdater>,
         logger_sender: LoggerSender,
-        port: u16,
+        addr: SocketAddr,
+        read_timeout: Duration,
+        trust_proxy: bool,
     ) -> std::io::Result<Server> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
+        let listener = TcpListener::bind(addr)?;
+        // The actual address, in case an ephemeral port was requested.
+        let addr = listener.local_addr()?;
         Ok(Server {
             listener,
             pool: ThreadPool::new(1000, logger_sender.clone()),
             status,
             logger_sender,
             stats_updater,
-            port,
+            addr,
+            read_timeout,
+            trust_proxy,
         })
     }
 
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
     /// Handles new connections to the server
     pub fn serve(&self) -> std::io::Result<()> {
-        let started_msg = format!("Serving on http://0.0.0.0:{}", self.port);
+        let started_msg = format!("Serving on http://{}", self.addr);
         self.logger_sender.info(&started_msg);
         println!("{}", started_msg);
 
         for stream in self.listener.incoming() {
             let stream = stream?;
-            let mut request_handler = RequestHandler::new(stream);
             let logger = self.logger_sender.clone();
+            // Without a timeout, a client that never sends its request would hold a worker forever.
+            if let Err(error) = stream.set_read_timeout(Some(self.read_timeout)) {
+                logger.error(&format!(
+                    "An error occurred while setting the read timeout of a connection: {:?}",
+                    error
+                ));
+                continue;
+            }
+            let mut request_handler = RequestHandler::new(stream, logger.clone(), self.trust_proxy);
             let status_clone = self.status.clone();
             let stats_updater = self.stats_updater.clone();
             let _ = self.pool.execute(move || {
@@ -68,3 +98,62 @@ impl Server {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::mpsc,
+        thread,
+    };
+
+    use crate::stats::stats_updater::DEFAULT_DAYS_TO_KEEP_STATS;
+
+    use super::*;
+
+    #[test]
+    fn test_serve_on_ipv6() {
+        let (sender, receiver) = mpsc::channel();
+        let server = create_test_server("[::1]:0".parse().unwrap(), LoggerSender::new(sender));
+        let addr = server.local_addr();
+        thread::spawn(move || server.serve());
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET /health HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        assert!(addr.is_ipv6());
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nOK"));
+        // The request is logged before being answered.
+        assert!(receiver
+            .try_iter()
+            .any(|log| log.contains("[::1] \"GET /health\" 200 OK")));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_server(addr: SocketAddr, logger_sender: LoggerSender) -> Server {
+        let status = Arc::new(AtomicTrackerStatus::default());
+        let stats_updater = Arc::new(
+            StatsUpdater::new(
+                status.clone(),
+                chrono::Duration::minutes(1),
+                DEFAULT_DAYS_TO_KEEP_STATS,
+                logger_sender.clone(),
+            )
+            .unwrap(),
+        );
+        Server::init(
+            status,
+            stats_updater,
+            logger_sender,
+            addr,
+            Duration::from_secs(1),
+            false,
+        )
+        .unwrap()
+    }
+}
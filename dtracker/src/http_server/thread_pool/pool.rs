@@ -48,6 +48,14 @@ impl ThreadPool {
         }
     }
 
+    /// Returns the number of worker threads still running.
+    pub fn live_workers(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|worker| worker.is_alive())
+            .count()
+    }
+
     /// Receives a closure and assigns it to a thread in the pool to run.
     pub fn execute<F>(&self, closure: F) -> Result<(), ThreadPoolError>
     where
@@ -89,3 +97,147 @@ impl Drop for ThreadPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::{TcpListener, TcpStream},
+        sync::mpsc::Receiver,
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        http_server::request_handler::{RequestHandler, RequestHandlerError},
+        stats::stats_updater::{StatsUpdater, DEFAULT_DAYS_TO_KEEP_STATS},
+        tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_connection_dropped_mid_request_does_not_kill_a_worker() {
+        let (logger_sender, _logs) = create_test_logger_sender();
+        let pool = ThreadPool::new(2, logger_sender.clone());
+        let handler_logger_sender = logger_sender.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let stats_updater = Arc::new(
+            StatsUpdater::new(
+                tracker_status.clone(),
+                chrono::Duration::minutes(1),
+                DEFAULT_DAYS_TO_KEEP_STATS,
+                logger_sender,
+            )
+            .unwrap(),
+        );
+
+        for request in [&b"GET /annou"[..], &b""[..]] {
+            let mut client = TcpStream::connect(address).unwrap();
+            client.write_all(request).unwrap();
+            drop(client);
+
+            let (stream, _) = listener.accept().unwrap();
+            let (done_sender, done_receiver) = channel();
+            let tracker_status = tracker_status.clone();
+            let stats_updater = stats_updater.clone();
+            let logger_sender = handler_logger_sender.clone();
+            let _ = pool.execute(move || {
+                let result = RequestHandler::new(stream, logger_sender, false)
+                    .handle(tracker_status, stats_updater);
+                done_sender.send(result.is_err()).unwrap();
+            });
+
+            assert!(done_receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        }
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(pool.live_workers(), 2);
+    }
+
+    #[test]
+    fn test_workers_run_jobs_concurrently() {
+        let (logger_sender, _logs) = create_test_logger_sender();
+        let pool = ThreadPool::new(2, logger_sender);
+        let (blocked_sender, blocked_receiver) = channel::<()>();
+        let (done_sender, done_receiver) = channel();
+
+        // The first job blocks its worker until the second one finishes.
+        let _ = pool.execute(move || {
+            let _ = blocked_receiver.recv_timeout(Duration::from_secs(5));
+        });
+        let _ = pool.execute(move || {
+            done_sender.send(()).unwrap();
+        });
+
+        assert!(done_receiver.recv_timeout(Duration::from_secs(2)).is_ok());
+        blocked_sender.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_panicking_job_does_not_kill_a_worker() {
+        let (logger_sender, logs) = create_test_logger_sender();
+        let pool = ThreadPool::new(1, logger_sender);
+        let (done_sender, done_receiver) = channel();
+
+        let _ = pool.execute(|| panic!("test panic"));
+        let _ = pool.execute(move || {
+            done_sender.send(()).unwrap();
+        });
+
+        assert!(done_receiver.recv_timeout(Duration::from_secs(2)).is_ok());
+        assert_eq!(pool.live_workers(), 1);
+        assert!(logs.try_iter().any(
+            |log| log.contains("[ERROR]") && log.contains("Worker 0 job panicked: test panic")
+        ));
+    }
+
+    #[test]
+    fn test_silent_connection_frees_the_worker_after_the_read_timeout() {
+        let (logger_sender, _logs) = create_test_logger_sender();
+        let pool = ThreadPool::new(1, logger_sender.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        let stats_updater = Arc::new(
+            StatsUpdater::new(
+                tracker_status.clone(),
+                chrono::Duration::minutes(1),
+                DEFAULT_DAYS_TO_KEEP_STATS,
+                logger_sender.clone(),
+            )
+            .unwrap(),
+        );
+        let (timeout_sender, timeout_receiver) = channel();
+        let (done_sender, done_receiver) = channel();
+
+        let _ = pool.execute(move || {
+            let result = RequestHandler::new(stream, logger_sender, false)
+                .handle(tracker_status, stats_updater);
+            timeout_sender
+                .send(matches!(result, Err(RequestHandlerError::ReadTimeout)))
+                .unwrap();
+        });
+        let _ = pool.execute(move || {
+            done_sender.send(()).unwrap();
+        });
+
+        assert!(timeout_receiver
+            .recv_timeout(Duration::from_secs(2))
+            .unwrap());
+        assert!(done_receiver.recv_timeout(Duration::from_secs(2)).is_ok());
+    }
+
+    // Auxiliary functions
+
+    fn create_test_logger_sender() -> (LoggerSender, Receiver<String>) {
+        let (sender, receiver) = channel();
+        (LoggerSender::new(sender), receiver)
+    }
+}
@@ -1,4 +1,6 @@
 use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
     sync::{mpsc::Receiver, Arc, Mutex},
     thread,
 };
@@ -19,25 +21,44 @@ pub struct Worker {
 }
 
 impl Worker {
-    /// Returns a new Worker instance that holds the `id` and a thread spawned with an empty closure.
+    /// Returns a new Worker instance that holds the `id` and a thread that runs the jobs received from the pool until it's told to terminate.
+    ///
+    /// A job that panics is logged and doesn't stop the worker from running the next ones.
     pub fn new(
         id: usize,
         receiver: Arc<Mutex<Receiver<Message>>>,
         logger_sender: LoggerSender,
     ) -> Worker {
         let thread = thread::spawn(move || loop {
-            while let Ok(message) = receiver.lock().unwrap().recv() {
-                // unwrap is safe because we are the only one using the Receiver.
-                match message {
-                    Message::NewJob(job) => {
-                        logger_sender.info(&format!("Worker {} got a job; executing.", id));
-                        job();
-                    }
-                    Message::Terminate => {
-                        logger_sender.info(&format!("Worker {} was told to terminate.", id));
-                        break;
+            // The lock is released before running the job, so the other workers can receive messages meanwhile.
+            // A poisoned lock still holds a valid receiver, so the worker keeps using it.
+            let message = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(poisoned) => poisoned.into_inner().recv(),
+            };
+
+            match message {
+                Ok(Message::NewJob(job)) => {
+                    logger_sender.info(&format!("Worker {} got a job; executing.", id));
+                    if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                        logger_sender.error(&format!(
+                            "Worker {} job panicked: {}",
+                            id,
+                            panic_message(&panic)
+                        ));
                     }
                 }
+                Ok(Message::Terminate) => {
+                    logger_sender.info(&format!("Worker {} was told to terminate.", id));
+                    break;
+                }
+                Err(_) => {
+                    logger_sender.warn(&format!(
+                        "Worker {} lost the connection with the pool; terminating.",
+                        id
+                    ));
+                    break;
+                }
             }
         });
 
@@ -46,4 +67,22 @@ impl Worker {
             thread: Some(thread),
         }
     }
+
+    /// Returns whether the worker thread is still running.
+    pub fn is_alive(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+}
+
+/// Returns the message of a panic payload, which is a `&str` or a `String` when created with `panic!`.
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
 }
@@ -2,6 +2,7 @@ pub mod announce;
 pub mod bt_tracker;
 pub mod http;
 pub mod http_server;
+pub mod scrape;
 pub mod stats;
 pub mod torrent_swarm;
 pub mod tracker_peer;
@@ -1,17 +1,17 @@
 use std::env;
 
-use dtracker::bt_tracker::tracker::BtTracker;
+use dtracker::bt_tracker::{settings::TrackerSettings, tracker::BtTracker};
+
+const USAGE: &str = "Usage: dtracker <port or address> [--trust-proxy] [--stats-days <days>] [--min-interval <seconds>] [--peer-timeout <hours>] [--max-swarm-peers <peers>] [--read-timeout <seconds>]";
 
 fn main() {
-    if env::args().count() != 2 {
-        return eprintln!("Incorrect number of arguments. Only a port number should be passed");
-    };
-    let port = match env::args().last().unwrap() {
-        s if s.parse::<u16>().is_ok() => s.parse::<u16>().unwrap(),
-        _ => return eprintln!("Invalid port number"),
+    let args: Vec<String> = env::args().skip(1).collect();
+    let settings = match TrackerSettings::from_args(&args) {
+        Ok(settings) => settings,
+        Err(error) => return eprintln!("{}\n{}", error, USAGE),
     };
 
-    match BtTracker::init(port) {
+    match BtTracker::init(settings) {
         Ok(tracker) => match tracker.run() {
             Ok(_) => (),
             Err(e) => eprintln!("Error: {:?}", e),
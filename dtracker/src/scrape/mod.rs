@@ -0,0 +1 @@
+pub mod scrape_response;
@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use bencoder::bencode::{Bencode, ToBencode};
+
+use crate::{
+    http::query_params::QueryParams, tracker_status::atomic_tracker_status::AtomicTrackerStatus,
+};
+
+type InfoHash = [u8; 20];
+
+/// Posible scrape request errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScrapeResponseError {
+    InvalidInfoHash,
+}
+
+/// Struct representing the statistics of a torrent in a scrape response.
+///
+/// ## Fields
+/// * `complete`: The number of peers with the entire file, i.e. seeders.
+/// * `downloaded`: The total number of times the tracker has registered a completion.
+/// * `incomplete`: The number of non-seeder peers, aka "leechers".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrapeFile {
+    pub complete: u32,
+    pub downloaded: u32,
+    pub incomplete: u32,
+}
+
+/// Struct representing the response of a tracker scrape request.
+///
+/// ## Fields
+/// * `files`: The statistics of each torrent requested, by info hash. Torrents unknown to the tracker are left out.
+#[derive(Debug)]
+pub struct ScrapeResponse {
+    pub files: BTreeMap<InfoHash, ScrapeFile>,
+}
+
+impl ScrapeResponse {
+    /// Creates a new `ScrapeResponse` for the torrents of every `info_hash` param. If there are none, every torrent is scraped.
+    ///
+    /// It returns an `InvalidInfoHash` error if an `info_hash` is not 20 bytes long.
+    pub fn from(
+        query_params: QueryParams,
+        tracker_status: Arc<AtomicTrackerStatus>,
+    ) -> Result<Self, ScrapeResponseError> {
+        let info_hashes = query_params
+            .get_all("info_hash")
+            .iter()
+            .map(|info_hash| {
+                info_hash
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| ScrapeResponseError::InvalidInfoHash)
+            })
+            .collect::<Result<Vec<InfoHash>, _>>()?;
+
+        Ok(Self {
+            files: tracker_status.scrape(&info_hashes),
+        })
+    }
+}
+
+impl ToBencode for ScrapeFile {
+    fn to_bencode(&self) -> Bencode {
+        let mut file = BTreeMap::new();
+        file.insert(b"complete".to_vec(), self.complete.to_bencode());
+        file.insert(b"downloaded".to_vec(), self.downloaded.to_bencode());
+        file.insert(b"incomplete".to_vec(), self.incomplete.to_bencode());
+        file.to_bencode()
+    }
+}
+
+impl ToBencode for ScrapeResponse {
+    fn to_bencode(&self) -> Bencode {
+        let mut files = BTreeMap::new();
+        for (info_hash, file) in &self.files {
+            files.insert(info_hash.to_vec(), file.to_bencode());
+        }
+
+        let mut scrape_response = BTreeMap::new();
+        scrape_response.insert(b"files".to_vec(), files.to_bencode());
+        scrape_response.to_bencode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use crate::tracker_peer::{event::PeerEvent, peer::Peer, peer_status::PeerStatus};
+
+    use super::*;
+
+    #[test]
+    fn test_scrape_single_torrent() {
+        let tracker_status = create_test_tracker_status();
+        let mut query_params = QueryParams::default();
+        query_params.insert("info_hash", vec![1; 20]);
+
+        let response = ScrapeResponse::from(query_params, tracker_status).unwrap();
+
+        assert_eq!(response.files.len(), 1);
+        assert_eq!(
+            response.files[&[1; 20]],
+            ScrapeFile {
+                complete: 1,
+                downloaded: 0,
+                incomplete: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrape_completed_downloads() {
+        let tracker_status = create_test_tracker_status();
+        let mut peer = create_test_peer([2; 20], 0);
+        peer.status.event = Some(PeerEvent::Completed);
+        tracker_status.incoming_peer([1; 20], peer, 50);
+
+        let response = ScrapeResponse::from(QueryParams::default(), tracker_status).unwrap();
+
+        assert_eq!(
+            response.files[&[1; 20]],
+            ScrapeFile {
+                complete: 2,
+                downloaded: 1,
+                incomplete: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrape_all_torrents() {
+        let tracker_status = create_test_tracker_status();
+
+        let response = ScrapeResponse::from(QueryParams::default(), tracker_status).unwrap();
+
+        assert_eq!(response.files.len(), 2);
+        assert_eq!(response.files[&[1; 20]].complete, 1);
+        assert_eq!(response.files[&[2; 20]].incomplete, 1);
+    }
+
+    #[test]
+    fn test_scrape_unknown_torrent() {
+        let tracker_status = create_test_tracker_status();
+        let mut query_params = QueryParams::default();
+        query_params.insert("info_hash", vec![3; 20]);
+
+        let response = ScrapeResponse::from(query_params, tracker_status).unwrap();
+
+        assert!(response.files.is_empty());
+    }
+
+    #[test]
+    fn test_scrape_invalid_info_hash() {
+        let mut query_params = QueryParams::default();
+        query_params.insert("info_hash", vec![1; 19]);
+
+        let response = ScrapeResponse::from(query_params, create_test_tracker_status());
+
+        assert_eq!(response.unwrap_err(), ScrapeResponseError::InvalidInfoHash);
+    }
+
+    #[test]
+    fn test_scrape_response_to_bencode() {
+        let mut files = BTreeMap::new();
+        files.insert(
+            [b'a'; 20],
+            ScrapeFile {
+                complete: 1,
+                downloaded: 2,
+                incomplete: 3,
+            },
+        );
+        let response = ScrapeResponse { files };
+
+        assert_eq!(
+            Bencode::encode(&response),
+            b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei1e10:downloadedi2e10:incompletei3eeee"
+        );
+    }
+
+    // Auxiliary functions
+
+    /// Creates a tracker status with a seeder and a leecher on torrent `[1; 20]`, and a leecher on torrent `[2; 20]`.
+    fn create_test_tracker_status() -> Arc<AtomicTrackerStatus> {
+        let tracker_status = Arc::new(AtomicTrackerStatus::default());
+        tracker_status.incoming_peer([1; 20], create_test_peer([1; 20], 0), 50);
+        tracker_status.incoming_peer([1; 20], create_test_peer([2; 20], 100), 50);
+        tracker_status.incoming_peer([2; 20], create_test_peer([3; 20], 100), 50);
+        tracker_status
+    }
+
+    fn create_test_peer(peer_id: [u8; 20], left: u64) -> Peer {
+        let peer_status = PeerStatus {
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event: None,
+            last_seen: Local::now(),
+        };
+
+        Peer::new(peer_id, "0".to_string(), 0, None, peer_status)
+    }
+}
@@ -1,8 +1,10 @@
 use super::stats_updater::StatsUpdater;
-use crate::tracker_status::current_tracker_stats::CurrentTrackerStats;
+use crate::{
+    http::query_params::QueryParams, tracker_status::current_tracker_stats::CurrentTrackerStats,
+};
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 /// Struct that represents the response of the stats request.
 ///
@@ -26,11 +28,11 @@ impl StatsResponse {
     /// ## Returns
     /// * `Result<StatsResponse, StatsResponseError>`: The response of the stats request.
     pub fn from(
-        query_params: HashMap<String, String>,
+        query_params: QueryParams,
         stats_updater: Arc<StatsUpdater>,
     ) -> Result<Self, StatsResponseError> {
         let since_in_hours = query_params
-            .get("since")
+            .get_str("since")
             .ok_or(StatsResponseError::InvalidQueryParamError)?
             .parse::<u64>()
             .map_err(|_| StatsResponseError::InvalidQueryParamError)?;
@@ -1,5 +1,6 @@
 use chrono::Duration;
 use std::sync::{Mutex, MutexGuard};
+use std::{fs, io};
 use std::{sync::Arc, thread::sleep};
 
 use logger::logger_sender::LoggerSender;
@@ -8,48 +9,72 @@ use crate::tracker_status::atomic_tracker_status::AtomicTrackerStatus;
 use crate::tracker_status::current_tracker_stats::CurrentTrackerStats;
 
 // for 1 month it takes 0.5 miliseconds to update the stats. And 0.5 Megabytes to store the stats.
-const MAX_DAYS_TO_KEEP_STATS: u64 = 30;
+pub const DEFAULT_DAYS_TO_KEEP_STATS: u64 = 30;
+
+const SECONDS_IN_A_DAY: u64 = 24 * 60 * 60;
+
+/// Header of the CSV export of the stats history.
+pub const CSV_HEADER: &str = "timestamp,torrents,seeders,leechers";
+
+/// Posible `StatsUpdater` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StatsUpdaterError {
+    InvalidDuration,
+    RetentionTooLong,
+}
 
 /// Struct that represents the current status of the stats.
 #[derive(Debug)]
 pub struct StatsUpdater {
     stats_history: Mutex<Vec<CurrentTrackerStats>>,
     duration: chrono::Duration,
+    max_history_len: usize,
     tracker_status: Arc<AtomicTrackerStatus>,
     logger_sender: Mutex<LoggerSender>,
 }
 
 impl StatsUpdater {
-    /// Creates a new `StatsUpdater`.
+    /// Creates a new `StatsUpdater` that updates the stats every `timeout` and keeps them for `days_to_keep_stats` days.
+    ///
+    /// The history keeps one entry per update in the retention window, and at least the last one.
+    ///
+    /// It returns a `StatsUpdaterError` if:
+    /// - The `timeout` is not at least one second.
+    /// - The retention window in seconds overflows.
     pub fn new(
         tracker_status: Arc<AtomicTrackerStatus>,
         timeout: Duration,
+        days_to_keep_stats: u64,
         logger_sender: LoggerSender,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, StatsUpdaterError> {
+        let timeout_secs = timeout.num_seconds();
+        if timeout_secs <= 0 {
+            return Err(StatsUpdaterError::InvalidDuration);
+        }
+
+        let retention_secs = days_to_keep_stats
+            .checked_mul(SECONDS_IN_A_DAY)
+            .and_then(|secs| i64::try_from(secs).ok())
+            .ok_or(StatsUpdaterError::RetentionTooLong)?;
+        let max_history_len = usize::try_from(retention_secs / timeout_secs)
+            .map_err(|_| StatsUpdaterError::RetentionTooLong)?
+            .max(1);
+
+        Ok(Self {
             duration: timeout,
+            max_history_len,
             tracker_status,
             stats_history: Mutex::new(Vec::new()),
             logger_sender: Mutex::new(logger_sender),
-        }
+        })
     }
 
     /// Starts the loop that updates the stats every `duration` seconds and saves them in the history.
     pub fn run(&self) {
         loop {
             self.tracker_status.remove_inactive_peers();
-            let mut stats_history = self.lock_stats_history();
-
-            // If we reached the maximum number of days to keep stats, remove the oldest one.
-            let max_secs_to_keep_stats = MAX_DAYS_TO_KEEP_STATS * 24 * 60 * 60;
-            if self.duration.num_seconds() * stats_history.len() as i64
-                > max_secs_to_keep_stats as i64
-            {
-                stats_history.rotate_left(1);
-                stats_history.pop();
-            }
-
-            stats_history.push(self.tracker_status.get_global_statistics());
+            self.push_stats(self.tracker_status.get_global_statistics());
+
             let logger = self.lock_logger_sender();
             logger.info("Stats updated");
             let std_duration = match self.duration.to_std() {
@@ -60,11 +85,45 @@ impl StatsUpdater {
                 }
             };
             // Drop lock before sleeping.
-            drop(stats_history);
+            drop(logger);
             sleep(std_duration);
         }
     }
 
+    /// Saves stats in the history, removing the oldest ones once the retention window is full.
+    fn push_stats(&self, stats: CurrentTrackerStats) {
+        let mut stats_history = self.lock_stats_history();
+
+        if stats_history.len() >= self.max_history_len {
+            let excess = stats_history.len() + 1 - self.max_history_len;
+            stats_history.drain(..excess);
+        }
+
+        stats_history.push(stats);
+    }
+
+    /// Returns the whole history of the stats as CSV, with a `timestamp,torrents,seeders,leechers` header and one row per update from oldest to newest.
+    ///
+    /// The timestamp is the Unix timestamp in seconds of the update.
+    pub fn to_csv(&self) -> String {
+        let stats_history = self.lock_stats_history();
+
+        let mut csv = String::from(CSV_HEADER);
+        csv.push('\n');
+        for stats in stats_history.iter() {
+            csv.push_str(&stats.to_csv_row());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Writes the whole history of the stats as CSV to the given path.
+    ///
+    /// It returns an error if the file could not be written.
+    pub fn export_csv(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+
     /// Gets the history of the stats since a given time. If the is less than `since` histories, all the histories are returned.
     ///
     /// ## Returns
@@ -95,3 +154,161 @@ impl StatsUpdater {
         self.logger_sender.lock().unwrap() // unwrap is safe because we are the only one who use the logger_sender
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_export_csv() {
+        let path = "./test_export_csv.csv";
+        let stats_updater = create_test_stats_updater(Duration::minutes(1));
+        let history = vec![
+            create_test_stats(1, 2, 3, 1000),
+            create_test_stats(4, 5, 6, 1060),
+        ];
+        for stats in &history {
+            stats_updater.push_stats(*stats);
+        }
+
+        stats_updater.export_csv(path).unwrap();
+
+        let csv = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(parse_csv(&csv), vec![(1000, 1, 2, 3), (1060, 4, 5, 6)]);
+    }
+
+    #[test]
+    fn test_export_csv_without_history() {
+        let stats_updater = create_test_stats_updater(Duration::minutes(1));
+
+        assert_eq!(stats_updater.to_csv(), format!("{}\n", CSV_HEADER));
+    }
+
+    #[test]
+    fn test_export_csv_after_the_oldest_stats_are_dropped() {
+        // One update per day, so the history is full after `DEFAULT_DAYS_TO_KEEP_STATS` updates.
+        let stats_updater = create_test_stats_updater(Duration::days(1));
+        let updates = DEFAULT_DAYS_TO_KEEP_STATS as i64 + 5;
+        for day in 0..updates {
+            stats_updater.push_stats(create_test_stats(day as u32, 0, 0, day));
+        }
+
+        let rows = parse_csv(&stats_updater.to_csv());
+
+        assert_eq!(rows.len(), DEFAULT_DAYS_TO_KEEP_STATS as usize);
+        assert_eq!(rows.first().unwrap().0, 5);
+        assert_eq!(rows.last().unwrap().0, updates - 1);
+    }
+
+    #[test]
+    fn test_short_retention_evicts_old_stats() {
+        // A day of retention with an update every 6 hours keeps 4 entries.
+        let stats_updater =
+            create_test_stats_updater_with_retention(Duration::hours(6), 1).unwrap();
+        for update in 0..10 {
+            stats_updater.push_stats(create_test_stats(0, 0, 0, update));
+        }
+
+        let history = stats_updater.get_history(Duration::days(365));
+
+        let timestamps: Vec<i64> = history.iter().map(|stats| stats.timestamp).collect();
+        assert_eq!(timestamps, vec![6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_long_retention_keeps_old_stats() {
+        let stats_updater =
+            create_test_stats_updater_with_retention(Duration::minutes(1), 365).unwrap();
+        let updates = 2 * DEFAULT_DAYS_TO_KEEP_STATS as i64 * 24 * 60;
+        for update in 0..updates {
+            stats_updater.push_stats(create_test_stats(0, 0, 0, update));
+        }
+
+        let history = stats_updater.get_history(Duration::days(365));
+
+        assert_eq!(history.len(), updates as usize);
+        assert_eq!(history[0].timestamp, 0);
+    }
+
+    #[test]
+    fn test_retention_shorter_than_the_duration_keeps_the_last_stats() {
+        let stats_updater = create_test_stats_updater_with_retention(Duration::days(7), 1).unwrap();
+        stats_updater.push_stats(create_test_stats(0, 0, 0, 1));
+        stats_updater.push_stats(create_test_stats(0, 0, 0, 2));
+
+        let history = stats_updater.get_history(Duration::days(365));
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].timestamp, 2);
+    }
+
+    #[test]
+    fn test_retention_overflow() {
+        assert_eq!(
+            create_test_stats_updater_with_retention(Duration::minutes(1), u64::MAX).unwrap_err(),
+            StatsUpdaterError::RetentionTooLong
+        );
+    }
+
+    #[test]
+    fn test_invalid_duration() {
+        assert_eq!(
+            create_test_stats_updater_with_retention(Duration::zero(), 1).unwrap_err(),
+            StatsUpdaterError::InvalidDuration
+        );
+    }
+
+    // Auxiliary functions
+
+    fn create_test_stats_updater(duration: Duration) -> StatsUpdater {
+        create_test_stats_updater_with_retention(duration, DEFAULT_DAYS_TO_KEEP_STATS).unwrap()
+    }
+
+    fn create_test_stats_updater_with_retention(
+        duration: Duration,
+        days_to_keep_stats: u64,
+    ) -> Result<StatsUpdater, StatsUpdaterError> {
+        let (sender, _) = mpsc::channel();
+        StatsUpdater::new(
+            Arc::new(AtomicTrackerStatus::default()),
+            duration,
+            days_to_keep_stats,
+            LoggerSender::new(sender),
+        )
+    }
+
+    fn create_test_stats(
+        torrents: u32,
+        seeders: u32,
+        leechers: u32,
+        timestamp: i64,
+    ) -> CurrentTrackerStats {
+        CurrentTrackerStats {
+            torrents,
+            seeders,
+            leechers,
+            timestamp,
+        }
+    }
+
+    fn parse_csv(csv: &str) -> Vec<(i64, u32, u32, u32)> {
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+
+        lines
+            .map(|line| {
+                let columns: Vec<&str> = line.split(',').collect();
+                assert_eq!(columns.len(), 4);
+                (
+                    columns[0].parse().unwrap(),
+                    columns[1].parse().unwrap(),
+                    columns[2].parse().unwrap(),
+                    columns[3].parse().unwrap(),
+                )
+            })
+            .collect()
+    }
+}
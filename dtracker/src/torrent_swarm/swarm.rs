@@ -1,24 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use chrono::{Duration, Local};
+use chrono::{DateTime, Duration, Local};
 use rand::{seq::IteratorRandom, thread_rng};
 
-use crate::tracker_peer::peer::Peer;
+use crate::tracker_peer::{event::PeerEvent, peer::Peer};
 
 type PeerId = [u8; 20];
 
 /// Struct that represents the status of a torrent.
 ///
 /// ## Fields
+/// * `peers_by_last_seen`: The peers of the swarm ordered by the last time they were seen, so the least recently seen ones are found without going through every peer.
 /// * `peer_timeout`: The time after which a peer is considered as inactive.
+/// * `max_peers`: The maximum amount of peers kept in the swarm. When a new peer arrives to a full swarm, the least recently seen peer is evicted.
 /// * `seeders`: The current amount of seeders of the torrent.
 /// * `leechers`: The current amount of leechers of the torrent.
+/// * `completed`: The amount of times the torrent has been completed.
+/// * `completed_peers`: The peers in the swarm that have announced completing the torrent, so each one is counted once while it stays in the swarm.
 #[derive(Debug, Clone)]
 pub struct Swarm {
     peers: HashMap<PeerId, Peer>,
+    peers_by_last_seen: BTreeSet<(DateTime<Local>, PeerId)>,
     peer_timeout: Duration,
+    max_peers: usize,
     seeders: u32,
     leechers: u32,
+    completed: u32,
+    completed_peers: HashSet<PeerId>,
 }
 
 /// Struct that represents the response to an active peers request.
@@ -39,25 +47,62 @@ impl Swarm {
     ///
     /// ## Arguments
     /// * `peer_timeout`: The timeout for a peer to be considered inactive.
-    pub fn new(peer_timeout: Duration) -> Self {
+    /// * `max_peers`: The maximum amount of peers kept in the swarm.
+    pub fn new(peer_timeout: Duration, max_peers: usize) -> Self {
         Self {
             peers: HashMap::new(),
+            peers_by_last_seen: BTreeSet::new(),
             peer_timeout,
+            max_peers,
             seeders: 0,
             leechers: 0,
+            completed: 0,
+            completed_peers: HashSet::new(),
         }
     }
 
+    /// Adds or updates a peer in the swarm.
+    ///
+    /// Peers are identified by id, so a re-announce updates the stored IP and port. If the stored peer sent a `key`, an announce from another IP must send the same one, otherwise it's ignored so a peer can't be impersonated.
+    ///
+    /// If the peer announces the `completed` event, it is counted as a completed download, unless it was already counted.
+    ///
+    /// If the swarm can't keep any peer, the announce is ignored.
     pub fn announce(&mut self, incoming_peer: Peer) {
+        if self.max_peers == 0 {
+            return;
+        }
+
+        if let Some(old_peer) = self.peers.get(&incoming_peer.id) {
+            if old_peer.ip != incoming_peer.ip
+                && old_peer.key.is_some()
+                && old_peer.key != incoming_peer.key
+            {
+                return;
+            }
+        }
+
+        if incoming_peer.status.event == Some(PeerEvent::Completed)
+            && self.completed_peers.insert(incoming_peer.id)
+        {
+            self.completed += 1;
+        }
+
+        if !self.peers.contains_key(&incoming_peer.id) && self.peers.len() >= self.max_peers {
+            self.evict_least_recently_seen_peer();
+        }
+
+        self.peers_by_last_seen
+            .insert((incoming_peer.get_last_seen(), incoming_peer.id));
         let old_peer = self.peers.insert(incoming_peer.id, incoming_peer.clone());
         // If the peer was already in the swarm, we update it accordingly.
 
         if let Some(old_peer) = old_peer {
-            if old_peer.is_leecher() {
-                self.leechers -= 1;
-            } else {
-                self.seeders -= 1;
+            if old_peer.get_last_seen() != incoming_peer.get_last_seen() {
+                self.peers_by_last_seen
+                    .remove(&(old_peer.get_last_seen(), old_peer.id));
             }
+            self.uncount_peer(&old_peer);
         };
 
         if incoming_peer.is_leecher() {
@@ -70,8 +115,18 @@ impl Swarm {
     ///
     /// ## Arguments
     /// * `wanted_peers`: The amount of active peers to include in the vector, unless the swarm does not contain as many active peers, in which case it equals the number of elements available.
-    pub fn get_active_peers(&self, wanted_peers: u32) -> ActivePeers {
-        let peers = self.peers.values().cloned();
+    /// * `requester`: The id of the peer asking for peers, which is left out of the vector *(Optional)*. If it's a seeder, only leechers are included, as it has nothing to download from other seeders.
+    pub fn get_active_peers(&self, wanted_peers: u32, requester: Option<PeerId>) -> ActivePeers {
+        let requester_is_seeder = requester
+            .and_then(|id| self.peers.get(&id))
+            .is_some_and(|peer| peer.is_seeder());
+
+        let peers = self
+            .peers
+            .values()
+            .filter(|peer| Some(peer.id) != requester)
+            .filter(|peer| !requester_is_seeder || peer.is_leecher())
+            .cloned();
 
         let mut rng = thread_rng();
         let active_peers = peers
@@ -90,20 +145,286 @@ impl Swarm {
         (self.seeders, self.leechers)
     }
 
+    /// Returns the amount of times the torrent has been completed.
+    pub fn get_completed(&self) -> u32 {
+        self.completed
+    }
+
     /// Removes any inactive peers from the swarm.
     pub fn remove_inactive_peers(&mut self) {
-        self.peers.retain(|_, peer| {
-            let last_seen = peer.get_last_seen();
-            if Local::now().signed_duration_since(last_seen) > self.peer_timeout {
-                if peer.is_leecher() {
-                    self.leechers -= 1;
-                } else {
-                    self.seeders -= 1;
-                }
-                false
-            } else {
-                true
-            }
-        });
+        let now = Local::now();
+        let inactive_peers: Vec<PeerId> = self
+            .peers_by_last_seen
+            .iter()
+            .take_while(|(last_seen, _)| now.signed_duration_since(*last_seen) > self.peer_timeout)
+            .map(|(_, id)| *id)
+            .collect();
+
+        for id in inactive_peers {
+            self.remove_peer(&id);
+        }
+    }
+
+    fn evict_least_recently_seen_peer(&mut self) {
+        if let Some((_, id)) = self.peers_by_last_seen.first().copied() {
+            self.remove_peer(&id);
+        }
+    }
+
+    /// Removes a peer from the swarm. The completed downloads it was counted in are kept.
+    fn remove_peer(&mut self, id: &PeerId) {
+        if let Some(peer) = self.peers.remove(id) {
+            self.peers_by_last_seen
+                .remove(&(peer.get_last_seen(), peer.id));
+            self.uncount_peer(&peer);
+        }
+        self.completed_peers.remove(id);
+    }
+
+    fn uncount_peer(&mut self, peer: &Peer) {
+        if peer.is_leecher() {
+            self.leechers -= 1;
+        } else {
+            self.seeders -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tracker_peer::peer_status::PeerStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_completed_announce_is_counted() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Started)));
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Completed)));
+
+        assert_eq!(swarm.get_completed(), 1);
+    }
+
+    #[test]
+    fn test_completed_announce_is_not_counted_twice() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Completed)));
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Completed)));
+        swarm.announce(create_test_peer([1; 20], Some(PeerEvent::Completed)));
+
+        assert_eq!(swarm.get_completed(), 2);
+    }
+
+    #[test]
+    fn test_completed_count_is_kept_after_the_peer_leaves() {
+        let mut swarm = Swarm::new(Duration::zero(), TEST_MAX_PEERS);
+
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Completed)));
+        swarm.remove_inactive_peers();
+
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (0, 0));
+        assert_eq!(swarm.get_completed(), 1);
+    }
+
+    #[test]
+    fn test_completed_peers_are_forgotten_when_they_leave() {
+        let mut swarm = Swarm::new(Duration::zero(), TEST_MAX_PEERS);
+        for id in 0..10 {
+            swarm.announce(create_test_peer([id; 20], Some(PeerEvent::Completed)));
+        }
+
+        swarm.remove_inactive_peers();
+
+        assert!(swarm.completed_peers.is_empty());
+        assert_eq!(swarm.get_completed(), 10);
+    }
+
+    #[test]
+    fn test_evicted_completed_peer_is_forgotten() {
+        let mut swarm = Swarm::new(Duration::hours(1), 1);
+        let mut peer = create_test_peer([0; 20], Some(PeerEvent::Completed));
+        peer.status.last_seen = Local::now() - Duration::minutes(10);
+        swarm.announce(peer);
+
+        swarm.announce(create_test_peer([1; 20], None));
+
+        assert!(!swarm.completed_peers.contains(&[0; 20]));
+        assert_eq!(swarm.get_completed(), 1);
+    }
+
+    #[test]
+    fn test_get_active_peers_respects_wanted_peers() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        for id in 0..10 {
+            swarm.announce(create_test_peer([id; 20], None));
+        }
+
+        assert_eq!(swarm.get_active_peers(5, None).peers.len(), 5);
+        assert_eq!(swarm.get_active_peers(50, None).peers.len(), 10);
+    }
+
+    #[test]
+    fn test_get_active_peers_leaves_out_the_requester() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        for id in 0..3 {
+            swarm.announce(create_test_peer([id; 20], None));
+        }
+
+        let active_peers = swarm.get_active_peers(50, Some([0; 20]));
+
+        assert_eq!(active_peers.peers.len(), 2);
+        assert!(active_peers.peers.iter().all(|peer| peer.id != [0; 20]));
+    }
+
+    #[test]
+    fn test_seeder_only_gets_leechers() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Completed)));
+        swarm.announce(create_test_peer([1; 20], Some(PeerEvent::Completed)));
+        swarm.announce(create_test_peer([2; 20], None));
+
+        let active_peers = swarm.get_active_peers(50, Some([0; 20]));
+
+        assert_eq!(active_peers.peers.len(), 1);
+        assert_eq!(active_peers.peers[0].id, [2; 20]);
+    }
+
+    #[test]
+    fn test_reannounces_replace_the_peer() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        let first_seen = Local::now() - Duration::minutes(30);
+        let mut peer = create_test_peer([0; 20], None);
+        peer.status.last_seen = first_seen;
+        swarm.announce(peer);
+
+        for _ in 0..100 {
+            swarm.announce(create_test_peer([0; 20], None));
+        }
+
+        let active_peers = swarm.get_active_peers(200, None);
+        assert_eq!(active_peers.peers.len(), 1);
+        assert_eq!((active_peers.seeders, active_peers.leechers), (0, 1));
+        assert!(active_peers.peers[0].get_last_seen() > first_seen);
+    }
+
+    #[test]
+    fn test_reannounce_from_a_new_ip_updates_the_peer() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        swarm.announce(create_test_peer_with_key("10.0.0.1", Some("key")));
+
+        swarm.announce(create_test_peer_with_key("10.0.0.2", Some("key")));
+
+        let active_peers = swarm.get_active_peers(50, None);
+        assert_eq!(active_peers.peers.len(), 1);
+        assert_eq!(active_peers.leechers, 1);
+        assert_eq!(active_peers.peers[0].ip, "10.0.0.2");
+        assert_eq!(active_peers.peers[0].port, 6882);
+    }
+
+    #[test]
+    fn test_reannounce_from_a_new_ip_with_another_key_is_ignored() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        swarm.announce(create_test_peer_with_key("10.0.0.1", Some("key")));
+
+        swarm.announce(create_test_peer_with_key("10.0.0.2", Some("another key")));
+        swarm.announce(create_test_peer_with_key("10.0.0.2", None));
+
+        let active_peers = swarm.get_active_peers(50, None);
+        assert_eq!(active_peers.peers.len(), 1);
+        assert_eq!(active_peers.peers[0].ip, "10.0.0.1");
+    }
+
+    #[test]
+    fn test_new_peer_in_a_full_swarm_evicts_the_least_recently_seen() {
+        let mut swarm = Swarm::new(Duration::hours(1), 3);
+        let now = Local::now();
+        for (id, minutes_ago) in [(0, 10), (1, 30), (2, 20)] {
+            let mut peer = create_test_peer([id; 20], None);
+            peer.status.last_seen = now - Duration::minutes(minutes_ago);
+            swarm.announce(peer);
+        }
+
+        swarm.announce(create_test_peer([3; 20], Some(PeerEvent::Completed)));
+
+        let active_peers = swarm.get_active_peers(50, None);
+        let mut ids: Vec<PeerId> = active_peers.peers.iter().map(|peer| peer.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![[0; 20], [2; 20], [3; 20]]);
+        assert_eq!((active_peers.seeders, active_peers.leechers), (1, 2));
+    }
+
+    #[test]
+    fn test_swarm_without_room_ignores_announces() {
+        let mut swarm = Swarm::new(Duration::hours(1), 0);
+
+        swarm.announce(create_test_peer([0; 20], Some(PeerEvent::Completed)));
+
+        assert!(swarm.get_active_peers(50, None).peers.is_empty());
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (0, 0));
+        assert_eq!(swarm.get_completed(), 0);
+    }
+
+    #[test]
+    fn test_reannounce_keeps_one_entry_by_last_seen() {
+        let mut swarm = Swarm::new(Duration::hours(1), TEST_MAX_PEERS);
+        let mut peer = create_test_peer([0; 20], None);
+        peer.status.last_seen = Local::now() - Duration::minutes(30);
+        swarm.announce(peer);
+
+        swarm.announce(create_test_peer([0; 20], None));
+
+        assert_eq!(swarm.peers_by_last_seen.len(), 1);
+        assert_eq!(
+            swarm.peers_by_last_seen.first().unwrap().0,
+            swarm.peers[&[0; 20]].get_last_seen()
+        );
+    }
+
+    #[test]
+    fn test_reannounce_in_a_full_swarm_does_not_evict() {
+        let mut swarm = Swarm::new(Duration::hours(1), 2);
+        swarm.announce(create_test_peer([0; 20], None));
+        swarm.announce(create_test_peer([1; 20], None));
+
+        swarm.announce(create_test_peer([1; 20], Some(PeerEvent::Completed)));
+
+        assert_eq!(swarm.get_active_peers(50, None).peers.len(), 2);
+        assert_eq!(swarm.get_current_seeders_and_leechers(), (1, 1));
+    }
+
+    // Auxiliary functions
+
+    const TEST_MAX_PEERS: usize = 1000;
+
+    fn create_test_peer_with_key(ip: &str, key: Option<&str>) -> Peer {
+        let port = if ip == "10.0.0.1" { 6881 } else { 6882 };
+        let peer_status = PeerStatus::new(0, 0, 100, None);
+
+        Peer::new(
+            [0; 20],
+            ip.to_string(),
+            port,
+            key.map(|key| key.to_string()),
+            peer_status,
+        )
+    }
+
+    fn create_test_peer(peer_id: [u8; 20], event: Option<PeerEvent>) -> Peer {
+        let left = if event == Some(PeerEvent::Completed) {
+            0
+        } else {
+            100
+        };
+        let peer_status = PeerStatus {
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event,
+            last_seen: Local::now() - Duration::seconds(1),
+        };
+
+        Peer::new(peer_id, "0".to_string(), 0, None, peer_status)
     }
 }
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::IpAddr};
 
 use bencoder::bencode::ToBencode;
 use chrono::{DateTime, Local};
@@ -42,12 +42,11 @@ impl Peer {
     }
 
     /// Creates a new peer from an AnnounceRequest.
+    ///
+    /// The `ip` param of the request is not used directly, as it can only be trusted behind a proxy, so `peer_ip` is expected to already take it into account.
     pub fn from_request(request: AnnounceRequest, peer_ip: String) -> Self {
         let id = request.peer_id;
-        let ip = match request.ip {
-            Some(ip) => ip,
-            None => peer_ip,
-        };
+        let ip = peer_ip;
         let port = request.port;
         let key = request.key;
 
@@ -74,12 +73,24 @@ impl Peer {
     pub fn is_seeder(&self) -> bool {
         self.status.left == 0 || self.status.event == Some(PeerEvent::Completed)
     }
+
+    /// Returns the peer in compact format: its IP address followed by its port, in network (big endian) notation.
+    ///
+    /// It's 6 bytes long for IPv4 peers and 18 bytes long for IPv6 peers, or `None` if the ip is not an IP address.
+    pub fn to_compact(&self) -> Option<Vec<u8>> {
+        let mut compact = match self.ip.parse::<IpAddr>().ok()? {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        compact.extend_from_slice(&self.port.to_be_bytes());
+        Some(compact)
+    }
 }
 
 impl ToBencode for Peer {
     fn to_bencode(&self) -> bencoder::bencode::Bencode {
         let mut peer = BTreeMap::new();
-        peer.insert(b"peer_id".to_vec(), self.id.to_vec().to_bencode());
+        peer.insert(b"peer id".to_vec(), self.id.to_vec().to_bencode());
         peer.insert(b"ip".to_vec(), self.ip.to_bencode());
         peer.insert(b"port".to_vec(), self.port.to_bencode());
         peer.to_bencode()
@@ -1,39 +1,100 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     sync::{Mutex, MutexGuard},
 };
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Local};
 
 use crate::{
+    scrape::scrape_response::ScrapeFile,
     torrent_swarm::swarm::{ActivePeers, Swarm},
     tracker_peer::peer::Peer,
 };
 
 use super::current_tracker_stats::CurrentTrackerStats;
 
-const PEER_HOURS_TIMEOUT: i64 = 1;
+pub const DEFAULT_PEER_HOURS_TIMEOUT: i64 = 1;
+pub const DEFAULT_MAX_SWARM_PEERS: usize = 10000;
+pub const DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS: i64 = 30;
 type InfoHash = [u8; 20];
 
 /// Struct that represents the current status of the tracker.
 ///
 /// ## Fields
 /// * `torrents`: The current torrents supported by the tracker. The key is the torrent `Info Hash`. The value is the `Torrent Status`.
+/// * `peer_timeout`: The time after which a peer that didn't announce is removed from its swarm.
+/// * `max_swarm_peers`: The maximum amount of peers kept for each torrent.
+/// * `last_announces`: The time of the last accepted announce for each source IP and torrent `Info Hash`.
+/// * `min_announce_interval`: The minimum time a source IP must wait between announces for the same torrent.
 #[derive(Debug)]
 pub struct AtomicTrackerStatus {
     torrent_swarms: Mutex<HashMap<InfoHash, Swarm>>,
+    peer_timeout: Duration,
+    max_swarm_peers: usize,
+    last_announces: Mutex<HashMap<(String, InfoHash), DateTime<Local>>>,
+    min_announce_interval: Duration,
 }
 
 impl Default for AtomicTrackerStatus {
     /// Creates a new tracker status.
     fn default() -> Self {
+        Self::new(
+            Duration::hours(DEFAULT_PEER_HOURS_TIMEOUT),
+            Duration::seconds(DEFAULT_MIN_ANNOUNCE_INTERVAL_SECONDS),
+            DEFAULT_MAX_SWARM_PEERS,
+        )
+    }
+}
+
+impl AtomicTrackerStatus {
+    /// Creates a new tracker status.
+    ///
+    /// ## Arguments
+    /// * `peer_timeout`: The time after which a peer that didn't announce is removed from its swarm.
+    /// * `min_announce_interval`: The minimum time a source IP must wait between announces for the same torrent.
+    /// * `max_swarm_peers`: The maximum amount of peers kept for each torrent.
+    pub fn new(
+        peer_timeout: Duration,
+        min_announce_interval: Duration,
+        max_swarm_peers: usize,
+    ) -> Self {
         AtomicTrackerStatus {
             torrent_swarms: Mutex::new(HashMap::new()),
+            peer_timeout,
+            max_swarm_peers,
+            last_announces: Mutex::new(HashMap::new()),
+            min_announce_interval,
+        }
+    }
+
+    /// Returns the minimum time a source IP must wait between announces for the same torrent.
+    pub fn min_announce_interval(&self) -> Duration {
+        self.min_announce_interval
+    }
+
+    /// Checks if an announce from the given source IP for a torrent respects the minimum announce interval.
+    ///
+    /// If it does, the announce time is recorded.
+    ///
+    /// ## Arguments
+    /// * `ip`: The IP the announce comes from.
+    /// * `info_hash`: The info hash of the torrent.
+    ///
+    /// ## Returns
+    /// * `bool`: `true` if the announce is allowed, `false` if it comes too soon after the previous one.
+    pub fn allow_announce(&self, ip: &str, info_hash: InfoHash) -> bool {
+        let now = Local::now();
+        let mut last_announces = self.lock_last_announces();
+
+        match last_announces.get(&(ip.to_string(), info_hash)) {
+            Some(last) if now.signed_duration_since(*last) < self.min_announce_interval => false,
+            _ => {
+                last_announces.insert((ip.to_string(), info_hash), now);
+                true
+            }
         }
     }
-}
 
-impl AtomicTrackerStatus {
     /// Adds or updates a peer for a torrent in the tracker status and returns an `ActivePeers` struct.
     ///
     /// ## Arguments
@@ -42,31 +103,37 @@ impl AtomicTrackerStatus {
     /// * `numwant`: The number of peers wanted by the client.
     ///
     /// ## Returns
-    /// * `ActivePeers`: Struct containing the peers of the torrent requested, the number of seeders and leechers.
+    /// * `ActivePeers`: Struct containing the peers of the torrent requested, without the incoming one, the number of seeders and leechers.
     pub fn incoming_peer(&self, info_hash: InfoHash, peer: Peer, wanted_peers: u32) -> ActivePeers {
         let mut swarms = self.lock_swarms();
         let torrent_swarm = swarms
             .entry(info_hash)
-            .or_insert_with(|| Swarm::new(Duration::hours(PEER_HOURS_TIMEOUT)));
+            .or_insert_with(|| Swarm::new(self.peer_timeout, self.max_swarm_peers));
 
+        let peer_id = peer.id;
         torrent_swarm.announce(peer);
 
-        torrent_swarm.get_active_peers(wanted_peers)
+        torrent_swarm.get_active_peers(wanted_peers, Some(peer_id))
     }
 
     /// Gets the current statistics of the tracker.
     ///
+    /// Only torrents with at least one peer are counted, so once `remove_inactive_peers` is called the torrents left without peers aren't part of the totals.
+    ///
     /// ## Returns
     /// * `CurrentTrackerStats`: Struct containing the total number of torrents, seeders and leechers.
     pub fn get_global_statistics(&self) -> CurrentTrackerStats {
         let swarms = self.lock_swarms();
 
-        let total_torrents = swarms.len() as u32;
+        let mut total_torrents = 0;
         let mut global_seeders = 0;
         let mut global_leechers = 0;
 
         for swarm in swarms.values() {
             let (seeders, leechers) = swarm.get_current_seeders_and_leechers();
+            if seeders + leechers > 0 {
+                total_torrents += 1;
+            }
             global_seeders += seeders;
             global_leechers += leechers;
         }
@@ -74,16 +141,67 @@ impl AtomicTrackerStatus {
         CurrentTrackerStats::new(total_torrents, global_seeders, global_leechers)
     }
 
+    /// Gets the scrape statistics of the given torrents.
+    ///
+    /// ## Arguments
+    /// * `info_hashes`: The info hashes of the torrents to scrape. If empty, every torrent is scraped.
+    ///
+    /// ## Returns
+    /// * `BTreeMap<InfoHash, ScrapeFile>`: The statistics of each torrent, by info hash. Torrents unknown to the tracker are left out.
+    pub fn scrape(&self, info_hashes: &[InfoHash]) -> BTreeMap<InfoHash, ScrapeFile> {
+        let swarms = self.lock_swarms();
+
+        let scraped_swarms: Vec<(&InfoHash, &Swarm)> = if info_hashes.is_empty() {
+            swarms.iter().collect()
+        } else {
+            info_hashes
+                .iter()
+                .filter_map(|info_hash| swarms.get_key_value(info_hash))
+                .collect()
+        };
+
+        scraped_swarms
+            .into_iter()
+            .map(|(info_hash, swarm)| {
+                let (seeders, leechers) = swarm.get_current_seeders_and_leechers();
+                let file = ScrapeFile {
+                    complete: seeders,
+                    downloaded: swarm.get_completed(),
+                    incomplete: leechers,
+                };
+                (*info_hash, file)
+            })
+            .collect()
+    }
+
     /// Removes any inactive peers from each swarm.
+    ///
+    /// It also forgets the announce times that no longer limit any source IP.
     pub fn remove_inactive_peers(&self) {
         for swarm in self.lock_swarms().values_mut() {
             swarm.remove_inactive_peers();
         }
+
+        let now = Local::now();
+        self.lock_last_announces()
+            .retain(|_, last| now.signed_duration_since(*last) < self.min_announce_interval);
+    }
+
+    /// Moves the time of every last announce back by `elapsed`, as if that time had passed since them.
+    #[cfg(test)]
+    pub(crate) fn backdate_last_announces(&self, elapsed: Duration) {
+        for last in self.lock_last_announces().values_mut() {
+            *last -= elapsed;
+        }
     }
 
     fn lock_swarms(&self) -> MutexGuard<HashMap<InfoHash, Swarm>> {
         self.torrent_swarms.lock().unwrap() // Unwrap is safe here because we're the only ones who call this function.
     }
+
+    fn lock_last_announces(&self) -> MutexGuard<'_, HashMap<(String, InfoHash), DateTime<Local>>> {
+        self.last_announces.lock().unwrap() // Unwrap is safe here because we're the only ones who call this function.
+    }
 }
 
 #[cfg(test)]
@@ -159,6 +277,29 @@ mod tests {
         assert_there_is_only_one_leecher(&tracker_status, another_info_hash);
     }
 
+    #[test]
+    fn test_incoming_peers_are_in_the_swarm_active_peers() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = [0; 20];
+
+        let first_response =
+            tracker_status.incoming_peer(info_hash, create_test_seeder([0; 20]), 50);
+        let second_response =
+            tracker_status.incoming_peer(info_hash, create_test_leecher([1; 20]), 50);
+
+        assert!(first_response.peers.is_empty());
+        assert_eq!(second_response.peers.len(), 1);
+        assert_eq!(second_response.peers[0].id, [0; 20]);
+        assert_eq!((second_response.seeders, second_response.leechers), (1, 1));
+
+        let (active_peers, seeders, leechers) =
+            get_active_peers_for(&tracker_status, info_hash, 50).unwrap();
+        let mut ids: Vec<[u8; 20]> = active_peers.iter().map(|peer| peer.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![[0; 20], [1; 20]]);
+        assert_eq!((seeders, leechers), (1, 1));
+    }
+
     #[test]
     fn test_peer_can_get_inactive() {
         let tracker_status = AtomicTrackerStatus::default();
@@ -174,6 +315,135 @@ mod tests {
         assert_there_are_only_these_peers(&tracker_status, an_info_hash, 0, 0);
     }
 
+    #[test]
+    fn test_incoming_peer_is_not_in_its_own_peers() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = [0; 20];
+        for id in 1..10 {
+            tracker_status.incoming_peer(info_hash, create_test_leecher([id; 20]), 50);
+        }
+
+        for _ in 0..10 {
+            let active_peers =
+                tracker_status.incoming_peer(info_hash, create_test_leecher([0; 20]), 50);
+
+            assert_eq!(active_peers.peers.len(), 9);
+            assert!(active_peers.peers.iter().all(|peer| peer.id != [0; 20]));
+        }
+    }
+
+    #[test]
+    fn test_global_statistics_of_several_torrents() {
+        let tracker_status = AtomicTrackerStatus::default();
+        tracker_status.incoming_peer([0; 20], create_test_seeder([0; 20]), 50);
+        tracker_status.incoming_peer([0; 20], create_test_leecher([1; 20]), 50);
+        tracker_status.incoming_peer([1; 20], create_test_leecher([0; 20]), 50);
+        tracker_status.incoming_peer([2; 20], create_test_seeder([2; 20]), 50);
+        tracker_status.incoming_peer([2; 20], create_test_seeder([3; 20]), 50);
+
+        let stats = tracker_status.get_global_statistics();
+
+        assert_eq!(stats.torrents, 3);
+        assert_eq!(stats.seeders, 3);
+        assert_eq!(stats.leechers, 2);
+    }
+
+    #[test]
+    fn test_global_statistics_after_removing_inactive_peers() {
+        let tracker_status = AtomicTrackerStatus::default();
+        tracker_status.incoming_peer([0; 20], create_test_seeder([0; 20]), 50);
+        tracker_status.incoming_peer([0; 20], create_inactive_peer([1; 20]), 50);
+        tracker_status.incoming_peer([1; 20], create_inactive_peer([0; 20]), 50);
+
+        tracker_status.remove_inactive_peers();
+        let stats = tracker_status.get_global_statistics();
+
+        assert_eq!(stats.torrents, 1);
+        assert_eq!(stats.seeders, 1);
+        assert_eq!(stats.leechers, 0);
+    }
+
+    #[test]
+    fn test_too_fast_reannounce_is_rejected() {
+        let tracker_status = AtomicTrackerStatus::default();
+        let info_hash = [0; 20];
+
+        assert!(tracker_status.allow_announce("127.0.0.1", info_hash));
+        assert!(!tracker_status.allow_announce("127.0.0.1", info_hash));
+    }
+
+    #[test]
+    fn test_compliant_reannounce_is_accepted() {
+        let tracker_status =
+            create_test_tracker_status_with_min_announce_interval(Duration::zero());
+        let info_hash = [0; 20];
+
+        assert!(tracker_status.allow_announce("127.0.0.1", info_hash));
+        assert!(tracker_status.allow_announce("127.0.0.1", info_hash));
+    }
+
+    #[test]
+    fn test_rate_limit_is_per_ip_and_torrent() {
+        let tracker_status = AtomicTrackerStatus::default();
+
+        assert!(tracker_status.allow_announce("127.0.0.1", [0; 20]));
+        assert!(tracker_status.allow_announce("127.0.0.2", [0; 20]));
+        assert!(tracker_status.allow_announce("127.0.0.1", [1; 20]));
+    }
+
+    #[test]
+    fn test_expired_announce_times_are_removed() {
+        let tracker_status =
+            create_test_tracker_status_with_min_announce_interval(Duration::zero());
+
+        tracker_status.allow_announce("127.0.0.1", [0; 20]);
+        tracker_status.remove_inactive_peers();
+
+        assert!(tracker_status.lock_last_announces().is_empty());
+    }
+
+    #[test]
+    fn test_peer_older_than_the_timeout_is_removed() {
+        let tracker_status = AtomicTrackerStatus::new(
+            Duration::minutes(5),
+            Duration::seconds(0),
+            DEFAULT_MAX_SWARM_PEERS,
+        );
+        let mut a_peer = create_test_leecher([0; 20]);
+        a_peer.status.last_seen = Local::now() - Duration::minutes(6);
+        tracker_status.incoming_peer([0; 20], a_peer, 50);
+
+        tracker_status.remove_inactive_peers();
+
+        assert_there_are_only_these_peers(&tracker_status, [0; 20], 0, 0);
+    }
+
+    #[test]
+    fn test_recent_peer_is_not_removed() {
+        let tracker_status = AtomicTrackerStatus::new(
+            Duration::minutes(5),
+            Duration::seconds(0),
+            DEFAULT_MAX_SWARM_PEERS,
+        );
+        let mut a_peer = create_test_leecher([0; 20]);
+        a_peer.status.last_seen = Local::now() - Duration::minutes(4);
+        tracker_status.incoming_peer([0; 20], a_peer, 50);
+
+        tracker_status.remove_inactive_peers();
+
+        assert_there_is_only_one_leecher(&tracker_status, [0; 20]);
+    }
+
+    fn create_test_tracker_status_with_min_announce_interval(
+        min_announce_interval: Duration,
+    ) -> AtomicTrackerStatus {
+        AtomicTrackerStatus::new(
+            Duration::hours(DEFAULT_PEER_HOURS_TIMEOUT),
+            min_announce_interval,
+            DEFAULT_MAX_SWARM_PEERS,
+        )
+    }
+
     fn assert_there_are_only_these_peers(
         status: &AtomicTrackerStatus,
         info_hash: [u8; 20],
@@ -210,7 +480,7 @@ mod tests {
         let all_swarms = status.lock_swarms();
         let swarm = all_swarms.get(&info_hash)?;
 
-        let active_peers = swarm.get_active_peers(wanted_peers);
+        let active_peers = swarm.get_active_peers(wanted_peers, None);
 
         Some((
             active_peers.peers,
@@ -244,7 +514,7 @@ mod tests {
     }
 
     fn create_inactive_peer(peer_id: [u8; 20]) -> Peer {
-        let old_date = Local::now().sub(Duration::hours(PEER_HOURS_TIMEOUT) * 2);
+        let old_date = Local::now().sub(Duration::hours(DEFAULT_PEER_HOURS_TIMEOUT) * 2);
         let peer_status = PeerStatus {
             uploaded: 0,
             downloaded: 0,
@@ -1,3 +1,4 @@
+use chrono::Local;
 use serde::{Deserialize, Serialize};
 
 /// Struct containing the current stats of the tracker.
@@ -6,20 +7,32 @@ use serde::{Deserialize, Serialize};
 /// * `torrents`: The total number of torrents in the tracker.
 /// * `seeders`: The total number of seeders in the tracker.
 /// * `leechers`: The total number of leechers in the tracker.
+/// * `timestamp`: The Unix timestamp in seconds of when the stats were taken.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CurrentTrackerStats {
     pub torrents: u32,
     pub seeders: u32,
     pub leechers: u32,
+    #[serde(default)]
+    pub timestamp: i64,
 }
 
 impl CurrentTrackerStats {
-    /// Creates a new `CurrentTrackerStats`.
+    /// Creates a new `CurrentTrackerStats` taken at the current time.
     pub fn new(torrents: u32, seeders: u32, leechers: u32) -> Self {
         Self {
             torrents,
             seeders,
             leechers,
+            timestamp: Local::now().timestamp(),
         }
     }
+
+    /// Returns the stats as a CSV row with the `timestamp,torrents,seeders,leechers` columns.
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.timestamp, self.torrents, self.seeders, self.leechers
+        )
+    }
 }
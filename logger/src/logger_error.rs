@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug)]
 
 /// Logger posible errors
@@ -7,3 +9,20 @@ pub enum LoggerError {
     LogDirectoryError(String),
     LogFileError(String),
 }
+
+impl fmt::Display for LoggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SpawnThreadError => write!(f, "couldn't spawn the logger thread"),
+            Self::SendError(message) => write!(f, "couldn't send the log: {}", message),
+            Self::LogDirectoryError(message) => {
+                write!(f, "couldn't create the log directory: {}", message)
+            }
+            Self::LogFileError(directory) => {
+                write!(f, "couldn't create a log file in: {}", directory)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoggerError {}